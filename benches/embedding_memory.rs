@@ -0,0 +1,101 @@
+//! Compares the memory footprint and brute-force scan speed of a 3072-dimension corpus
+//! (the size of `text-embedding-3-large` vectors) held as `f32` versus `f64`.
+//!
+//! Run with `cargo bench --bench embedding_memory`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use langchain_rust::embedding::embedding_to_f64;
+
+const DOCUMENTS: usize = 10_000;
+const DIMENSIONS: usize = 3072;
+
+/// Counts live heap bytes so the corpus size can be measured rather than computed.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn corpus() -> Vec<Vec<f32>> {
+    (0..DOCUMENTS)
+        .map(|i| {
+            (0..DIMENSIONS)
+                .map(|d| ((i * 31 + d * 7) % 1000) as f32 / 1000.0 - 0.5)
+                .collect()
+        })
+        .collect()
+}
+
+fn heap_size<T>(build: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = build();
+    (value, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+fn dot_f32(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn dot_f64(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn embedding_memory(c: &mut Criterion) {
+    let (corpus_f32, bytes_f32) = heap_size(corpus);
+    let (corpus_f64, bytes_f64) = heap_size(|| {
+        corpus_f32
+            .iter()
+            .map(|e| embedding_to_f64(e))
+            .collect::<Vec<_>>()
+    });
+    println!(
+        "{} x {} corpus: f32 {:.1} MiB, f64 {:.1} MiB ({:.0}% less with f32)",
+        DOCUMENTS,
+        DIMENSIONS,
+        bytes_f32 as f64 / (1024.0 * 1024.0),
+        bytes_f64 as f64 / (1024.0 * 1024.0),
+        100.0 * (1.0 - bytes_f32 as f64 / bytes_f64 as f64)
+    );
+
+    let query_f32 = corpus_f32[0].clone();
+    let query_f64 = corpus_f64[0].clone();
+
+    c.bench_function("scan_3072_f32", |b| {
+        b.iter(|| {
+            corpus_f32
+                .iter()
+                .map(|e| dot_f32(black_box(&query_f32), e))
+                .fold(f32::MIN, f32::max)
+        })
+    });
+    c.bench_function("scan_3072_f64", |b| {
+        b.iter(|| {
+            corpus_f64
+                .iter()
+                .map(|e| dot_f64(black_box(&query_f64), e))
+                .fold(f64::MIN, f64::max)
+        })
+    });
+}
+
+criterion_group!(benches, embedding_memory);
+criterion_main!(benches);
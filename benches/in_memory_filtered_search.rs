@@ -0,0 +1,79 @@
+//! Compares filtered similarity search on the in-memory store with and without its
+//! metadata index.
+//!
+//! Run with `cargo bench --features in-memory --bench in_memory_filtered_search`.
+
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, Criterion};
+use langchain_rust::{
+    embedding::{Embedder, EmbedderError},
+    schemas::Document,
+    vectorstore::{in_memory::StoreBuilder, VecStoreOptions, VectorStore},
+};
+use serde_json::json;
+
+const DOCUMENTS: usize = 20_000;
+const TENANTS: usize = 100;
+const DIMENSIONS: usize = 64;
+
+/// Deterministic embedder so the benchmark measures the store, not a model.
+struct HashEmbedder;
+
+fn embed(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0; DIMENSIONS];
+    for (i, byte) in text.bytes().enumerate() {
+        vector[(i * 31 + byte as usize) % DIMENSIONS] += byte as f32;
+    }
+    vector
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        Ok(documents.iter().map(|d| embed(d)).collect())
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        Ok(embed(text))
+    }
+}
+
+fn filtered_search(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let docs: Vec<Document> = (0..DOCUMENTS)
+        .map(|i| {
+            Document::new(format!("document {} about topic {}", i, i % 37)).with_metadata(
+                [("tenant".to_string(), json!(i % TENANTS))]
+                    .into_iter()
+                    .collect(),
+            )
+        })
+        .collect();
+    let options = VecStoreOptions::new().with_filters(json!({ "tenant": 7 }));
+
+    for (name, metadata_index) in [("with_index", true), ("without_index", false)] {
+        let store = rt.block_on(async {
+            let store = StoreBuilder::new()
+                .embedder(HashEmbedder)
+                .metadata_index(metadata_index)
+                .build()
+                .await
+                .unwrap();
+            store
+                .add_documents(&docs, &VecStoreOptions::default())
+                .await
+                .unwrap();
+            store
+        });
+
+        c.bench_function(&format!("filtered_search_{}", name), |b| {
+            b.iter(|| {
+                rt.block_on(store.similarity_search("topic 5", 10, &options))
+                    .unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, filtered_search);
+criterion_main!(benches);
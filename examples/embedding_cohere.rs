@@ -0,0 +1,20 @@
+#[cfg(feature = "cohere")]
+use langchain_rust::embedding::{cohere::CohereEmbedder, embedder_trait::Embedder};
+
+#[cfg(feature = "cohere")]
+#[tokio::main]
+async fn main() {
+    // Requires the API key to be set in the environment variable COHERE_API_KEY
+    let cohere = CohereEmbedder::default();
+
+    let embedding = cohere.embed_query("Why is the sky blue?").await.unwrap();
+
+    println!("{:?}", embedding);
+}
+
+#[cfg(not(feature = "cohere"))]
+fn main() {
+    println!("This example requires the 'cohere' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example embedding_cohere --features=cohere");
+}
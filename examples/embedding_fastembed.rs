@@ -13,6 +13,8 @@ async fn main() {
 
     println!("Len: {}", embeddings.len());
     println!("Embeddings: {:?}", embeddings);
+    // The dimension to pass to a vector store's `vector_dimensions`
+    println!("Dimension: {:?}", fastembed.dimension());
 
     // With custom model
     let model = TextEmbedding::try_new(
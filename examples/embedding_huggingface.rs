@@ -0,0 +1,23 @@
+#[cfg(feature = "huggingface")]
+use langchain_rust::embedding::{embedder_trait::Embedder, huggingface::HuggingFaceEmbedder};
+
+#[cfg(feature = "huggingface")]
+#[tokio::main]
+async fn main() {
+    // Requires the API token to be set in the environment variable HF_TOKEN
+    let huggingface = HuggingFaceEmbedder::default().with_wait_for_model(true);
+
+    let embedding = huggingface
+        .embed_query("Why is the sky blue?")
+        .await
+        .unwrap();
+
+    println!("{:?}", embedding);
+}
+
+#[cfg(not(feature = "huggingface"))]
+fn main() {
+    println!("This example requires the 'huggingface' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example embedding_huggingface --features=huggingface");
+}
@@ -0,0 +1,70 @@
+// A retrieval-augmented chat that never leaves the machine: FastEmbed embeds locally, the
+// vectors live in SQLite and Ollama answers. Pull the model first with `ollama pull llama3.2`.
+// To run this example execute:
+// cargo run --example rag_offline --features fastembed,ollama,sqlite-vec
+
+#[cfg(all(feature = "fastembed", feature = "ollama", feature = "sqlite-vec"))]
+use langchain_rust::{
+    add_documents,
+    chain::{Chain, ConversationalRetrieverChainBuilder},
+    embedding::{EmbeddingModel, FastEmbed},
+    llm::ollama::client::Ollama,
+    memory::SimpleMemory,
+    prompt_args,
+    schemas::Document,
+    vectorstore::{sqlite_vec::StoreBuilder, Retriever, VectorStore},
+};
+
+#[cfg(all(feature = "fastembed", feature = "ollama", feature = "sqlite-vec"))]
+#[tokio::main]
+async fn main() {
+    // The model is downloaded on the first run only; later runs work offline.
+    let embedder = FastEmbed::builder()
+        .model(EmbeddingModel::AllMiniLML6V2)
+        .show_download_progress(true)
+        .build()
+        .unwrap();
+    let dimensions = embedder.dimension().unwrap();
+
+    let store = StoreBuilder::new()
+        .embedder(embedder)
+        .connection_url("sqlite::memory:")
+        .table("documents")
+        .vector_dimensions(dimensions as i32)
+        .build()
+        .await
+        .unwrap();
+    store.initialize().await.unwrap();
+
+    let documents = vec![
+        Document::new("langchain-rust is a port of the langchain python library to rust."),
+        Document::new("FastEmbed runs ONNX embedding models on the CPU."),
+        Document::new("Ollama serves large language models on your own machine."),
+    ];
+    add_documents!(store, &documents).await.unwrap();
+
+    let chain = ConversationalRetrieverChainBuilder::new()
+        .llm(Ollama::default().with_model("llama3.2"))
+        .rephrase_question(true)
+        .memory(SimpleMemory::new().into())
+        .retriever(Retriever::new(store, 2))
+        .build()
+        .expect("Error building ConversationalChain");
+
+    let input_variables = prompt_args! {
+        "question" => "What does FastEmbed do?",
+    };
+    match chain.invoke(input_variables).await {
+        Ok(result) => println!("Result: {}", result),
+        Err(e) => println!("Error: {:?}", e),
+    }
+}
+
+#[cfg(not(all(feature = "fastembed", feature = "ollama", feature = "sqlite-vec")))]
+fn main() {
+    println!(
+        "This example requires the 'fastembed', 'ollama' and 'sqlite-vec' features to be enabled."
+    );
+    println!("Please run the command as follows:");
+    println!("cargo run --example rag_offline --features fastembed,ollama,sqlite-vec");
+}
@@ -0,0 +1,88 @@
+// To run this example execute: cargo run --example vector_store_mongodb --features mongodb
+
+#[cfg(feature = "mongodb")]
+use langchain_rust::{
+    embedding::openai::openai_embedder::OpenAiEmbedder,
+    schemas::Document,
+    vectorstore::mongodb::StoreBuilder,
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+#[cfg(feature = "mongodb")]
+use serde_json::json;
+#[cfg(feature = "mongodb")]
+use std::io::Write;
+
+#[cfg(feature = "mongodb")]
+#[tokio::main]
+async fn main() {
+    // Requires OpenAI API key to be set in the environment variable OPENAI_API_KEY
+    let embedder = OpenAiEmbedder::default();
+
+    // Requires an Atlas cluster; the "vector_index" search index on the
+    // "langchain.documents" collection is created if it doesn't exist.
+    let store = StoreBuilder::new()
+        .embedder(embedder)
+        .connection_uri(std::env::var("MONGODB_URI").expect("MONGODB_URI must be set"))
+        .database("langchain")
+        .collection("documents")
+        .index_name("vector_index")
+        .create_index(true)
+        .index_filter_field("source")
+        .build()
+        .await
+        .unwrap();
+
+    store.initialize().await.unwrap();
+
+    // Add documents to the database
+    let doc1 = Document::new(
+        "langchain-rust is a port of the langchain python library to rust and was written in 2024.",
+    )
+    .with_metadata([("source".to_string(), json!("github"))].into());
+    let doc2 = Document::new(
+        "langchaingo is a port of the langchain python library to go language and was written in 2023."
+    )
+    .with_metadata([("source".to_string(), json!("github"))].into());
+    let doc3 = Document::new(
+        "Capital of United States of America (USA) is Washington D.C. and the capital of France is Paris."
+    )
+    .with_metadata([("source".to_string(), json!("wiki"))].into());
+    let doc4 = Document::new("Capital of France is Paris.")
+        .with_metadata([("source".to_string(), json!("wiki"))].into());
+
+    store
+        .add_documents(&vec![doc1, doc2, doc3, doc4], &VecStoreOptions::default())
+        .await
+        .unwrap();
+
+    // Ask for user input
+    print!("Query> ");
+    std::io::stdout().flush().unwrap();
+    let mut query = String::new();
+    std::io::stdin().read_line(&mut query).unwrap();
+
+    let results = store
+        .similarity_search(
+            &query,
+            2,
+            &VecStoreOptions::default().with_filters(json!({"source": "wiki"})),
+        )
+        .await
+        .unwrap();
+
+    if results.is_empty() {
+        println!("No results found.");
+        return;
+    } else {
+        results.iter().for_each(|r| {
+            println!("Document: {}", r.page_content);
+        });
+    }
+}
+
+#[cfg(not(feature = "mongodb"))]
+fn main() {
+    println!("This example requires the 'mongodb' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example vector_store_mongodb --features mongodb");
+}
@@ -120,6 +120,7 @@ async fn add_documents_to_index(store: &Store) -> Result<Vec<String>, Box<dyn Er
         score_threshold: None,
         filters: None,
         embedder: Some(store.embedder.clone()),
+        ..VecStoreOptions::default()
     };
 
     let result = store
@@ -0,0 +1,74 @@
+// To run this example execute: cargo run --example vector_store_redis --features redis-vector
+
+#[cfg(feature = "redis-vector")]
+use langchain_rust::{
+    embedding::openai::openai_embedder::OpenAiEmbedder, schemas::Document,
+    vectorstore::redis::StoreBuilder, vectorstore::VectorStore,
+};
+#[cfg(feature = "redis-vector")]
+use std::io::Write;
+
+#[cfg(feature = "redis-vector")]
+#[tokio::main]
+async fn main() {
+    // Initialize Embedder
+
+    use langchain_rust::vectorstore::VecStoreOptions;
+
+    // Requires OpenAI API key to be set in the environment variable OPENAI_API_KEY
+    let embedder = OpenAiEmbedder::default();
+
+    // Ensure Redis with the RediSearch module is running at localhost, with port 6379
+    // docker run -p 6379:6379 redis/redis-stack-server:7.4.0-v1
+    let store = StoreBuilder::new()
+        .embedder(embedder)
+        .url("redis://localhost:6379")
+        .index_name("langchain-rs")
+        .build()
+        .await
+        .unwrap();
+
+    // Add documents to the database
+    let doc1 = Document::new(
+        "langchain-rust is a port of the langchain python library to rust and was written in 2024.",
+    );
+    let doc2 = Document::new(
+        "langchaingo is a port of the langchain python library to go language and was written in 2023."
+    );
+    let doc3 = Document::new(
+        "Capital of United States of America (USA) is Washington D.C. and the capital of France is Paris."
+    );
+    let doc4 = Document::new("Capital of France is Paris.");
+
+    store
+        .add_documents(&vec![doc1, doc2, doc3, doc4], &VecStoreOptions::default())
+        .await
+        .unwrap();
+
+    // Ask for user input
+    print!("Query> ");
+    std::io::stdout().flush().unwrap();
+    let mut query = String::new();
+    std::io::stdin().read_line(&mut query).unwrap();
+
+    let results = store
+        .similarity_search(&query, 2, &VecStoreOptions::default())
+        .await
+        .unwrap();
+
+    if results.is_empty() {
+        println!("No results found.");
+        return;
+    } else {
+        results.iter().for_each(|r| {
+            println!("Document: {}", r.page_content);
+        });
+    }
+}
+
+#[cfg(not(feature = "redis-vector"))]
+fn main() {
+    println!("This example requires the 'redis-vector' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example vector_store_redis --features redis-vector");
+}
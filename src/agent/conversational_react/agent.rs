@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::json;
+use tiktoken_rs::CoreBPE;
+use tokio::sync::Mutex;
+
+use crate::{
+    agent::{agent::Agent, AgentError},
+    prompt::PromptArgs,
+    schemas::{
+        agent::{AgentAction, AgentEvent},
+        memory::BaseMemory,
+        messages::Message,
+    },
+    tools::Tool,
+};
+
+/// `ConversationalReactAgent` wraps a ReAct style agent (for example the
+/// [`ConversationalAgent`](crate::agent::ConversationalAgent)) with a memory, so the
+/// agent keeps the context of previous turns.
+///
+/// On every run the agent:
+/// 1. loads the history from memory,
+/// 2. injects it as `chat_history` into the inner agent prompt,
+/// 3. runs the inner agent,
+/// 4. saves the input and the final answer back to memory.
+///
+/// When `max_history_tokens` is set, only the most recent messages that fit in
+/// the token budget are sent to the LLM. The memory itself is left untouched.
+///
+/// Use it with an [`AgentExecutor`](crate::agent::AgentExecutor) that has no memory
+/// of its own, otherwise each turn is saved twice.
+pub struct ConversationalReactAgent<A: Agent> {
+    pub(crate) agent: A,
+    pub(crate) memory: Arc<Mutex<dyn BaseMemory>>,
+    pub(crate) max_history_tokens: Option<usize>,
+    pub(crate) bpe: Option<CoreBPE>,
+}
+
+impl<A: Agent> ConversationalReactAgent<A> {
+    /// Returns the history to send to the inner agent, trimmed to `max_history_tokens`.
+    async fn load_history(&self) -> Vec<Message> {
+        let messages = self.memory.lock().await.messages();
+        match (self.max_history_tokens, &self.bpe) {
+            (Some(max_tokens), Some(bpe)) => trim_history(messages, max_tokens, |text| {
+                bpe.encode_with_special_tokens(text).len()
+            }),
+            _ => messages,
+        }
+    }
+
+    async fn save_turn(&self, inputs: &PromptArgs, output: &str) {
+        let mut memory = self.memory.lock().await;
+        if let Some(input) = inputs.get("input") {
+            match input {
+                // This avoids adding extra quotes to the user input in the history.
+                serde_json::Value::String(s) => memory.add_user_message(s),
+                x => memory.add_user_message(x),
+            }
+        }
+        memory.add_ai_message(&output);
+    }
+}
+
+/// Keeps the most recent messages whose accumulated token count fits in `max_tokens`.
+pub(crate) fn trim_history<F>(messages: Vec<Message>, max_tokens: usize, count: F) -> Vec<Message>
+where
+    F: Fn(&str) -> usize,
+{
+    let mut total = 0;
+    let mut kept = Vec::new();
+    for message in messages.into_iter().rev() {
+        total += count(&message.content);
+        if total > max_tokens {
+            break;
+        }
+        kept.push(message);
+    }
+    kept.reverse();
+    kept
+}
+
+#[async_trait]
+impl<A: Agent> Agent for ConversationalReactAgent<A> {
+    async fn plan(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<AgentEvent, AgentError> {
+        let mut inputs = inputs;
+        let history = self.load_history().await;
+        inputs.insert("chat_history".to_string(), json!(history));
+
+        let event = self.agent.plan(intermediate_steps, inputs.clone()).await?;
+        if let AgentEvent::Finish(finish) = &event {
+            self.save_turn(&inputs, &finish.output).await;
+        }
+
+        Ok(event)
+    }
+
+    fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.agent.get_tools()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_history_keeps_most_recent() {
+        let messages = vec![
+            Message::new_human_message("one two three"),
+            Message::new_ai_message("four five"),
+            Message::new_human_message("six"),
+        ];
+        let count = |text: &str| text.split_whitespace().count();
+
+        let trimmed = trim_history(messages.clone(), 3, count);
+        assert_eq!(trimmed.len(), 2);
+        assert_eq!(trimmed[0].content, "four five");
+        assert_eq!(trimmed[1].content, "six");
+
+        let trimmed = trim_history(messages, 100, count);
+        assert_eq!(trimmed.len(), 3);
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    agent::{agent::Agent, AgentError},
+    memory::SimpleMemory,
+    schemas::memory::BaseMemory,
+};
+
+use super::ConversationalReactAgent;
+
+pub struct ConversationalReactAgentBuilder {
+    memory: Option<Arc<Mutex<dyn BaseMemory>>>,
+    max_history_tokens: Option<usize>,
+}
+
+impl ConversationalReactAgentBuilder {
+    pub fn new() -> Self {
+        Self {
+            memory: None,
+            max_history_tokens: None,
+        }
+    }
+
+    pub fn memory(mut self, memory: Arc<Mutex<dyn BaseMemory>>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Limits the history sent to the LLM to the most recent messages that fit
+    /// in `max_history_tokens` (counted with the `cl100k_base` tokenizer).
+    pub fn max_history_tokens(mut self, max_history_tokens: usize) -> Self {
+        self.max_history_tokens = Some(max_history_tokens);
+        self
+    }
+
+    pub fn build<A: Agent>(self, agent: A) -> Result<ConversationalReactAgent<A>, AgentError> {
+        let bpe = match self.max_history_tokens {
+            Some(_) => Some(
+                tiktoken_rs::cl100k_base().map_err(|e| AgentError::OtherError(e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        Ok(ConversationalReactAgent {
+            agent,
+            memory: self.memory.unwrap_or_else(|| SimpleMemory::new().into()),
+            max_history_tokens: self.max_history_tokens,
+            bpe,
+        })
+    }
+}
@@ -12,3 +12,6 @@ pub use open_ai_tools::*;
 
 mod error;
 pub use error::*;
+
+mod conversational_react;
+pub use conversational_react::*;
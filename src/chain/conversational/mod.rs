@@ -137,12 +137,79 @@ impl Chain for ConversationalChain {
 mod tests {
     use crate::{
         chain::conversational::builder::ConversationalChainBuilder,
-        llm::openai::{OpenAI, OpenAIModel},
+        llm::openai::{OpenAI, OpenAIConfig, OpenAIModel},
+        memory::SimpleMemory,
         prompt_args,
+        schemas::MessageType,
     };
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_stream_saves_full_response_to_memory() {
+        let chunk = |delta: serde_json::Value, finish_reason: serde_json::Value| {
+            let chunk = serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            });
+            format!("data: {}\n\n", chunk)
+        };
+        let body = [
+            chunk(
+                serde_json::json!({"role": "assistant", "content": ""}),
+                serde_json::Value::Null,
+            ),
+            chunk(
+                serde_json::json!({"content": "Hello"}),
+                serde_json::Value::Null,
+            ),
+            chunk(
+                serde_json::json!({"content": " there"}),
+                serde_json::Value::Null,
+            ),
+            chunk(serde_json::json!({}), serde_json::json!("stop")),
+            "data: [DONE]\n\n".to_string(),
+        ]
+        .concat();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let config = OpenAIConfig::new()
+            .with_api_base(server.url())
+            .with_api_key("key");
+        let memory: Arc<Mutex<dyn BaseMemory>> = Arc::new(Mutex::new(SimpleMemory::new()));
+        let chain = ConversationalChainBuilder::new()
+            .llm(OpenAI::new(config))
+            .memory(memory.clone())
+            .build()
+            .expect("Error building ConversationalChain");
+
+        let deltas: Vec<String> = chain
+            .stream(prompt_args! { "input" => "Hi" })
+            .await
+            .unwrap()
+            .map(|data| data.unwrap().content)
+            .collect()
+            .await;
+        // The final chunk, with an empty delta, is not yielded.
+        assert_eq!(deltas, vec!["", "Hello", " there"]);
+
+        let messages = memory.lock().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message_type, MessageType::HumanMessage);
+        assert_eq!(messages[1].content, "Hello there");
+        mock.assert_async().await;
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_conversational() {
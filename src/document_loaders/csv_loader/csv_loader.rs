@@ -1,10 +1,11 @@
 use crate::document_loaders::{process_doc_stream, LoaderError};
 use crate::{document_loaders::Loader, schemas::Document, text_splitter::TextSplitter};
-use async_stream::stream;
 use async_trait::async_trait;
 use csv;
 use futures::Stream;
 use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 
 use std::collections::HashMap;
 use std::fs::File;
@@ -12,15 +13,63 @@ use std::io::{BufReader, Cursor, Read};
 use std::path::Path;
 use std::pin::Pin;
 
+// Number of parsed rows buffered ahead of the consumer.
+const ROW_CHANNEL_CAPACITY: usize = 64;
+
+/// Loads one `Document` per CSV row.
+///
+/// The content is a `column: value` line for each of `columns`, or, when `columns` is empty,
+/// all the row's values joined with spaces. Every document has its `row` number in the
+/// metadata, plus the values of the [`CsvLoader::with_metadata_columns`].
+///
+/// The file is read incrementally on a blocking thread and rows are handed to the
+/// returned stream through a bounded channel, so memory stays bounded for large files.
 #[derive(Debug, Clone)]
 pub struct CsvLoader<R> {
     reader: R,
     columns: Vec<String>,
+    metadata_columns: Vec<String>,
+    delimiter: u8,
+    has_headers: bool,
+    continue_on_error: bool,
 }
 
 impl<R: Read> CsvLoader<R> {
     pub fn new(reader: R, columns: Vec<String>) -> Self {
-        Self { reader, columns }
+        Self {
+            reader,
+            columns,
+            metadata_columns: Vec::new(),
+            delimiter: b',',
+            has_headers: true,
+            continue_on_error: false,
+        }
+    }
+
+    /// Stores the values of `metadata_columns` in the metadata, keyed by column name.
+    pub fn with_metadata_columns(mut self, metadata_columns: Vec<String>) -> Self {
+        self.metadata_columns = metadata_columns;
+        self
+    }
+
+    /// Sets the field delimiter. Defaults to `,`.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first row holds the column names. When false the columns are named by
+    /// their index, `"0"`, `"1"`, ... Defaults to true.
+    pub fn with_has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// When true, malformed rows are logged and skipped instead of ending the stream
+    /// with an error. Defaults to false.
+    pub fn with_continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
     }
 }
 
@@ -48,21 +97,65 @@ impl<R: Read + Send + Sync + 'static> Loader for CsvLoader<R> {
         Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
         LoaderError,
     > {
-        let mut reader = csv::Reader::from_reader(self.reader);
-        let headers = reader.headers()?.clone();
+        let (tx, rx) = mpsc::channel(ROW_CHANNEL_CAPACITY);
+        let reader = self.reader;
+        let columns = self.columns;
+        let metadata_columns = self.metadata_columns;
+        let continue_on_error = self.continue_on_error;
+        let delimiter = self.delimiter;
+        let has_headers = self.has_headers;
 
-        // Initialize rown to track row number
-        let mut row_number: i64 = 0;
-        let columns = self.columns.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(has_headers)
+                .from_reader(reader);
+            let headers = if has_headers {
+                match reader.headers() {
+                    Ok(headers) => Some(headers.clone()),
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e.into()));
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
+            let column_name = |i: usize| match &headers {
+                Some(headers) => headers[i].to_string(),
+                None => i.to_string(),
+            };
+
+            // Initialize rown to track row number
+            let mut row_number: i64 = 0;
 
-        let stream = stream! {
             for result in reader.records() {
-                let record = result?;
-                let mut content = String::new();
+                row_number += 1; // Increment the row number by 1 for each row
+
+                let record = match result {
+                    Ok(record) => record,
+                    Err(e) if continue_on_error => {
+                        log::warn!("Skipping malformed CSV row {}: {}", row_number, e);
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e.into()));
+                        return;
+                    }
+                };
 
+                let mut content = if columns.is_empty() {
+                    record.iter().collect::<Vec<_>>().join(" ")
+                } else {
+                    String::new()
+                };
+                let mut metadata = HashMap::new();
                 for (i, field) in record.iter().enumerate() {
-                    let header = &headers[i];
-                    if !columns.contains(&header.to_string()) {
+                    let header = column_name(i);
+                    if metadata_columns.contains(&header) {
+                        metadata.insert(header.clone(), Value::from(field));
+                    }
+                    if !columns.contains(&header) {
                         continue;
                     }
 
@@ -71,21 +164,21 @@ impl<R: Read + Send + Sync + 'static> Loader for CsvLoader<R> {
                     content.push('\n');
                 }
 
-                row_number += 1; // Increment the row number by 1 for each row
-
                 // Generate document with the content and metadata
                 let mut document = Document::new(content);
-                let mut metadata = HashMap::new();
                 metadata.insert("row".to_string(), Value::from(row_number));
 
                 // Attach the metadata to the document
                 document.metadata = metadata;
 
-                yield Ok(document);
+                // Stop reading when the consumer dropped the stream.
+                if tx.blocking_send(Ok(document)).is_err() {
+                    return;
+                }
             }
-        };
+        });
 
-        Ok(Box::pin(stream))
+        Ok(Box::pin(ReceiverStream::new(rx)))
     }
 
     async fn load_and_split<TS: TextSplitter + 'static>(
@@ -170,4 +263,84 @@ Jane Smith,32,London,United Kingdom";
         assert_eq!(documents[1].metadata.get("row").unwrap(), &Value::from(2));
         assert_eq!(documents[1].page_content, expected2);
     }
+
+    #[tokio::test]
+    async fn test_csv_loader_continue_on_error() {
+        let input = "name,age
+John Doe,25
+broken row,1,extra
+Jane Smith,32";
+        let columns = vec!["name".to_string(), "age".to_string()];
+
+        let documents = CsvLoader::new(input.as_bytes(), columns.clone())
+            .with_continue_on_error(true)
+            .load()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 2);
+        let last = documents[1].as_ref().unwrap();
+        assert_eq!(last.page_content, "name: Jane Smith\nage: 32\n");
+        assert_eq!(last.metadata.get("row").unwrap(), &Value::from(3));
+
+        let documents = CsvLoader::new(input.as_bytes(), columns)
+            .load()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0].is_ok());
+        assert!(documents[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_csv_loader_column_mapping() {
+        let path = "./src/document_loaders/test_data/products.csv";
+        let columns = vec!["name".to_string(), "description".to_string()];
+        let documents = CsvLoader::from_path(path, columns)
+            .expect("Failed to create csv loader")
+            .with_delimiter(b';')
+            .with_metadata_columns(vec!["id".to_string(), "price".to_string()])
+            .load()
+            .await
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 3);
+        assert_eq!(
+            documents[1].page_content,
+            "name: Notebook\ndescription: A5 notebook, dotted pages\n"
+        );
+        assert_eq!(documents[1].metadata["id"], Value::from("p-2"));
+        assert_eq!(documents[1].metadata["price"], Value::from("4.50"));
+        assert_eq!(documents[1].metadata["row"], Value::from(2));
+        assert!(!documents[1].metadata.contains_key("name"));
+    }
+
+    #[tokio::test]
+    async fn test_csv_loader_all_columns_without_headers() {
+        let input = "John Doe,25,New York
+Jane Smith,32,London";
+
+        let documents = CsvLoader::new(input.as_bytes(), Vec::new())
+            .with_has_headers(false)
+            .with_metadata_columns(vec!["2".to_string()])
+            .load()
+            .await
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].page_content, "John Doe 25 New York");
+        assert_eq!(documents[0].metadata["2"], Value::from("New York"));
+        assert_eq!(documents[1].page_content, "Jane Smith 32 London");
+    }
 }
@@ -0,0 +1,7 @@
+mod database_loader;
+pub use database_loader::*;
+
+#[cfg(feature = "postgres")]
+mod pg_database_loader;
+#[cfg(feature = "postgres")]
+pub use pg_database_loader::*;
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::{Stream, TryStreamExt};
+use serde_json::Value;
+use sqlx::{postgres::PgRow, Column, PgPool, Row};
+
+use crate::document_loaders::{process_doc_stream, Loader, LoaderError};
+use crate::{schemas::Document, text_splitter::TextSplitter};
+
+/// Loads one `Document` per row returned by a SQL query on a Postgres database.
+///
+/// The `content_column` becomes the `page_content` and every other column of a text,
+/// integer, float, boolean or JSON type is added to the metadata under its name. Rows are
+/// streamed from a server-side cursor, so large tables are never loaded at once.
+///
+/// # Usage
+/// ```rust,ignore
+/// let loader = PgDatabaseLoader::new(pool, "SELECT body, title FROM posts WHERE author = $1", "body")
+///     .with_params(vec![json!("alice")]);
+/// let docs = loader.load().await?;
+/// ```
+pub struct PgDatabaseLoader {
+    pool: PgPool,
+    query: String,
+    content_column: String,
+    params: Vec<Value>,
+}
+
+impl PgDatabaseLoader {
+    pub fn new<Q: Into<String>, C: Into<String>>(
+        pool: PgPool,
+        query: Q,
+        content_column: C,
+    ) -> Self {
+        Self {
+            pool,
+            query: query.into(),
+            content_column: content_column.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Values bound to the positional parameters (`$1`, `$2`, ...) of the query. Strings,
+    /// numbers, booleans and nulls are bound as such; arrays and objects as JSONB.
+    pub fn with_params(mut self, params: Vec<Value>) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+fn column_to_json(row: &PgRow, index: usize) -> Option<Value> {
+    if let Ok(value) = row.try_get::<Option<String>, _>(index) {
+        return Some(value.map(Value::String).unwrap_or(Value::Null));
+    }
+    if let Ok(value) = row.try_get::<Option<i64>, _>(index) {
+        return Some(value.map(Value::from).unwrap_or(Value::Null));
+    }
+    if let Ok(value) = row.try_get::<Option<i32>, _>(index) {
+        return Some(value.map(Value::from).unwrap_or(Value::Null));
+    }
+    if let Ok(value) = row.try_get::<Option<f64>, _>(index) {
+        return Some(value.map(Value::from).unwrap_or(Value::Null));
+    }
+    if let Ok(value) = row.try_get::<Option<bool>, _>(index) {
+        return Some(value.map(Value::from).unwrap_or(Value::Null));
+    }
+    if let Ok(value) = row.try_get::<Option<Value>, _>(index) {
+        return Some(value.unwrap_or(Value::Null));
+    }
+    None
+}
+
+#[async_trait]
+impl Loader for PgDatabaseLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let stream = stream! {
+            let mut query = sqlx::query(&self.query);
+            for param in &self.params {
+                query = match param {
+                    Value::Null => query.bind(None::<String>),
+                    Value::Bool(b) => query.bind(*b),
+                    Value::Number(n) => match n.as_i64() {
+                        Some(i) => query.bind(i),
+                        None => query.bind(n.as_f64()),
+                    },
+                    Value::String(s) => query.bind(s.clone()),
+                    other => query.bind(other.clone()),
+                };
+            }
+
+            let mut rows = query.fetch(&self.pool);
+            loop {
+                let row = match rows.try_next().await {
+                    Ok(Some(row)) => row,
+                    Ok(None) => break,
+                    Err(e) => {
+                        yield Err(LoaderError::from(e));
+                        break;
+                    }
+                };
+
+                let mut page_content = None;
+                let mut metadata = HashMap::new();
+                for (i, column) in row.columns().iter().enumerate() {
+                    let value = column_to_json(&row, i);
+                    if column.name() == self.content_column {
+                        page_content = Some(match value {
+                            Some(Value::String(s)) => s,
+                            Some(Value::Null) | None => String::new(),
+                            Some(other) => other.to_string(),
+                        });
+                    } else if let Some(value) = value {
+                        metadata.insert(column.name().to_string(), value);
+                    }
+                }
+
+                match page_content {
+                    Some(page_content) => {
+                        yield Ok(Document::new(page_content).with_metadata(metadata));
+                    }
+                    None => {
+                        yield Err(LoaderError::LoadDocumentError(format!(
+                            "Query has no column named {:?}",
+                            self.content_column
+                        )));
+                        break;
+                    }
+                }
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn load_and_split<TS: TextSplitter + 'static>(
+        self,
+        splitter: TS,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let doc_stream = self.load().await?;
+        let stream = process_doc_stream(doc_stream, splitter).await;
+        Ok(Box::pin(stream))
+    }
+}
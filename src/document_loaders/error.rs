@@ -21,6 +21,13 @@ pub enum LoaderError {
     #[error(transparent)]
     CSVError(#[from] csv::Error),
 
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[cfg(feature = "postgres")]
+    #[error(transparent)]
+    SqlxError(#[from] sqlx::Error),
+
     #[cfg(any(feature = "lopdf"))]
     #[error(transparent)]
     LoPdfError(#[from] lopdf::Error),
@@ -33,9 +40,24 @@ pub enum LoaderError {
     #[error(transparent)]
     PdfExtractOutputError(#[from] pdf_extract::OutputError),
 
+    #[cfg(feature = "pptx")]
+    #[error(transparent)]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[cfg(feature = "pptx")]
+    #[error(transparent)]
+    XmlError(#[from] quick_xml::Error),
+
+    #[cfg(feature = "pptx")]
+    #[error(transparent)]
+    XmlAttrError(#[from] quick_xml::events::attributes::AttrError),
+
     #[error(transparent)]
     ReadabilityError(#[from] readability::error::Error),
 
+    #[error(transparent)]
+    ReqwestError(#[from] reqwest::Error),
+
     #[error(transparent)]
     JoinError(#[from] tokio::task::JoinError),
 
@@ -8,6 +8,7 @@ use std::{
 
 use async_trait::async_trait;
 use futures::{stream, Stream};
+use scraper::{ElementRef, Html, Node, Selector};
 use serde_json::Value;
 use url::Url;
 
@@ -17,10 +18,58 @@ use crate::{
     text_splitter::TextSplitter,
 };
 
+/// Elements whose text is never visible.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style", "noscript", "template", "head"];
+
+/// Elements that start a new line of text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Loads an HTML page. By default the page is one document whose main content is picked out
+/// with `readability`. With [`HtmlLoader::with_selectors`] every element matching one of the
+/// CSS selectors becomes a document of its visible text instead, with the `selector` it
+/// matched and its `tag` in the metadata.
+///
+/// The page `<title>` is kept in the `title` metadata, falling back to the element's first
+/// `<h1>`, and `<meta name="description">` in `description`.
 #[derive(Debug, Clone)]
 pub struct HtmlLoader<R> {
     html: R,
     url: Url,
+    selectors: Vec<String>,
+    include_links: bool,
 }
 
 impl HtmlLoader<Cursor<Vec<u8>>> {
@@ -29,11 +78,49 @@ impl HtmlLoader<Cursor<Vec<u8>>> {
         let reader = Cursor::new(input.into_bytes());
         Self::new(reader, url)
     }
+
+    /// Fetches the page at `url`.
+    pub async fn from_url(url: &str) -> Result<Self, LoaderError> {
+        let url = Url::parse(url).map_err(|e| LoaderError::OtherError(e.to_string()))?;
+        let html = reqwest::get(url.clone())
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(Self::new(Cursor::new(html.to_vec()), url))
+    }
 }
 
 impl<R: Read> HtmlLoader<R> {
     pub fn new(html: R, url: Url) -> Self {
-        Self { html, url }
+        Self {
+            html,
+            url,
+            selectors: Vec::new(),
+            include_links: false,
+        }
+    }
+
+    /// Restricts extraction to the elements matching the CSS `selector`, e.g. `article`.
+    pub fn with_selector<S: Into<String>>(self, selector: S) -> Self {
+        self.with_selectors([selector])
+    }
+
+    /// Loads one document per element matching any of the CSS `selectors`, e.g. `["body"]`,
+    /// made of its text without scripts and styles. When nothing matches no document is
+    /// loaded.
+    pub fn with_selectors<S: Into<String>>(
+        mut self,
+        selectors: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.selectors = selectors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Stores the absolute targets of the links in each document in its `links` metadata.
+    pub fn with_include_links(mut self, include_links: bool) -> Self {
+        self.include_links = include_links;
+        self
     }
 }
 
@@ -53,13 +140,71 @@ impl<R: Read + Send + Sync + 'static> Loader for HtmlLoader<R> {
         Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
         LoaderError,
     > {
-        let cleaned_html = readability::extractor::extract(&mut self.html, &self.url)?;
-        let doc =
-            Document::new(format!("{}\n{}", cleaned_html.title, cleaned_html.text)).with_metadata(
-                HashMap::from([("source".to_string(), Value::from(self.url.as_str()))]),
-            );
+        let mut html = String::new();
+        self.html.read_to_string(&mut html)?;
+        let page = Html::parse_document(&html);
+
+        let mut metadata = HashMap::from([("source".to_string(), Value::from(self.url.as_str()))]);
+        if let Some(title) = select_first(&page, "title")
+            .map(|title| collapse_whitespace(&title.text().collect::<String>()))
+            .filter(|title| !title.is_empty())
+        {
+            metadata.insert("title".to_string(), Value::from(title));
+        }
+        if let Some(description) = select_first(&page, r#"meta[name="description"]"#)
+            .and_then(|meta| meta.value().attr("content"))
+            .map(collapse_whitespace)
+            .filter(|description| !description.is_empty())
+        {
+            metadata.insert("description".to_string(), Value::from(description));
+        }
+
+        let docs: Vec<Result<Document, LoaderError>> = if self.selectors.is_empty() {
+            let cleaned_html = readability::extractor::extract(&mut html.as_bytes(), &self.url)?;
+            if self.include_links {
+                metadata.insert(
+                    "links".to_string(),
+                    Value::from(links(&page.root_element(), &self.url)),
+                );
+            }
+            vec![Ok(Document::new(format!(
+                "{}\n{}",
+                cleaned_html.title, cleaned_html.text
+            ))
+            .with_metadata(metadata))]
+        } else {
+            let mut docs = Vec::new();
+            for selector in &self.selectors {
+                let parsed = Selector::parse(selector).map_err(|e| {
+                    LoaderError::OtherError(format!("Invalid selector {:?}: {}", selector, e))
+                })?;
+                for element in page.select(&parsed) {
+                    let text = visible_text(&element);
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let mut metadata = metadata.clone();
+                    metadata.insert("selector".to_string(), Value::from(selector.as_str()));
+                    metadata.insert("tag".to_string(), Value::from(element.value().name()));
+                    if !metadata.contains_key("title") {
+                        if let Some(h1) = select_first_in(&element, "h1")
+                            .map(|h1| visible_text(&h1))
+                            .filter(|h1| !h1.is_empty())
+                        {
+                            metadata.insert("title".to_string(), Value::from(h1));
+                        }
+                    }
+                    if self.include_links {
+                        metadata
+                            .insert("links".to_string(), Value::from(links(&element, &self.url)));
+                    }
+                    docs.push(Ok(Document::new(text).with_metadata(metadata)));
+                }
+            }
+            docs
+        };
 
-        let stream = stream::iter(vec![Ok(doc)]);
+        let stream = stream::iter(docs);
         Ok(Box::pin(stream))
     }
 
@@ -76,6 +221,68 @@ impl<R: Read + Send + Sync + 'static> Loader for HtmlLoader<R> {
     }
 }
 
+fn select_first<'a>(page: &'a Html, selector: &str) -> Option<ElementRef<'a>> {
+    let selector = Selector::parse(selector).ok()?;
+    page.select(&selector).next()
+}
+
+fn select_first_in<'a>(element: &ElementRef<'a>, selector: &str) -> Option<ElementRef<'a>> {
+    let selector = Selector::parse(selector).ok()?;
+    element.select(&selector).next()
+}
+
+// The distinct targets of the links in `element`, resolved against the page `url`.
+fn links(element: &ElementRef, url: &Url) -> Vec<String> {
+    let selector = Selector::parse("a[href]").unwrap();
+    let mut links: Vec<String> = Vec::new();
+    for href in element
+        .select(&selector)
+        .filter_map(|a| a.value().attr("href"))
+    {
+        if let Ok(link) = url.join(href.trim()) {
+            let link = link.to_string();
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+// The text a browser would show for `element`, one line per block element.
+fn visible_text(element: &ElementRef) -> String {
+    let mut text = String::new();
+    collect_visible_text(element, &mut text);
+    text.lines()
+        .map(collapse_whitespace)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collect_visible_text(element: &ElementRef, text: &mut String) {
+    for node in element.children() {
+        match node.value() {
+            Node::Text(t) => text.push_str(&t.replace('\n', " ")),
+            Node::Element(e) if !HIDDEN_ELEMENTS.contains(&e.name()) => {
+                let block = BLOCK_ELEMENTS.contains(&e.name());
+                if block {
+                    text.push('\n');
+                }
+                collect_visible_text(&ElementRef::wrap(node).unwrap(), text);
+                if block {
+                    text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 #[cfg(test)]
 mod tests {
     use futures_util::StreamExt;
@@ -132,4 +339,123 @@ mod tests {
         );
         assert_eq!(documents[0].page_content, expected);
     }
+
+    #[tokio::test]
+    async fn test_html_loader_selector_and_metadata() {
+        let input = r#"<html>
+  <head>
+    <title> Cat facts </title>
+    <meta name="description" content="Everything about cats.">
+    <style>p { color: red; }</style>
+  </head>
+  <body>
+    <nav>Home | About</nav>
+    <article>
+      <h1>Why cats purr</h1>
+      <script>track("article");</script>
+      <p>Cats purr when
+        they are content.</p>
+      <p>And <em>sometimes</em> when they are not.</p>
+    </article>
+  </body>
+</html>"#;
+
+        let documents = HtmlLoader::from_string(input, Url::parse("https://example.com/").unwrap())
+            .with_selector("article")
+            .load()
+            .await
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 1);
+        assert_eq!(
+            documents[0].page_content,
+            "Why cats purr\nCats purr when they are content.\nAnd sometimes when they are not."
+        );
+        assert_eq!(documents[0].metadata["title"], "Cat facts");
+        assert_eq!(
+            documents[0].metadata["description"],
+            "Everything about cats."
+        );
+        assert_eq!(documents[0].metadata["source"], "https://example.com/");
+
+        let unmatched = HtmlLoader::from_string(input, Url::parse("https://example.com/").unwrap())
+            .with_selector("main")
+            .load()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(unmatched.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_html_loader_selectors_and_links() {
+        let input = r#"<html><body>
+  <section>
+    <h1>Feeding</h1>
+    <p>See <a href="/food">food</a> and <a href="/food">more</a>.</p>
+  </section>
+  <section><h1>Grooming</h1><p>Brush <a href="https://brushes.example/">daily</a>.</p></section>
+  <aside>Ads</aside>
+</body></html>"#;
+
+        let documents =
+            HtmlLoader::from_string(input, Url::parse("https://example.com/cats/").unwrap())
+                .with_selectors(["section", "aside"])
+                .with_include_links(true)
+                .load()
+                .await
+                .unwrap()
+                .map(|x| x.unwrap())
+                .collect::<Vec<_>>()
+                .await;
+
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0].page_content, "Feeding\nSee food and more.");
+        assert_eq!(documents[0].metadata["title"], "Feeding");
+        assert_eq!(documents[0].metadata["selector"], "section");
+        assert_eq!(documents[0].metadata["tag"], "section");
+        assert_eq!(
+            documents[0].metadata["links"],
+            serde_json::json!(["https://example.com/food"])
+        );
+        assert_eq!(documents[1].metadata["title"], "Grooming");
+        assert_eq!(
+            documents[1].metadata["links"],
+            serde_json::json!(["https://brushes.example/"])
+        );
+        assert_eq!(documents[2].page_content, "Ads");
+        assert_eq!(documents[2].metadata["selector"], "aside");
+    }
+
+    #[tokio::test]
+    async fn test_html_loader_from_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/page")
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Page</title></head><body><p>Hi</p></body></html>")
+            .create_async()
+            .await;
+
+        let documents = HtmlLoader::from_url(&format!("{}/page", server.url()))
+            .await
+            .unwrap()
+            .with_selectors(["body"])
+            .load()
+            .await
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].page_content, "Hi");
+        assert_eq!(documents[0].metadata["title"], "Page");
+        assert_eq!(documents[0].metadata["tag"], "body");
+        mock.assert_async().await;
+    }
 }
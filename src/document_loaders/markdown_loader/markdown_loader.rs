@@ -0,0 +1,256 @@
+use std::{collections::HashMap, path::Path, pin::Pin};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde_json::Value;
+
+use crate::{
+    document_loaders::{process_doc_stream, Loader, LoaderError},
+    schemas::Document,
+    text_splitter::TextSplitter,
+};
+
+/// Deepest heading level that starts a new section.
+const MAX_SECTION_LEVEL: usize = 3;
+
+/// Loads a Markdown document, by default as one document per section. A section runs from a
+/// `#`, `##` or `###` heading to the next one, and its metadata holds the `header_level`, the
+/// `header_text`, the `header_path` of the headings it sits under, ending with its own, and
+/// its `section_index`. Text before the first heading is a section without a header.
+/// Documents loaded with `from_path` also carry the path as `source`.
+#[derive(Debug, Clone)]
+pub struct MarkdownLoader {
+    markdown: String,
+    source: Option<String>,
+    split_on_headers: bool,
+}
+
+impl MarkdownLoader {
+    pub fn from_string<S: Into<String>>(markdown: S) -> Self {
+        Self {
+            markdown: markdown.into(),
+            source: None,
+            split_on_headers: true,
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
+        let markdown = std::fs::read_to_string(&path)?;
+        Ok(Self {
+            source: Some(path.as_ref().to_string_lossy().into_owned()),
+            ..Self::from_string(markdown)
+        })
+    }
+
+    /// Whether each section is its own document. When false the whole file is one document.
+    /// Defaults to true.
+    pub fn with_split_on_headers(mut self, split_on_headers: bool) -> Self {
+        self.split_on_headers = split_on_headers;
+        self
+    }
+
+    fn sections(&self) -> Vec<Document> {
+        let headings = headings(&self.markdown);
+        let mut starts: Vec<usize> = headings.iter().map(|h| h.start).collect();
+        starts.push(self.markdown.len());
+
+        let mut docs = Vec::new();
+        let preamble = self.markdown[..starts[0]].trim();
+        if !preamble.is_empty() {
+            docs.push(self.document(preamble, 0, None, Vec::new()));
+        }
+
+        let mut path: Vec<(usize, String)> = Vec::new();
+        for (i, heading) in headings.iter().enumerate() {
+            path.retain(|(level, _)| *level < heading.level);
+            path.push((heading.level, heading.text.clone()));
+            let content = self.markdown[heading.start..starts[i + 1]].trim();
+            docs.push(self.document(
+                content,
+                docs.len(),
+                Some(heading),
+                path.iter().map(|(_, text)| text.clone()).collect(),
+            ));
+        }
+        docs
+    }
+
+    fn document(
+        &self,
+        content: &str,
+        section_index: usize,
+        heading: Option<&Heading>,
+        header_path: Vec<String>,
+    ) -> Document {
+        let mut metadata = HashMap::from([
+            ("section_index".to_string(), Value::from(section_index)),
+            ("header_path".to_string(), Value::from(header_path)),
+        ]);
+        if let Some(heading) = heading {
+            metadata.insert("header_level".to_string(), Value::from(heading.level));
+            metadata.insert(
+                "header_text".to_string(),
+                Value::from(heading.text.as_str()),
+            );
+        }
+        if let Some(source) = &self.source {
+            metadata.insert("source".to_string(), Value::from(source.as_str()));
+        }
+        Document::new(content).with_metadata(metadata)
+    }
+}
+
+struct Heading {
+    level: usize,
+    text: String,
+    // Byte offset of the heading in the Markdown.
+    start: usize,
+}
+
+// The headings that start a section, skipping anything that only looks like one, such as
+// a `#` line in a code block.
+fn headings(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut current: Option<Heading> = None;
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) if (level as usize) <= MAX_SECTION_LEVEL => {
+                current = Some(Heading {
+                    level: level as usize,
+                    text: String::new(),
+                    start: range.start,
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    heading.text = heading.text.trim().to_string();
+                    headings.push(heading);
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+#[async_trait]
+impl Loader for MarkdownLoader {
+    async fn load(
+        mut self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let docs = if self.split_on_headers {
+            self.sections()
+        } else {
+            let mut metadata = HashMap::new();
+            if let Some(source) = &self.source {
+                metadata.insert("source".to_string(), Value::from(source.as_str()));
+            }
+            vec![Document::new(self.markdown).with_metadata(metadata)]
+        };
+        let stream = stream::iter(docs.into_iter().map(Ok));
+        Ok(Box::pin(stream))
+    }
+
+    async fn load_and_split<TS: TextSplitter + 'static>(
+        mut self,
+        splitter: TS,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let doc_stream = self.load().await?;
+        let stream = process_doc_stream(doc_stream, splitter).await;
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    use super::*;
+
+    const MARKDOWN: &str = "Intro text.
+
+# Guide
+Read this.
+
+## Install
+Run the installer.
+
+```sh
+# not a heading
+cargo install
+```
+
+#### Details
+Still part of Install.
+
+## Usage
+Call `it`.
+";
+
+    async fn load(loader: MarkdownLoader) -> Vec<Document> {
+        loader
+            .load()
+            .await
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_markdown_loader_sections() {
+        let docs = load(MarkdownLoader::from_string(MARKDOWN)).await;
+
+        assert_eq!(docs.len(), 4);
+        assert_eq!(docs[0].page_content, "Intro text.");
+        assert_eq!(docs[0].metadata["header_path"], json!([]));
+        assert!(!docs[0].metadata.contains_key("header_level"));
+
+        assert_eq!(docs[1].page_content, "# Guide\nRead this.");
+        assert_eq!(docs[1].metadata["header_level"], 1);
+
+        assert_eq!(
+            docs[2].page_content,
+            "## Install\nRun the installer.\n\n```sh\n# not a heading\ncargo install\n```\n\n\
+             #### Details\nStill part of Install."
+        );
+        assert_eq!(docs[2].metadata["header_text"], "Install");
+        assert_eq!(docs[2].metadata["header_path"], json!(["Guide", "Install"]));
+        assert_eq!(docs[2].metadata["section_index"], 2);
+
+        assert_eq!(docs[3].metadata["header_level"], 2);
+        assert_eq!(docs[3].metadata["header_path"], json!(["Guide", "Usage"]));
+        assert!(!docs[3].metadata.contains_key("source"));
+    }
+
+    #[tokio::test]
+    async fn test_markdown_loader_whole_file() {
+        let path = std::env::temp_dir().join("langchain_rust_markdown_loader_test.md");
+        std::fs::write(&path, MARKDOWN).unwrap();
+
+        let docs = load(
+            MarkdownLoader::from_path(&path)
+                .unwrap()
+                .with_split_on_headers(false),
+        )
+        .await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, MARKDOWN);
+        assert_eq!(docs[0].metadata["source"], path.to_str().unwrap());
+    }
+}
@@ -0,0 +1,2 @@
+mod markdown_loader;
+pub use markdown_loader::*;
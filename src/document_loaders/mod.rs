@@ -7,6 +7,9 @@ pub use text_loader::*;
 mod csv_loader;
 pub use csv_loader::*;
 
+mod database_loader;
+pub use database_loader::*;
+
 #[cfg(feature = "git")]
 mod git_commit_loader;
 #[cfg(feature = "git")]
@@ -23,6 +26,16 @@ pub use pdf_loader::*;
 mod html_loader;
 pub use html_loader::*;
 
+#[cfg(feature = "markdown")]
+mod markdown_loader;
+#[cfg(feature = "markdown")]
+pub use markdown_loader::*;
+
+#[cfg(feature = "pptx")]
+mod pptx_loader;
+#[cfg(feature = "pptx")]
+pub use pptx_loader::*;
+
 #[cfg(feature = "html-to-markdown")]
 mod html_to_markdown_loader;
 #[cfg(feature = "html-to-markdown")]
@@ -1,7 +1,8 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt,
     io::Read,
+    ops::RangeInclusive,
     path::Path,
     pin::Pin,
     sync::{Arc, Mutex},
@@ -9,8 +10,8 @@ use std::{
 
 use async_stream::stream;
 use async_trait::async_trait;
-use futures::Stream;
-use pdf_extract::{output_doc, ConvertToFmt, OutputDev, OutputError, PlainTextOutput};
+use futures::{Stream, StreamExt};
+use pdf_extract::{output_doc_page, ConvertToFmt, Object, OutputDev, OutputError, PlainTextOutput};
 use serde_json::Value;
 
 use crate::{
@@ -19,9 +20,14 @@ use crate::{
     text_splitter::TextSplitter,
 };
 
+/// Loads a PDF as one document per page. Pages are extracted concurrently on blocking
+/// threads, so the first pages are yielded before the whole file is processed.
 #[derive(Debug, Clone)]
 pub struct PdfExtractLoader {
-    document: pdf_extract::Document,
+    document: Arc<pdf_extract::Document>,
+    pages: Option<BTreeSet<u32>>,
+    concurrency: usize,
+    ordered: bool,
 }
 
 struct PagePlainTextOutput {
@@ -120,7 +126,7 @@ impl PdfExtractLoader {
     ///
     pub fn new<R: Read>(reader: R) -> Result<Self, LoaderError> {
         let document = pdf_extract::Document::load_from(reader)?;
-        Ok(Self { document })
+        Ok(Self::from_document(document))
     }
     /// Creates a new PdfLoader from a path to a PDF file.
     /// This loads the PDF document and creates a PdfLoader from it.
@@ -133,8 +139,154 @@ impl PdfExtractLoader {
     ///
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
         let document = pdf_extract::Document::load(path)?;
-        Ok(Self { document })
+        Ok(Self::from_document(document))
     }
+
+    fn from_document(document: pdf_extract::Document) -> Self {
+        Self {
+            document: Arc::new(document),
+            pages: None,
+            concurrency: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            ordered: true,
+        }
+    }
+
+    /// Creates a PdfLoader that only loads the pages of `range`, see [`Self::with_pages`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let loader = PdfExtractLoader::from_path_with_range("/path/to/my.pdf", 10..=40)?;
+    /// ```
+    ///
+    pub fn from_path_with_range<P: AsRef<Path>>(
+        path: P,
+        range: RangeInclusive<u32>,
+    ) -> Result<Self, LoaderError> {
+        Ok(Self::from_path(path)?.with_pages(range))
+    }
+
+    /// Only loads the given 1-based pages, e.g. `10..=40` or `vec![1, 3]`. The documents keep
+    /// their original `page_number`. Pages the PDF doesn't have are skipped, so a selection
+    /// with none of its pages loads nothing.
+    pub fn with_pages(mut self, pages: impl IntoIterator<Item = u32>) -> Self {
+        self.pages = Some(pages.into_iter().collect());
+        self
+    }
+
+    /// Sets how many pages are extracted at once. Defaults to the number of CPUs.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Whether pages are yielded in page order, the default, or as soon as each one is
+    /// extracted, which gets the first documents out sooner when pages vary in size.
+    pub fn with_ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+}
+
+fn extract_page(
+    document: &pdf_extract::Document,
+    page_num: u32,
+) -> Result<(u32, String), LoaderError> {
+    let mut output = PagePlainTextOutput::new();
+    output_doc_page(document, &mut output, page_num)?;
+    let text = output.pages.into_values().next().unwrap_or_default();
+    Ok((page_num, text))
+}
+
+/// The document-level metadata shared by every page: `pdf.total_pages`, plus `pdf.title`,
+/// `pdf.author`, `pdf.subject` and `pdf.creation_date` when the info dictionary has them.
+fn document_metadata(document: &pdf_extract::Document) -> HashMap<String, Value> {
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        "pdf.total_pages".to_string(),
+        Value::from(document.get_pages().len()),
+    );
+
+    let info = match document.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => document.get_dictionary(*id).ok(),
+        Ok(Object::Dictionary(info)) => Some(info),
+        _ => None,
+    };
+    let Some(info) = info else {
+        return metadata;
+    };
+    let fields: [(&str, &[u8]); 4] = [
+        ("pdf.title", b"Title"),
+        ("pdf.author", b"Author"),
+        ("pdf.subject", b"Subject"),
+        ("pdf.creation_date", b"CreationDate"),
+    ];
+    for (key, name) in fields {
+        let Ok(Object::String(bytes, _)) = info.get(name) else {
+            continue;
+        };
+        let text = decode_text_string(bytes);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let value = if name == b"CreationDate" {
+            parse_pdf_date(text).unwrap_or_else(|| text.to_string())
+        } else {
+            text.to_string()
+        };
+        metadata.insert(key.to_string(), Value::from(value));
+    }
+    metadata
+}
+
+// PDF text strings are UTF-16BE with a byte order mark, or PDFDocEncoding, which matches
+// Latin-1 for the characters that show up in practice.
+fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+// Converts a PDF date, `D:YYYYMMDDHHmmSSOHH'mm'` with everything after the year optional,
+// to ISO 8601.
+fn parse_pdf_date(date: &str) -> Option<String> {
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits: String = date.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let part =
+        |start: usize, default: &'static str| digits.get(start..start + 2).unwrap_or(default);
+    let mut iso = format!(
+        "{}-{}-{}T{}:{}:{}",
+        &digits[..4],
+        part(4, "01"),
+        part(6, "01"),
+        part(8, "00"),
+        part(10, "00"),
+        part(12, "00"),
+    );
+
+    let offset = &date[digits.len()..];
+    match offset.chars().next() {
+        Some('Z') => iso.push('Z'),
+        Some(sign @ ('+' | '-')) => {
+            let offset: String = offset[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+            let hours = offset.get(..2)?;
+            let minutes = offset.get(2..4).unwrap_or("00");
+            iso.push_str(&format!("{}{}:{}", sign, hours, minutes));
+        }
+        _ => {}
+    }
+    Some(iso)
 }
 
 #[async_trait]
@@ -145,14 +297,35 @@ impl Loader for PdfExtractLoader {
         Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
         LoaderError,
     > {
+        let document_metadata = document_metadata(&self.document);
+        let page_numbers: Vec<u32> = self
+            .document
+            .get_pages()
+            .into_keys()
+            .filter(|p| self.pages.as_ref().map_or(true, |pages| pages.contains(p)))
+            .collect();
+
+        let document = Arc::clone(&self.document);
+        let tasks = futures::stream::iter(page_numbers).map(move |page_num| {
+            let document = Arc::clone(&document);
+            tokio::task::spawn_blocking(move || extract_page(&document, page_num))
+        });
+        let mut pages: Pin<Box<dyn Stream<Item = _> + Send>> = if self.ordered {
+            Box::pin(tasks.buffered(self.concurrency))
+        } else {
+            Box::pin(tasks.buffer_unordered(self.concurrency))
+        };
+
         let stream = stream! {
-            let mut output = PagePlainTextOutput::new();
-            output_doc(&self.document, &mut output)?;
-            for (page_num, text) in output.pages {
-                let mut metadata = HashMap::new();
-                metadata.insert("page_number".to_string(), Value::from(page_num));
-                let doc = Document::new(text).with_metadata(metadata);
-                yield Ok(doc);
+            while let Some(page) = pages.next().await {
+                match page.map_err(LoaderError::from).and_then(|page| page) {
+                    Ok((page_num, text)) => {
+                        let mut metadata = document_metadata.clone();
+                        metadata.insert("page_number".to_string(), Value::from(page_num));
+                        yield Ok(Document::new(text).with_metadata(metadata));
+                    }
+                    Err(e) => yield Err(e),
+                }
             }
         };
 
@@ -218,4 +391,91 @@ mod tests {
         assert_eq!(&docs[0].page_content[..100], "\n\nSample PDF Document\n\nRobert Maron\nGrzegorz Grudzi´nski\n\nFebruary 20, 1999\n\n2\n\nContents\n\n1 Templat");
         assert_eq!(docs.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_pdf_extract_loader_document_metadata() {
+        let loader = PdfExtractLoader::from_path("./src/document_loaders/test_data/sample.pdf")
+            .expect("Failed to create PdfExtractLoader");
+
+        let docs = loader
+            .load()
+            .await
+            .unwrap()
+            .map(|d| d.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        let metadata = &docs[0].metadata;
+        assert_eq!(metadata["pdf.total_pages"], 10);
+        assert_eq!(metadata["pdf.creation_date"], "1999-02-20T21:20:00");
+        // The sample's title, author and subject are empty strings.
+        assert!(!metadata.contains_key("pdf.title"));
+        assert!(!metadata.contains_key("pdf.author"));
+
+        assert_eq!(
+            parse_pdf_date("D:20240131102030+01'00'").unwrap(),
+            "2024-01-31T10:20:30+01:00"
+        );
+        assert_eq!(parse_pdf_date("D:2024").unwrap(), "2024-01-01T00:00:00");
+        assert_eq!(
+            decode_text_string(&[0xFE, 0xFF, 0x00, 0x48, 0x00, 0x69]),
+            "Hi"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pdf_extract_loader_unordered_pages() {
+        let path = "./src/document_loaders/test_data/sample.pdf";
+        let page_numbers = |loader: PdfExtractLoader| async move {
+            loader
+                .load()
+                .await
+                .unwrap()
+                .map(|d| d.unwrap().metadata["page_number"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let ordered = page_numbers(PdfExtractLoader::from_path(path).unwrap()).await;
+        assert!(ordered.windows(2).all(|w| w[0] < w[1]));
+
+        let loader = PdfExtractLoader::from_path(path)
+            .unwrap()
+            .with_concurrency(3)
+            .with_ordered(false);
+        let mut unordered = page_numbers(loader).await;
+        unordered.sort();
+        assert_eq!(unordered, ordered);
+    }
+
+    #[tokio::test]
+    async fn test_pdf_extract_loader_page_range() {
+        let path = "./src/document_loaders/test_data/sample.pdf";
+        let page_numbers = |loader: PdfExtractLoader| async move {
+            loader
+                .load()
+                .await
+                .unwrap()
+                .map(|d| d.unwrap().metadata["page_number"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let loader = PdfExtractLoader::from_path_with_range(path, 3..=4).unwrap();
+        let pages = page_numbers(loader).await;
+        assert!(!pages.is_empty());
+        assert!(pages.iter().all(|p| (3..=4).contains(p)));
+
+        let loader = PdfExtractLoader::from_path(path)
+            .unwrap()
+            .with_pages(vec![9, 10, 11, 50]);
+        let pages = page_numbers(loader).await;
+        assert!(!pages.is_empty());
+        assert!(pages.iter().all(|p| *p == 9 || *p == 10));
+
+        let loader = PdfExtractLoader::from_path(path)
+            .unwrap()
+            .with_pages(20..=30);
+        assert!(page_numbers(loader).await.is_empty());
+    }
 }
@@ -0,0 +1,2 @@
+mod pptx_loader;
+pub use pptx_loader::*;
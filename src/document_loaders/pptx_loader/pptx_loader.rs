@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::Path,
+    pin::Pin,
+};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use quick_xml::events::{BytesStart, Event};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::{
+    document_loaders::{process_doc_stream, Loader, LoaderError},
+    schemas::Document,
+    text_splitter::TextSplitter,
+};
+
+const SLIDE_RELATIONSHIP: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/slide";
+const NOTES_RELATIONSHIP: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/notesSlide";
+
+/// Loads a PowerPoint (.pptx) deck, yielding one `Document` per slide in presentation order.
+///
+/// Each document holds the slide's text, one paragraph per line, with `slide_number` in its
+/// metadata and the speaker notes under `notes` when the slide has any. Images, charts and
+/// other non-text content are skipped.
+#[derive(Debug, Clone)]
+pub struct PptxLoader {
+    data: Vec<u8>,
+}
+
+impl PptxLoader {
+    /// Creates a new PptxLoader from anything that implements the Read trait.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let file = std::fs::File::open("/path/to/deck.pptx")?;
+    /// let loader = PptxLoader::new(file)?;
+    /// ```
+    ///
+    pub fn new<R: Read>(mut reader: R) -> Result<Self, LoaderError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Self { data })
+    }
+
+    /// Creates a new PptxLoader from a path to a .pptx file.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let loader = PptxLoader::from_path("/path/to/deck.pptx")?;
+    /// ```
+    ///
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
+        Ok(Self {
+            data: std::fs::read(path)?,
+        })
+    }
+
+    fn read_slides(&self) -> Result<Vec<Document>, LoaderError> {
+        let mut archive = ZipArchive::new(Cursor::new(self.data.as_slice()))?;
+
+        let mut documents = Vec::new();
+        for (i, slide_path) in slide_paths(&mut archive)?.iter().enumerate() {
+            let slide_xml = read_entry(&mut archive, slide_path)?;
+            let text = extract_paragraphs(&slide_xml)?.join("\n");
+
+            let mut metadata = HashMap::new();
+            metadata.insert("slide_number".to_string(), Value::from(i + 1));
+
+            let rels_path = rels_path_for(slide_path);
+            if archive.by_name(&rels_path).is_ok() {
+                let rels = relationships(&read_entry(&mut archive, &rels_path)?)?;
+                if let Some((_, target)) = rels.iter().find(|(kind, _)| kind == NOTES_RELATIONSHIP)
+                {
+                    let notes_path = resolve_target(slide_path, target);
+                    let notes = extract_paragraphs(&read_entry(&mut archive, &notes_path)?)?;
+                    if !notes.is_empty() {
+                        metadata.insert("notes".to_string(), Value::from(notes.join("\n")));
+                    }
+                }
+            }
+
+            documents.push(Document::new(text).with_metadata(metadata));
+        }
+
+        Ok(documents)
+    }
+}
+
+fn read_entry<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<String, LoaderError> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Slide parts in presentation order, falling back to `slideN.xml` numbering when the
+/// presentation part doesn't list them.
+fn slide_paths<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<String>, LoaderError> {
+    let presentation = "ppt/presentation.xml";
+    if archive.by_name(presentation).is_ok() {
+        let rels = relationship_targets(&read_entry(archive, &rels_path_for(presentation))?)?;
+        let ordered: Vec<String> = slide_ids(&read_entry(archive, presentation)?)?
+            .iter()
+            .filter_map(|id| rels.get(id))
+            .filter(|(kind, _)| kind == SLIDE_RELATIONSHIP)
+            .map(|(_, target)| resolve_target(presentation, target))
+            .collect();
+        if !ordered.is_empty() {
+            return Ok(ordered);
+        }
+    }
+
+    let mut numbered: Vec<(u32, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix("ppt/slides/slide")?
+                .strip_suffix(".xml")?
+                .parse()
+                .ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    numbered.sort();
+    Ok(numbered.into_iter().map(|(_, name)| name).collect())
+}
+
+/// `ppt/slides/slide1.xml` -> `ppt/slides/_rels/slide1.xml.rels`
+fn rels_path_for(part: &str) -> String {
+    match part.rsplit_once('/') {
+        Some((dir, file)) => format!("{}/_rels/{}.rels", dir, file),
+        None => format!("_rels/{}.rels", part),
+    }
+}
+
+/// Resolves a relationship target relative to the directory of `part`.
+fn resolve_target(part: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+    let mut segments: Vec<&str> = part.split('/').collect();
+    segments.pop();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Result<Option<String>, LoaderError> {
+    match element.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(attr.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// `(type, target)` of every relationship in a `.rels` part.
+fn relationships(xml: &str) -> Result<Vec<(String, String)>, LoaderError> {
+    Ok(relationship_targets(xml)?.into_values().collect())
+}
+
+/// Relationship id -> `(type, target)`.
+fn relationship_targets(xml: &str) -> Result<HashMap<String, (String, String)>, LoaderError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut rels = HashMap::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                if let (Some(id), Some(kind), Some(target)) = (
+                    attribute(&e, b"Id")?,
+                    attribute(&e, b"Type")?,
+                    attribute(&e, b"Target")?,
+                ) {
+                    rels.insert(id, (kind, target));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(rels)
+}
+
+/// Relationship ids of the slides listed in `ppt/presentation.xml`, in order.
+fn slide_ids(xml: &str) -> Result<Vec<String>, LoaderError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut ids = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"p:sldId" => {
+                if let Some(id) = attribute(&e, b"r:id")? {
+                    ids.push(id);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(ids)
+}
+
+/// Text of every non-empty `<a:p>` paragraph, skipping fields such as slide numbers.
+fn extract_paragraphs(xml: &str) -> Result<Vec<String>, LoaderError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+    let mut in_field = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"a:t" => in_text = true,
+                b"a:fld" => in_field = true,
+                _ => {}
+            },
+            Event::End(e) => match e.name().as_ref() {
+                b"a:t" => in_text = false,
+                b"a:fld" => in_field = false,
+                b"a:p" => {
+                    let paragraph = current.trim();
+                    if !paragraph.is_empty() {
+                        paragraphs.push(paragraph.to_string());
+                    }
+                    current.clear();
+                }
+                _ => {}
+            },
+            Event::Empty(e) if e.name().as_ref() == b"a:br" => current.push('\n'),
+            Event::Text(t) if in_text && !in_field => current.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(paragraphs)
+}
+
+#[async_trait]
+impl Loader for PptxLoader {
+    async fn load(
+        mut self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let documents = self.read_slides()?;
+        Ok(Box::pin(stream::iter(documents.into_iter().map(Ok))))
+    }
+
+    async fn load_and_split<TS: TextSplitter + 'static>(
+        mut self,
+        splitter: TS,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let doc_stream = self.load().await?;
+        let stream = process_doc_stream(doc_stream, splitter).await;
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pptx_loader() {
+        let path = "./src/document_loaders/test_data/sample.pptx";
+
+        let loader = PptxLoader::from_path(path).expect("Failed to create PptxLoader");
+
+        let docs = loader
+            .load()
+            .await
+            .unwrap()
+            .map(|d| d.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(docs.len(), 2);
+        assert_eq!(
+            docs[0].page_content,
+            "Quarterly Review\nRevenue grew 12% & costs fell"
+        );
+        assert_eq!(docs[0].metadata["slide_number"], 1);
+        assert_eq!(docs[0].metadata["notes"], "Mention the new region.");
+        // slide2.xml comes first in the file order but second in the presentation
+        assert_eq!(docs[1].page_content, "Chart below");
+        assert_eq!(docs[1].metadata["slide_number"], 2);
+        assert!(!docs[1].metadata.contains_key("notes"));
+    }
+
+    #[test]
+    fn test_resolve_target() {
+        assert_eq!(
+            resolve_target("ppt/slides/slide1.xml", "../notesSlides/notesSlide1.xml"),
+            "ppt/notesSlides/notesSlide1.xml"
+        );
+        assert_eq!(
+            resolve_target("ppt/presentation.xml", "slides/slide1.xml"),
+            "ppt/slides/slide1.xml"
+        );
+    }
+}
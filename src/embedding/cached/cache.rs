@@ -0,0 +1,297 @@
+use std::{
+    num::NonZeroUsize,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lru::LruCache;
+use rusqlite::{params, OptionalExtension};
+
+use crate::embedding::EmbedderError;
+
+/// Storage of the embeddings memoized by a [`CachingEmbedder`](super::CachingEmbedder),
+/// keyed by a hash of the embedded text.
+#[async_trait]
+pub trait EmbeddingCache: Send + Sync {
+    /// The cached embedding of each key, `None` for the keys not in the cache.
+    async fn get(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>, EmbedderError>;
+
+    async fn put(&self, entries: Vec<(String, Vec<f32>)>) -> Result<(), EmbedderError>;
+
+    async fn clear(&self) -> Result<(), EmbedderError>;
+}
+
+/// Lets several embedders share one cache, each under its own
+/// [`namespace`](super::CachingEmbedder::with_namespace).
+#[async_trait]
+impl<C: EmbeddingCache + ?Sized> EmbeddingCache for Arc<C> {
+    async fn get(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>, EmbedderError> {
+        (**self).get(keys).await
+    }
+
+    async fn put(&self, entries: Vec<(String, Vec<f32>)>) -> Result<(), EmbedderError> {
+        (**self).put(entries).await
+    }
+
+    async fn clear(&self) -> Result<(), EmbedderError> {
+        (**self).clear().await
+    }
+}
+
+/// Keeps the embeddings in an LRU map. When a capacity is set, the least recently used
+/// entries are evicted to stay within it; when a TTL is set, entries older than it are
+/// treated as missing.
+pub struct InMemoryCache {
+    entries: Mutex<LruCache<String, (Vec<f32>, Instant)>>,
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+}
+
+impl Default for InMemoryCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryCache {
+    /// An unbounded cache.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(LruCache::unbounded()),
+            capacity: None,
+            ttl: None,
+        }
+    }
+
+    /// A cache holding at most `capacity` embeddings.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )),
+            capacity: Some(capacity),
+            ttl: None,
+        }
+    }
+
+    /// Expires embeddings `ttl` after they were cached.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Locks the entries, failing with a `CacheError` if another thread panicked while
+    /// holding them.
+    fn lock_entries(
+        &self,
+    ) -> Result<MutexGuard<'_, LruCache<String, (Vec<f32>, Instant)>>, EmbedderError> {
+        self.entries
+            .lock()
+            .map_err(|_| EmbedderError::CacheError("in-memory cache lock poisoned".to_string()))
+    }
+}
+
+#[async_trait]
+impl EmbeddingCache for InMemoryCache {
+    async fn get(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>, EmbedderError> {
+        let mut entries = self.lock_entries()?;
+        Ok(keys
+            .iter()
+            .map(|key| {
+                let (_, cached_at) = entries.get(key)?;
+                if self.ttl.is_some_and(|ttl| cached_at.elapsed() >= ttl) {
+                    entries.pop(key);
+                    return None;
+                }
+                entries.peek(key).map(|(embedding, _)| embedding.clone())
+            })
+            .collect())
+    }
+
+    async fn put(&self, new_entries: Vec<(String, Vec<f32>)>) -> Result<(), EmbedderError> {
+        if self.capacity == Some(0) {
+            return Ok(());
+        }
+        let mut entries = self.lock_entries()?;
+        let now = Instant::now();
+        for (key, embedding) in new_entries {
+            entries.put(key, (embedding, now));
+        }
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), EmbedderError> {
+        self.lock_entries()?.clear();
+        Ok(())
+    }
+}
+
+/// Keeps the embeddings in an `embedding_cache` table of a SQLite database, so that they
+/// outlive the process. Embeddings are stored as little-endian `f32` blobs.
+pub struct SqliteCache {
+    conn: tokio::sync::Mutex<rusqlite::Connection>,
+}
+
+impl SqliteCache {
+    /// Opens or creates the database file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, EmbedderError> {
+        let conn = rusqlite::Connection::open(path).map_err(cache_error)?;
+        Self::from_connection(conn)
+    }
+
+    /// Uses `conn`, creating the `embedding_cache` table if it doesn't exist.
+    pub fn from_connection(conn: rusqlite::Connection) -> Result<Self, EmbedderError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS embedding_cache (
+                key TEXT PRIMARY KEY,
+                embedding BLOB NOT NULL
+            )",
+            [],
+        )
+        .map_err(cache_error)?;
+        Ok(Self {
+            conn: tokio::sync::Mutex::new(conn),
+        })
+    }
+}
+
+#[async_trait]
+impl EmbeddingCache for SqliteCache {
+    async fn get(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>, EmbedderError> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare_cached("SELECT embedding FROM embedding_cache WHERE key = ?1")
+            .map_err(cache_error)?;
+        keys.iter()
+            .map(|key| {
+                let blob: Option<Vec<u8>> = stmt
+                    .query_row(params![key], |row| row.get(0))
+                    .optional()
+                    .map_err(cache_error)?;
+                Ok(blob.map(|blob| {
+                    blob.chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect()
+                }))
+            })
+            .collect()
+    }
+
+    async fn put(&self, entries: Vec<(String, Vec<f32>)>) -> Result<(), EmbedderError> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction().map_err(cache_error)?;
+        {
+            let mut stmt = tx
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO embedding_cache (key, embedding) VALUES (?1, ?2)",
+                )
+                .map_err(cache_error)?;
+            for (key, embedding) in entries {
+                let blob: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+                stmt.execute(params![key, blob]).map_err(cache_error)?;
+            }
+        }
+        tx.commit().map_err(cache_error)
+    }
+
+    async fn clear(&self) -> Result<(), EmbedderError> {
+        let conn = self.conn.lock().await;
+        conn.execute("DELETE FROM embedding_cache", [])
+            .map_err(cache_error)?;
+        Ok(())
+    }
+}
+
+fn cache_error(e: rusqlite::Error) -> EmbedderError {
+    EmbedderError::CacheError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_cache_evicts_least_recently_used() {
+        let cache = InMemoryCache::with_capacity(2);
+        cache
+            .put(vec![
+                ("a".to_string(), vec![1.0]),
+                ("b".to_string(), vec![2.0]),
+            ])
+            .await
+            .unwrap();
+        // Reading "a" makes "b" the least recently used.
+        cache.get(&["a".to_string()]).await.unwrap();
+        cache.put(vec![("c".to_string(), vec![3.0])]).await.unwrap();
+
+        let keys = ["a", "b", "c"].map(String::from);
+        assert_eq!(
+            cache.get(&keys).await.unwrap(),
+            vec![Some(vec![1.0]), None, Some(vec![3.0])]
+        );
+        cache.clear().await.unwrap();
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache_ttl() {
+        let cache = InMemoryCache::new().with_ttl(Duration::from_millis(50));
+        cache.put(vec![("a".to_string(), vec![1.0])]).await.unwrap();
+        let keys = ["a".to_string()];
+        assert_eq!(cache.get(&keys).await.unwrap(), vec![Some(vec![1.0])]);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(cache.get(&keys).await.unwrap(), vec![None]);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache_poisoned_lock_is_an_error() {
+        let cache = Arc::new(InMemoryCache::new());
+        let poisoner = cache.clone();
+        std::thread::spawn(move || {
+            let _entries = poisoner.entries.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+
+        let keys = ["a".to_string()];
+        assert!(matches!(
+            cache.get(&keys).await,
+            Err(EmbedderError::CacheError(_))
+        ));
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_cache_round_trip() {
+        let cache =
+            SqliteCache::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        cache
+            .put(vec![("a".to_string(), vec![0.5, -1.25])])
+            .await
+            .unwrap();
+
+        let keys = ["a", "b"].map(String::from);
+        assert_eq!(
+            cache.get(&keys).await.unwrap(),
+            vec![Some(vec![0.5, -1.25]), None]
+        );
+        cache.clear().await.unwrap();
+        assert_eq!(cache.get(&keys).await.unwrap(), vec![None, None]);
+    }
+}
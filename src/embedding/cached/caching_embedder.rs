@@ -0,0 +1,372 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use super::{EmbeddingCache, InMemoryCache};
+use crate::embedding::{EmbedKind, Embedder, EmbedderError};
+
+/// Wraps an [`Embedder`] and memoizes its embeddings by the SHA-256 of the embedded text, so
+/// that texts seen before, e.g. unchanged chunks of a re-ingested corpus, are not embedded
+/// again. Documents and queries are cached separately, as some embedders embed them
+/// differently.
+///
+/// Embeddings are kept in an unbounded [`InMemoryCache`] by default; use
+/// [`with_cache`](Self::with_cache) for another [`EmbeddingCache`], such as a
+/// [`SqliteCache`](super::SqliteCache) that outlives the process. A cache shared by several
+/// embedders, or kept across a model change, needs a [`namespace`](Self::with_namespace) per
+/// model, as the same text embeds differently under another one.
+///
+/// # Usage
+/// ```rust,ignore
+/// let embedder = CachingEmbedder::builder(Arc::new(OpenAiEmbedder::default()))
+///     .capacity(10_000)
+///     .ttl(Duration::from_secs(24 * 60 * 60))
+///     .namespace("text-embedding-3-small")
+///     .build();
+/// ```
+pub struct CachingEmbedder {
+    inner: Arc<dyn Embedder>,
+    cache: Arc<dyn EmbeddingCache>,
+    namespace: String,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// How many texts a [`CachingEmbedder`] found in its cache, and how many it had to embed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CachingEmbedder {
+    pub fn new(inner: Arc<dyn Embedder>) -> Self {
+        Self::from_cache(inner, Arc::new(InMemoryCache::new()))
+    }
+
+    pub fn builder(inner: Arc<dyn Embedder>) -> CachingEmbedderBuilder {
+        CachingEmbedderBuilder::new(inner)
+    }
+
+    fn from_cache(inner: Arc<dyn Embedder>, cache: Arc<dyn EmbeddingCache>) -> Self {
+        Self {
+            inner,
+            cache,
+            namespace: String::new(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_cache<C: EmbeddingCache + 'static>(mut self, cache: C) -> Self {
+        self.cache = Arc::new(cache);
+        self
+    }
+
+    /// Prefixes the cache keys with `namespace`, e.g. the model name, so that embedders
+    /// sharing a cache don't return each other's embeddings. Empty by default.
+    pub fn with_namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Keeps at most `capacity` embeddings in memory, evicting the least recently used.
+    /// Replaces the cache set with `with_cache`.
+    pub fn with_cache_capacity(self, capacity: usize) -> Self {
+        self.with_cache(InMemoryCache::with_capacity(capacity))
+    }
+
+    /// Empties the cache, e.g. between tests. The stats are kept.
+    pub async fn clear_cache(&self) -> Result<(), EmbedderError> {
+        self.cache.clear().await
+    }
+
+    /// The cache hits and misses of every text embedded so far.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn embed_cached(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let keys: Vec<String> = texts
+            .iter()
+            .map(|text| cache_key(&self.namespace, text, kind))
+            .collect();
+        let mut embeddings = self.cache.get(&keys).await?;
+
+        let misses: Vec<usize> = (0..texts.len())
+            .filter(|&i| embeddings[i].is_none())
+            .collect();
+        self.hits
+            .fetch_add((texts.len() - misses.len()) as u64, Ordering::Relaxed);
+        self.misses
+            .fetch_add(misses.len() as u64, Ordering::Relaxed);
+        if !misses.is_empty() {
+            let miss_texts: Vec<String> = misses.iter().map(|&i| texts[i].clone()).collect();
+            let computed = self.inner.embed(&miss_texts, kind).await?;
+            if computed.len() != misses.len() {
+                return Err(EmbedderError::EmbeddingCountMismatch {
+                    expected: misses.len(),
+                    got: computed.len(),
+                });
+            }
+
+            let mut entries = Vec::with_capacity(misses.len());
+            for (&i, embedding) in misses.iter().zip(computed) {
+                entries.push((keys[i].clone(), embedding.clone()));
+                embeddings[i] = Some(embedding);
+            }
+            self.cache.put(entries).await?;
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+}
+
+/// Builds a [`CachingEmbedder`] with an [`InMemoryCache`] bounded by `capacity` and
+/// `ttl`, or with the given `cache`.
+pub struct CachingEmbedderBuilder {
+    inner: Arc<dyn Embedder>,
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+    cache: Option<Arc<dyn EmbeddingCache>>,
+    namespace: String,
+}
+
+impl CachingEmbedderBuilder {
+    pub fn new(inner: Arc<dyn Embedder>) -> Self {
+        Self {
+            inner,
+            capacity: None,
+            ttl: None,
+            cache: None,
+            namespace: String::new(),
+        }
+    }
+
+    /// Keeps at most `capacity` embeddings, evicting the least recently used. Unbounded by
+    /// default.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Expires embeddings `ttl` after they were cached. They never expire by default.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Uses `cache` instead of an in-memory one, ignoring `capacity` and `ttl`.
+    pub fn cache<C: EmbeddingCache + 'static>(mut self, cache: C) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// See [`CachingEmbedder::with_namespace`].
+    pub fn namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    pub fn build(self) -> CachingEmbedder {
+        let cache = self.cache.unwrap_or_else(|| {
+            let cache = match self.capacity {
+                Some(capacity) => InMemoryCache::with_capacity(capacity),
+                None => InMemoryCache::new(),
+            };
+            Arc::new(match self.ttl {
+                Some(ttl) => cache.with_ttl(ttl),
+                None => cache,
+            })
+        });
+        CachingEmbedder::from_cache(self.inner, cache).with_namespace(self.namespace)
+    }
+}
+
+// Namespaced keys are `{namespace}:{hash}`, which can't collide with the bare hex hashes.
+fn cache_key(namespace: &str, text: &str, kind: EmbedKind) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(match kind {
+        EmbedKind::Document => b"d",
+        EmbedKind::Query => b"q",
+    });
+    hasher.update(text.as_bytes());
+    let hash: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if namespace.is_empty() {
+        hash
+    } else {
+        format!("{}:{}", namespace, hash)
+    }
+}
+
+#[async_trait]
+impl Embedder for CachingEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        self.embed_cached(documents, EmbedKind::Document).await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let mut embeddings = self
+            .embed_cached(&[text.to_string()], EmbedKind::Query)
+            .await?;
+        embeddings
+            .pop()
+            .ok_or_else(|| EmbedderError::CacheError("No embedding returned".to_string()))
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        self.embed_cached(texts, kind).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingEmbedder {
+        seen: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Embedder for RecordingEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+            self.seen.lock().unwrap().extend(documents.iter().cloned());
+            Ok(documents.iter().map(|d| vec![d.len() as f32]).collect())
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+            self.seen.lock().unwrap().push(text.to_string());
+            Ok(vec![-(text.len() as f32)])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_misses_are_embedded() {
+        let inner = Arc::new(RecordingEmbedder::default());
+        let embedder = CachingEmbedder::new(inner.clone());
+        let texts = |texts: &[&str]| texts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        embedder
+            .embed_documents(&texts(&["a", "bb"]))
+            .await
+            .unwrap();
+        let embeddings = embedder
+            .embed_documents(&texts(&["ccc", "a", "bb"]))
+            .await
+            .unwrap();
+        assert_eq!(embeddings, vec![vec![3.0], vec![1.0], vec![2.0]]);
+        // Queries are cached apart from documents.
+        assert_eq!(embedder.embed_query("a").await.unwrap(), vec![-1.0]);
+        assert_eq!(*inner.seen.lock().unwrap(), vec!["a", "bb", "ccc", "a"]);
+
+        assert_eq!(embedder.cache_stats(), CacheStats { hits: 2, misses: 4 });
+
+        embedder.clear_cache().await.unwrap();
+        embedder.embed_documents(&texts(&["a"])).await.unwrap();
+        assert_eq!(inner.seen.lock().unwrap().len(), 5);
+    }
+
+    struct ShortEmbedder;
+
+    #[async_trait]
+    impl Embedder for ShortEmbedder {
+        async fn embed_documents(
+            &self,
+            _documents: &[String],
+        ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+            Ok(vec![vec![1.0]])
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f32>, EmbedderError> {
+            Ok(vec![1.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embedding_count_mismatch_is_an_error() {
+        let embedder = CachingEmbedder::new(Arc::new(ShortEmbedder));
+        let texts = vec!["a".to_string(), "b".to_string()];
+
+        let result = embedder.embed_documents(&texts).await;
+        assert!(matches!(
+            result,
+            Err(EmbedderError::EmbeddingCountMismatch {
+                expected: 2,
+                got: 1
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_builder_capacity() {
+        let inner = Arc::new(RecordingEmbedder::default());
+        let embedder = CachingEmbedder::builder(inner.clone())
+            .capacity(1)
+            .ttl(Duration::from_secs(60))
+            .build();
+        let texts = |texts: &[&str]| texts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        embedder.embed_documents(&texts(&["a"])).await.unwrap();
+        embedder.embed_documents(&texts(&["b"])).await.unwrap();
+        embedder.embed_documents(&texts(&["b", "a"])).await.unwrap();
+        assert_eq!(*inner.seen.lock().unwrap(), vec!["a", "b", "a"]);
+        assert_eq!(embedder.cache_stats(), CacheStats { hits: 1, misses: 3 });
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_share_a_cache_without_mixing() {
+        let cache = Arc::new(InMemoryCache::new());
+        let small = Arc::new(RecordingEmbedder::default());
+        let large = Arc::new(RecordingEmbedder::default());
+        let embedder = |inner: Arc<RecordingEmbedder>, namespace: &str| {
+            CachingEmbedder::builder(inner)
+                .cache(cache.clone())
+                .namespace(namespace)
+                .build()
+        };
+        let texts = vec!["a".to_string()];
+
+        embedder(small.clone(), "small")
+            .embed_documents(&texts)
+            .await
+            .unwrap();
+        embedder(large.clone(), "large")
+            .embed_documents(&texts)
+            .await
+            .unwrap();
+        embedder(small.clone(), "small")
+            .embed_documents(&texts)
+            .await
+            .unwrap();
+        assert_eq!(small.seen.lock().unwrap().len(), 1);
+        assert_eq!(large.seen.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,5 @@
+mod cache;
+mod caching_embedder;
+
+pub use cache::*;
+pub use caching_embedder::*;
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{embedder_trait::Embedder, EmbedderError, Preprocessor, Preprocessors};
+
+/// The most texts the Cohere embed endpoint takes in one request.
+const MAX_TEXTS_PER_REQUEST: usize = 96;
+
+/// What the embeddings are for, which Cohere's v3 models embed differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbedInputType {
+    SearchDocument,
+    SearchQuery,
+    Classification,
+    Clustering,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    texts: &'a [String],
+    model: &'a str,
+    input_type: EmbedInputType,
+    truncate: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+pub struct CohereEmbedder {
+    client: ClientWithMiddleware,
+    api_key: String,
+    base_url: String,
+    model: String,
+    document_input_type: EmbedInputType,
+    query_input_type: EmbedInputType,
+    preprocessors: Preprocessors,
+}
+
+impl Default for CohereEmbedder {
+    /// Reads the API key from the `COHERE_API_KEY` environment variable.
+    fn default() -> Self {
+        CohereEmbedder::new(std::env::var("COHERE_API_KEY").unwrap_or_default())
+    }
+}
+
+impl CohereEmbedder {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self {
+            client: Self::build_client(3),
+            api_key: api_key.into(),
+            base_url: String::from("https://api.cohere.com"),
+            model: String::from("embed-english-v3.0"),
+            document_input_type: EmbedInputType::SearchDocument,
+            query_input_type: EmbedInputType::SearchQuery,
+            preprocessors: Preprocessors::default(),
+        }
+    }
+
+    fn build_client(max_retries: u32) -> ClientWithMiddleware {
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(max_retries);
+        ClientBuilder::new(reqwest::Client::new())
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build()
+    }
+
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Embeds both documents and queries as `input_type`, e.g. for
+    /// [`EmbedInputType::Clustering`]. By default `embed_documents` uses
+    /// [`EmbedInputType::SearchDocument`] and `embed_query` [`EmbedInputType::SearchQuery`].
+    pub fn with_input_type(mut self, input_type: EmbedInputType) -> Self {
+        self.document_input_type = input_type;
+        self.query_input_type = input_type;
+        self
+    }
+
+    /// Sets how many times a request failing with a rate limit, a server error or a network
+    /// error is retried, with exponential backoff. Defaults to 3.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.client = Self::build_client(max_retries);
+        self
+    }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: &[String],
+        input_type: EmbedInputType,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        // Texts over the model's 512 tokens are cut at the end by the API.
+        let request = EmbedRequest {
+            texts,
+            model: &self.model,
+            input_type,
+            truncate: "END",
+        };
+        let response = self
+            .client
+            .post(format!("{}/v1/embed", self.base_url.trim_end_matches('/')))
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(EmbedderError::RateLimited { retry_after });
+        }
+        if !status.is_success() {
+            return Err(EmbedderError::HttpError {
+                status_code: status,
+                error_message: response.text().await.unwrap_or_default(),
+            });
+        }
+
+        Ok(response.json::<EmbedResponse>().await?.embeddings)
+    }
+}
+
+#[async_trait]
+impl Embedder for CohereEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
+        log::debug!("Embedding documents: {:?}", documents);
+
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for batch in documents.chunks(MAX_TEXTS_PER_REQUEST) {
+            embeddings.extend(self.embed_texts(batch, self.document_input_type).await?);
+        }
+        Ok(embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
+        log::debug!("Embedding query: {:?}", text);
+
+        let mut embeddings = self
+            .embed_texts(&[text.into_owned()], self.query_input_type)
+            .await?;
+        Ok(embeddings.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cohere_batches_and_input_types() {
+        let mut server = mockito::Server::new_async().await;
+        let documents_mock = server
+            .mock("POST", "/v1/embed")
+            .match_header("authorization", "Bearer key")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "embed-english-v3.0",
+                "input_type": "search_document",
+            })))
+            .with_body_from_request(|request| {
+                let body: Value = serde_json::from_slice(request.body().unwrap()).unwrap();
+                let count = body["texts"].as_array().unwrap().len();
+                json!({ "embeddings": vec![[1.0, 0.0]; count] })
+                    .to_string()
+                    .into()
+            })
+            .expect(2)
+            .create_async()
+            .await;
+        let query_mock = server
+            .mock("POST", "/v1/embed")
+            .match_body(Matcher::PartialJson(
+                json!({ "input_type": "search_query" }),
+            ))
+            .with_body(r#"{"embeddings": [[0.0, 1.0]]}"#)
+            .create_async()
+            .await;
+
+        let cohere = CohereEmbedder::new("key").with_base_url(server.url());
+        let documents: Vec<String> = (0..100).map(|i| format!("document {}", i)).collect();
+        let embeddings = cohere.embed_documents(&documents).await.unwrap();
+        assert_eq!(embeddings.len(), 100);
+        documents_mock.assert_async().await;
+
+        let query = cohere.embed_query("question").await.unwrap();
+        assert_eq!(query, vec![0.0, 1.0]);
+        query_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cohere_rate_limit() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/embed")
+            .with_status(429)
+            .with_header("retry-after", "7")
+            .create_async()
+            .await;
+
+        let cohere = CohereEmbedder::new("key")
+            .with_base_url(server.url())
+            .with_max_retries(0);
+        let err = cohere.embed_query("question").await.unwrap_err();
+        assert!(matches!(
+            err,
+            EmbedderError::RateLimited {
+                retry_after: Some(d)
+            } if d == Duration::from_secs(7)
+        ));
+    }
+}
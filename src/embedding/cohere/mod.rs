@@ -0,0 +1,2 @@
+pub mod cohere_embedder;
+pub use cohere_embedder::*;
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use super::{EmbedKind, Embedder, EmbedderError};
+
+/// Wraps an [`Embedder`] so that identical texts within one `embed_documents` call are
+/// embedded once, and the embedding is copied back to every position they appeared at.
+/// Useful for corpora with repeated boilerplate chunks. Batches without duplicates are
+/// passed through as is.
+///
+/// # Usage
+/// ```rust,ignore
+/// let embedder = DedupEmbedder::new(OpenAiEmbedder::default());
+/// ```
+pub struct DedupEmbedder<E: Embedder> {
+    inner: E,
+}
+
+impl<E: Embedder> DedupEmbedder<E> {
+    pub fn new(inner: E) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+
+    async fn embed_deduplicated(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        // For each text, the index of its first occurrence among the unique texts.
+        let mut positions: HashMap<&str, usize> = HashMap::with_capacity(texts.len());
+        let mut unique: Vec<String> = Vec::new();
+        let indices: Vec<usize> = texts
+            .iter()
+            .map(|text| {
+                *positions.entry(text.as_str()).or_insert_with(|| {
+                    unique.push(text.clone());
+                    unique.len() - 1
+                })
+            })
+            .collect();
+
+        if unique.len() == texts.len() {
+            return self.inner.embed(texts, kind).await;
+        }
+
+        let embeddings = self.inner.embed(&unique, kind).await?;
+        if embeddings.len() != unique.len() {
+            // Let the caller's length check report the mismatch.
+            return Ok(embeddings);
+        }
+        Ok(indices.into_iter().map(|i| embeddings[i].clone()).collect())
+    }
+}
+
+#[async_trait]
+impl<E: Embedder> Embedder for DedupEmbedder<E> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        self.embed_deduplicated(documents, EmbedKind::Document)
+            .await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        self.inner.embed_query(text).await
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        self.embed_deduplicated(texts, kind).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingEmbedder {
+        seen: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Embedder for RecordingEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+            self.seen.lock().unwrap().extend(documents.iter().cloned());
+            Ok(documents
+                .iter()
+                .map(|d| vec![d.len() as f32, d.as_bytes()[0] as f32])
+                .collect())
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+            Ok(vec![text.len() as f32, 0.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_are_embedded_once() {
+        let embedder = DedupEmbedder::new(RecordingEmbedder::default());
+        let texts: Vec<String> = ["a", "bb", "a", "c", "bb", "a"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let embeddings = embedder.embed_documents(&texts).await.unwrap();
+
+        assert_eq!(
+            *embedder.into_inner().seen.lock().unwrap(),
+            vec!["a", "bb", "c"]
+        );
+        let expected: Vec<Vec<f32>> = texts
+            .iter()
+            .map(|t| vec![t.len() as f32, t.as_bytes()[0] as f32])
+            .collect();
+        assert_eq!(embeddings, expected);
+    }
+}
@@ -2,8 +2,94 @@ use async_trait::async_trait;
 
 use super::EmbedderError;
 
+/// Whether a text is a search query or a passage to be indexed. Retrieval-tuned models
+/// (e5, bge, Cohere's `input_type`, ...) embed the two differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmbedKind {
+    Query,
+    Document,
+}
+
+/// Produces embeddings as `f32`, the precision embedding APIs return and vector databases
+/// store, so vectors are never widened in memory.
+///
+/// Implementations written against the former `f64` signatures can keep computing in `f64`
+/// and convert explicitly with [`embedding_from_f64`]; callers that need `f64` vectors can
+/// use [`embedding_to_f64`].
+///
+/// Passages that are stored and searched must go through `embed_documents`, and search
+/// queries through `embed_query`, so that embedders treating the two asymmetrically
+/// produce comparable vectors.
 #[async_trait]
 pub trait Embedder: Send + Sync {
-    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError>;
-    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError>;
+    /// Embeds passages to be indexed ([`EmbedKind::Document`]).
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError>;
+
+    /// Embeds a search query ([`EmbedKind::Query`]).
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError>;
+
+    /// Embeds `texts` as `kind`, dispatching to `embed_documents` or `embed_query`.
+    async fn embed(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        match kind {
+            EmbedKind::Document => self.embed_documents(texts).await,
+            EmbedKind::Query => {
+                let mut embeddings = Vec::with_capacity(texts.len());
+                for text in texts {
+                    embeddings.push(self.embed_query(text).await?);
+                }
+                Ok(embeddings)
+            }
+        }
+    }
+}
+
+/// Widens an embedding to `f64`.
+pub fn embedding_to_f64(embedding: &[f32]) -> Vec<f64> {
+    embedding.iter().map(|&x| x as f64).collect()
+}
+
+/// Narrows an `f64` embedding to `f32`, the precision returned by [`Embedder`].
+pub fn embedding_from_f64(embedding: &[f64]) -> Vec<f32> {
+    embedding.iter().map(|&x| x as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct KindEmbedder;
+
+    #[async_trait]
+    impl Embedder for KindEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f32>, EmbedderError> {
+            Ok(vec![0.0, 1.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_dispatches_on_kind() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            KindEmbedder
+                .embed(&texts, EmbedKind::Document)
+                .await
+                .unwrap(),
+            vec![vec![1.0, 0.0], vec![1.0, 0.0]]
+        );
+        assert_eq!(
+            KindEmbedder.embed(&texts, EmbedKind::Query).await.unwrap(),
+            vec![vec![0.0, 1.0], vec![0.0, 1.0]]
+        );
+    }
 }
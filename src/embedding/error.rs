@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use async_openai::error::OpenAIError;
 #[cfg(feature = "mistralai")]
 use mistralai_client::v1::error::{ApiError, ClientError};
@@ -23,9 +25,47 @@ pub enum EmbedderError {
         error_message: String,
     },
 
+    #[error("Rate limited by the embedding API, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("Embedding request timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Embedding document {index} failed: {source}")]
+    DocumentError {
+        index: usize,
+        #[source]
+        source: Box<EmbedderError>,
+    },
+
+    /// A batch of `embed_documents` failed. `embedded` holds the embeddings of the documents
+    /// before `start`, which all succeeded, so a caller can resume from there.
+    #[error("Embedding batch {batch} (documents {start}..{end}) failed: {source}")]
+    BatchError {
+        batch: usize,
+        start: usize,
+        end: usize,
+        embedded: Vec<Vec<f32>>,
+        #[source]
+        source: Box<EmbedderError>,
+    },
+
+    #[error("Embedding cache error: {0}")]
+    CacheError(String),
+
+    #[error("Embedder returned {got} embeddings for {expected} texts")]
+    EmbeddingCountMismatch { expected: usize, got: usize },
+
     #[error("FastEmbed error: {0}")]
     FastEmbedError(String),
 
+    #[error("Candle error: {0}")]
+    CandleError(String),
+
+    #[cfg(feature = "cohere")]
+    #[error("Network request failed: {0}")]
+    MiddlewareError(#[from] reqwest_middleware::Error),
+
     #[cfg(feature = "ollama")]
     #[error("Ollama error: {0}")]
     OllamaError(#[from] OllamaError),
@@ -1,63 +1,201 @@
+use std::{path::PathBuf, sync::Arc};
+
 use async_trait::async_trait;
 
-use crate::embedding::{Embedder, EmbedderError};
-use fastembed::TextEmbedding;
+use crate::embedding::{Embedder, EmbedderError, Preprocessor, Preprocessors};
+use fastembed::{Embedding, EmbeddingModel, InitOptions, TextEmbedding};
 
+/// Embeds locally with an ONNX model run by the `fastembed` crate, so it needs no API key and,
+/// once the model is downloaded, no network. The model is loaded once and shared by all
+/// calls, which run on blocking threads so that inference doesn't stall the async runtime.
 pub struct FastEmbed {
-    model: TextEmbedding,
+    model: Arc<TextEmbedding>,
+    dimension: Option<usize>,
     batch_size: Option<usize>,
+    parallelism: usize,
+    preprocessors: Preprocessors,
 }
 
 impl FastEmbed {
+    /// Loads the default model, `BGESmallENV15`.
     pub fn try_new() -> Result<Self, EmbedderError> {
-        Ok(Self {
-            model: TextEmbedding::try_new(Default::default())
-                .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?,
-            batch_size: None,
-        })
+        Self::try_with_model(EmbeddingModel::BGESmallENV15)
+    }
+
+    /// Loads `model`, downloading it to the fastembed cache on first use.
+    pub fn try_with_model(model: EmbeddingModel) -> Result<Self, EmbedderError> {
+        Self::builder().model(model).build()
+    }
+
+    pub fn builder() -> FastEmbedBuilder {
+        FastEmbedBuilder::new()
+    }
+
+    /// Length of the embeddings, e.g. for `StoreBuilder::vector_dimensions`. Known for
+    /// embedders made with `try_new`, `try_with_model` or the builder; `None` for one made
+    /// from a `TextEmbedding`, whose dimension the store builders' `probe_dimensions` can find.
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
     }
 
     pub fn with_batch_size(mut self, batch_size: usize) -> Self {
         self.batch_size = Some(batch_size);
         self
     }
+
+    /// Splits `embed_documents` inputs into `parallelism` contiguous chunks, each embedded
+    /// on its own blocking thread. The output order matches the input order.
+    /// Defaults to 1, which embeds the whole input in a single call.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
+
+    async fn embed_blocking(
+        &self,
+        documents: Vec<String>,
+    ) -> Result<Vec<Embedding>, EmbedderError> {
+        let model = Arc::clone(&self.model);
+        let batch_size = self.batch_size;
+        tokio::task::spawn_blocking(move || model.embed(documents, batch_size))
+            .await
+            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?
+            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))
+    }
+
+    async fn embed_parallel(&self, documents: &[String]) -> Result<Vec<Embedding>, EmbedderError> {
+        let chunk_size = documents.len().div_ceil(self.parallelism);
+        let tasks = documents.chunks(chunk_size).map(|chunk| {
+            let model = Arc::clone(&self.model);
+            let chunk = chunk.to_vec();
+            let batch_size = self.batch_size;
+            tokio::task::spawn_blocking(move || model.embed(chunk, batch_size))
+        });
+
+        let results = futures::future::try_join_all(tasks)
+            .await
+            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?;
+
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for result in results {
+            embeddings.extend(result.map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Loads the model of a [`FastEmbed`]. Once the model files are in the cache directory,
+/// loading needs no network, so embedding can run fully offline.
+pub struct FastEmbedBuilder {
+    model: EmbeddingModel,
+    show_download_progress: bool,
+    cache_dir: Option<PathBuf>,
+}
+
+impl Default for FastEmbedBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FastEmbedBuilder {
+    pub fn new() -> Self {
+        Self {
+            model: EmbeddingModel::BGESmallENV15,
+            show_download_progress: false,
+            cache_dir: None,
+        }
+    }
+
+    /// The model to load, `BGESmallENV15` by default.
+    pub fn model(mut self, model: EmbeddingModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Prints a progress bar while the model is downloaded. Off by default.
+    pub fn show_download_progress(mut self, show_download_progress: bool) -> Self {
+        self.show_download_progress = show_download_progress;
+        self
+    }
+
+    /// Where models are downloaded to and loaded from, instead of fastembed's default
+    /// `.fastembed_cache` in the working directory.
+    pub fn cache_dir<P: Into<PathBuf>>(mut self, cache_dir: P) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    pub fn build(self) -> Result<FastEmbed, EmbedderError> {
+        let dimension = model_dimension(&self.model);
+        let mut options =
+            InitOptions::new(self.model).with_show_download_progress(self.show_download_progress);
+        if let Some(cache_dir) = self.cache_dir {
+            options = options.with_cache_dir(cache_dir);
+        }
+        let text_embedding = TextEmbedding::try_new(options)
+            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?;
+        Ok(FastEmbed {
+            dimension,
+            ..FastEmbed::from(text_embedding)
+        })
+    }
 }
 
 impl From<TextEmbedding> for FastEmbed {
     fn from(model: TextEmbedding) -> Self {
         Self {
-            model,
+            model: Arc::new(model),
+            dimension: None,
             batch_size: None,
+            parallelism: 1,
+            preprocessors: Preprocessors::default(),
         }
     }
 }
 
+fn model_dimension(model: &EmbeddingModel) -> Option<usize> {
+    TextEmbedding::get_model_info(model)
+        .ok()
+        .map(|info| info.dim)
+}
+
 #[async_trait]
 impl Embedder for FastEmbed {
-    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
-        let embeddings = self
-            .model
-            .embed(documents.to_vec(), self.batch_size)
-            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?;
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
+        let embeddings = if self.parallelism > 1 && documents.len() > 1 {
+            self.embed_parallel(&documents).await?
+        } else {
+            self.embed_blocking(documents.into_owned()).await?
+        };
 
-        Ok(embeddings
-            .into_iter()
-            .map(|inner_vec| {
-                inner_vec
-                    .into_iter()
-                    .map(|x| x as f64)
-                    .collect::<Vec<f64>>()
-            })
-            .collect::<Vec<Vec<f64>>>())
-    }
-
-    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
-        let embedding = self
-            .model
-            .embed(vec![text], self.batch_size)
-            .map_err(|e| EmbedderError::FastEmbedError(e.to_string()))?;
+        Ok(embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
+        let mut embedding = self.embed_blocking(vec![text.into_owned()]).await?;
 
-        Ok(embedding[0].iter().map(|x| *x as f64).collect())
+        Ok(embedding.swap_remove(0))
     }
 }
 
@@ -72,5 +210,30 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(embeddings.len(), 2);
+        assert_eq!(fastembed.dimension(), Some(384));
+        assert!(embeddings.iter().all(|embedding| embedding.len() == 384));
+    }
+
+    #[tokio::test]
+    async fn test_fastembed_parallel_preserves_order() {
+        let documents: Vec<String> = (0..5).map(|i| format!("document number {}", i)).collect();
+
+        let sequential = FastEmbed::try_new()
+            .unwrap()
+            .embed_documents(&documents)
+            .await
+            .unwrap();
+        let parallel = FastEmbed::try_new()
+            .unwrap()
+            .with_parallelism(3)
+            .embed_documents(&documents)
+            .await
+            .unwrap();
+
+        assert_eq!(parallel.len(), documents.len());
+        for (a, b) in sequential.iter().zip(parallel.iter()) {
+            let diff: f32 = a.iter().zip(b.iter()).map(|(x, y)| (x - y).abs()).sum();
+            assert!(diff < 1e-3);
+        }
     }
 }
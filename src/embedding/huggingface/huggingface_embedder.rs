@@ -0,0 +1,209 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::embedding::{embedder_trait::Embedder, EmbedderError, Preprocessor, Preprocessors};
+
+/// Longest wait between two polls of a model that is still loading.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Embeds with a `feature-extraction` model of the HuggingFace hosted Inference API. The
+/// model must pool its output into one vector per text, as sentence-transformers models do.
+pub struct HuggingFaceEmbedder {
+    client: reqwest::Client,
+    api_key: String,
+    model_id: String,
+    base_url: String,
+    wait_for_model: bool,
+    load_timeout: Duration,
+    preprocessors: Preprocessors,
+}
+
+impl Default for HuggingFaceEmbedder {
+    /// Reads the API key from the `HF_TOKEN` environment variable.
+    fn default() -> Self {
+        HuggingFaceEmbedder::new(std::env::var("HF_TOKEN").unwrap_or_default())
+    }
+}
+
+impl HuggingFaceEmbedder {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            model_id: String::from("sentence-transformers/all-MiniLM-L6-v2"),
+            base_url: String::from("https://api-inference.huggingface.co"),
+            wait_for_model: false,
+            load_timeout: Duration::from_secs(120),
+            preprocessors: Preprocessors::default(),
+        }
+    }
+
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
+    pub fn with_model_id<S: Into<String>>(mut self, model_id: S) -> Self {
+        self.model_id = model_id.into();
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Asks the API to hold requests until a cold model is loaded, instead of answering
+    /// 503 right away. Off by default.
+    pub fn with_wait_for_model(mut self, wait_for_model: bool) -> Self {
+        self.wait_for_model = wait_for_model;
+        self
+    }
+
+    /// Sets how long requests answered with 503 while the model loads are retried, with
+    /// exponential backoff, before failing with [`EmbedderError::Timeout`]. Defaults to two
+    /// minutes.
+    pub fn with_load_timeout(mut self, load_timeout: Duration) -> Self {
+        self.load_timeout = load_timeout;
+        self
+    }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
+
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let url = format!(
+            "{}/models/{}",
+            self.base_url.trim_end_matches('/'),
+            self.model_id
+        );
+        let body = json!({
+            "inputs": texts,
+            "options": { "wait_for_model": self.wait_for_model },
+        });
+
+        let deadline = Instant::now() + self.load_timeout;
+        let mut delay = Duration::from_millis(500);
+        loop {
+            let response = self
+                .client
+                .post(&url)
+                .bearer_auth(&self.api_key)
+                .json(&body)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if status == StatusCode::SERVICE_UNAVAILABLE {
+                // The model is loading.
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(EmbedderError::Timeout(self.load_timeout));
+                }
+                log::debug!(
+                    "Model {} is loading, retrying in {:?}",
+                    self.model_id,
+                    delay
+                );
+                tokio::time::sleep(delay.min(deadline - now)).await;
+                delay = (delay * 2).min(MAX_POLL_INTERVAL);
+                continue;
+            }
+            if !status.is_success() {
+                return Err(EmbedderError::HttpError {
+                    status_code: status,
+                    error_message: response.text().await.unwrap_or_default(),
+                });
+            }
+
+            return Ok(response.json::<Vec<Vec<f32>>>().await?);
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for HuggingFaceEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
+        log::debug!("Embedding documents: {:?}", documents);
+
+        self.embed_texts(&documents).await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
+        log::debug!("Embedding query: {:?}", text);
+
+        let mut embeddings = self.embed_texts(&[text.into_owned()]).await?;
+        Ok(embeddings.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_huggingface_embed_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/models/sentence-transformers/all-MiniLM-L6-v2")
+            .match_header("authorization", "Bearer hf_key")
+            .match_body(Matcher::PartialJson(json!({
+                "inputs": ["a", "b"],
+                "options": { "wait_for_model": true },
+            })))
+            .with_body("[[0.5, 1.0], [1.5, 2.0]]")
+            .create_async()
+            .await;
+
+        let huggingface = HuggingFaceEmbedder::new("hf_key")
+            .with_base_url(server.url())
+            .with_wait_for_model(true);
+        let embeddings = huggingface
+            .embed_documents(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(embeddings, vec![vec![0.5, 1.0], vec![1.5, 2.0]]);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_huggingface_model_loading_timeout() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/models/sentence-transformers/all-MiniLM-L6-v2")
+            .with_status(503)
+            .with_body(r#"{"error": "Model is currently loading", "estimated_time": 20.0}"#)
+            .expect_at_least(2)
+            .create_async()
+            .await;
+
+        let huggingface = HuggingFaceEmbedder::new("hf_key")
+            .with_base_url(server.url())
+            .with_load_timeout(Duration::from_millis(300));
+        let err = huggingface.embed_query("question").await.unwrap_err();
+        assert!(matches!(err, EmbedderError::Timeout(_)));
+        mock.assert_async().await;
+    }
+}
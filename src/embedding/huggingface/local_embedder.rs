@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use hf_hub::{api::sync::Api, Repo, RepoType};
+use tokenizers::{PaddingParams, Tokenizer};
+
+use crate::embedding::{embedder_trait::Embedder, EmbedderError, Preprocessor, Preprocessors};
+
+/// Embeds locally with a BERT model from the HuggingFace Hub, run on the CPU by `candle`.
+/// Embeddings are the mean of the token embeddings, normalized to unit length, as
+/// sentence-transformers computes them. Inference runs on blocking threads.
+pub struct HuggingFaceLocalEmbedder {
+    model: Arc<BertModel>,
+    tokenizer: Arc<Tokenizer>,
+    device: Device,
+    preprocessors: Preprocessors,
+}
+
+fn candle_error(e: impl std::fmt::Display) -> EmbedderError {
+    EmbedderError::CandleError(e.to_string())
+}
+
+impl HuggingFaceLocalEmbedder {
+    /// Loads `sentence-transformers/all-MiniLM-L6-v2`.
+    pub fn try_new() -> Result<Self, EmbedderError> {
+        Self::try_with_model("sentence-transformers/all-MiniLM-L6-v2")
+    }
+
+    /// Loads the BERT model `model_id`, downloading its `config.json`, `tokenizer.json` and
+    /// `model.safetensors` to the HuggingFace cache on first use.
+    pub fn try_with_model(model_id: &str) -> Result<Self, EmbedderError> {
+        let repo = Api::new()
+            .map_err(candle_error)?
+            .repo(Repo::new(model_id.to_string(), RepoType::Model));
+        let config = repo.get("config.json").map_err(candle_error)?;
+        let tokenizer = repo.get("tokenizer.json").map_err(candle_error)?;
+        let weights = repo.get("model.safetensors").map_err(candle_error)?;
+
+        let config: Config =
+            serde_json::from_str(&std::fs::read_to_string(config).map_err(candle_error)?)
+                .map_err(candle_error)?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer).map_err(candle_error)?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+
+        let device = Device::Cpu;
+        // Safety: the weights file is only read, and not modified while mapped.
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DTYPE, &device) }
+            .map_err(candle_error)?;
+        let model = BertModel::load(vb, &config).map_err(candle_error)?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            device,
+            preprocessors: Preprocessors::default(),
+        })
+    }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
+
+    async fn embed_blocking(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let model = Arc::clone(&self.model);
+        let tokenizer = Arc::clone(&self.tokenizer);
+        let device = self.device.clone();
+        tokio::task::spawn_blocking(move || embed(&model, &tokenizer, &device, texts))
+            .await
+            .map_err(candle_error)?
+    }
+}
+
+fn embed(
+    model: &BertModel,
+    tokenizer: &Tokenizer,
+    device: &Device,
+    texts: Vec<String>,
+) -> Result<Vec<Vec<f32>>, EmbedderError> {
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let encodings = tokenizer.encode_batch(texts, true).map_err(candle_error)?;
+    let stack = |rows: Vec<&[u32]>| -> Result<Tensor, EmbedderError> {
+        let rows = rows
+            .into_iter()
+            .map(|row| Tensor::new(row, device))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(candle_error)?;
+        Tensor::stack(&rows, 0).map_err(candle_error)
+    };
+    let token_ids = stack(encodings.iter().map(|e| e.get_ids()).collect())?;
+    let attention_mask = stack(encodings.iter().map(|e| e.get_attention_mask()).collect())?;
+
+    let pooled = (|| -> candle_core::Result<Vec<Vec<f32>>> {
+        let token_type_ids = token_ids.zeros_like()?;
+        let hidden = model.forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+        // Mean over the real tokens only, leaving out the padding.
+        let mask = attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let pooled = summed.broadcast_div(&mask.sum(1)?)?;
+        let norm = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+        pooled.broadcast_div(&norm)?.to_vec2::<f32>()
+    })();
+    pooled.map_err(candle_error)
+}
+
+#[async_trait]
+impl Embedder for HuggingFaceLocalEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
+        self.embed_blocking(documents.into_owned()).await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
+        let mut embeddings = self.embed_blocking(vec![text.into_owned()]).await?;
+        Ok(embeddings.swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_huggingface_local_embed() {
+        let embedder = HuggingFaceLocalEmbedder::try_new().unwrap();
+        let embeddings = embedder
+            .embed_documents(&["Why is the sky blue?".to_string(), "Hi".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings[0].len(), 384);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod huggingface_embedder;
+pub use huggingface_embedder::*;
+
+#[cfg(feature = "candle")]
+pub mod local_embedder;
+#[cfg(feature = "candle")]
+pub use local_embedder::*;
@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
-use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+use crate::embedding::{embedder_trait::Embedder, EmbedderError, Preprocessor, Preprocessors};
 use async_trait::async_trait;
 use mistralai_client::v1::{client::Client, constants::EmbedModel};
 
 pub struct MistralAIEmbedder {
     client: Arc<Client>,
     model: EmbedModel,
+    preprocessors: Preprocessors,
 }
 
 impl MistralAIEmbedder {
@@ -16,42 +17,59 @@ impl MistralAIEmbedder {
                 Client::new(None, None, None, None).map_err(EmbedderError::MistralAIClientError)?,
             ),
             model: EmbedModel::MistralEmbed,
+            preprocessors: Preprocessors::default(),
         })
     }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
 }
 
 #[async_trait]
 impl Embedder for MistralAIEmbedder {
-    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
         log::debug!("Embedding documents: {:?}", documents);
 
         let response = self
             .client
-            .embeddings_async(self.model.clone(), documents.into(), None)
+            .embeddings_async(self.model.clone(), documents.into_owned(), None)
             .await
             .map_err(EmbedderError::MistralAIApiError)?;
 
         Ok(response
             .data
             .into_iter()
-            .map(|item| item.embedding.into_iter().map(|x| x as f64).collect())
-            .collect::<Vec<Vec<f64>>>())
+            .map(|item| item.embedding)
+            .collect::<Vec<Vec<f32>>>())
     }
 
-    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
         log::debug!("Embedding query: {:?}", text);
 
-        let response = self
+        let mut response = self
             .client
-            .embeddings_async(self.model.clone(), vec![text.to_string()], None)
+            .embeddings_async(self.model.clone(), vec![text.into_owned()], None)
             .await
             .map_err(EmbedderError::MistralAIApiError)?;
 
-        Ok(response.data[0]
-            .embedding
-            .iter()
-            .map(|x| *x as f64)
-            .collect())
+        Ok(response.data.swap_remove(0).embedding)
     }
 }
 
@@ -3,6 +3,25 @@ mod error;
 pub mod embedder_trait;
 pub use embedder_trait::*;
 
+mod preprocessor;
+pub use preprocessor::*;
+
+mod dedup_embedder;
+pub use dedup_embedder::*;
+
+pub mod cached;
+pub use cached::*;
+
+#[cfg(feature = "cohere")]
+pub mod cohere;
+#[cfg(feature = "cohere")]
+pub use cohere::*;
+
+#[cfg(feature = "huggingface")]
+pub mod huggingface;
+#[cfg(feature = "huggingface")]
+pub use huggingface::*;
+
 #[cfg(feature = "ollama")]
 pub mod ollama;
 #[cfg(feature = "ollama")]
@@ -1,7 +1,8 @@
-use std::sync::Arc;
+use std::{future::Future, sync::Arc, time::Duration};
 
-use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+use crate::embedding::{embedder_trait::Embedder, EmbedderError, Preprocessor, Preprocessors};
 use async_trait::async_trait;
+use futures::future::join_all;
 use ollama_rs::{
     generation::{
         embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest},
@@ -9,12 +10,17 @@ use ollama_rs::{
     },
     Ollama as OllamaClient,
 };
+use url::Url;
 
 #[derive(Debug)]
 pub struct OllamaEmbedder {
     pub(crate) client: Arc<OllamaClient>,
     pub(crate) model: String,
     pub(crate) options: Option<GenerationOptions>,
+    pub(crate) preprocessors: Preprocessors,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) sequential: bool,
+    pub(crate) batch_size: usize,
 }
 
 /// [nomic-embed-text](https://ollama.com/library/nomic-embed-text) is a 137M parameters, 274MB model.
@@ -30,9 +36,49 @@ impl OllamaEmbedder {
             client,
             model: model.into(),
             options,
+            preprocessors: Preprocessors::default(),
+            timeout: None,
+            sequential: false,
+            batch_size: 1,
         }
     }
 
+    /// Talks to the Ollama server at `base_url`, e.g. `http://gpu-box:11434`, instead of the
+    /// client's. The port defaults to 11434 when the URL has none.
+    pub fn with_base_url(mut self, base_url: &str) -> Result<Self, EmbedderError> {
+        let url = Url::parse(base_url)?;
+        let host = format!(
+            "{}://{}",
+            url.scheme(),
+            url.host_str().ok_or(url::ParseError::EmptyHost)?
+        );
+        self.client = Arc::new(OllamaClient::new(host, url.port().unwrap_or(11434)));
+        Ok(self)
+    }
+
+    /// Fails each embedding request that takes longer than `timeout` with
+    /// [`EmbedderError::Timeout`]. Unlimited by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes `embed_documents` send one request per document, in order, instead of a single
+    /// request for all of them, for servers or models that don't take a batch of inputs. An
+    /// error is returned as [`EmbedderError::DocumentError`] with the index of the failing
+    /// document. Off by default.
+    pub fn with_sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    /// Sets how many of the per-document requests of [`with_sequential`](Self::with_sequential)
+    /// are in flight at once. Defaults to 1, one document after the other.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
     pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
         self.model = model.into();
         self
@@ -42,6 +88,48 @@ impl OllamaEmbedder {
         self.options = Some(options);
         self
     }
+
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
+
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
+    }
+}
+
+impl OllamaEmbedder {
+    async fn generate(&self, input: EmbeddingsInput) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let request = GenerateEmbeddingsRequest::new(self.model.clone(), input);
+        let response = with_timeout(self.timeout, self.client.generate_embeddings(request)).await?;
+        Ok(response.embeddings)
+    }
+}
+
+async fn with_timeout<T, E>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, EmbedderError>
+where
+    EmbedderError: From<E>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| EmbedderError::Timeout(timeout))?
+            .map_err(EmbedderError::from),
+        None => future.await.map_err(EmbedderError::from),
+    }
 }
 
 impl Default for OllamaEmbedder {
@@ -53,45 +141,44 @@ impl Default for OllamaEmbedder {
 
 #[async_trait]
 impl Embedder for OllamaEmbedder {
-    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
         log::debug!("Embedding documents: {:?}", documents);
 
-        let response = self
-            .client
-            .generate_embeddings(GenerateEmbeddingsRequest::new(
-                self.model.clone(),
-                EmbeddingsInput::Multiple(documents.to_vec()),
-            ))
-            .await?;
-
-        let embeddings = response
-            .embeddings
-            .into_iter()
-            .map(|embedding| embedding.into_iter().map(f64::from).collect())
-            .collect();
+        if !self.sequential {
+            return self
+                .generate(EmbeddingsInput::Multiple(documents.into_owned()))
+                .await;
+        }
 
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for (batch, chunk) in documents.chunks(self.batch_size).enumerate() {
+            let responses = join_all(
+                chunk
+                    .iter()
+                    .map(|document| self.generate(EmbeddingsInput::Single(document.clone()))),
+            )
+            .await;
+            for (offset, response) in responses.into_iter().enumerate() {
+                let embedding = response.map_err(|source| EmbedderError::DocumentError {
+                    index: batch * self.batch_size + offset,
+                    source: Box::new(source),
+                })?;
+                embeddings.extend(embedding);
+            }
+        }
         Ok(embeddings)
     }
 
-    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
         log::debug!("Embedding query: {:?}", text);
 
         let response = self
-            .client
-            .generate_embeddings(GenerateEmbeddingsRequest::new(
-                self.model.clone(),
-                EmbeddingsInput::Single(text.into()),
-            ))
+            .generate(EmbeddingsInput::Single(text.into_owned()))
             .await?;
 
-        let embeddings = response
-            .embeddings
-            .into_iter()
-            .next()
-            .unwrap()
-            .into_iter()
-            .map(f64::from)
-            .collect();
+        let embeddings = response.into_iter().next().unwrap();
 
         Ok(embeddings)
     }
@@ -112,4 +199,48 @@ mod tests {
 
         assert_eq!(response.len(), 768);
     }
+
+    #[tokio::test]
+    async fn test_ollama_timeout_and_document_errors() {
+        // Nothing listens on the discard port, or the request outlives the timeout.
+        let ollama = OllamaEmbedder::default()
+            .with_base_url("http://127.0.0.1:9")
+            .unwrap()
+            .with_timeout(Duration::from_secs(5))
+            .with_sequential(true);
+
+        let err = ollama
+            .embed_documents(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, EmbedderError::DocumentError { index: 0, .. }));
+
+        assert!(OllamaEmbedder::default()
+            .with_base_url("not a url")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_concurrent_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embed")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embeddings": [[0.5, 1.5]]}"#)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let ollama = OllamaEmbedder::default()
+            .with_base_url(&server.url())
+            .unwrap()
+            .with_sequential(true)
+            .with_batch_size(2);
+
+        let documents = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let embeddings = ollama.embed_documents(&documents).await.unwrap();
+        assert_eq!(embeddings, vec![vec![0.5, 1.5]; 3]);
+        mock.assert_async().await;
+    }
 }
@@ -2,10 +2,13 @@
 
 use std::time::Duration;
 
-use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+use crate::embedding::{
+    embedder_trait::Embedder, EmbedKind, EmbedderError, Preprocessor, Preprocessors,
+};
 pub use async_openai::config::{AzureConfig, Config, OpenAIConfig};
 use async_openai::{
-    types::{CreateEmbeddingRequestArgs, EmbeddingInput},
+    error::OpenAIError,
+    types::{CreateEmbeddingRequest, CreateEmbeddingRequestArgs, EmbeddingInput},
     Client,
 };
 use async_trait::async_trait;
@@ -17,6 +20,9 @@ pub struct OpenAiEmbedder<C: Config> {
     model: String,
     timeout: Duration,
     retry_count: u32,
+    batch_size: usize,
+    dimensions: Option<u32>,
+    preprocessors: Preprocessors,
 }
 
 impl<C: Config + Send + Sync + 'static> Into<Box<dyn Embedder>> for OpenAiEmbedder<C> {
@@ -32,6 +38,9 @@ impl<C: Config> OpenAiEmbedder<C> {
             model: String::from("text-embedding-ada-002"),
             timeout: Duration::from_secs(30),
             retry_count: 3,
+            batch_size: 512,
+            dimensions: None,
+            preprocessors: Preprocessors::default(),
         }
     }
 
@@ -54,55 +63,64 @@ impl<C: Config> OpenAiEmbedder<C> {
         self.retry_count = retry_count;
         self
     }
-}
 
-impl Default for OpenAiEmbedder<OpenAIConfig> {
-    fn default() -> Self {
-        OpenAiEmbedder::new(OpenAIConfig::default())
+    /// Sets how many documents `embed_documents` sends per request, 512 by default. The
+    /// API takes at most 2048 inputs per request. Each batch is retried with backoff on its
+    /// own, and a failing batch is reported as [`EmbedderError::BatchError`] carrying the
+    /// embeddings of the batches before it.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
     }
-}
 
-#[async_trait]
-impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
-    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
-        let backoff = ExponentialBackoff {
-            max_elapsed_time: Some(self.timeout),
-            max_interval: Duration::from_secs(30),
-            ..ExponentialBackoff::default()
-        };
+    /// Asks for embeddings of `dimensions` elements instead of the model's native length.
+    /// Only supported by the `text-embedding-3-*` models.
+    pub fn with_dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
 
-        let client = Client::build(
-            reqwest::Client::builder()
-                .timeout(self.timeout)
-                .build()
-                .unwrap(),
-            self.config.clone(),
-            backoff,
-        );
+    /// The embedding length set with [`OpenAiEmbedder::with_dimensions`], e.g. for
+    /// `StoreBuilder::vector_dimensions`.
+    pub fn dimensions(&self) -> Option<u32> {
+        self.dimensions
+    }
 
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(&self.model)
-            .input(EmbeddingInput::StringArray(documents.into()))
-            .build()?;
+    #[deprecated = "Use `with_batch_size` instead"]
+    pub fn with_max_batch_size(self, max_batch_size: usize) -> Self {
+        self.with_batch_size(max_batch_size)
+    }
 
-        let response = client.embeddings().create(request).await?;
+    /// Applies `preprocessor` to every text, both documents and queries, before embedding.
+    pub fn with_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_both(preprocessor);
+        self
+    }
 
-        let embeddings = response
-            .data
-            .into_iter()
-            .map(|item| item.embedding)
-            .map(|embedding| {
-                embedding
-                    .into_iter()
-                    .map(|x| x as f64)
-                    .collect::<Vec<f64>>()
-            })
-            .collect();
+    /// Applies `preprocessor` to the texts passed to `embed_documents`.
+    pub fn with_document_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_document(preprocessor);
+        self
+    }
 
-        Ok(embeddings)
+    /// Applies `preprocessor` to the text passed to `embed_query`.
+    pub fn with_query_preprocessor(mut self, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set_query(preprocessor);
+        self
     }
 
-    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+    /// Applies `preprocessor` to texts of `kind` only, e.g. to add the instruction prefix an
+    /// instruction-tuned model expects on queries.
+    pub fn with_kind_preprocessor(mut self, kind: EmbedKind, preprocessor: Preprocessor) -> Self {
+        self.preprocessors.set(kind, preprocessor);
+        self
+    }
+}
+
+impl<C: Config> OpenAiEmbedder<C> {
+    // A client retrying failed requests with exponential backoff, up to `retry_count` times
+    // the timeout.
+    fn client(&self) -> Client<C> {
         let backoff = ExponentialBackoff {
             max_elapsed_time: Some(self.timeout * (self.retry_count + 1)),
             max_interval: self.timeout,
@@ -111,28 +129,220 @@ impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
             ..ExponentialBackoff::default()
         };
 
-        let client = Client::build(
+        Client::build(
             reqwest::Client::builder()
                 .timeout(self.timeout)
                 .build()
                 .unwrap(),
             self.config.clone(),
             backoff,
-        );
+        )
+    }
+
+    fn request(&self, input: EmbeddingInput) -> Result<CreateEmbeddingRequest, OpenAIError> {
+        let mut request = CreateEmbeddingRequestArgs::default();
+        request.model(&self.model).input(input);
+        if let Some(dimensions) = self.dimensions {
+            request.dimensions(dimensions);
+        }
+        request.build()
+    }
 
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(&self.model)
-            .input(text)
-            .build()?;
+    /// Embeds one batch of documents, the first of which is document `start`.
+    async fn embed_batch(
+        &self,
+        client: &Client<C>,
+        start: usize,
+        documents: &[String],
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        // The API rejects the whole request for an empty input, so name the offending one.
+        if let Some(i) = documents.iter().position(|d| d.is_empty()) {
+            return Err(EmbedderError::DocumentError {
+                index: start + i,
+                source: Box::new(
+                    OpenAIError::InvalidArgument("input must not be empty".into()).into(),
+                ),
+            });
+        }
+
+        let request = self.request(EmbeddingInput::StringArray(documents.to_vec()))?;
+
+        let mut response = client.embeddings().create(request).await?;
+
+        // Each embedding carries the position of its input.
+        response.data.sort_by_key(|item| item.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect())
+    }
+}
+
+impl Default for OpenAiEmbedder<OpenAIConfig> {
+    fn default() -> Self {
+        OpenAiEmbedder::new(OpenAIConfig::default())
+    }
+}
+
+#[async_trait]
+impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let documents = self.preprocessors.documents(documents);
+        let client = self.client();
+
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for (batch, chunk) in documents.chunks(self.batch_size).enumerate() {
+            let start = batch * self.batch_size;
+            match self.embed_batch(&client, start, chunk).await {
+                Ok(batch_embeddings) => embeddings.extend(batch_embeddings),
+                Err(e) => {
+                    return Err(EmbedderError::BatchError {
+                        batch,
+                        start,
+                        end: start + chunk.len(),
+                        embedded: embeddings,
+                        source: Box::new(e),
+                    })
+                }
+            }
+        }
+
+        Ok(embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let text = self.preprocessors.query(text);
+        let client = self.client();
+
+        let request = self.request(EmbeddingInput::String(text.into_owned()))?;
 
         let mut response = client.embeddings().create(request).await?;
 
         let item = response.data.swap_remove(0);
 
-        Ok(item
-            .embedding
-            .into_iter()
-            .map(|x| x as f64)
-            .collect::<Vec<f64>>())
+        Ok(item.embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed_documents_reports_failing_batch() {
+        let embedder = OpenAiEmbedder::default().with_batch_size(2);
+        let documents = vec!["".to_string(), "hello".to_string(), "world".to_string()];
+
+        match embedder.embed_documents(&documents).await {
+            Err(EmbedderError::BatchError {
+                batch,
+                start,
+                end,
+                embedded,
+                source,
+            }) => {
+                assert_eq!((batch, start, end), (0, 0, 2));
+                assert!(embedded.is_empty());
+                assert!(matches!(
+                    *source,
+                    EmbedderError::DocumentError { index: 0, .. }
+                ));
+            }
+            other => panic!("expected a batch error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_documents_in_batches() {
+        let mut server = mockito::Server::new_async().await;
+        // Answers each input "doc{i}" with [i, number of inputs in its request], listing the
+        // embeddings in reverse to check that they're put back in input order.
+        let mock = server
+            .mock("POST", "/embeddings")
+            .with_header("content-type", "application/json")
+            .with_body_from_request(|request| {
+                let body: serde_json::Value =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let inputs = body["input"].as_array().unwrap();
+                let data: Vec<_> = inputs
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, input)| {
+                        let i: f32 = input.as_str().unwrap()[3..].parse().unwrap();
+                        serde_json::json!({
+                            "object": "embedding",
+                            "index": index,
+                            "embedding": [i, inputs.len() as f32],
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "object": "list",
+                    "data": data,
+                    "model": "text-embedding-ada-002",
+                    "usage": { "prompt_tokens": 1, "total_tokens": 1 },
+                })
+                .to_string()
+                .into()
+            })
+            .expect(3)
+            .create_async()
+            .await;
+
+        let config = OpenAIConfig::new()
+            .with_api_base(server.url())
+            .with_api_key("key");
+        let embedder = OpenAiEmbedder::new(config).with_batch_size(2);
+        let documents: Vec<String> = (0..5).map(|i| format!("doc{}", i)).collect();
+
+        let embeddings = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(
+            embeddings,
+            vec![
+                vec![0.0, 2.0],
+                vec![1.0, 2.0],
+                vec![2.0, 2.0],
+                vec![3.0, 2.0],
+                vec![4.0, 1.0],
+            ]
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_embed_query_with_dimensions() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "text-embedding-3-small",
+                "dimensions": 2,
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(
+                serde_json::json!({
+                    "object": "list",
+                    "data": [{ "object": "embedding", "index": 0, "embedding": [0.6, 0.8] }],
+                    "model": "text-embedding-3-small",
+                    "usage": { "prompt_tokens": 1, "total_tokens": 1 },
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let config = OpenAIConfig::new()
+            .with_api_base(server.url())
+            .with_api_key("key");
+        let embedder = OpenAiEmbedder::new(config)
+            .with_model("text-embedding-3-small")
+            .with_dimensions(2);
+        assert_eq!(embedder.dimensions(), Some(2));
+
+        let embedding = embedder.embed_query("hello").await.unwrap();
+        assert_eq!(embedding, vec![0.6, 0.8]);
+        mock.assert_async().await;
     }
 }
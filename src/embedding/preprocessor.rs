@@ -0,0 +1,93 @@
+use std::{borrow::Cow, fmt, sync::Arc};
+
+use super::EmbedKind;
+
+/// Transforms text before it is sent to an embedding model, e.g. to strip boilerplate or
+/// to add the `"query: "`/`"passage: "` prefixes expected by `e5` models.
+pub type Preprocessor = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// The document and query preprocessors of an embedder. Both default to identity.
+#[derive(Clone, Default)]
+pub struct Preprocessors {
+    document: Option<Arc<dyn Fn(&str) -> String + Send + Sync>>,
+    query: Option<Arc<dyn Fn(&str) -> String + Send + Sync>>,
+}
+
+impl Preprocessors {
+    /// Uses the same preprocessor for documents and queries.
+    pub fn set_both(&mut self, preprocessor: Preprocessor) {
+        let preprocessor: Arc<dyn Fn(&str) -> String + Send + Sync> = Arc::from(preprocessor);
+        self.document = Some(preprocessor.clone());
+        self.query = Some(preprocessor);
+    }
+
+    pub fn set_document(&mut self, preprocessor: Preprocessor) {
+        self.document = Some(Arc::from(preprocessor));
+    }
+
+    pub fn set_query(&mut self, preprocessor: Preprocessor) {
+        self.query = Some(Arc::from(preprocessor));
+    }
+
+    /// Sets the preprocessor applied to texts of `kind`.
+    pub fn set(&mut self, kind: EmbedKind, preprocessor: Preprocessor) {
+        match kind {
+            EmbedKind::Document => self.set_document(preprocessor),
+            EmbedKind::Query => self.set_query(preprocessor),
+        }
+    }
+
+    /// Applies the preprocessor of `kind` to `text`.
+    pub fn apply<'a>(&self, kind: EmbedKind, text: &'a str) -> Cow<'a, str> {
+        let preprocessor = match kind {
+            EmbedKind::Document => &self.document,
+            EmbedKind::Query => &self.query,
+        };
+        match preprocessor {
+            Some(f) => Cow::Owned(f(text)),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    pub fn documents<'a>(&self, documents: &'a [String]) -> Cow<'a, [String]> {
+        match &self.document {
+            Some(f) => Cow::Owned(documents.iter().map(|d| f(d)).collect()),
+            None => Cow::Borrowed(documents),
+        }
+    }
+
+    pub fn query<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.apply(EmbedKind::Query, text)
+    }
+}
+
+impl fmt::Debug for Preprocessors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Preprocessors")
+            .field("document", &self.document.is_some())
+            .field("query", &self.query.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preprocessors() {
+        let mut preprocessors = Preprocessors::default();
+        let docs = vec!["Rust".to_string()];
+        assert!(matches!(preprocessors.documents(&docs), Cow::Borrowed(_)));
+        assert_eq!(preprocessors.query("Rust"), "Rust");
+
+        preprocessors.set_both(Box::new(|s| s.to_lowercase()));
+        preprocessors.set_query(Box::new(|s| format!("query: {}", s)));
+        assert_eq!(preprocessors.documents(&docs)[0], "rust");
+        assert_eq!(preprocessors.query("Rust"), "query: Rust");
+
+        preprocessors.set(EmbedKind::Document, Box::new(|s| format!("passage: {}", s)));
+        assert_eq!(preprocessors.documents(&docs)[0], "passage: Rust");
+        assert_eq!(preprocessors.apply(EmbedKind::Query, "Rust"), "query: Rust");
+    }
+}
@@ -6,6 +6,7 @@ pub mod embedding;
 pub mod language_models;
 pub mod llm;
 pub mod memory;
+pub mod metrics;
 pub mod output_parsers;
 pub mod prompt;
 pub mod schemas;
@@ -10,12 +10,12 @@ use async_openai::{
         ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
         ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions,
         ChatCompletionToolArgs, ChatCompletionToolType, CreateChatCompletionRequest,
-        CreateChatCompletionRequestArgs, FunctionObjectArgs,
+        CreateChatCompletionRequestArgs, CreateChatCompletionStreamResponse, FunctionObjectArgs,
     },
     Client,
 };
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use futures::{future, Stream, StreamExt};
 
 use crate::{
     language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
@@ -172,29 +172,14 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
 
         let original_stream = client.chat().create_stream(request).await?;
 
-        let new_stream = original_stream.map(|result| match result {
-            Ok(completion) => {
-                let value_completion = serde_json::to_value(completion).map_err(LLMError::from)?;
-                let usage = value_completion.pointer("/usage");
-                if usage.is_some() && !usage.unwrap().is_null() {
-                    let usage = serde_json::from_value::<TokenUsage>(usage.unwrap().clone())
-                        .map_err(LLMError::from)?;
-                    return Ok(StreamData::new(value_completion, Some(usage), ""));
-                }
-                let content = value_completion
-                    .pointer("/choices/0/delta/content")
-                    .ok_or(LLMError::ContentNotFound(
-                        "/choices/0/delta/content".to_string(),
-                    ))?
-                    .clone();
-
-                Ok(StreamData::new(
-                    value_completion,
-                    None,
-                    content.as_str().unwrap_or(""),
-                ))
-            }
-            Err(e) => Err(LLMError::from(e)),
+        // The completion is streamed as token deltas. Callers that need the full
+        // response accumulate `StreamData::content`, as `ConversationalChain::stream` does
+        // to save it to memory once the stream ends.
+        let new_stream = original_stream.filter_map(|result| {
+            future::ready(match result {
+                Ok(completion) => completion_to_stream_data(completion).transpose(),
+                Err(e) => Some(Err(LLMError::from(e))),
+            })
         });
 
         Ok(Box::pin(new_stream))
@@ -205,6 +190,33 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
     }
 }
 
+/// Converts a streamed chunk into `StreamData`.
+/// Returns `None` for the terminal chunk that only carries the `finish_reason`
+/// and an empty delta, so it is not yielded to the caller.
+fn completion_to_stream_data(
+    completion: CreateChatCompletionStreamResponse,
+) -> Result<Option<StreamData>, LLMError> {
+    let value_completion = serde_json::to_value(completion)?;
+    if let Some(usage) = value_completion.pointer("/usage").filter(|u| !u.is_null()) {
+        let usage = serde_json::from_value::<TokenUsage>(usage.clone())?;
+        return Ok(Some(StreamData::new(value_completion, Some(usage), "")));
+    }
+
+    let content = value_completion
+        .pointer("/choices/0/delta/content")
+        .and_then(|content| content.as_str())
+        .unwrap_or("")
+        .to_string();
+    let finished = value_completion
+        .pointer("/choices/0/finish_reason")
+        .is_some_and(|reason| !reason.is_null());
+    if finished && content.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(StreamData::new(value_completion, None, content)))
+}
+
 impl<C: Config> OpenAI<C> {
     fn to_openai_messages(
         &self,
@@ -341,6 +353,28 @@ mod tests {
     use tokio::sync::Mutex;
     use tokio::test;
 
+    #[test]
+    async fn test_completion_to_stream_data_skips_final_empty_delta() {
+        let chunk = |delta: serde_json::Value, finish_reason: serde_json::Value| {
+            serde_json::from_value::<CreateChatCompletionStreamResponse>(json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gpt-4o-mini",
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            }))
+            .unwrap()
+        };
+
+        let data = completion_to_stream_data(chunk(json!({"content": "Hi"}), json!(null)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.content, "Hi");
+
+        let data = completion_to_stream_data(chunk(json!({}), json!("stop"))).unwrap();
+        assert!(data.is_none());
+    }
+
     #[test]
     #[ignore]
     async fn test_invoke() {
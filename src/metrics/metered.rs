@@ -0,0 +1,320 @@
+use std::{error::Error, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+
+use crate::{
+    embedding::{EmbedKind, Embedder, EmbedderError},
+    schemas::Document,
+    vectorstore::{DocumentStream, VecStoreOptions, VectorStore},
+};
+
+use super::{MetricEvent, MetricsSink, Operation};
+
+fn record<T, E>(
+    sink: &dyn MetricsSink,
+    operation: Operation,
+    start: Instant,
+    result: &Result<T, E>,
+    items: impl FnOnce(&T) -> usize,
+) {
+    sink.record(MetricEvent {
+        operation,
+        duration: start.elapsed(),
+        items: result.as_ref().map(items).unwrap_or(0),
+        success: result.is_ok(),
+    });
+}
+
+/// Wraps an [`Embedder`] and reports every `embed_documents`/`embed_query` call to a
+/// [`MetricsSink`].
+pub struct MeteredEmbedder<E: Embedder> {
+    inner: E,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl<E: Embedder> MeteredEmbedder<E> {
+    pub fn new(inner: E, sink: Arc<dyn MetricsSink>) -> Self {
+        Self { inner, sink }
+    }
+}
+
+#[async_trait]
+impl<E: Embedder> Embedder for MeteredEmbedder<E> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let start = Instant::now();
+        let result = self.inner.embed_documents(documents).await;
+        record(
+            self.sink.as_ref(),
+            Operation::EmbedDocuments,
+            start,
+            &result,
+            |_| documents.len(),
+        );
+        result
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        let start = Instant::now();
+        let result = self.inner.embed_query(text).await;
+        record(
+            self.sink.as_ref(),
+            Operation::EmbedQuery,
+            start,
+            &result,
+            |_| 1,
+        );
+        result
+    }
+
+    async fn embed(
+        &self,
+        texts: &[String],
+        kind: EmbedKind,
+    ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let operation = match kind {
+            EmbedKind::Query => Operation::EmbedQuery,
+            EmbedKind::Document => Operation::EmbedDocuments,
+        };
+        let start = Instant::now();
+        let result = self.inner.embed(texts, kind).await;
+        record(self.sink.as_ref(), operation, start, &result, |_| {
+            texts.len()
+        });
+        result
+    }
+}
+
+/// Wraps a [`VectorStore`] and reports every add, upsert and search call to a [`MetricsSink`].
+/// The other methods are forwarded to the inner store unmetered. Pair it with a [`MeteredEmbedder`] inside the store to also see the
+/// embedding calls each search makes.
+pub struct MeteredStore<V: VectorStore> {
+    inner: V,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl<V: VectorStore> MeteredStore<V> {
+    pub fn new(inner: V, sink: Arc<dyn MetricsSink>) -> Self {
+        Self { inner, sink }
+    }
+
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<V: VectorStore> VectorStore for MeteredStore<V> {
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let start = Instant::now();
+        let result = self.inner.add_documents(docs, opt).await;
+        record(
+            self.sink.as_ref(),
+            Operation::AddDocuments,
+            start,
+            &result,
+            |ids| ids.len(),
+        );
+        result
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let start = Instant::now();
+        let result = self.inner.similarity_search(query, limit, opt).await;
+        record(
+            self.sink.as_ref(),
+            Operation::SimilaritySearch,
+            start,
+            &result,
+            |docs| docs.len(),
+        );
+        result
+    }
+
+    async fn keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let start = Instant::now();
+        let result = self.inner.keyword_search(query, limit, opt).await;
+        record(
+            self.sink.as_ref(),
+            Operation::KeywordSearch,
+            start,
+            &result,
+            |docs| docs.len(),
+        );
+        result
+    }
+
+    async fn similarity_search_threshold_stream(
+        &self,
+        query: &str,
+        opt: &VecStoreOptions,
+    ) -> Result<DocumentStream, Box<dyn Error>> {
+        self.inner
+            .similarity_search_threshold_stream(query, opt)
+            .await
+    }
+
+    async fn mmr_search(
+        &self,
+        query: &str,
+        k: usize,
+        fetch_k: usize,
+        lambda: f64,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let start = Instant::now();
+        let result = self.inner.mmr_search(query, k, fetch_k, lambda, opt).await;
+        record(
+            self.sink.as_ref(),
+            Operation::MmrSearch,
+            start,
+            &result,
+            |docs| docs.len(),
+        );
+        result
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        self.inner.count_documents(opt).await
+    }
+
+    async fn upsert_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let start = Instant::now();
+        let result = self.inner.upsert_documents(docs, opt).await;
+        record(
+            self.sink.as_ref(),
+            Operation::UpsertDocuments,
+            start,
+            &result,
+            |ids| ids.len(),
+        );
+        result
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        self.inner.get_documents_by_ids(ids, opt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::InMemoryMetricsSink;
+
+    use super::*;
+
+    struct FakeEmbedder;
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f32>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f32>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metered_embedder_aggregates_calls() {
+        let sink = Arc::new(InMemoryMetricsSink::new());
+        let embedder = MeteredEmbedder::new(FakeEmbedder, sink.clone());
+
+        embedder
+            .embed_documents(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        embedder.embed_query("q").await.unwrap();
+        embedder.embed_query("q").await.unwrap();
+
+        let metrics = sink.snapshot();
+        let docs = metrics.get(Operation::EmbedDocuments);
+        assert_eq!((docs.calls, docs.items, docs.errors), (1, 2, 0));
+        assert_eq!(metrics.get(Operation::EmbedQuery).calls, 2);
+        assert_eq!(metrics.get(Operation::SimilaritySearch).calls, 0);
+
+        sink.reset();
+        assert_eq!(sink.snapshot().get(Operation::EmbedQuery).calls, 0);
+    }
+
+    struct FakeStore;
+
+    #[async_trait]
+    impl VectorStore for FakeStore {
+        async fn add_documents(
+            &self,
+            docs: &[Document],
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(docs.iter().map(|d| d.page_content.clone()).collect())
+        }
+
+        async fn similarity_search(
+            &self,
+            query: &str,
+            _limit: usize,
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(vec![Document::new(query)])
+        }
+
+        async fn keyword_search(
+            &self,
+            query: &str,
+            _limit: usize,
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(vec![Document::new(query)])
+        }
+
+        async fn count_documents(&self, _opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+            Ok(7)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metered_store_forwards_every_method() {
+        let sink = Arc::new(InMemoryMetricsSink::new());
+        let store = MeteredStore::new(FakeStore, sink.clone());
+        let opt = VecStoreOptions::default();
+
+        assert_eq!(store.keyword_search("q", 1, &opt).await.unwrap().len(), 1);
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 7);
+        // Unsupported by the inner store, so the metered call fails like the inner one.
+        assert!(store
+            .upsert_documents(&[Document::new("a")], &opt)
+            .await
+            .is_err());
+
+        let metrics = sink.snapshot();
+        let keyword = metrics.get(Operation::KeywordSearch);
+        assert_eq!((keyword.calls, keyword.items, keyword.errors), (1, 1, 0));
+        let upsert = metrics.get(Operation::UpsertDocuments);
+        assert_eq!((upsert.calls, upsert.errors), (1, 1));
+        assert_eq!(metrics.get(Operation::SimilaritySearch).calls, 0);
+    }
+}
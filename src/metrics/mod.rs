@@ -0,0 +1,5 @@
+mod metered;
+mod sink;
+
+pub use metered::*;
+pub use sink::*;
@@ -0,0 +1,108 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    EmbedDocuments,
+    EmbedQuery,
+    SimilaritySearch,
+    KeywordSearch,
+    MmrSearch,
+    AddDocuments,
+    UpsertDocuments,
+}
+
+/// One timed call to an embedder or vector store.
+#[derive(Debug, Clone)]
+pub struct MetricEvent {
+    pub operation: Operation,
+    pub duration: Duration,
+    /// Texts embedded, documents added or documents returned by the call.
+    pub items: usize,
+    pub success: bool,
+}
+
+/// Receives a [`MetricEvent`] for every metered call. Implementations must be cheap, since
+/// `record` runs inline on the call path.
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, event: MetricEvent);
+}
+
+/// Sink that discards every event.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetricsSink;
+
+impl MetricsSink for NoopMetricsSink {
+    fn record(&self, _event: MetricEvent) {}
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OperationStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub items: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+}
+
+impl OperationStats {
+    pub fn mean_duration(&self) -> Duration {
+        if self.calls == 0 {
+            return Duration::ZERO;
+        }
+        self.total_duration / self.calls as u32
+    }
+}
+
+/// Per-operation totals, as returned by [`InMemoryMetricsSink::snapshot`].
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    pub operations: HashMap<Operation, OperationStats>,
+}
+
+impl Metrics {
+    pub fn get(&self, operation: Operation) -> OperationStats {
+        self.operations.get(&operation).cloned().unwrap_or_default()
+    }
+}
+
+/// Sink that aggregates events in memory, e.g. to report totals for a retrieval session.
+///
+/// # Usage
+/// ```rust,ignore
+/// let sink = Arc::new(InMemoryMetricsSink::new());
+/// let embedder = MeteredEmbedder::new(OpenAiEmbedder::default(), sink.clone());
+/// // ...
+/// let embed_calls = sink.snapshot().get(Operation::EmbedDocuments).calls;
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryMetricsSink {
+    metrics: Mutex<Metrics>,
+}
+
+impl InMemoryMetricsSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> Metrics {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        *self.metrics.lock().unwrap() = Metrics::default();
+    }
+}
+
+impl MetricsSink for InMemoryMetricsSink {
+    fn record(&self, event: MetricEvent) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let stats = metrics.operations.entry(event.operation).or_default();
+        stats.calls += 1;
+        if !event.success {
+            stats.errors += 1;
+        }
+        stats.items += event.items as u64;
+        stats.total_duration += event.duration;
+        stats.max_duration = stats.max_duration.max(event.duration);
+    }
+}
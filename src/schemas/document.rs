@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 /// The `Document` struct represents a document with content, metadata, and a score.
 /// The `page_content` field is a string that contains the content of the document.
@@ -46,6 +47,212 @@ impl Document {
         self.score = score;
         self
     }
+
+    /// Appends `other` to this `Document`, joining both contents with `separator`.
+    /// Metadata keys already present in `self` take precedence and the scores are summed.
+    pub fn merge(mut self, other: Document, separator: &str) -> Self {
+        self.page_content.push_str(separator);
+        self.page_content.push_str(&other.page_content);
+        for (key, value) in other.metadata {
+            self.metadata.entry(key).or_insert(value);
+        }
+        self.score += other.score;
+        self
+    }
+
+    /// Returns the metadata value at `key` as a `u64`. Integral floats (e.g. `3.0`) and
+    /// numeric strings are coerced; negative or fractional values yield `None`.
+    pub fn meta_u64(&self, key: &str) -> Option<u64> {
+        match self.metadata.get(key)? {
+            Value::Number(n) => n.as_u64().or_else(|| {
+                n.as_f64()
+                    .filter(|f| f.fract() == 0.0 && *f >= 0.0 && *f <= u64::MAX as f64)
+                    .map(|f| f as u64)
+            }),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the metadata value at `key` as an `i64`. Integral floats (e.g. `-3.0`) and
+    /// numeric strings are coerced; fractional or out of range values yield `None`.
+    pub fn meta_i64(&self, key: &str) -> Option<i64> {
+        match self.metadata.get(key)? {
+            Value::Number(n) => n.as_i64().or_else(|| {
+                n.as_f64()
+                    .filter(|f| f.fract() == 0.0 && *f >= i64::MIN as f64 && *f <= i64::MAX as f64)
+                    .map(|f| f as i64)
+            }),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the metadata value at `key` as an `f64`, coercing integers and numeric strings.
+    pub fn meta_f64(&self, key: &str) -> Option<f64> {
+        match self.metadata.get(key)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the metadata value at `key` as a `bool`, accepting `"true"`/`"false"` strings.
+    pub fn meta_bool(&self, key: &str) -> Option<bool> {
+        match self.metadata.get(key)? {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the metadata value at `key` if it is a string.
+    pub fn meta_str(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key)?.as_str()
+    }
+
+    /// Returns the metadata value at `key` if it is an array.
+    pub fn meta_array(&self, key: &str) -> Option<&Vec<Value>> {
+        self.metadata.get(key)?.as_array()
+    }
+}
+
+/// Stitches adjacent chunks of the same source back together.
+///
+/// Documents are grouped by the metadata key `by` (e.g. `doc_id`) and ordered by their
+/// `chunk_index` metadata. Runs of contiguous chunk indices are concatenated into a single
+/// `Document` whose score is the average of the merged chunks. Chunks are never merged
+/// across a gap in `chunk_index`.
+///
+/// Documents missing `by` or a numeric `chunk_index` are returned unchanged. Groups keep
+/// the position of their first chunk in the input.
+///
+/// # Usage
+/// ```rust,ignore
+/// let merged = merge_adjacent(retrieved_docs, "doc_id");
+/// ```
+pub fn merge_adjacent(docs: Vec<Document>, by: &str) -> Vec<Document> {
+    enum Slot {
+        Single(Document),
+        Group(usize),
+    }
+
+    let mut slots: Vec<Slot> = Vec::new();
+    let mut groups: Vec<Vec<(u64, Document)>> = Vec::new();
+    let mut group_ids: HashMap<String, usize> = HashMap::new();
+
+    for doc in docs {
+        let key = doc.metadata.get(by).map(|v| v.to_string());
+        let chunk_index = doc.metadata.get("chunk_index").and_then(|v| v.as_u64());
+        match (key, chunk_index) {
+            (Some(key), Some(chunk_index)) => {
+                let group = *group_ids.entry(key).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    slots.push(Slot::Group(groups.len() - 1));
+                    groups.len() - 1
+                });
+                groups[group].push((chunk_index, doc));
+            }
+            _ => slots.push(Slot::Single(doc)),
+        }
+    }
+
+    let mut groups: Vec<Option<Vec<(u64, Document)>>> = groups.into_iter().map(Some).collect();
+    let mut merged = Vec::new();
+    for slot in slots {
+        match slot {
+            Slot::Single(doc) => merged.push(doc),
+            Slot::Group(group) => {
+                let mut chunks = groups[group].take().unwrap_or_default();
+                chunks.sort_by_key(|(chunk_index, _)| *chunk_index);
+
+                let mut run: Option<(u64, usize, Document)> = None;
+                for (chunk_index, doc) in chunks {
+                    run = match run {
+                        Some((last, count, acc)) if chunk_index == last + 1 => {
+                            Some((chunk_index, count + 1, acc.merge(doc, "\n")))
+                        }
+                        Some((_, count, acc)) => {
+                            merged.push(average_score(acc, count));
+                            Some((chunk_index, 1, doc))
+                        }
+                        None => Some((chunk_index, 1, doc)),
+                    };
+                }
+                if let Some((_, count, acc)) = run {
+                    merged.push(average_score(acc, count));
+                }
+            }
+        }
+    }
+
+    merged
+}
+
+fn average_score(mut doc: Document, count: usize) -> Document {
+    doc.score /= count as f64;
+    doc
+}
+
+/// What [`dedup_documents`] counts as a duplicate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupStrategy {
+    /// The same `page_content`.
+    #[default]
+    Content,
+    /// The same `page_content` and the same metadata.
+    ContentAndMetadata,
+}
+
+/// Drops duplicate documents, e.g. chunks of overlapping sources, before they are embedded
+/// and stored. Documents are compared by a SHA-256 hash chosen by `strategy`, and the first
+/// of each set of duplicates is kept in place. With `merge_metadata` it also receives the
+/// metadata keys of its duplicates that it lacks.
+///
+/// Returns the remaining documents and how many were removed.
+///
+/// # Usage
+/// ```rust,ignore
+/// let (docs, removed) = dedup_documents(docs, DedupStrategy::Content, true);
+/// store.add_documents(&docs, &VecStoreOptions::default()).await?;
+/// ```
+pub fn dedup_documents(
+    docs: Vec<Document>,
+    strategy: DedupStrategy,
+    merge_metadata: bool,
+) -> (Vec<Document>, usize) {
+    let mut kept: Vec<Document> = Vec::with_capacity(docs.len());
+    let mut seen: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut removed = 0;
+
+    for doc in docs {
+        let mut hasher = Sha256::new();
+        hasher.update(doc.page_content.as_bytes());
+        if strategy == DedupStrategy::ContentAndMetadata {
+            // Sorted, so that the hash doesn't depend on the map's iteration order.
+            let metadata: BTreeMap<&String, &Value> = doc.metadata.iter().collect();
+            hasher.update([0u8]);
+            hasher.update(serde_json::to_vec(&metadata).unwrap_or_default());
+        }
+        let hash = hasher.finalize().to_vec();
+
+        match seen.get(&hash) {
+            Some(&index) => {
+                removed += 1;
+                if merge_metadata {
+                    for (key, value) in doc.metadata {
+                        kept[index].metadata.entry(key).or_insert(value);
+                    }
+                }
+            }
+            None => {
+                seen.insert(hash, kept.len());
+                kept.push(doc);
+            }
+        }
+    }
+
+    (kept, removed)
 }
 
 impl Default for Document {
@@ -58,3 +265,104 @@ impl Default for Document {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn chunk(doc_id: &str, chunk_index: u64, content: &str, score: f64) -> Document {
+        Document::new(content)
+            .with_metadata(HashMap::from([
+                ("doc_id".to_string(), json!(doc_id)),
+                ("chunk_index".to_string(), json!(chunk_index)),
+            ]))
+            .with_score(score)
+    }
+
+    #[test]
+    fn test_merge_adjacent() {
+        let docs = vec![
+            chunk("a", 1, "a1", 0.4),
+            chunk("b", 0, "b0", 0.9),
+            chunk("a", 0, "a0", 0.8),
+            Document::new("loose"),
+            chunk("a", 3, "a3", 0.5),
+        ];
+
+        let merged = merge_adjacent(docs, "doc_id");
+
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[0].page_content, "a0\na1");
+        assert!((merged[0].score - 0.6).abs() < 1e-9);
+        assert_eq!(merged[0].metadata["chunk_index"], json!(0));
+        // chunk 3 is not contiguous with chunk 1
+        assert_eq!(merged[1].page_content, "a3");
+        assert_eq!(merged[2].page_content, "b0");
+        assert_eq!(merged[3].page_content, "loose");
+    }
+
+    #[test]
+    fn test_dedup_documents() {
+        let doc = |content: &str, source: &str| {
+            Document::new(content)
+                .with_metadata(HashMap::from([("source".to_string(), json!(source))]))
+        };
+        let docs = vec![
+            doc("alpha", "a.txt"),
+            doc("beta", "a.txt"),
+            doc("alpha", "b.txt").with_metadata(HashMap::from([
+                ("source".to_string(), json!("b.txt")),
+                ("page".to_string(), json!(2)),
+            ])),
+            doc("beta", "a.txt"),
+        ];
+
+        let (deduped, removed) = dedup_documents(docs.clone(), DedupStrategy::Content, true);
+        assert_eq!(removed, 2);
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].page_content, "alpha");
+        // The first document's own metadata wins; only missing keys are merged in.
+        assert_eq!(deduped[0].metadata["source"], json!("a.txt"));
+        assert_eq!(deduped[0].metadata["page"], json!(2));
+        assert_eq!(deduped[1].page_content, "beta");
+
+        let (deduped, removed) = dedup_documents(docs, DedupStrategy::ContentAndMetadata, false);
+        assert_eq!(removed, 1);
+        let contents: Vec<_> = deduped.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["alpha", "beta", "alpha"]);
+        assert!(!deduped[0].metadata.contains_key("page"));
+    }
+
+    #[test]
+    fn test_meta_accessors() {
+        let doc = Document::new("").with_metadata(HashMap::from([
+            ("int".to_string(), json!(7)),
+            ("negative".to_string(), json!(-2)),
+            ("integral_float".to_string(), json!(3.0)),
+            ("float".to_string(), json!(0.25)),
+            ("numeric_string".to_string(), json!(" 12 ")),
+            ("bool_string".to_string(), json!("true")),
+            ("text".to_string(), json!("hello")),
+            ("list".to_string(), json!([1, 2])),
+        ]));
+
+        assert_eq!(doc.meta_u64("int"), Some(7));
+        assert_eq!(doc.meta_u64("negative"), None);
+        assert_eq!(doc.meta_i64("negative"), Some(-2));
+        assert_eq!(doc.meta_u64("integral_float"), Some(3));
+        assert_eq!(doc.meta_i64("integral_float"), Some(3));
+        assert_eq!(doc.meta_u64("float"), None);
+        assert_eq!(doc.meta_f64("float"), Some(0.25));
+        assert_eq!(doc.meta_f64("int"), Some(7.0));
+        assert_eq!(doc.meta_u64("numeric_string"), Some(12));
+        assert_eq!(doc.meta_f64("numeric_string"), Some(12.0));
+        assert_eq!(doc.meta_bool("bool_string"), Some(true));
+        assert_eq!(doc.meta_bool("int"), None);
+        assert_eq!(doc.meta_str("text"), Some("hello"));
+        assert_eq!(doc.meta_str("int"), None);
+        assert_eq!(doc.meta_array("list").map(Vec::len), Some(2));
+        assert_eq!(doc.meta_u64("missing"), None);
+    }
+}
@@ -11,7 +11,7 @@ pub trait Index {
     /// Query the index with a vector and return the top_k most similar routes.
     /// Returns a list of tuples with the route name and the similarity score.
     /// Result<Vec<(route_name,similarity_score)>>
-    async fn query(&self, vector: &[f64], top_k: usize) -> Result<Vec<(String, f64)>, IndexError>;
+    async fn query(&self, vector: &[f32], top_k: usize) -> Result<Vec<(String, f64)>, IndexError>;
 
     async fn get_routers(&self) -> Result<Vec<Router>, IndexError>;
 
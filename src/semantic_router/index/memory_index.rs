@@ -40,7 +40,7 @@ impl Index for MemoryIndex {
         Ok(())
     }
 
-    async fn query(&self, vector: &[f64], top_k: usize) -> Result<Vec<(String, f64)>, IndexError> {
+    async fn query(&self, vector: &[f32], top_k: usize) -> Result<Vec<(String, f64)>, IndexError> {
         let mut all_similarities: Vec<(String, f64)> = Vec::new();
 
         // Compute similarity for each embedding of each router
@@ -70,7 +70,7 @@ impl RouteLayer {
 
     async fn filter_similar_routes(
         &self,
-        query_vector: &[f64],
+        query_vector: &[f32],
     ) -> Result<Vec<(String, f64)>, RouteLayerError> {
         let similar_routes = self.index.query(query_vector, self.top_k).await?;
 
@@ -158,7 +158,7 @@ impl RouteLayer {
     /// this just returns the route
     pub async fn call_embedding(
         &self,
-        embedding: &[f64],
+        embedding: &[f32],
     ) -> Result<Option<RouteChoise>, RouteLayerError> {
         let similar_routes = self.filter_similar_routes(embedding).await?;
 
@@ -4,7 +4,7 @@ use std::hash::{Hash, Hasher};
 pub struct Router {
     pub name: String,
     pub utterances: Vec<String>,
-    pub embedding: Option<Vec<Vec<f64>>>,
+    pub embedding: Option<Vec<Vec<f32>>>,
     pub similarity: Option<f64>,
     pub tool_description: Option<String>,
 }
@@ -19,7 +19,7 @@ impl Router {
         }
     }
 
-    pub fn with_embedding(mut self, embedding: Vec<Vec<f64>>) -> Self {
+    pub fn with_embedding(mut self, embedding: Vec<Vec<f32>>) -> Self {
         self.embedding = Some(embedding);
         self
     }
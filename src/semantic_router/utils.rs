@@ -1,10 +1,10 @@
-pub fn combine_embeddings(embeddings: &[Vec<f64>]) -> Vec<f64> {
+pub fn combine_embeddings(embeddings: &[Vec<f32>]) -> Vec<f32> {
     embeddings
         .iter()
         // Initialize a vector with zeros based on the length of the first embedding vector.
         // It's assumed all embeddings have the same dimensions.
         .fold(
-            vec![0f64; embeddings[0].len()],
+            vec![0f32; embeddings[0].len()],
             |mut accumulator, embedding_vec| {
                 for (i, &value) in embedding_vec.iter().enumerate() {
                     accumulator[i] += value;
@@ -14,18 +14,23 @@ pub fn combine_embeddings(embeddings: &[Vec<f64>]) -> Vec<f64> {
         )
         // Calculate the mean for each element across all embeddings.
         .iter()
-        .map(|&sum| sum / embeddings.len() as f64)
+        .map(|&sum| sum / embeddings.len() as f32)
         .collect()
 }
 
-pub fn cosine_similarity(vec1: &[f64], vec2: &[f64]) -> f64 {
-    let dot_product: f64 = vec1.iter().zip(vec2.iter()).map(|(a, b)| a * b).sum();
-    let magnitude_vec1: f64 = vec1.iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
-    let magnitude_vec2: f64 = vec2.iter().map(|x| x.powi(2)).sum::<f64>().sqrt();
+/// Accumulates in f64, so long f32 vectors don't lose precision in the sums.
+pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f64 {
+    let dot_product: f64 = vec1
+        .iter()
+        .zip(vec2.iter())
+        .map(|(&a, &b)| a as f64 * b as f64)
+        .sum();
+    let magnitude_vec1: f64 = vec1.iter().map(|&x| (x as f64).powi(2)).sum::<f64>().sqrt();
+    let magnitude_vec2: f64 = vec2.iter().map(|&x| (x as f64).powi(2)).sum::<f64>().sqrt();
     dot_product / (magnitude_vec1 * magnitude_vec2)
 }
 
-pub fn sum_vectors(vectors: &[Vec<f64>]) -> Vec<f64> {
+pub fn sum_vectors(vectors: &[Vec<f32>]) -> Vec<f32> {
     let mut sum_vec = vec![0.0; vectors[0].len()];
     for vec in vectors {
         for (i, &value) in vec.iter().enumerate() {
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::Value;
+
+use super::{TextChunkStream, TextSplitter, TextSplitterError};
+
+/// What [`CappedTextSplitter`] does with the chunks past its cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkOverflow {
+    /// Drops them.
+    #[default]
+    Truncate,
+    /// Appends them to the last kept chunk, separated by newlines, so no text is lost.
+    Merge,
+}
+
+/// Wraps a [`TextSplitter`] so that a single text yields at most `max_chunks` chunks, e.g.
+/// to keep a pathological document from being ingested as thousands of chunks.
+///
+/// # Usage
+/// ```rust,ignore
+/// let splitter = CappedTextSplitter::new(TokenSplitter::default(), 100)
+///     .with_overflow(ChunkOverflow::Merge);
+/// let docs = loader.load_and_split(splitter).await?;
+/// ```
+pub struct CappedTextSplitter<T: TextSplitter> {
+    inner: T,
+    max_chunks: usize,
+    overflow: ChunkOverflow,
+}
+
+impl<T: TextSplitter> CappedTextSplitter<T> {
+    pub fn new(inner: T, max_chunks: usize) -> Self {
+        Self {
+            inner,
+            max_chunks: max_chunks.max(1),
+            overflow: ChunkOverflow::default(),
+        }
+    }
+
+    pub fn with_overflow(mut self, overflow: ChunkOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Applies the cap to `chunks`, whose text `text` points to. Merged chunks keep the
+    /// metadata of the last kept chunk.
+    fn cap<C>(&self, mut chunks: Vec<C>, text: impl Fn(&mut C) -> &mut String) -> Vec<C> {
+        if chunks.len() <= self.max_chunks {
+            return chunks;
+        }
+        let overflow = chunks.split_off(self.max_chunks);
+        if self.overflow == ChunkOverflow::Merge {
+            let last = text(chunks.last_mut().unwrap());
+            for mut chunk in overflow {
+                last.push('\n');
+                last.push_str(text(&mut chunk));
+            }
+        }
+        chunks
+    }
+}
+
+#[async_trait]
+impl<T: TextSplitter> TextSplitter for CappedTextSplitter<T> {
+    async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+        let chunks = self.inner.split_text(text).await?;
+        Ok(self.cap(chunks, |chunk| chunk))
+    }
+
+    async fn split_text_with_metadata(
+        &self,
+        text: &str,
+    ) -> Result<Vec<(String, HashMap<String, Value>)>, TextSplitterError> {
+        let chunks = self.inner.split_text_with_metadata(text).await?;
+        Ok(self.cap(chunks, |(chunk, _)| chunk))
+    }
+
+    fn split_text_stream<'a>(&'a self, text: &'a str) -> TextChunkStream<'a> {
+        match self.overflow {
+            // Stop pulling from the inner splitter once the cap is reached.
+            ChunkOverflow::Truncate => {
+                Box::pin(self.inner.split_text_stream(text).take(self.max_chunks))
+            }
+            ChunkOverflow::Merge => Box::pin(stream! {
+                match self.split_text(text).await {
+                    Ok(chunks) => {
+                        for chunk in chunks {
+                            yield Ok(chunk);
+                        }
+                    }
+                    Err(e) => yield Err(e),
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WordSplitter;
+
+    #[async_trait]
+    impl TextSplitter for WordSplitter {
+        async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+            Ok(text.split_whitespace().map(String::from).collect())
+        }
+
+        async fn split_text_with_metadata(
+            &self,
+            text: &str,
+        ) -> Result<Vec<(String, HashMap<String, Value>)>, TextSplitterError> {
+            Ok(text
+                .split_whitespace()
+                .enumerate()
+                .map(|(i, word)| {
+                    (
+                        word.to_string(),
+                        HashMap::from([("word".to_string(), Value::from(i))]),
+                    )
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capped_splitter() {
+        let truncating = CappedTextSplitter::new(WordSplitter, 2);
+        assert_eq!(
+            truncating.split_text("a b c d").await.unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(truncating.split_text("a b").await.unwrap(), vec!["a", "b"]);
+        let streamed: Vec<String> = truncating
+            .split_text_stream("a b c d")
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(streamed, vec!["a", "b"]);
+
+        let merging = CappedTextSplitter::new(WordSplitter, 2).with_overflow(ChunkOverflow::Merge);
+        assert_eq!(
+            merging.split_text("a b c d").await.unwrap(),
+            vec!["a", "b\nc\nd"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_capped_splitter_keeps_chunk_metadata() {
+        let merging = CappedTextSplitter::new(WordSplitter, 2).with_overflow(ChunkOverflow::Merge);
+        let chunks = merging.split_text_with_metadata("a b c d").await.unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].0, "b\nc\nd");
+        assert_eq!(chunks[1].1["word"], 1);
+
+        let docs = CappedTextSplitter::new(WordSplitter, 2)
+            .create_documents(&["a b c d".to_string()], &[])
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[1].metadata["word"], 1);
+        assert_eq!(docs[1].metadata["total_chunks"], 2);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{RecursiveCharacterTextSplitter, SplitterOptions, TextSplitter, TextSplitterError};
+
+/// Where [`MarkdownTextSplitter`] puts the trail of headings a chunk sits under, e.g.
+/// `"Install > From source"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeadingTrail {
+    /// Drops it.
+    None,
+    /// Prepends it to the chunk text, followed by a blank line.
+    #[default]
+    Text,
+    /// Stores it in the `headings` metadata of the chunk's document.
+    Metadata,
+}
+
+/// Splits Markdown into its sections, so that no chunk spans two headings, then packs each
+/// section's paragraphs and fenced code blocks into chunks of at most `chunk_size`
+/// characters. Code blocks are never cut, even when they are bigger than `chunk_size`;
+/// larger paragraphs are split with a [`RecursiveCharacterTextSplitter`]. Heading lines
+/// themselves are replaced by the [`HeadingTrail`].
+///
+/// Unlike [`MarkdownSplitter`](super::MarkdownSplitter), which packs as much Markdown as fits
+/// across section boundaries, this follows the document structure.
+///
+/// # Usage
+/// ```rust,ignore
+/// let splitter = MarkdownTextSplitter::new(SplitterOptions::new().with_chunk_size(1000))
+///     .with_heading_trail(HeadingTrail::Metadata);
+/// let docs = splitter.split_documents(&docs).await?;
+/// ```
+pub struct MarkdownTextSplitter {
+    splitter_options: SplitterOptions,
+    heading_trail: HeadingTrail,
+}
+
+impl Default for MarkdownTextSplitter {
+    fn default() -> Self {
+        MarkdownTextSplitter::new(SplitterOptions::default())
+    }
+}
+
+impl MarkdownTextSplitter {
+    pub fn new(options: SplitterOptions) -> MarkdownTextSplitter {
+        MarkdownTextSplitter {
+            splitter_options: options,
+            heading_trail: HeadingTrail::default(),
+        }
+    }
+
+    pub fn with_heading_trail(mut self, heading_trail: HeadingTrail) -> Self {
+        self.heading_trail = heading_trail;
+        self
+    }
+
+    // The chunks of every section, with the section's heading trail.
+    async fn split_sections(&self, text: &str) -> Result<Vec<(String, String)>, TextSplitterError> {
+        let chunk_size = self.splitter_options.chunk_size.max().max(1);
+        let paragraph_splitter = RecursiveCharacterTextSplitter::new(self.splitter_options.clone());
+
+        let mut chunks = Vec::new();
+        for section in parse_sections(text) {
+            let trail = section.trail.join(" > ");
+            let mut current = String::new();
+            for block in section.blocks {
+                let pieces = if !block.code && block.text.chars().count() > chunk_size {
+                    paragraph_splitter.split_text(&block.text).await?
+                } else {
+                    vec![block.text]
+                };
+                for piece in pieces {
+                    if !current.is_empty()
+                        && current.chars().count() + 2 + piece.chars().count() > chunk_size
+                    {
+                        chunks.push((trail.clone(), std::mem::take(&mut current)));
+                    }
+                    if !current.is_empty() {
+                        current.push_str("\n\n");
+                    }
+                    current.push_str(&piece);
+                }
+            }
+            if !current.is_empty() {
+                chunks.push((trail, current));
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+struct Block {
+    text: String,
+    code: bool,
+}
+
+struct Section {
+    trail: Vec<String>,
+    blocks: Vec<Block>,
+}
+
+fn parse_sections(text: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut trail: Vec<(usize, String)> = Vec::new();
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    let mut fence: Option<String> = None;
+    let mut code = String::new();
+
+    let flush_paragraph = |paragraph: &mut String, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block {
+                text: std::mem::take(paragraph).trim_end().to_string(),
+                code: false,
+            });
+        }
+    };
+    let flush_section =
+        |trail: &[(usize, String)], blocks: &mut Vec<Block>, sections: &mut Vec<Section>| {
+            if !blocks.is_empty() {
+                sections.push(Section {
+                    trail: trail.iter().map(|(_, title)| title.clone()).collect(),
+                    blocks: std::mem::take(blocks),
+                });
+            }
+        };
+
+    for line in text.lines() {
+        if let Some(marker) = &fence {
+            code.push_str(line);
+            code.push('\n');
+            if is_closing_fence(line.trim(), marker) {
+                blocks.push(Block {
+                    text: std::mem::take(&mut code).trim_end().to_string(),
+                    code: true,
+                });
+                fence = None;
+            }
+            continue;
+        }
+
+        if let Some(marker) = opening_fence(line.trim_start()) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            fence = Some(marker);
+            code.push_str(line);
+            code.push('\n');
+        } else if let Some((level, title)) = heading(line) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            flush_section(&trail, &mut blocks, &mut sections);
+            trail.retain(|(l, _)| *l < level);
+            trail.push((level, title));
+        } else if line.trim().is_empty() {
+            flush_paragraph(&mut paragraph, &mut blocks);
+        } else {
+            paragraph.push_str(line);
+            paragraph.push('\n');
+        }
+    }
+
+    // An unterminated fence runs to the end of the document.
+    if !code.is_empty() {
+        blocks.push(Block {
+            text: code.trim_end().to_string(),
+            code: true,
+        });
+    }
+    flush_paragraph(&mut paragraph, &mut blocks);
+    flush_section(&trail, &mut blocks, &mut sections);
+    sections
+}
+
+// The ``` or ~~~ run opening a fenced code block.
+fn opening_fence(line: &str) -> Option<String> {
+    let marker_char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let marker: String = line.chars().take_while(|c| *c == marker_char).collect();
+    (marker.len() >= 3).then_some(marker)
+}
+
+fn is_closing_fence(line: &str, marker: &str) -> bool {
+    let marker_char = marker.chars().next().unwrap();
+    line.starts_with(marker) && line.chars().all(|c| c == marker_char)
+}
+
+// The level and title of an ATX heading such as `## Install ##`.
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some((
+        level,
+        rest.trim().trim_end_matches('#').trim_end().to_string(),
+    ))
+}
+
+#[async_trait]
+impl TextSplitter for MarkdownTextSplitter {
+    async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+        Ok(self
+            .split_sections(text)
+            .await?
+            .into_iter()
+            .map(|(trail, chunk)| match self.heading_trail {
+                HeadingTrail::Text if !trail.is_empty() => format!("{}\n\n{}", trail, chunk),
+                _ => chunk,
+            })
+            .collect())
+    }
+
+    async fn split_text_with_metadata(
+        &self,
+        text: &str,
+    ) -> Result<Vec<(String, HashMap<String, Value>)>, TextSplitterError> {
+        if self.heading_trail != HeadingTrail::Metadata {
+            return Ok(self
+                .split_text(text)
+                .await?
+                .into_iter()
+                .map(|chunk| (chunk, HashMap::new()))
+                .collect());
+        }
+        Ok(self
+            .split_sections(text)
+            .await?
+            .into_iter()
+            .map(|(trail, chunk)| {
+                let mut metadata = HashMap::new();
+                if !trail.is_empty() {
+                    metadata.insert("headings".to_string(), Value::from(trail));
+                }
+                (chunk, metadata)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::Document;
+
+    const MARKDOWN: &str = "# Guide
+Intro text.
+
+## Install
+Run the installer.
+
+### From source
+Clone it first.
+
+```sh
+cargo build --release
+cargo install --path .
+```
+
+## Usage
+Call it.
+";
+
+    #[tokio::test]
+    async fn test_markdown_text_splitter_nested_headings() {
+        let splitter = MarkdownTextSplitter::new(SplitterOptions::new().with_chunk_size(30));
+
+        let chunks = splitter.split_text(MARKDOWN).await.unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                "Guide\n\nIntro text.",
+                "Guide > Install\n\nRun the installer.",
+                "Guide > Install > From source\n\nClone it first.",
+                // Bigger than chunk_size, but a code block is never cut.
+                "Guide > Install > From source\n\n\
+                 ```sh\ncargo build --release\ncargo install --path .\n```",
+                "Guide > Usage\n\nCall it.",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_markdown_text_splitter_heading_metadata() {
+        let splitter = MarkdownTextSplitter::new(SplitterOptions::new().with_chunk_size(1000))
+            .with_heading_trail(HeadingTrail::Metadata);
+
+        let docs = splitter
+            .split_documents(&[Document::new(MARKDOWN)])
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 4);
+        assert_eq!(
+            docs[2].page_content,
+            "Clone it first.\n\n```sh\ncargo build --release\ncargo install --path .\n```"
+        );
+        assert_eq!(
+            docs[2].metadata["headings"],
+            "Guide > Install > From source"
+        );
+        assert_eq!(docs[3].metadata["headings"], "Guide > Usage");
+        assert_eq!(docs[3].metadata["total_chunks"], 4);
+    }
+}
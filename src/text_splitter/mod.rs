@@ -1,14 +1,20 @@
+mod capped_splitter;
 mod error;
 mod markdown_splitter;
+mod markdown_text_splitter;
 mod options;
 mod plain_text_splitter;
+mod recursive_character_splitter;
 mod text_splitter;
 mod token_splitter;
 
 pub use ::text_splitter::{ChunkCapacity, ChunkConfig};
+pub use capped_splitter::*;
 pub use error::*;
 pub use markdown_splitter::*;
+pub use markdown_text_splitter::*;
 pub use options::*;
 pub use plain_text_splitter::*;
+pub use recursive_character_splitter::*;
 pub use text_splitter::*;
 pub use token_splitter::*;
@@ -1,8 +1,9 @@
+use async_stream::try_stream;
 use async_trait::async_trait;
 use text_splitter::ChunkConfig;
 use tiktoken_rs::tokenizer::Tokenizer;
 
-use super::{SplitterOptions, TextSplitter, TextSplitterError};
+use super::{SplitterOptions, TextChunkStream, TextSplitter, TextSplitterError};
 
 pub struct PlainTextSplitter {
     splitter_options: SplitterOptions,
@@ -43,4 +44,14 @@ impl TextSplitter for PlainTextSplitter {
             .map(|x| x.to_string())
             .collect())
     }
+
+    fn split_text_stream<'a>(&'a self, text: &'a str) -> TextChunkStream<'a> {
+        Box::pin(try_stream! {
+            let chunk_config = ChunkConfig::try_from(&self.splitter_options)?;
+            let splitter = text_splitter::TextSplitter::new(chunk_config);
+            for chunk in splitter.chunks(text) {
+                yield chunk.to_string();
+            }
+        })
+    }
 }
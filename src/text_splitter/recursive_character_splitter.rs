@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+use async_trait::async_trait;
+
+use super::{SplitterOptions, TextSplitter, TextSplitterError};
+
+/// Splits text the way LangChain's `RecursiveCharacterTextSplitter` does: on the first
+/// separator of a prioritized list that occurs in the text, merging the pieces back up to
+/// the chunk size and recursing with the next separators into pieces that are still too
+/// big. With the default separators paragraphs stay whole when they fit, then lines, then
+/// words.
+///
+/// Sizes are counted in characters against the maximum of `chunk_size`, and no chunk
+/// exceeds it: a piece that no separator can split is cut on character boundaries.
+///
+/// # Usage
+/// ```rust,ignore
+/// let splitter = RecursiveCharacterTextSplitter::new(
+///     SplitterOptions::new().with_chunk_size(1000).with_chunk_overlap(100),
+/// );
+/// let docs = loader.load_and_split(splitter).await?;
+/// ```
+pub struct RecursiveCharacterTextSplitter {
+    splitter_options: SplitterOptions,
+    separators: Vec<String>,
+    keep_separator: bool,
+}
+
+impl Default for RecursiveCharacterTextSplitter {
+    fn default() -> Self {
+        RecursiveCharacterTextSplitter::new(SplitterOptions::default())
+    }
+}
+
+impl RecursiveCharacterTextSplitter {
+    pub fn new(options: SplitterOptions) -> RecursiveCharacterTextSplitter {
+        RecursiveCharacterTextSplitter {
+            splitter_options: options,
+            separators: ["\n\n", "\n", " ", ""].map(String::from).to_vec(),
+            keep_separator: true,
+        }
+    }
+
+    /// Sets the separators to try, in order. An empty separator splits between characters.
+    pub fn with_separators<S: Into<String>>(
+        mut self,
+        separators: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.separators = separators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether a separator stays in the chunks, at the start of the piece that followed it.
+    /// When false the separators between pieces in the same chunk are re-inserted, and the
+    /// ones at chunk boundaries are dropped. Defaults to true.
+    pub fn with_keep_separator(mut self, keep_separator: bool) -> Self {
+        self.keep_separator = keep_separator;
+        self
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.splitter_options.chunk_size.max().max(1)
+    }
+
+    fn split_recursive(&self, text: &str, separators: &[String], chunks: &mut Vec<String>) {
+        let chunk_size = self.chunk_size();
+        let Some(position) = separators
+            .iter()
+            .position(|s| s.is_empty() || text.contains(s.as_str()))
+        else {
+            // No separator left to split on, so cut on character boundaries.
+            let chars: Vec<char> = text.chars().collect();
+            let pieces: Vec<String> = chars
+                .chunks(chunk_size)
+                .map(|c| c.iter().collect())
+                .collect();
+            chunks.extend(self.merge_splits(&pieces, ""));
+            return;
+        };
+        let separator = separators[position].as_str();
+        let remaining = &separators[position + 1..];
+
+        let mut small = Vec::new();
+        for piece in self.split_on(text, separator) {
+            if piece.chars().count() <= chunk_size {
+                small.push(piece);
+                continue;
+            }
+            chunks.extend(self.merge_splits(&small, separator));
+            small.clear();
+            self.split_recursive(&piece, remaining, chunks);
+        }
+        chunks.extend(self.merge_splits(&small, separator));
+    }
+
+    fn split_on(&self, text: &str, separator: &str) -> Vec<String> {
+        if separator.is_empty() {
+            return text.chars().map(String::from).collect();
+        }
+        if !self.keep_separator {
+            return text
+                .split(separator)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for (index, _) in text.match_indices(separator) {
+            if index > start {
+                pieces.push(text[start..index].to_string());
+            }
+            start = index;
+        }
+        pieces.push(text[start..].to_string());
+        pieces
+    }
+
+    // Joins consecutive pieces into chunks of at most `chunk_size` characters, starting each
+    // chunk with up to `chunk_overlap` characters of trailing pieces from the previous one.
+    fn merge_splits(&self, pieces: &[String], separator: &str) -> Vec<String> {
+        let chunk_size = self.chunk_size();
+        let overlap = self.splitter_options.chunk_overlap;
+        let joiner = if self.keep_separator { "" } else { separator };
+        let joiner_len = joiner.chars().count();
+
+        let mut chunks = Vec::new();
+        let mut current: VecDeque<(&str, usize)> = VecDeque::new();
+        let mut total = 0;
+        for piece in pieces {
+            let len = piece.chars().count();
+            let added =
+                |current: &VecDeque<_>| len + if current.is_empty() { 0 } else { joiner_len };
+            if !current.is_empty() && total + added(&current) > chunk_size {
+                self.push_chunk(&current, joiner, &mut chunks);
+                while !current.is_empty()
+                    && (total > overlap || total + added(&current) > chunk_size)
+                {
+                    let (_, first_len) = current.pop_front().unwrap();
+                    total -= first_len + if current.is_empty() { 0 } else { joiner_len };
+                }
+            }
+            total += added(&current);
+            current.push_back((piece.as_str(), len));
+        }
+        self.push_chunk(&current, joiner, &mut chunks);
+        chunks
+    }
+
+    fn push_chunk(&self, pieces: &VecDeque<(&str, usize)>, joiner: &str, chunks: &mut Vec<String>) {
+        let chunk = pieces
+            .iter()
+            .map(|(piece, _)| *piece)
+            .collect::<Vec<_>>()
+            .join(joiner);
+        let chunk = if self.splitter_options.trim_chunks {
+            chunk.trim().to_string()
+        } else {
+            chunk
+        };
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+    }
+}
+
+#[async_trait]
+impl TextSplitter for RecursiveCharacterTextSplitter {
+    async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+        if self.splitter_options.chunk_overlap >= self.chunk_size() {
+            return Err(TextSplitterError::InvalidSplitterOptions);
+        }
+        let mut chunks = Vec::new();
+        self.split_recursive(text, &self.separators, &mut chunks);
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recursive_splitter_keeps_paragraphs() {
+        let text = "First paragraph here.\n\nSecond one.\n\nA third paragraph that is \
+                    much too long to fit.";
+        let splitter = RecursiveCharacterTextSplitter::new(
+            SplitterOptions::new()
+                .with_chunk_size(35)
+                .with_trim_chunks(true),
+        );
+
+        let chunks = splitter.split_text(text).await.unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                "First paragraph here.\n\nSecond one.",
+                "A third paragraph that is much too",
+                "long to fit.",
+            ]
+        );
+        assert!(chunks.iter().all(|c| c.chars().count() <= 35));
+    }
+
+    #[tokio::test]
+    async fn test_recursive_splitter_overlap_and_limits() {
+        let splitter = RecursiveCharacterTextSplitter::new(
+            SplitterOptions::new()
+                .with_chunk_size(10)
+                .with_chunk_overlap(4)
+                .with_trim_chunks(true),
+        )
+        .with_separators([" "]);
+
+        let chunks = splitter
+            .split_text("aa bb cc dd supercalifragilistic")
+            .await
+            .unwrap();
+        assert_eq!(
+            chunks,
+            vec!["aa bb cc", "cc dd", "supercali", "fragilisti", "c"]
+        );
+
+        let dropped =
+            RecursiveCharacterTextSplitter::new(SplitterOptions::new().with_chunk_size(5))
+                .with_separators([","])
+                .with_keep_separator(false);
+        assert_eq!(
+            dropped.split_text("a,b,c,d").await.unwrap(),
+            vec!["a,b,c", "d"]
+        );
+
+        let invalid = RecursiveCharacterTextSplitter::new(
+            SplitterOptions::new()
+                .with_chunk_size(4)
+                .with_chunk_overlap(4),
+        );
+        assert!(invalid.split_text("text").await.is_err());
+    }
+}
@@ -1,16 +1,52 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, pin::Pin};
 
+use async_stream::stream;
 use async_trait::async_trait;
+use futures::Stream;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 use crate::schemas::Document;
 
 use super::TextSplitterError;
 
+pub type TextChunkStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<String, TextSplitterError>> + Send + 'a>>;
+
 #[async_trait]
 pub trait TextSplitter: Send + Sync {
     async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError>;
 
+    /// Like `split_text`, but yields chunks one at a time instead of collecting them, to keep
+    /// memory bounded on very large inputs. The default implementation calls `split_text`;
+    /// splitters backed by the `text-splitter` crate yield chunks as it produces them.
+    fn split_text_stream<'a>(&'a self, text: &'a str) -> TextChunkStream<'a> {
+        Box::pin(stream! {
+            match self.split_text(text).await {
+                Ok(chunks) => {
+                    for chunk in chunks {
+                        yield Ok(chunk);
+                    }
+                }
+                Err(e) => yield Err(e),
+            }
+        })
+    }
+
+    /// Like `split_text`, with metadata for each chunk that `create_documents` adds to the
+    /// chunk's document, e.g. the section a chunk comes from. None by default.
+    async fn split_text_with_metadata(
+        &self,
+        text: &str,
+    ) -> Result<Vec<(String, HashMap<String, Value>)>, TextSplitterError> {
+        Ok(self
+            .split_text(text)
+            .await?
+            .into_iter()
+            .map(|chunk| (chunk, HashMap::new()))
+            .collect())
+    }
+
     async fn split_documents(
         &self,
         documents: &[Document],
@@ -41,9 +77,19 @@ pub trait TextSplitter: Send + Sync {
 
         let mut documents: Vec<Document> = Vec::new();
         for i in 0..text.len() {
-            let chunks = self.split_text(&text[i]).await?;
-            for chunk in chunks {
-                let document = Document::new(chunk).with_metadata(metadatas[i].clone());
+            let chunks = self.split_text_with_metadata(&text[i]).await?;
+            let total_chunks = chunks.len();
+            let source_id = chunk_source_id(&text[i], &metadatas[i]);
+            for (chunk_index, (chunk, chunk_metadata)) in chunks.into_iter().enumerate() {
+                let mut metadata = metadatas[i].clone();
+                metadata.extend(chunk_metadata);
+                metadata.insert("chunk_index".into(), Value::from(chunk_index));
+                metadata.insert("total_chunks".into(), Value::from(total_chunks));
+                metadata.insert(
+                    "chunk_id".into(),
+                    Value::from(chunk_id(&source_id, chunk_index, &chunk)),
+                );
+                let document = Document::new(chunk).with_metadata(metadata);
                 documents.push(document);
             }
         }
@@ -51,3 +97,78 @@ pub trait TextSplitter: Send + Sync {
         Ok(documents)
     }
 }
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+        // Separator so that ("ab", "c") and ("a", "bc") hash differently.
+        hasher.update([0u8]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// The source document's `source` metadata when set, otherwise a hash of its full text.
+fn chunk_source_id(text: &str, metadata: &HashMap<String, Value>) -> String {
+    match metadata.get("source") {
+        Some(Value::String(source)) => source.clone(),
+        Some(source) => source.to_string(),
+        None => sha256_hex(&[text.as_bytes()]),
+    }
+}
+
+/// Deterministic id for a chunk, so re-splitting the same input yields the same ids.
+pub fn chunk_id(source_id: &str, chunk_index: usize, content: &str) -> String {
+    sha256_hex(&[
+        source_id.as_bytes(),
+        chunk_index.to_string().as_bytes(),
+        content.as_bytes(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WordSplitter;
+
+    #[async_trait]
+    impl TextSplitter for WordSplitter {
+        async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+            Ok(text.split_whitespace().map(String::from).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_documents_adds_chunk_metadata() {
+        let doc = Document::new("a b a").with_metadata(HashMap::from([(
+            "source".to_string(),
+            Value::from("file.txt"),
+        )]));
+
+        let chunks = WordSplitter.split_documents(&[doc.clone()]).await.unwrap();
+        assert_eq!(chunks.len(), 3);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.metadata["source"], "file.txt");
+            assert_eq!(chunk.metadata["chunk_index"], i);
+            assert_eq!(chunk.metadata["total_chunks"], 3);
+        }
+        // Same content at a different position gets a different id.
+        assert_ne!(
+            chunks[0].metadata["chunk_id"],
+            chunks[2].metadata["chunk_id"]
+        );
+
+        let again = WordSplitter.split_documents(&[doc]).await.unwrap();
+        let ids = |docs: &[Document]| -> Vec<Value> {
+            docs.iter()
+                .map(|d| d.metadata["chunk_id"].clone())
+                .collect()
+        };
+        assert_eq!(ids(&chunks), ids(&again));
+    }
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+
+use tokio::sync::Mutex;
+
+use crate::schemas::Document;
+
+use super::{VecStoreOptions, VectorStore};
+
+struct BatchState {
+    buffer: Vec<Document>,
+    ids: Vec<String>,
+}
+
+/// Buffers documents and writes them to a `VectorStore` in batches of `batch_size`.
+///
+/// Rust has no async `Drop`, so a partially-filled batch is never written implicitly.
+/// Callers must call [`BatchWriter::finish`] (or [`BatchWriter::flush`]) once they are done
+/// writing; dropping a writer that still holds buffered documents logs a warning and the
+/// documents are lost.
+///
+/// # Usage
+/// ```rust,ignore
+/// let writer = BatchWriter::new(store, 256);
+/// for doc in docs {
+///     writer.write(doc).await?;
+/// }
+/// let ids = writer.finish().await?;
+/// ```
+pub struct BatchWriter {
+    store: Box<dyn VectorStore>,
+    options: VecStoreOptions,
+    batch_size: usize,
+    state: Mutex<BatchState>,
+}
+
+impl BatchWriter {
+    pub fn new<V: Into<Box<dyn VectorStore>>>(store: V, batch_size: usize) -> Self {
+        Self {
+            store: store.into(),
+            options: VecStoreOptions::default(),
+            batch_size: batch_size.max(1),
+            state: Mutex::new(BatchState {
+                buffer: Vec::new(),
+                ids: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn with_options(mut self, options: VecStoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Buffers a document, writing the batch once it reaches `batch_size`.
+    pub async fn write(&self, doc: Document) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().await;
+        state.buffer.push(doc);
+        if state.buffer.len() >= self.batch_size {
+            self.write_buffer(&mut state).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered documents. On error the buffer is kept, so the flush can be retried.
+    pub async fn flush(&self) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().await;
+        self.write_buffer(&mut state).await
+    }
+
+    /// Flushes the remaining documents and returns the ids of every document written.
+    pub async fn finish(self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut state = self.state.lock().await;
+        self.write_buffer(&mut state).await?;
+        Ok(std::mem::take(&mut state.ids))
+    }
+
+    async fn write_buffer(&self, state: &mut BatchState) -> Result<(), Box<dyn Error>> {
+        if state.buffer.is_empty() {
+            return Ok(());
+        }
+        let ids = self
+            .store
+            .add_documents(&state.buffer, &self.options)
+            .await?;
+        state.buffer.clear();
+        state.ids.extend(ids);
+        Ok(())
+    }
+}
+
+impl Drop for BatchWriter {
+    fn drop(&mut self) {
+        let pending = self.state.get_mut().buffer.len();
+        if pending > 0 {
+            log::warn!(
+                "BatchWriter dropped with {} unwritten documents; call `finish` or `flush` before dropping it",
+                pending
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    struct RecordingStore {
+        batches: Arc<StdMutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl VectorStore for RecordingStore {
+        async fn add_documents(
+            &self,
+            docs: &[Document],
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            let mut batches = self.batches.lock().unwrap();
+            let offset: usize = batches.iter().sum();
+            batches.push(docs.len());
+            Ok((offset..offset + docs.len())
+                .map(|i| i.to_string())
+                .collect())
+        }
+
+        async fn similarity_search(
+            &self,
+            _query: &str,
+            _limit: usize,
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finish_flushes_partial_batch() {
+        let batches = Arc::new(StdMutex::new(Vec::new()));
+        let writer = BatchWriter::new(
+            RecordingStore {
+                batches: batches.clone(),
+            },
+            2,
+        );
+
+        for i in 0..5 {
+            writer
+                .write(Document::new(format!("doc {}", i)))
+                .await
+                .unwrap();
+        }
+        let ids = writer.finish().await.unwrap();
+
+        assert_eq!(*batches.lock().unwrap(), vec![2, 2, 1]);
+        assert_eq!(ids, vec!["0", "1", "2", "3", "4"]);
+    }
+}
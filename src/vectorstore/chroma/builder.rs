@@ -0,0 +1,91 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::sync::OnceCell;
+
+use super::Store;
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+pub struct StoreBuilder {
+    host: String,
+    port: u16,
+    collection_name: Option<String>,
+    auth_token: Option<String>,
+    distance_function: DistanceMetric,
+    embedder: Option<Arc<dyn Embedder>>,
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            host: "http://localhost".to_string(),
+            port: 8000,
+            collection_name: None,
+            auth_token: None,
+            distance_function: DistanceMetric::Cosine,
+            embedder: None,
+        }
+    }
+
+    /// Host of the Chroma server, with its scheme. Defaults to `http://localhost`.
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Defaults to 8000.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Name of the collection. REQUIRED. It is created on the first `add_documents` if it
+    /// doesn't exist; searching a missing collection finds nothing.
+    pub fn collection_name(mut self, collection_name: &str) -> Self {
+        self.collection_name = Some(collection_name.to_string());
+        self
+    }
+
+    /// Token sent as `Authorization: Bearer <token>`, for servers with token authentication.
+    pub fn auth_token<S: Into<String>>(mut self, auth_token: S) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    /// Distance function (`hnsw:space`) of the collection when the store creates it, which
+    /// also decides how distances are turned into scores. Defaults to
+    /// [`DistanceMetric::Cosine`]. An existing collection keeps the function it was created
+    /// with.
+    pub fn distance_function(mut self, distance_function: DistanceMetric) -> Self {
+        self.distance_function = distance_function;
+        self
+    }
+
+    /// Embeddings provider for the Store. REQUIRED.
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("'embedder' is required")?;
+        let collection_name = self
+            .collection_name
+            .ok_or("'collection_name' is required")?;
+
+        Ok(Store {
+            client: reqwest::Client::new(),
+            base_url: format!("{}:{}/api/v1", self.host.trim_end_matches('/'), self.port),
+            collection_name,
+            collection_id: OnceCell::new(),
+            auth_token: self.auth_token,
+            distance_function: self.distance_function,
+            embedder,
+        })
+    }
+}
@@ -0,0 +1,447 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        Condition, DistanceMetric, FilterOp, MetadataFilter, VecStoreOptions, VectorStore,
+    },
+};
+
+/// A vector store backed by a collection of a [Chroma](https://www.trychroma.com) server,
+/// through its v1 REST API.
+pub struct Store {
+    pub(crate) client: reqwest::Client,
+    pub(crate) base_url: String,
+    pub(crate) collection_name: String,
+    /// Id of the collection, looked up on first use.
+    pub(crate) collection_id: OnceCell<String>,
+    pub(crate) auth_token: Option<String>,
+    pub(crate) distance_function: DistanceMetric,
+    pub(crate) embedder: Arc<dyn Embedder>,
+}
+
+impl Store {
+    /// Deletes the documents with the given ids. Ids that don't exist are ignored.
+    pub async fn delete_documents_by_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        let Some(collection_id) = self.collection_id(false).await? else {
+            return Ok(());
+        };
+        self.send(
+            Method::POST,
+            &format!("/collections/{}/delete", collection_id),
+            Some(json!({ "ids": ids })),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Id of the collection, which is created when `create` is set and it doesn't exist.
+    /// `None` if it doesn't exist and isn't created.
+    async fn collection_id(&self, create: bool) -> Result<Option<String>, Box<dyn Error>> {
+        if let Some(id) = self.collection_id.get() {
+            return Ok(Some(id.clone()));
+        }
+
+        let collection = if create {
+            let space = match self.distance_function {
+                DistanceMetric::Cosine => "cosine",
+                DistanceMetric::L2 => "l2",
+                DistanceMetric::InnerProduct => "ip",
+            };
+            self.send(
+                Method::POST,
+                "/collections",
+                Some(json!({
+                    "name": self.collection_name,
+                    "metadata": { "hnsw:space": space },
+                    "get_or_create": true,
+                })),
+            )
+            .await?
+        } else {
+            let response = self
+                .request(
+                    Method::GET,
+                    &format!("/collections/{}", self.collection_name),
+                    None,
+                )
+                .send()
+                .await?;
+            let status = response.status();
+            let body = response.text().await?;
+            if status == StatusCode::NOT_FOUND || body.contains("does not exist") {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                return Err(format!("Chroma request failed with {}: {}", status, body).into());
+            }
+            serde_json::from_str(&body)?
+        };
+
+        let id = collection["id"]
+            .as_str()
+            .ok_or("Chroma returned a collection without an id")?
+            .to_string();
+        // A concurrent call may have set it first, to the same id.
+        let _ = self.collection_id.set(id.clone());
+        Ok(Some(id))
+    }
+
+    fn request(&self, method: Method, path: &str, body: Option<Value>) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        request
+    }
+
+    /// Sends a request and returns its JSON response, or an error with the response body if
+    /// Chroma didn't succeed.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value, Box<dyn Error>> {
+        let response = self.request(method, path, body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Chroma request failed with {}: {}", status, body).into());
+        }
+        Ok(response.json().await?)
+    }
+
+    fn score(&self, distance: f64) -> f64 {
+        match self.distance_function {
+            // Chroma's l2 is the squared euclidean distance.
+            DistanceMetric::L2 => 1.0 / (1.0 + distance),
+            // cosine is `1 - cosine similarity` and ip is `1 - inner product`.
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+        }
+    }
+}
+
+/// Translates `VecStoreOptions::filters` to a Chroma `where` clause, e.g.
+/// `{"year": {"$gte": 2023}, "lang": "rust"}` to
+/// `{"$and": [{"year": {"$gte": 2023}}, {"lang": {"$eq": "rust"}}]}`. `None` for an empty
+/// filter. `$ilike` has no Chroma equivalent and errors.
+pub(crate) fn chroma_where(filters: &Value) -> Result<Option<Value>, Box<dyn Error>> {
+    let filter = MetadataFilter::parse(filters)?;
+    let mut clauses = filter
+        .conditions
+        .iter()
+        .map(chroma_condition)
+        .collect::<Result<Vec<Value>, _>>()?;
+    Ok(match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(json!({ "$and": clauses })),
+    })
+}
+
+fn chroma_condition(condition: &Condition) -> Result<Value, Box<dyn Error>> {
+    let scalar = |value: &Value| match value {
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(value.clone()),
+        _ => Err(format!(
+            "Chroma can only compare {:?} with a string, number or boolean, got {}",
+            condition.key, value
+        )),
+    };
+    let (op, operand) = match &condition.op {
+        FilterOp::Eq(value) => ("$eq", scalar(value)?),
+        FilterOp::Ne(value) => ("$ne", scalar(value)?),
+        FilterOp::Gt(value) => ("$gt", scalar(value)?),
+        FilterOp::Gte(value) => ("$gte", scalar(value)?),
+        FilterOp::Lt(value) => ("$lt", scalar(value)?),
+        FilterOp::Lte(value) => ("$lte", scalar(value)?),
+        FilterOp::In(values) => (
+            "$in",
+            Value::Array(values.iter().map(scalar).collect::<Result<_, _>>()?),
+        ),
+        FilterOp::ILike(_) => {
+            return Err(format!(
+                "$ilike on {:?} is not supported by the Chroma vector store",
+                condition.key
+            )
+            .into())
+        }
+    };
+    Ok(json!({ &condition.key: { op: operand } }))
+}
+
+/// Chroma metadata is a flat map of strings, numbers and booleans, and must not be empty, so
+/// documents without metadata are sent with `null`.
+fn chroma_metadata(metadata: &HashMap<String, Value>) -> Result<Value, Box<dyn Error>> {
+    if metadata.is_empty() {
+        return Ok(Value::Null);
+    }
+    for (key, value) in metadata {
+        if !matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_)) {
+            return Err(format!(
+                "Chroma metadata values must be strings, numbers or booleans, {:?} is {}",
+                key, value
+            )
+            .into());
+        }
+    }
+    Ok(json!(metadata))
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Adds the documents under new UUIDs, creating the collection if it doesn't exist.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let metadatas = docs
+            .iter()
+            .map(|doc| chroma_metadata(&doc.metadata))
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let embeddings = embedder.embed_documents(&texts).await?;
+        if embeddings.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let documents: Vec<&str> = docs.iter().map(|d| d.page_content.as_str()).collect();
+        let collection_id = self
+            .collection_id(true)
+            .await?
+            .ok_or("Chroma collection could not be created")?;
+        self.send(
+            Method::POST,
+            &format!("/collections/{}/add", collection_id),
+            Some(json!({
+                "ids": ids,
+                "embeddings": embeddings,
+                "documents": documents,
+                "metadatas": metadatas,
+            })),
+        )
+        .await?;
+
+        Ok(ids)
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Chroma doesn't support namespaces".into());
+        }
+        if let Some(metric) = opt.distance_metric {
+            if metric != self.distance_function {
+                return Err(format!(
+                    "The Chroma store searches with {:?}, not {:?}",
+                    self.distance_function, metric
+                )
+                .into());
+            }
+        }
+        let where_clause = match &opt.filters {
+            Some(filters) => chroma_where(filters)?,
+            None => None,
+        };
+
+        let Some(collection_id) = self.collection_id(false).await? else {
+            return Ok(Vec::new());
+        };
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let mut body = json!({
+            "query_embeddings": [query_vector],
+            "n_results": limit,
+            "include": ["documents", "metadatas", "distances"],
+        });
+        if let Some(where_clause) = where_clause {
+            body["where"] = where_clause;
+        }
+        let response = self
+            .send(
+                Method::POST,
+                &format!("/collections/{}/query", collection_id),
+                Some(body),
+            )
+            .await?;
+
+        // Results are grouped per query embedding, of which there is one.
+        let first = |field: &str| response[field][0].as_array().cloned().unwrap_or_default();
+        let (contents, metadatas, distances) =
+            (first("documents"), first("metadatas"), first("distances"));
+
+        let mut docs = Vec::with_capacity(distances.len());
+        for (i, distance) in distances.iter().enumerate() {
+            let page_content = contents
+                .get(i)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let metadata = match metadatas.get(i) {
+                Some(Value::Object(map)) => map.clone().into_iter().collect(),
+                _ => HashMap::new(),
+            };
+            let score = self.score(distance.as_f64().unwrap_or(f64::INFINITY));
+            docs.push(Document {
+                page_content,
+                metadata,
+                score,
+            });
+        }
+
+        if let Some(threshold) = opt.score_threshold {
+            docs.retain(|doc| doc.score >= threshold as f64);
+        }
+        Ok(docs)
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let where_clause = match &opt.filters {
+            Some(filters) => chroma_where(filters)?,
+            None => None,
+        };
+        let Some(collection_id) = self.collection_id(false).await? else {
+            return Ok(0);
+        };
+
+        match where_clause {
+            None => {
+                let count = self
+                    .send(
+                        Method::GET,
+                        &format!("/collections/{}/count", collection_id),
+                        None,
+                    )
+                    .await?;
+                Ok(count.as_u64().ok_or("Chroma returned an invalid count")? as usize)
+            }
+            // The count endpoint takes no filter, so count the ids of the matching documents.
+            Some(where_clause) => {
+                let response = self
+                    .send(
+                        Method::POST,
+                        &format!("/collections/{}/get", collection_id),
+                        Some(json!({ "where": where_clause, "include": [] })),
+                    )
+                    .await?;
+                Ok(response["ids"].as_array().map_or(0, Vec::len))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chroma_where() {
+        assert_eq!(
+            chroma_where(&json!({"lang": "rust"})).unwrap(),
+            Some(json!({"lang": {"$eq": "rust"}}))
+        );
+        assert_eq!(
+            chroma_where(&json!({"tags": ["a", "b"]})).unwrap(),
+            Some(json!({"tags": {"$in": ["a", "b"]}}))
+        );
+
+        let clause = chroma_where(&json!({"year": {"$gte": 2023, "$lt": 2025}}))
+            .unwrap()
+            .unwrap();
+        let clauses = clause["$and"].as_array().unwrap();
+        assert_eq!(clauses.len(), 2);
+        assert!(clauses.contains(&json!({"year": {"$gte": 2023}})));
+        assert!(clauses.contains(&json!({"year": {"$lt": 2025}})));
+
+        assert_eq!(chroma_where(&json!({})).unwrap(), None);
+        assert!(chroma_where(&json!({"format": {"$ilike": "pdf"}})).is_err());
+        assert!(chroma_where(&json!({"author": {"name": "x"}})).is_err());
+    }
+
+    #[test]
+    fn test_chroma_metadata() {
+        assert_eq!(chroma_metadata(&HashMap::new()).unwrap(), Value::Null);
+        let metadata = HashMap::from([("year".to_string(), json!(2024))]);
+        assert_eq!(chroma_metadata(&metadata).unwrap(), json!({"year": 2024}));
+        let nested = HashMap::from([("author".to_string(), json!({"name": "x"}))]);
+        assert!(chroma_metadata(&nested).is_err());
+    }
+
+    /// Runs against a Chroma container, so needs Docker.
+    #[tokio::test]
+    #[ignore]
+    async fn test_chroma_store() {
+        use testcontainers::{
+            core::{IntoContainerPort, WaitFor},
+            runners::AsyncRunner,
+            GenericImage,
+        };
+
+        use crate::vectorstore::{chroma::StoreBuilder, test_utils::LetterEmbedder};
+
+        let container = GenericImage::new("chromadb/chroma", "0.5.5")
+            .with_exposed_port(8000.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Application startup complete"))
+            .start()
+            .await
+            .unwrap();
+        let port = container.get_host_port_ipv4(8000).await.unwrap();
+
+        let store = StoreBuilder::new()
+            .port(port)
+            .collection_name("langchain-rust")
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        let opt = VecStoreOptions::default();
+
+        // The collection doesn't exist until the first documents are added.
+        assert!(store
+            .similarity_search("a", 2, &opt)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 0);
+
+        let docs = [("aaa", "x"), ("aab", "y"), ("ccc", "x")].map(|(text, tag)| {
+            Document::new(text).with_metadata(HashMap::from([("tag".to_string(), json!(tag))]))
+        });
+        let ids = store.add_documents(&docs, &opt).await.unwrap();
+
+        let found = store.similarity_search("a", 2, &opt).await.unwrap();
+        assert_eq!(found[0].page_content, "aaa");
+        assert_eq!(found[1].page_content, "aab");
+
+        let tagged = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        let found = store.similarity_search("c", 10, &tagged).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].page_content, "ccc");
+        assert_eq!(store.count_documents(&tagged).await.unwrap(), 2);
+
+        store.delete_documents_by_ids(&ids[..1]).await.unwrap();
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 2);
+    }
+}
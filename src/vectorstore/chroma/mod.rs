@@ -0,0 +1,5 @@
+mod builder;
+mod chroma;
+
+pub use builder::*;
+pub use chroma::*;
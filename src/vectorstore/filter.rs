@@ -0,0 +1,228 @@
+use std::{cmp::Ordering, collections::HashMap, error::Error};
+
+use serde_json::Value;
+
+/// How a metadata value is compared in a [`Condition`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    /// `{"key": value}` or `{"key": {"$eq": value}}`.
+    Eq(Value),
+    /// `{"key": {"$ne": value}}`. Also matches documents without the key.
+    Ne(Value),
+    Gt(Value),
+    Gte(Value),
+    Lt(Value),
+    Lte(Value),
+    /// `{"key": [a, b]}` or `{"key": {"$in": [a, b]}}`.
+    In(Vec<Value>),
+    /// `{"key": {"$ilike": "pdf"}}` (or an array of strings): case-insensitive equality.
+    ILike(Vec<String>),
+}
+
+/// One condition on a metadata key. A dotted key addresses nested metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub key: String,
+    pub op: FilterOp,
+}
+
+impl Condition {
+    /// Whether `metadata` satisfies the condition, with the semantics of the SQLite stores:
+    /// `$gt`, `$gte`, `$lt` and `$lte` only hold between two numbers or two strings.
+    pub fn matches(&self, metadata: &HashMap<String, Value>) -> bool {
+        let actual = nested_value(metadata, &self.key);
+        let ordering = |expected: &Value| actual.and_then(|actual| compare(actual, expected));
+        match &self.op {
+            FilterOp::Eq(expected) => actual == Some(expected),
+            FilterOp::Ne(expected) => actual != Some(expected),
+            FilterOp::Gt(expected) => ordering(expected) == Some(Ordering::Greater),
+            FilterOp::Gte(expected) => ordering(expected).is_some_and(Ordering::is_ge),
+            FilterOp::Lt(expected) => ordering(expected) == Some(Ordering::Less),
+            FilterOp::Lte(expected) => ordering(expected).is_some_and(Ordering::is_le),
+            FilterOp::In(values) => actual.is_some_and(|actual| values.contains(actual)),
+            FilterOp::ILike(values) => actual.is_some_and(|actual| {
+                let actual = match actual {
+                    Value::String(s) => s.to_lowercase(),
+                    other => other.to_string().to_lowercase(),
+                };
+                values.iter().any(|value| value.to_lowercase() == actual)
+            }),
+        }
+    }
+}
+
+/// The value under a possibly dotted `key`: `source.type` looks up `type` in `source`.
+fn nested_value<'a>(metadata: &'a HashMap<String, Value>, key: &str) -> Option<&'a Value> {
+    let mut segments = key.split('.');
+    let first = metadata.get(segments.next()?)?;
+    segments.try_fold(first, |value, segment| value.get(segment))
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// A metadata filter parsed from `VecStoreOptions::filters`: conditions that must all hold.
+///
+/// Each entry of the filter object is either a value, matched by equality (an array matches
+/// any of its elements), or an object of operators such as `{"$gte": 2023, "$lt": 2025}`.
+/// Supported operators are `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in` and `$ilike`.
+///
+/// ```rust,ignore
+/// let filter = MetadataFilter::parse(&json!({"year": {"$gte": 2023}, "lang": {"$ne": "go"}}))?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataFilter {
+    pub conditions: Vec<Condition>,
+}
+
+impl MetadataFilter {
+    /// Parses a filter object. Errors on unknown operators and on operands of the wrong type,
+    /// rather than comparing against the operator object.
+    pub fn parse(filter: &Value) -> Result<Self, Box<dyn Error>> {
+        match filter {
+            Value::Object(map) => Self::from_entries(map),
+            _ => Err("Metadata filter must be a JSON object".into()),
+        }
+    }
+
+    /// Parses `(key, value)` filter entries, e.g. a filter map chained with a base filter.
+    pub fn from_entries<'a, I>(entries: I) -> Result<Self, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = (&'a String, &'a Value)>,
+    {
+        let mut conditions = Vec::new();
+        for (key, value) in entries {
+            match value {
+                Value::Array(values) => conditions.push(Condition {
+                    key: key.clone(),
+                    op: FilterOp::In(values.clone()),
+                }),
+                Value::Object(ops) if ops.keys().any(|op| op.starts_with('$')) => {
+                    for (op, operand) in ops {
+                        conditions.push(Condition {
+                            key: key.clone(),
+                            op: parse_op(key, op, operand)?,
+                        });
+                    }
+                }
+                _ => conditions.push(Condition {
+                    key: key.clone(),
+                    op: FilterOp::Eq(value.clone()),
+                }),
+            }
+        }
+        Ok(Self { conditions })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Whether `metadata` satisfies every condition, for stores that filter in memory. See
+    /// [`Condition::matches`].
+    pub fn matches(&self, metadata: &HashMap<String, Value>) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(metadata))
+    }
+}
+
+fn parse_op(key: &str, op: &str, operand: &Value) -> Result<FilterOp, Box<dyn Error>> {
+    let scalar = || match operand {
+        Value::Array(_) | Value::Object(_) | Value::Null => Err(format!(
+            "Operator {} on {:?} needs a string, number or boolean, got {}",
+            op, key, operand
+        )),
+        _ => Ok(operand.clone()),
+    };
+    Ok(match op {
+        "$eq" => FilterOp::Eq(operand.clone()),
+        "$ne" => FilterOp::Ne(operand.clone()),
+        "$gt" => FilterOp::Gt(scalar()?),
+        "$gte" => FilterOp::Gte(scalar()?),
+        "$lt" => FilterOp::Lt(scalar()?),
+        "$lte" => FilterOp::Lte(scalar()?),
+        "$in" => match operand {
+            Value::Array(values) => FilterOp::In(values.clone()),
+            _ => return Err(format!("Operator $in on {:?} needs an array", key).into()),
+        },
+        "$ilike" => {
+            let operands: Vec<&Value> = match operand {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            FilterOp::ILike(
+                operands
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect(),
+            )
+        }
+        _ => {
+            return Err(format!("Unknown metadata filter operator {} on {:?}", op, key).into());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata_filter() {
+        let filter = MetadataFilter::parse(&json!({
+            "year": {"$gte": 2023, "$lt": 2025},
+            "lang": {"$ne": "go"},
+            "tags": ["a", "b"],
+            "format": {"$ilike": "PDF"},
+            "author": {"name": "x"},
+        }))
+        .unwrap();
+
+        let ops: Vec<(&str, &FilterOp)> = filter
+            .conditions
+            .iter()
+            .map(|c| (c.key.as_str(), &c.op))
+            .collect();
+        assert!(ops.contains(&("year", &FilterOp::Gte(json!(2023)))));
+        assert!(ops.contains(&("year", &FilterOp::Lt(json!(2025)))));
+        assert!(ops.contains(&("lang", &FilterOp::Ne(json!("go")))));
+        assert!(ops.contains(&("tags", &FilterOp::In(vec![json!("a"), json!("b")]))));
+        assert!(ops.contains(&("format", &FilterOp::ILike(vec!["PDF".to_string()]))));
+        assert!(ops.contains(&("author", &FilterOp::Eq(json!({"name": "x"})))));
+
+        assert!(MetadataFilter::parse(&json!({"year": {"$between": [1, 2]}})).is_err());
+        assert!(MetadataFilter::parse(&json!({"year": {"$gt": [1]}})).is_err());
+        assert!(MetadataFilter::parse(&json!({"year": {"$in": 1}})).is_err());
+        assert!(MetadataFilter::parse(&json!(["year"])).is_err());
+    }
+
+    #[test]
+    fn test_metadata_filter_matches() {
+        let metadata = HashMap::from([
+            ("year".to_string(), json!(2024)),
+            ("format".to_string(), json!("PDF")),
+            ("source".to_string(), json!({"type": "web"})),
+        ]);
+        let matches = |filter: Value| MetadataFilter::parse(&filter).unwrap().matches(&metadata);
+
+        assert!(matches(json!({"year": {"$gte": 2024, "$lt": 2025}})));
+        assert!(!matches(json!({"year": {"$gt": 2024}})));
+        assert!(!matches(json!({"year": {"$gt": "2000"}})));
+        assert!(matches(json!({"year": [2023, 2024]})));
+        assert!(matches(json!({"format": {"$ilike": "pdf"}})));
+        assert!(matches(json!({"source.type": "web"})));
+        assert!(matches(json!({"lang": {"$ne": "go"}})));
+        assert!(!matches(json!({"lang": "go"})));
+    }
+}
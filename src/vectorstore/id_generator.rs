@@ -0,0 +1,84 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::schemas::Document;
+
+/// Produces the id `add_documents` returns for a document, given the row id the store
+/// assigned to it. Lets a deployment pick its id scheme (row ids, content hashes, external
+/// keys, ...) without changing the store.
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self, doc: &Document, rowid: i64) -> String;
+}
+
+/// The row id itself, as a string. The default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RowIdGenerator;
+
+impl IdGenerator for RowIdGenerator {
+    fn generate(&self, _doc: &Document, rowid: i64) -> String {
+        rowid.to_string()
+    }
+}
+
+/// Hex SHA-256 of the document's `page_content`, so the same content always gets the same
+/// id, whichever process ingests it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ContentHashIdGenerator;
+
+impl IdGenerator for ContentHashIdGenerator {
+    fn generate(&self, doc: &Document, _rowid: i64) -> String {
+        Sha256::digest(doc.page_content.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// The value of a metadata key, e.g. an id from the source system. Documents without the
+/// key fall back to their row id.
+#[derive(Debug, Clone)]
+pub struct MetadataIdGenerator {
+    key: String,
+}
+
+impl MetadataIdGenerator {
+    pub fn new<S: Into<String>>(key: S) -> Self {
+        Self { key: key.into() }
+    }
+}
+
+impl IdGenerator for MetadataIdGenerator {
+    fn generate(&self, doc: &Document, rowid: i64) -> String {
+        match doc.metadata.get(&self.key) {
+            Some(Value::String(id)) => id.clone(),
+            Some(id) => id.to_string(),
+            None => rowid.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_id_generators() {
+        let doc = Document::new("hello")
+            .with_metadata(HashMap::from([("external_id".to_string(), json!("ext-1"))]));
+
+        assert_eq!(RowIdGenerator.generate(&doc, 7), "7");
+        assert_eq!(
+            ContentHashIdGenerator.generate(&doc, 7),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            MetadataIdGenerator::new("external_id").generate(&doc, 7),
+            "ext-1"
+        );
+        assert_eq!(MetadataIdGenerator::new("missing").generate(&doc, 7), "7");
+    }
+}
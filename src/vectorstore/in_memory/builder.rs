@@ -0,0 +1,49 @@
+use std::{error::Error, sync::atomic::AtomicU64, sync::Arc};
+
+use dashmap::DashMap;
+
+use super::{MetadataIndex, Store};
+use crate::embedding::embedder_trait::Embedder;
+
+pub struct StoreBuilder {
+    embedder: Option<Arc<dyn Embedder>>,
+    metadata_index: bool,
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            embedder: None,
+            metadata_index: true,
+        }
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Maintains a [`MetadataIndex`] so filtered searches only score matching documents.
+    /// Enabled by default; disabling it saves memory for stores that are rarely filtered.
+    pub fn metadata_index(mut self, metadata_index: bool) -> Self {
+        self.metadata_index = metadata_index;
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("Embedder is required")?;
+
+        Ok(Store {
+            embedder,
+            documents: DashMap::new(),
+            next_id: AtomicU64::new(1),
+            index: self.metadata_index.then(MetadataIndex::new),
+        })
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
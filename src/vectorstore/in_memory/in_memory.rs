@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde_json::Value;
+
+use super::MetadataIndex;
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        maximal_marginal_relevance, order_by_ids, Condition, FilterOp, MetadataFilter,
+        VecStoreOptions, VectorStore,
+    },
+};
+
+pub(crate) struct Entry {
+    pub(crate) document: Document,
+    pub(crate) embedding: Vec<f32>,
+}
+
+/// A vector store that keeps documents and their embeddings in memory and ranks them by
+/// cosine similarity with a linear scan.
+///
+/// It needs no database or service, which makes it a test double for chains and retrievers;
+/// the crate's own tests can use it without the `in-memory` feature.
+pub struct Store {
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) documents: DashMap<u64, Entry>,
+    pub(crate) next_id: AtomicU64,
+    pub(crate) index: Option<MetadataIndex>,
+}
+
+impl Store {
+    fn get_filters(&self, opt: &VecStoreOptions) -> Result<MetadataFilter, Box<dyn Error>> {
+        match &opt.filters {
+            Some(filters) => MetadataFilter::parse(filters),
+            None => Ok(MetadataFilter::default()),
+        }
+    }
+
+    /// Ids of the documents matching `filter`. The metadata index narrows down the candidates
+    /// by the filter's equality conditions on top-level keys; every candidate is then checked
+    /// against the whole filter. Without an index, every document is scanned.
+    fn candidate_ids(&self, filter: &MetadataFilter) -> Vec<u64> {
+        if filter.is_empty() {
+            return self.documents.iter().map(|entry| *entry.key()).collect();
+        }
+        let indexed = self
+            .index
+            .as_ref()
+            .map(|index| (index, index_terms(filter)))
+            .filter(|(_, terms)| !terms.is_empty());
+        match indexed {
+            Some((index, terms)) => index
+                .candidates(&terms)
+                .into_iter()
+                .filter(|id| {
+                    self.documents
+                        .get(id)
+                        .is_some_and(|entry| filter.matches(&entry.document.metadata))
+                })
+                .collect(),
+            None => self
+                .documents
+                .iter()
+                .filter(|entry| filter.matches(&entry.document.metadata))
+                .map(|entry| *entry.key())
+                .collect(),
+        }
+    }
+
+    pub async fn delete_documents_by_ids(&self, ids: &[u64]) -> Result<(), Box<dyn Error>> {
+        for id in ids {
+            if let Some((_, entry)) = self.documents.remove(id) {
+                if let Some(index) = &self.index {
+                    index.remove(*id, &entry.document.metadata);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The equality conditions of `filter` the [`MetadataIndex`] can look up: `$eq` and `$in` on
+/// top-level keys, at most one per key.
+fn index_terms(filter: &MetadataFilter) -> HashMap<String, Value> {
+    let mut terms = HashMap::new();
+    for Condition { key, op } in &filter.conditions {
+        let value = match op {
+            FilterOp::Eq(value) if !value.is_array() => value.clone(),
+            FilterOp::In(values) => Value::Array(values.clone()),
+            _ => continue,
+        };
+        if !key.contains('.') {
+            terms.entry(key.clone()).or_insert(value);
+        }
+    }
+    terms
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum();
+    let norm_a = a.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut ids = Vec::with_capacity(docs.len());
+        for (doc, embedding) in docs.iter().zip(vectors) {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            if let Some(index) = &self.index {
+                index.insert(id, &doc.metadata);
+            }
+            self.documents.insert(
+                id,
+                Entry {
+                    document: doc.clone(),
+                    embedding,
+                },
+            );
+            ids.push(id.to_string());
+        }
+
+        Ok(ids)
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        let filter = self.get_filters(opt)?;
+        let score_threshold = opt.score_threshold.map(f64::from);
+
+        let mut docs: Vec<Document> = self
+            .candidate_ids(&filter)
+            .into_iter()
+            .filter_map(|id| {
+                let entry = self.documents.get(&id)?;
+                let score = cosine_similarity(&query_vector, &entry.embedding);
+                if score_threshold.is_some_and(|threshold| score < threshold) {
+                    return None;
+                }
+                Some(entry.document.clone().with_score(score))
+            })
+            .collect();
+
+        docs.sort_by(|a, b| b.score.total_cmp(&a.score));
+        docs.truncate(limit);
+        Ok(docs)
+    }
+
+    /// Uses the stored embeddings of the candidates.
+    async fn mmr_search(
+        &self,
+        query: &str,
+        k: usize,
+        fetch_k: usize,
+        lambda: f64,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        let filter = self.get_filters(opt)?;
+
+        let mut candidates: Vec<(Document, Vec<f32>)> = self
+            .candidate_ids(&filter)
+            .into_iter()
+            .filter_map(|id| {
+                let entry = self.documents.get(&id)?;
+                let score = cosine_similarity(&query_vector, &entry.embedding);
+                Some((
+                    entry.document.clone().with_score(score),
+                    entry.embedding.clone(),
+                ))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.0.score.total_cmp(&a.0.score));
+        candidates.truncate(fetch_k.max(k));
+
+        let embeddings: Vec<Vec<f32>> = candidates.iter().map(|(_, e)| e.clone()).collect();
+        let selected = maximal_marginal_relevance(&query_vector, &embeddings, k, lambda);
+        Ok(selected
+            .into_iter()
+            .map(|i| candidates[i].0.clone())
+            .collect())
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = self.get_filters(opt)?;
+        Ok(self.candidate_ids(&filter).len())
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let found = ids
+            .iter()
+            .filter_map(|id| {
+                let entry = self.documents.get(&id.parse::<u64>().ok()?)?;
+                Some((id.clone(), entry.document.clone()))
+            })
+            .collect();
+        order_by_ids(ids, found, opt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::vectorstore::{in_memory::StoreBuilder, test_utils::LetterEmbedder};
+
+    #[tokio::test]
+    async fn test_search_count_and_get() {
+        let store = StoreBuilder::new()
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        let docs = [("aaa", "x"), ("aab", "y"), ("ccc", "x")].map(|(text, tag)| {
+            Document::new(text).with_metadata(HashMap::from([("tag".to_string(), json!(tag))]))
+        });
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let found = store
+            .similarity_search("a", 2, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(found[0].page_content, "aaa");
+        assert_eq!(found[1].page_content, "aab");
+
+        let tagged = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        let found = store.similarity_search("c", 10, &tagged).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].page_content, "ccc");
+        assert_eq!(store.count_documents(&tagged).await.unwrap(), 2);
+        assert_eq!(
+            store
+                .count_documents(&VecStoreOptions::default())
+                .await
+                .unwrap(),
+            3
+        );
+
+        let got = store
+            .get_documents_by_ids(
+                &[ids[2].clone(), "404".to_string(), ids[0].clone()],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+        let contents: Vec<&str> = got.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["ccc", "aaa"]);
+    }
+
+    #[tokio::test]
+    async fn test_operator_filters() {
+        for metadata_index in [true, false] {
+            let store = StoreBuilder::new()
+                .embedder(LetterEmbedder)
+                .metadata_index(metadata_index)
+                .build()
+                .await
+                .unwrap();
+            let docs = [("aaa", "x", 2023), ("aab", "x", 2024), ("ccc", "y", 2025)].map(
+                |(text, tag, year)| {
+                    Document::new(text).with_metadata(HashMap::from([
+                        ("tag".to_string(), json!(tag)),
+                        ("year".to_string(), json!(year)),
+                    ]))
+                },
+            );
+            store
+                .add_documents(&docs, &VecStoreOptions::default())
+                .await
+                .unwrap();
+
+            let opt =
+                VecStoreOptions::new().with_filters(json!({"tag": "x", "year": {"$gte": 2024}}));
+            let found = store.similarity_search("a", 10, &opt).await.unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].page_content, "aab");
+
+            let opt = VecStoreOptions::new().with_filters(json!({"tag": {"$ne": "x"}}));
+            assert_eq!(store.count_documents(&opt).await.unwrap(), 1);
+
+            let opt = VecStoreOptions::new().with_filters(json!({"year": {"$between": [1, 2]}}));
+            assert!(store.count_documents(&opt).await.is_err());
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{HashMap, HashSet};
+
+use dashmap::DashMap;
+use serde_json::Value;
+
+/// Concurrent inverted index from a metadata `key = value` pair to the ids of the documents
+/// carrying it, used to prune candidates of a filtered search before computing distances.
+///
+/// Values are compared by their JSON serialization, so `1` and `1.0` are different values.
+#[derive(Debug, Default)]
+pub struct MetadataIndex {
+    postings: DashMap<(String, String), HashSet<u64>>,
+}
+
+impl MetadataIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn term(key: &str, value: &Value) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    pub fn insert(&self, id: u64, metadata: &HashMap<String, Value>) {
+        for (key, value) in metadata {
+            self.postings
+                .entry(Self::term(key, value))
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    pub fn remove(&self, id: u64, metadata: &HashMap<String, Value>) {
+        for (key, value) in metadata {
+            let term = Self::term(key, value);
+            if let Some(mut ids) = self.postings.get_mut(&term) {
+                ids.remove(&id);
+            }
+            self.postings.remove_if(&term, |_, ids| ids.is_empty());
+        }
+    }
+
+    /// Ids of the documents matching every filter. An array filter value matches any of
+    /// its elements, as in the other stores.
+    pub fn candidates(&self, filters: &HashMap<String, Value>) -> HashSet<u64> {
+        let mut result: Option<HashSet<u64>> = None;
+        for (key, value) in filters {
+            let values: Vec<&Value> = match value {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            let mut matching = HashSet::new();
+            for value in values {
+                if let Some(ids) = self.postings.get(&Self::term(key, value)) {
+                    matching.extend(ids.iter().copied());
+                }
+            }
+            let narrowed = match result {
+                Some(ids) => ids.intersection(&matching).copied().collect(),
+                None => matching,
+            };
+            if narrowed.is_empty() {
+                return narrowed;
+            }
+            result = Some(narrowed);
+        }
+        result.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::vectorstore::MetadataFilter;
+
+    #[test]
+    fn test_candidates_match_scan() {
+        let docs: Vec<HashMap<String, Value>> = vec![
+            HashMap::from([("tenant".into(), json!("a")), ("year".into(), json!(2023))]),
+            HashMap::from([("tenant".into(), json!("b")), ("year".into(), json!(2024))]),
+            HashMap::from([("tenant".into(), json!("a")), ("year".into(), json!(2024))]),
+        ];
+        let index = MetadataIndex::new();
+        for (id, metadata) in docs.iter().enumerate() {
+            index.insert(id as u64, metadata);
+        }
+
+        let filters = HashMap::from([
+            ("tenant".to_string(), json!("a")),
+            ("year".to_string(), json!([2024, 2025])),
+        ]);
+        let filter = MetadataFilter::from_entries(&filters).unwrap();
+        let scanned: HashSet<u64> = (0..docs.len() as u64)
+            .filter(|id| filter.matches(&docs[*id as usize]))
+            .collect();
+        assert_eq!(index.candidates(&filters), scanned);
+        assert_eq!(scanned, HashSet::from([2]));
+
+        index.remove(2, &docs[2]);
+        assert!(index.candidates(&filters).is_empty());
+        assert_eq!(index.postings.len(), 4);
+    }
+}
@@ -0,0 +1,7 @@
+mod builder;
+mod in_memory;
+mod metadata_index;
+
+pub use builder::*;
+pub use in_memory::*;
+pub use metadata_index::*;
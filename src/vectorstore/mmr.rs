@@ -0,0 +1,94 @@
+/// Greedily picks up to `k` of the candidate `embeddings` by maximal marginal relevance, and
+/// returns their indices in the order they were picked.
+///
+/// Each step picks the candidate maximizing
+/// `lambda * sim(query, c) - (1 - lambda) * max(sim(c, s) for s already picked)`, with cosine
+/// similarity as `sim`. A `lambda` of 1.0 orders by similarity to the query, 0.0 maximizes
+/// diversity. Ties go to the earlier candidate.
+pub fn maximal_marginal_relevance(
+    query_embedding: &[f32],
+    embeddings: &[Vec<f32>],
+    k: usize,
+    lambda: f64,
+) -> Vec<usize> {
+    let relevance: Vec<f64> = embeddings
+        .iter()
+        .map(|embedding| cosine(query_embedding, embedding))
+        .collect();
+    // Highest similarity of each candidate to the picked documents.
+    let mut redundancy = vec![f64::NEG_INFINITY; embeddings.len()];
+    let mut picked = vec![false; embeddings.len()];
+    let mut selected = Vec::with_capacity(k.min(embeddings.len()));
+
+    while selected.len() < k.min(embeddings.len()) {
+        let mut best: Option<(usize, f64)> = None;
+        for i in (0..embeddings.len()).filter(|&i| !picked[i]) {
+            let penalty = if selected.is_empty() {
+                0.0
+            } else {
+                redundancy[i]
+            };
+            let score = lambda * relevance[i] - (1.0 - lambda) * penalty;
+            let better = match best {
+                Some((_, best_score)) => score > best_score,
+                None => true,
+            };
+            if better {
+                best = Some((i, score));
+            }
+        }
+        let Some((chosen, _)) = best else { break };
+
+        picked[chosen] = true;
+        selected.push(chosen);
+        for i in (0..embeddings.len()).filter(|&i| !picked[i]) {
+            redundancy[i] = redundancy[i].max(cosine(&embeddings[i], &embeddings[chosen]));
+        }
+    }
+    selected
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum();
+    let norm_a = a.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maximal_marginal_relevance() {
+        let query = [1.0, 0.2];
+        let embeddings = vec![
+            vec![1.0, 0.0],
+            vec![0.99, 0.05],
+            vec![0.7, 0.7],
+            vec![0.0, 1.0],
+        ];
+
+        assert_eq!(
+            maximal_marginal_relevance(&query, &embeddings, 3, 1.0),
+            vec![1, 0, 2]
+        );
+        assert_eq!(
+            maximal_marginal_relevance(&query, &embeddings, 2, 0.5),
+            vec![1, 3]
+        );
+        // With no weight on relevance, the first candidate is picked first and each later pick is the
+        // candidate least similar to the picked ones.
+        assert_eq!(
+            maximal_marginal_relevance(&query, &embeddings, 2, 0.0),
+            vec![0, 3]
+        );
+        assert_eq!(
+            maximal_marginal_relevance(&query, &embeddings, 10, 0.5).len(),
+            4
+        );
+    }
+}
@@ -1,3 +1,7 @@
+mod batch_writer;
+mod filter;
+mod id_generator;
+mod mmr;
 mod options;
 
 #[cfg(feature = "postgres")]
@@ -12,6 +16,13 @@ pub mod sqlite_vec;
 #[cfg(feature = "sqlite-bm25")]
 pub mod sqlite_bm25;
 
+#[cfg(any(
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid"
+))]
+pub(crate) mod sqlite_utils;
+
 #[cfg(feature = "surrealdb")]
 pub mod surrealdb;
 
@@ -21,7 +32,36 @@ pub mod opensearch;
 #[cfg(feature = "qdrant")]
 pub mod qdrant;
 
+#[cfg(feature = "mongodb")]
+pub mod mongodb;
+
+#[cfg(feature = "chroma")]
+pub mod chroma;
+
+#[cfg(feature = "weaviate")]
+pub mod weaviate;
+
+#[cfg(feature = "pinecone")]
+pub mod pinecone;
+
+#[cfg(feature = "redis-vector")]
+pub mod redis;
+
+// Also built for the crate's own tests, as the test double of other components.
+#[cfg(any(feature = "in-memory", test))]
+pub mod in_memory;
+
+#[cfg(feature = "tantivy")]
+pub mod tantivy;
+
 mod vectorstore;
 
+#[cfg(test)]
+pub(crate) mod test_utils;
+
+pub use batch_writer::*;
+pub use filter::*;
+pub use id_generator::*;
+pub use mmr::*;
 pub use options::*;
 pub use vectorstore::*;
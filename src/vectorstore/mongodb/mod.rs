@@ -0,0 +1,5 @@
+mod builder;
+mod mongodb;
+
+pub use self::mongodb::*;
+pub use builder::*;
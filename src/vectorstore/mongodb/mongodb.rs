@@ -0,0 +1,312 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, oid::ObjectId, Bson, Document as BsonDocument};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+pub use mongodb::{Client, Collection, Database};
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{probe_vector_dimensions, DistanceMetric, VecStoreOptions, VectorStore},
+};
+
+pub struct Store {
+    pub collection: Collection<BsonDocument>,
+    pub database: Database,
+    pub embedder: Arc<dyn Embedder>,
+    pub index_name: String,
+    pub content_field: String,
+    pub metadata_field: String,
+    pub embedding_field: String,
+    pub num_candidates: Option<u32>,
+    pub metadata_at_top_level: bool,
+    pub create_index: bool,
+    pub vector_dimensions: i32,
+    pub similarity: DistanceMetric,
+    pub filter_fields: Vec<String>,
+}
+
+// https://www.mongodb.com/docs/atlas/atlas-vector-search/vector-search-stage/
+
+impl Store {
+    /// Creates the collection and its Atlas Vector Search index `index_name` when the store
+    /// was built with `create_index` and they don't exist. Atlas builds the index in the
+    /// background, so searches may find nothing for a short while after it's created.
+    pub async fn initialize(&self) -> Result<(), Box<dyn Error>> {
+        if !self.create_index {
+            return Ok(());
+        }
+
+        let collection = self.collection.name();
+        if let Err(e) = self.database.create_collection(collection).await {
+            if !e.to_string().contains("already exists") {
+                return Err(e.into());
+            }
+        }
+
+        let dimensions =
+            probe_vector_dimensions(self.embedder.as_ref(), self.vector_dimensions).await?;
+        let similarity = match self.similarity {
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::L2 => "euclidean",
+            DistanceMetric::InnerProduct => "dotProduct",
+        };
+        let mut fields = vec![doc! {
+            "type": "vector",
+            "path": self.embedding_field.as_str(),
+            "numDimensions": dimensions,
+            "similarity": similarity,
+        }];
+        for key in &self.filter_fields {
+            fields.push(doc! { "type": "filter", "path": self.metadata_path(key) });
+        }
+
+        let command = doc! {
+            "createSearchIndexes": collection,
+            "indexes": [{
+                "name": self.index_name.as_str(),
+                "type": "vectorSearch",
+                "definition": { "fields": fields },
+            }],
+        };
+        match self.database.run_command(command).await {
+            Ok(_) => Ok(()),
+            Err(e)
+                if e.to_string().contains("already exists")
+                    || e.to_string().contains("Duplicate Index") =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Path of the metadata `key` in the MongoDB documents.
+    fn metadata_path(&self, key: &str) -> String {
+        if self.metadata_at_top_level {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.metadata_field, key)
+        }
+    }
+
+    /// Maps a JSON object of metadata key/values to a MongoDB filter on the metadata field.
+    /// Arrays match any of their values.
+    fn build_filter(
+        &self,
+        filters: &HashMap<String, Value>,
+    ) -> Result<BsonDocument, Box<dyn Error>> {
+        let mut clauses = filters
+            .iter()
+            .map(|(k, v)| {
+                let path = self.metadata_path(k);
+                let value = bson::to_bson(v)?;
+                Ok(match value {
+                    Bson::Array(values) => doc! { path: { "$in": values } },
+                    value => doc! { path: { "$eq": value } },
+                })
+            })
+            .collect::<Result<Vec<BsonDocument>, Box<dyn Error>>>()?;
+
+        Ok(match clauses.len() {
+            0 => BsonDocument::new(),
+            1 => clauses.remove(0),
+            _ => doc! { "$and": clauses },
+        })
+    }
+
+    fn get_filters(&self, opt: &VecStoreOptions) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+        match &opt.filters {
+            Some(Value::Object(map)) => Ok(map.clone().into_iter().collect()),
+            None => Ok(HashMap::new()),
+            _ => Err("Invalid filters format".into()),
+        }
+    }
+
+    pub async fn delete_documents_by_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let ids = ids
+            .iter()
+            .map(|id| ObjectId::parse_str(id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.collection
+            .delete_many(doc! { "_id": { "$in": ids } })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_documents_by_metadata(
+        &self,
+        metadata_filters: &HashMap<String, Value>,
+    ) -> Result<(), Box<dyn Error>> {
+        let filter = self.build_filter(metadata_filters)?;
+        self.collection.delete_many(filter).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_documents(&self) -> Result<(), Box<dyn Error>> {
+        self.collection.delete_many(doc! {}).await?;
+        Ok(())
+    }
+
+    fn to_document(&self, mut result: BsonDocument) -> Result<Document, Box<dyn Error>> {
+        let page_content = result.get_str(&self.content_field)?.to_string();
+        let score = result.get_f64("score")?;
+        let metadata = if self.metadata_at_top_level {
+            // Every other field is metadata, as the embedding and `_id` are projected out.
+            result.remove(&self.content_field);
+            result.remove("score");
+            result
+                .into_iter()
+                .map(|(k, v)| (k, v.into_relaxed_extjson()))
+                .collect()
+        } else {
+            match result.remove(&self.metadata_field) {
+                Some(Bson::Document(metadata)) => metadata
+                    .into_iter()
+                    .map(|(k, v)| (k, v.into_relaxed_extjson()))
+                    .collect(),
+                _ => HashMap::new(),
+            }
+        };
+
+        Ok(Document {
+            page_content,
+            metadata,
+            score,
+        })
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the store.
+    /// Returns the ObjectIds of the inserted documents as hex strings.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<ObjectId> = docs.iter().map(|_| ObjectId::new()).collect();
+        let records = docs
+            .iter()
+            .zip(vectors)
+            .zip(&ids)
+            .map(|((d, vector), id)| {
+                let mut record = doc! {
+                    "_id": *id,
+                    &self.content_field: d.page_content.as_str(),
+                    &self.embedding_field: vector,
+                };
+                if !self.metadata_at_top_level {
+                    record.insert(&self.metadata_field, bson::to_bson(&d.metadata)?);
+                    return Ok(record);
+                }
+                for (key, value) in &d.metadata {
+                    if record.contains_key(key) || key == "score" {
+                        return Err(format!(
+                            "Metadata key {:?} collides with a field of the store",
+                            key
+                        )
+                        .into());
+                    }
+                    record.insert(key, bson::to_bson(value)?);
+                }
+                Ok(record)
+            })
+            .collect::<Result<Vec<BsonDocument>, Box<dyn Error>>>()?;
+
+        if !records.is_empty() {
+            self.collection.insert_many(records).await?;
+        }
+
+        Ok(ids.iter().map(|id| id.to_hex()).collect())
+    }
+
+    /// Perform a similarity search on the store using a `$vectorSearch` aggregation.
+    /// Returns a list of documents similar to the query.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("MongoDB doesn't support namespaces".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let num_candidates = self
+            .num_candidates
+            .map(|n| n as i64)
+            .unwrap_or(limit as i64 * 10)
+            .max(limit as i64);
+        let mut vector_search = doc! {
+            "index": self.index_name.as_str(),
+            "path": self.embedding_field.as_str(),
+            "queryVector": query_vector,
+            "numCandidates": num_candidates,
+            "limit": limit as i64,
+        };
+        let filter = self.build_filter(&self.get_filters(opt)?)?;
+        if !filter.is_empty() {
+            vector_search.insert("filter", filter);
+        }
+
+        let pipeline = if self.metadata_at_top_level {
+            vec![
+                doc! { "$vectorSearch": vector_search },
+                doc! { "$set": { "score": { "$meta": "vectorSearchScore" } } },
+                doc! { "$project": { "_id": 0, &self.embedding_field: 0 } },
+            ]
+        } else {
+            vec![
+                doc! { "$vectorSearch": vector_search },
+                doc! {
+                    "$project": {
+                        &self.content_field: 1,
+                        &self.metadata_field: 1,
+                        "score": { "$meta": "vectorSearchScore" },
+                    }
+                },
+            ]
+        };
+
+        let results: Vec<BsonDocument> = self
+            .collection
+            .aggregate(pipeline)
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut documents = results
+            .into_iter()
+            .map(|result| self.to_document(result))
+            .collect::<Result<Vec<Document>, Box<dyn Error>>>()?;
+
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|d| d.score >= score_threshold as f64);
+        }
+
+        Ok(documents)
+    }
+}
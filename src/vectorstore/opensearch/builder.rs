@@ -1,5 +1,5 @@
 use crate::embedding::Embedder;
-use crate::vectorstore::opensearch::Store;
+use crate::vectorstore::opensearch::{SpaceType, Store};
 use opensearch::OpenSearch;
 use std::error::Error;
 use std::sync::Arc;
@@ -11,6 +11,9 @@ pub struct StoreBuilder {
     index: Option<String>,
     vector_field: String,
     content_field: String,
+    vector_dimensions: i32,
+    space_type: SpaceType,
+    ef_search: Option<usize>,
 }
 
 impl StoreBuilder {
@@ -23,6 +26,9 @@ impl StoreBuilder {
             index: None,
             vector_field: "vector_field".to_string(),
             content_field: "page_content".to_string(),
+            vector_dimensions: 1536,
+            space_type: SpaceType::default(),
+            ef_search: None,
         }
     }
 
@@ -56,6 +62,27 @@ impl StoreBuilder {
         self
     }
 
+    /// Dimension of the `knn_vector` field created by `Store::create_index`. Defaults to 1536.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    /// Distance of the kNN index. Used when creating the index, and `build` errors if an
+    /// existing index was created with another one. Defaults to [`SpaceType::L2`].
+    pub fn space_type(mut self, space_type: SpaceType) -> Self {
+        self.space_type = space_type;
+        self
+    }
+
+    /// Size of the HNSW candidate list at search time: higher improves recall at the cost of
+    /// latency. Set in the index settings on creation (512 when unset) and, when set, passed
+    /// with every query as `method_parameters.ef_search`, which needs OpenSearch 2.16+.
+    pub fn ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = Some(ef_search);
+        self
+    }
+
     // Finalize the builder and construct the Store object
     pub async fn build(self) -> Result<Store, Box<dyn Error>> {
         if self.client.is_none() {
@@ -70,13 +97,19 @@ impl StoreBuilder {
             return Err("Index is required".into());
         }
 
-        Ok(Store {
+        let store = Store {
             client: self.client.unwrap(),
             embedder: self.embedder.unwrap(),
             k: self.k,
             index: self.index.unwrap(),
             vector_field: self.vector_field,
             content_field: self.content_field,
-        })
+            vector_dimensions: self.vector_dimensions,
+            space_type: self.space_type,
+            ef_search: self.ef_search,
+        };
+        store.check_space_type().await?;
+
+        Ok(store)
     }
 }
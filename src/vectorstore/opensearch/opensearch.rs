@@ -1,8 +1,8 @@
 use async_trait::async_trait;
 use opensearch::http::request::JsonBody;
 use opensearch::http::response::Response;
-use opensearch::indices::{IndicesCreateParts, IndicesDeleteParts};
-use opensearch::{BulkParts, SearchParts};
+use opensearch::indices::{IndicesCreateParts, IndicesDeleteParts, IndicesGetMappingParts};
+use opensearch::{BulkParts, CountParts, MgetParts, SearchParts};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::error::Error;
@@ -16,9 +16,28 @@ pub use opensearch::OpenSearch;
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{order_by_ids, VecStoreOptions, VectorStore},
 };
 
+/// Distance used by the kNN index, the `space_type` of its `knn_vector` field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpaceType {
+    #[default]
+    L2,
+    CosineSimil,
+    InnerProduct,
+}
+
+impl SpaceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpaceType::L2 => "l2",
+            SpaceType::CosineSimil => "cosinesimil",
+            SpaceType::InnerProduct => "innerproduct",
+        }
+    }
+}
+
 pub struct Store {
     pub client: OpenSearch,
     pub embedder: Arc<dyn Embedder>,
@@ -26,8 +45,13 @@ pub struct Store {
     pub index: String,
     pub vector_field: String,
     pub content_field: String,
+    pub vector_dimensions: i32,
+    pub space_type: SpaceType,
+    pub ef_search: Option<usize>,
 }
 
+const DEFAULT_EF_SEARCH: usize = 512;
+
 // https://opensearch.org/docs/latest/search-plugins/knn/approximate-knn/
 // https://opensearch.org/blog/efficient-filters-in-knn/
 // https://opensearch.org/docs/latest/clients/rust/
@@ -51,18 +75,18 @@ impl Store {
             "settings": {
                 "index.knn": true,
                 "knn.algo_param": {
-                    "ef_search": "512"
+                    "ef_search": self.ef_search.unwrap_or(DEFAULT_EF_SEARCH).to_string()
                 },
             },
             "mappings": {
                 "properties": {
                     &self.vector_field: {
                         "type": "knn_vector",
-                        "dimension": 1536,
+                        "dimension": self.vector_dimensions,
                         "method": {
                             "engine": "faiss",
                             "name": "hnsw",
-                            "space_type": "l2",
+                            "space_type": self.space_type.as_str(),
                             "parameters": {
                                 "ef_construction": 512,
                                 "m": 16
@@ -95,6 +119,41 @@ impl Store {
 
         Ok(result)
     }
+
+    /// Errors if the index exists and its vector field uses another `space_type` than the
+    /// configured one, since scores would then not mean what the store assumes.
+    pub async fn check_space_type(&self) -> Result<(), Box<dyn Error>> {
+        let response = self
+            .client
+            .indices()
+            .get_mapping(IndicesGetMappingParts::Index(&[&self.index]))
+            .send()
+            .await?;
+        if response.status_code().as_u16() == 404 {
+            return Ok(());
+        }
+        let mapping = response
+            .error_for_status_code()
+            .map_err(|e| Box::new(e))?
+            .json::<Value>()
+            .await?;
+
+        let field = &mapping[&self.index]["mappings"]["properties"][&self.vector_field];
+        // Newer versions may set `space_type` on the field rather than on its method.
+        let space_type = field["method"]["space_type"]
+            .as_str()
+            .or_else(|| field["space_type"].as_str());
+        match space_type {
+            Some(space_type) if space_type != self.space_type.as_str() => Err(format!(
+                "Index {} uses space_type {} but the store is configured for {}",
+                self.index,
+                space_type,
+                self.space_type.as_str()
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[async_trait]
@@ -104,7 +163,7 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
         let vectors = embedder.embed_documents(&texts).await?;
 
@@ -162,6 +221,7 @@ impl VectorStore for Store {
             &self.vector_field,
             limit,
             self.k,
+            self.ef_search,
             opt.filters.clone(),
         );
 
@@ -185,7 +245,7 @@ impl VectorStore for Store {
             })
             .collect::<Vec<_>>();
 
-        let documents = aoss_documents
+        let mut documents: Vec<Document> = aoss_documents
             .into_iter()
             .map(|item| {
                 let page_content =
@@ -203,45 +263,95 @@ impl VectorStore for Store {
                 }
             })
             .collect();
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
 
         Ok(documents)
     }
+
+    /// `opt.filters`, if set, is an OpenSearch query, as for `similarity_search`.
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let query = opt
+            .filters
+            .clone()
+            .unwrap_or_else(|| json!({ "match_all": {} }));
+        let response = self
+            .client
+            .count(CountParts::Index(&[&self.index]))
+            .body(json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status_code()
+            .map_err(|e| Box::new(e))?;
+
+        let response_body = response.json::<Value>().await?;
+        response_body["count"]
+            .as_u64()
+            .map(|count| count as usize)
+            .ok_or_else(|| "Missing count in the OpenSearch response".into())
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self
+            .client
+            .mget(MgetParts::Index(&self.index))
+            .body(json!({ "ids": ids }))
+            .send()
+            .await?
+            .error_for_status_code()
+            .map_err(|e| Box::new(e))?;
+
+        let response_body = response.json::<Value>().await?;
+
+        let mut found = HashMap::new();
+        for item in response_body["docs"].as_array().into_iter().flatten() {
+            if item["found"] != json!(true) {
+                continue;
+            }
+            let id = serde_json::from_value::<String>(item["_id"].clone())?;
+            let page_content =
+                serde_json::from_value::<String>(item["_source"][&self.content_field].clone())?;
+            let metadata = serde_json::from_value::<HashMap<String, Value>>(
+                item["_source"]["metadata"].clone(),
+            )?;
+            found.insert(id, Document::new(page_content).with_metadata(metadata));
+        }
+        order_by_ids(ids, found, opt)
+    }
 }
 
 fn build_similarity_search_query(
-    embedded_query: Vec<f64>,
+    embedded_query: Vec<f32>,
     vector_field: &str,
     size: usize,
     k: i32,
+    ef_search: Option<usize>,
     maybe_filter: Option<Value>,
 ) -> Value {
-    match maybe_filter {
-        Some(filter) => {
-            json!({
-              "size": size,
-              "query": {
-                "knn": {
-                  vector_field: {
-                    "vector": embedded_query,
-                    "k": k,
-                    "filter": filter,
-                  }
-                }
-              }
-            })
-        }
-        None => {
-            json!({
-              "size": size,
-              "query": {
-                "knn": {
-                  vector_field: {
-                    "vector": embedded_query,
-                    "k": k,
-                  }
-                }
-              }
-            })
-        }
+    let mut knn = json!({
+        "vector": embedded_query,
+        "k": k,
+    });
+    if let Some(filter) = maybe_filter {
+        knn["filter"] = filter;
+    }
+    if let Some(ef_search) = ef_search {
+        knn["method_parameters"] = json!({ "ef_search": ef_search });
     }
+    json!({
+      "size": size,
+      "query": {
+        "knn": {
+          vector_field: knn
+        }
+      }
+    })
 }
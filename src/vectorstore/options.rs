@@ -1,12 +1,26 @@
 use std::sync::Arc;
 
+use regex::{Captures, Regex};
 use serde_json::Value;
 
-use crate::embedding::embedder_trait::Embedder;
+use crate::{embedding::embedder_trait::Embedder, schemas::Document};
+
+/// How a vector store measures the distance between the query and document embeddings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// `1 - cosine similarity`.
+    Cosine,
+    /// Euclidean distance.
+    #[default]
+    L2,
+    /// The inner product, which is already a similarity: higher means closer.
+    InnerProduct,
+}
 
 /// The `VecStoreOptions` struct is responsible for determining options when
 /// interacting with a Vector Store. The options include `name_space`, `score_threshold`,
-/// `filters`, and `embedder`.
+/// `filters`, `embedder`, the per-signal candidate counts of hybrid stores, and the
+/// `embedding_template` used when adding documents.
 ///
 /// # Usage
 /// ```rust,ignore
@@ -18,9 +32,41 @@ use crate::embedding::embedder_trait::Embedder;
 /// ```
 pub struct VecStoreOptions {
     pub name_space: Option<String>,
+    /// Minimum `Document::score` of search results; lower-scoring documents are dropped before
+    /// the `limit` cut, so a search may return fewer than `limit`, or no, documents.
     pub score_threshold: Option<f32>,
     pub filters: Option<Value>,
     pub embedder: Option<Arc<dyn Embedder>>,
+    /// Candidates fetched from the vector index before the final `limit` cut.
+    /// Honored by hybrid stores; defaults to a multiple of `limit`.
+    pub vec_candidates: Option<usize>,
+    /// Candidates fetched from the keyword index before the final `limit` cut.
+    /// Honored by hybrid stores; defaults to a multiple of `limit`.
+    pub keyword_candidates: Option<usize>,
+    /// Template for the text embedded by `add_documents`, so that metadata such as a title
+    /// influences the embedding. The stored `page_content` is left unchanged. Off by default.
+    pub embedding_template: Option<String>,
+    /// Attaches each signal's rank and score to the metadata of hybrid search results.
+    /// Honored by hybrid stores; off by default.
+    pub rank_debug: bool,
+    /// Skips documents whose exact `page_content` is already stored, returning the existing
+    /// id for them. Honored by the SQLite vector stores; off by default.
+    pub reject_duplicates: bool,
+    /// Number of results returned by `VectorStore::search`, which takes no explicit limit.
+    pub default_limit: Option<usize>,
+    /// Ids for the documents passed to `add_documents`, one per document and in the same
+    /// order. A document whose id is already stored replaces it. Honored by the SQLite stores.
+    pub ids: Option<Vec<String>>,
+    /// Makes `VectorStore::get_documents_by_ids` error on ids that are not stored instead of
+    /// omitting them. Off by default.
+    pub error_on_missing_ids: bool,
+    /// Metadata key whose value identifies a document for `VectorStore::upsert_documents`.
+    /// Defaults to `"source"`.
+    pub upsert_key: Option<String>,
+    /// Distance metric of searches. Honored by pgvector, which defaults to cosine. The SQLite
+    /// vector stores search with the metric their table was created with, and error if this
+    /// asks for another one.
+    pub distance_metric: Option<DistanceMetric>,
 }
 
 impl Default for VecStoreOptions {
@@ -36,6 +82,16 @@ impl VecStoreOptions {
             score_threshold: None,
             filters: None,
             embedder: None,
+            vec_candidates: None,
+            keyword_candidates: None,
+            embedding_template: None,
+            rank_debug: false,
+            reject_duplicates: false,
+            default_limit: None,
+            ids: None,
+            error_on_missing_ids: false,
+            upsert_key: None,
+            distance_metric: None,
         }
     }
 
@@ -49,6 +105,9 @@ impl VecStoreOptions {
         self
     }
 
+    /// Metadata filter, e.g. `json!({"year": {"$gte": 2023}, "lang": {"$ne": "go"}})`. The
+    /// SQLite and in-memory stores accept the operators of
+    /// [`MetadataFilter`](super::MetadataFilter).
     pub fn with_filters(mut self, filters: Value) -> Self {
         self.filters = Some(filters);
         self
@@ -58,4 +117,141 @@ impl VecStoreOptions {
         self.embedder = Some(Arc::new(embedder));
         self
     }
+
+    pub fn with_vec_candidates(mut self, vec_candidates: usize) -> Self {
+        self.vec_candidates = Some(vec_candidates);
+        self
+    }
+
+    pub fn with_keyword_candidates(mut self, keyword_candidates: usize) -> Self {
+        self.keyword_candidates = Some(keyword_candidates);
+        self
+    }
+
+    /// Adds `vec_rank`/`bm25_rank` (1-based, or null when the signal didn't return the
+    /// document) and `vec_score`/`bm25_score` to the metadata of hybrid search results,
+    /// to see which signal retrieved each document.
+    pub fn with_rank_debug(mut self, rank_debug: bool) -> Self {
+        self.rank_debug = rank_debug;
+        self
+    }
+
+    /// Sets the limit used by `VectorStore::search`, so callers with a fixed limit configure
+    /// it once instead of passing it to every `similarity_search`.
+    pub fn with_default_limit(mut self, default_limit: usize) -> Self {
+        self.default_limit = Some(default_limit);
+        self
+    }
+
+    /// Makes `add_documents` store the documents under `ids` instead of generated ids, so that
+    /// re-running an ingestion overwrites the documents instead of duplicating them. There
+    /// must be one id per document; `add_documents` returns them in the same order.
+    pub fn with_ids(mut self, ids: Vec<String>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    /// Makes `get_documents_by_ids` return an error naming the ids that are not stored,
+    /// instead of returning only the documents that were found.
+    pub fn with_error_on_missing_ids(mut self, error_on_missing_ids: bool) -> Self {
+        self.error_on_missing_ids = error_on_missing_ids;
+        self
+    }
+
+    /// Sets the metadata key `upsert_documents` uses to tell whether a document is already
+    /// stored, e.g. a path or URL. Ignored when `ids` are given.
+    pub fn with_upsert_key<S: Into<String>>(mut self, upsert_key: S) -> Self {
+        self.upsert_key = Some(upsert_key.into());
+        self
+    }
+
+    pub fn with_distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = Some(distance_metric);
+        self
+    }
+
+    /// Makes `add_documents` skip documents whose exact `page_content` is already stored (or
+    /// repeated earlier in the same call) and return the existing id in their place. Unlike
+    /// an upsert, the stored document, metadata included, is left untouched.
+    ///
+    /// Each document costs an extra indexed lookup on the content column, and the first use
+    /// creates that index, which is as large as the stored text. Duplicates are not embedded.
+    pub fn with_reject_duplicates(mut self, reject_duplicates: bool) -> Self {
+        self.reject_duplicates = reject_duplicates;
+        self
+    }
+
+    /// Embeds documents as the rendered `template` instead of their bare `page_content`.
+    /// `{page_content}` is replaced by the content and `{key}` by the metadata value at `key`.
+    /// Lines whose metadata placeholders are all missing are dropped.
+    ///
+    /// ```rust,ignore
+    /// let options = VecStoreOptions::new()
+    ///     .with_embedding_template("Title: {title}\nSection: {section}\n\n{page_content}");
+    /// ```
+    pub fn with_embedding_template<S: Into<String>>(mut self, template: S) -> Self {
+        self.embedding_template = Some(template.into());
+        self
+    }
+
+    /// The text to embed for `doc`: its `page_content`, or the rendered `embedding_template`.
+    pub fn embedding_text(&self, doc: &Document) -> String {
+        let template = match &self.embedding_template {
+            Some(template) => template,
+            None => return doc.page_content.clone(),
+        };
+
+        let placeholder = Regex::new(r"\{([^{}\s]+)\}").unwrap();
+        template
+            .lines()
+            .filter(|line| {
+                let mut keys = placeholder
+                    .captures_iter(line)
+                    .map(|c| c.get(1).unwrap().as_str())
+                    .filter(|key| *key != "page_content")
+                    .peekable();
+                keys.peek().is_none() || keys.any(|key| doc.metadata.contains_key(key))
+            })
+            .map(|line| {
+                placeholder
+                    .replace_all(line, |c: &Captures| match &c[1] {
+                        "page_content" => doc.page_content.clone(),
+                        key => match doc.metadata.get(key) {
+                            Some(Value::String(s)) => s.clone(),
+                            Some(value) => value.to_string(),
+                            None => String::new(),
+                        },
+                    })
+                    .into_owned()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_embedding_text() {
+        let doc = Document::new("Body text.").with_metadata(HashMap::from([
+            ("title".to_string(), json!("Rust")),
+            ("year".to_string(), json!(2024)),
+        ]));
+
+        assert_eq!(VecStoreOptions::new().embedding_text(&doc), "Body text.");
+
+        let options = VecStoreOptions::new().with_embedding_template(
+            "Title: {title} ({year})\nSection: {section}\n\n{page_content}",
+        );
+        assert_eq!(
+            options.embedding_text(&doc),
+            "Title: Rust (2024)\n\nBody text."
+        );
+    }
 }
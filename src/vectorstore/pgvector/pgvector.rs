@@ -3,13 +3,13 @@ use std::{collections::HashMap, error::Error, sync::Arc};
 use async_trait::async_trait;
 use pgvector::Vector;
 use serde_json::{json, Value};
-use sqlx::{Pool, Postgres, Row};
+use sqlx::{postgres::PgPoolCopyExt, Pool, Postgres, Row};
 use uuid::Uuid;
 
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{order_by_ids, upsert_keys, DistanceMetric, VecStoreOptions, VectorStore},
 };
 
 pub struct Store {
@@ -40,6 +40,31 @@ impl HNSWIndex {
             distance_function: distance_function.into(),
         }
     }
+
+    pub(crate) fn index_name(table: &str) -> String {
+        format!("{}_embedding_hnsw", table)
+    }
+
+    pub(crate) fn create_sql(&self, table: &str) -> String {
+        let mut sql = format!(
+            r#"CREATE INDEX IF NOT EXISTS {} ON {} USING hnsw (embedding {})"#,
+            Self::index_name(table),
+            table,
+            self.distance_function
+        );
+        if self.m > 0 && self.ef_construction > 0 {
+            sql = format!(
+                "{} WITH (m={}, ef_construction = {})",
+                sql, self.m, self.ef_construction
+            );
+        }
+        sql
+    }
+}
+
+// Quotes a field for `COPY ... WITH (FORMAT csv)`.
+fn csv_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
 }
 
 impl Store {
@@ -94,6 +119,109 @@ impl Store {
         Ok(())
     }
 
+    /// Creates the HNSW index configured with `StoreBuilder::hns_index`, if it does not exist
+    /// yet. Used after a bulk load into a store built with
+    /// `StoreBuilder::defer_index_creation(true)`:
+    ///
+    /// ```rust,ignore
+    /// let store = StoreBuilder::new()
+    ///     .hns_index(HNSWIndex::new(16, 64, "vector_cosine_ops"))
+    ///     .defer_index_creation(true)
+    ///     // ...
+    ///     .build()
+    ///     .await?;
+    /// store.copy_documents(&docs, &VecStoreOptions::default()).await?;
+    /// store.create_index().await?;
+    /// store.analyze().await?;
+    /// ```
+    pub async fn create_index(&self) -> Result<(), Box<dyn Error>> {
+        let hns_index = self
+            .hns_index
+            .as_ref()
+            .ok_or("No HNSW index configured for this store")?;
+        sqlx::query(&hns_index.create_sql(&self.embedder_table_name))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Rebuilds the HNSW index, e.g. after many updates or deletes have degraded recall.
+    /// Runs `REINDEX INDEX`, which blocks writes to the table while it runs.
+    pub async fn reindex(&self) -> Result<(), Box<dyn Error>> {
+        if self.hns_index.is_none() {
+            return Err("No HNSW index configured for this store".into());
+        }
+        sqlx::query(&format!(
+            "REINDEX INDEX {}",
+            HNSWIndex::index_name(&self.embedder_table_name)
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Runs `ANALYZE` on the embedding table so the planner has fresh statistics, which it
+    /// needs to pick the HNSW index after a bulk load.
+    pub async fn analyze(&self) -> Result<(), Box<dyn Error>> {
+        sqlx::query(&format!("ANALYZE {}", self.embedder_table_name))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Like `add_documents`, but streams the rows with `COPY ... FROM STDIN` instead of one
+    /// `INSERT` per document, which is much faster for bulk loads.
+    pub async fn copy_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vectors = embedder.embed_documents(&texts).await?;
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut ids = Vec::with_capacity(docs.len());
+        let mut data = String::new();
+        for (doc, vector) in docs.iter().zip(vectors) {
+            let id = Uuid::new_v4().to_string();
+            let embedding = format!(
+                "[{}]",
+                vector
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+            data.push_str(&format!(
+                "{},{},{},{},{}\n",
+                csv_field(&id),
+                csv_field(&doc.page_content),
+                csv_field(&embedding),
+                csv_field(&json!(&doc.metadata).to_string()),
+                csv_field(&self.collection_uuid),
+            ));
+            ids.push(id);
+        }
+
+        let mut copy = self
+            .pool
+            .copy_in_raw(&format!(
+                r#"COPY {} ("uuid", document, embedding, cmetadata, collection_id) FROM STDIN WITH (FORMAT csv)"#,
+                self.embedder_table_name
+            ))
+            .await?;
+        if let Err(e) = copy.send(data.into_bytes()).await {
+            copy.abort(e.to_string()).await?;
+            return Err(e.into());
+        }
+        copy.finish().await?;
+
+        Ok(ids)
+    }
+
     async fn remove_collection(&self) -> Result<(), Box<dyn Error>> {
         sqlx::query(r#"DELETE FROM collection WHERE uuid = $1"#)
             .bind(&self.collection_uuid)
@@ -115,7 +243,7 @@ impl VectorStore for Store {
                 "score_threshold, filters, and name_space are not supported in pgvector",
             )));
         }
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
 
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
 
@@ -132,12 +260,11 @@ impl VectorStore for Store {
 
         let mut ids = Vec::with_capacity(docs.len());
 
-        for (doc, vector) in docs.iter().zip(vectors.iter()) {
+        for (doc, vector) in docs.iter().zip(vectors) {
             let id = Uuid::new_v4().to_string();
             ids.push(id.clone());
 
-            let vector_value =
-                Vector::from(vector.into_iter().map(|x| *x as f32).collect::<Vec<f32>>());
+            let vector_value = Vector::from(vector);
 
             sqlx::query(&format!(
                 r#"INSERT INTO {} 
@@ -182,6 +309,14 @@ impl VectorStore for Store {
             where_querys = "TRUE".to_string();
         }
 
+        let distance_metric = opt.distance_metric.unwrap_or(DistanceMetric::Cosine);
+        let operator = match distance_metric {
+            DistanceMetric::L2 => "<->",
+            DistanceMetric::Cosine => "<=>",
+            // The negative inner product, so that smaller is closer as for the others.
+            DistanceMetric::InnerProduct => "<#>",
+        };
+
         let sql = format!(
             r#"WITH filtered_embedding_dims AS MATERIALIZED (
                 SELECT
@@ -198,7 +333,7 @@ impl VectorStore for Store {
             FROM (
                 SELECT
                     filtered_embedding_dims.*,
-                    embedding <=> $2 AS distance
+                    embedding {} $2 AS distance
                 FROM
                     filtered_embedding_dims
                     JOIN {} ON filtered_embedding_dims.collection_id = {}.uuid
@@ -206,9 +341,10 @@ impl VectorStore for Store {
             ) AS data
             WHERE {}
             ORDER BY
-                data.distance DESC
+                data.distance ASC
             LIMIT $3"#,
             self.embedder_table_name,
+            operator,
             self.collection_table_name,
             self.collection_table_name,
             self.collection_table_name,
@@ -222,22 +358,17 @@ impl VectorStore for Store {
 
         let rows = sqlx::query(&sql)
             .bind(vector_dims as i64)
-            .bind(&Vector::from(
-                query_vector
-                    .into_iter()
-                    .map(|x| x as f32)
-                    .collect::<Vec<f32>>(),
-            ))
+            .bind(&Vector::from(query_vector))
             .bind(limit as i32)
             .fetch_all(&self.pool)
             .await?;
 
-        let docs = rows
+        let mut docs = rows
             .into_iter()
             .map(|row| {
                 let page_content: String = row.try_get(0)?;
                 let metadata_json: Value = row.try_get(1)?;
-                let score: f64 = row.try_get(2)?;
+                let distance: f64 = row.try_get(2)?;
 
                 let metadata = if let Value::Object(obj) = metadata_json {
                     obj.into_iter().collect()
@@ -245,6 +376,12 @@ impl VectorStore for Store {
                     HashMap::new() // Or handle this case as needed
                 };
 
+                let score = match distance_metric {
+                    DistanceMetric::L2 => 1.0 / (1.0 + distance),
+                    DistanceMetric::Cosine => 1.0 - distance,
+                    DistanceMetric::InnerProduct => -distance,
+                };
+
                 Ok(Document {
                     page_content,
                     metadata,
@@ -253,6 +390,149 @@ impl VectorStore for Store {
             })
             .collect::<Result<Vec<Document>, sqlx::Error>>()?;
 
+        if let Some(score_threshold) = opt.score_threshold {
+            // Inner products are unbounded, the other scores are at most 1.
+            if distance_metric != DistanceMetric::InnerProduct {
+                self.get_score_threshold(opt)?;
+            }
+            docs.retain(|doc| doc.score >= score_threshold as f64);
+        }
+
         Ok(docs)
     }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = self.get_filters(opt)?;
+        let mut sql = format!(
+            r#"SELECT COUNT(*) FROM {} e JOIN {} c ON e.collection_id = c.uuid WHERE c.name = $1"#,
+            self.embedder_table_name, self.collection_table_name
+        );
+        for i in 0..filter.len() {
+            sql.push_str(&format!(
+                " AND (e.cmetadata ->> ${}) = ${}",
+                2 * i + 2,
+                2 * i + 3
+            ));
+        }
+
+        let mut query = sqlx::query(&sql).bind(self.get_name_space(opt));
+        for (k, v) in &filter {
+            query = query
+                .bind(k)
+                .bind(v.to_string().trim_matches('"').to_string());
+        }
+        let count: i64 = query.fetch_one(&self.pool).await?.try_get(0)?;
+        Ok(count as usize)
+    }
+
+    /// The key of each document is stored as its `uuid`.
+    async fn upsert_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let keys = upsert_keys(docs, opt)?;
+
+        let rows = sqlx::query(&format!(
+            r#"SELECT "uuid", document FROM {} WHERE "uuid" = ANY($1)"#,
+            self.embedder_table_name
+        ))
+        .bind(&keys)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut stored = HashMap::new();
+        for row in rows {
+            let id: String = row.try_get(0)?;
+            let document: String = row.try_get(1)?;
+            stored.insert(id, document);
+        }
+
+        // The embedding of an unchanged text is kept, unless a template may embed metadata.
+        let reembed: Vec<bool> = docs
+            .iter()
+            .zip(&keys)
+            .map(|(doc, key)| {
+                stored.get(key) != Some(&doc.page_content) || opt.embedding_template.is_some()
+            })
+            .collect();
+        let texts: Vec<String> = docs
+            .iter()
+            .zip(&reembed)
+            .filter(|(_, reembed)| **reembed)
+            .map(|(d, _)| opt.embedding_text(d))
+            .collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vectors = if texts.is_empty() {
+            Vec::new()
+        } else {
+            embedder.embed_documents(&texts).await?
+        };
+        if vectors.len() != texts.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut vectors = vectors.into_iter();
+        for ((doc, key), reembed) in docs.iter().zip(&keys).zip(reembed) {
+            if !reembed {
+                sqlx::query(&format!(
+                    r#"UPDATE {} SET cmetadata = $1 WHERE "uuid" = $2"#,
+                    self.embedder_table_name
+                ))
+                .bind(json!(&doc.metadata))
+                .bind(key)
+                .execute(&mut *tx)
+                .await?;
+                continue;
+            }
+            let vector = vectors
+                .next()
+                .ok_or("Missing embedding for upserted document")?;
+            sqlx::query(&format!(
+                r#"INSERT INTO {}
+(uuid, document, embedding, cmetadata, collection_id) VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT ("uuid") DO UPDATE SET
+document = EXCLUDED.document, embedding = EXCLUDED.embedding,
+cmetadata = EXCLUDED.cmetadata, collection_id = EXCLUDED.collection_id"#,
+                self.embedder_table_name
+            ))
+            .bind(key)
+            .bind(&doc.page_content)
+            .bind(&Vector::from(vector))
+            .bind(json!(&doc.metadata))
+            .bind(&self.collection_uuid)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(keys)
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let rows = sqlx::query(&format!(
+            r#"SELECT "uuid", document, cmetadata FROM {} WHERE "uuid" = ANY($1) AND collection_id = $2"#,
+            self.embedder_table_name
+        ))
+        .bind(ids)
+        .bind(&self.collection_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut found = HashMap::new();
+        for row in rows {
+            let id: String = row.try_get(0)?;
+            let page_content: String = row.try_get(1)?;
+            let metadata = match row.try_get::<Value, _>(2)? {
+                Value::Object(obj) => obj.into_iter().collect(),
+                _ => HashMap::new(),
+            };
+            found.insert(id, Document::new(page_content).with_metadata(metadata));
+        }
+        order_by_ids(ids, found, opt)
+    }
 }
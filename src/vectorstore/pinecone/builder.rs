@@ -0,0 +1,140 @@
+use std::{error::Error, sync::Arc};
+
+use serde_json::Value;
+
+use super::{Store, API_VERSION};
+use crate::embedding::embedder_trait::Embedder;
+
+pub struct StoreBuilder {
+    api_key: Option<String>,
+    index_name: Option<String>,
+    namespace: String,
+    environment: Option<String>,
+    host: Option<String>,
+    embedder: Option<Arc<dyn Embedder>>,
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            api_key: None,
+            index_name: None,
+            namespace: String::new(),
+            environment: None,
+            host: None,
+            embedder: None,
+        }
+    }
+
+    /// Pinecone API key. REQUIRED.
+    pub fn api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Name of the index, used to look up its host when `host` isn't set.
+    pub fn index_name<S: Into<String>>(mut self, index_name: S) -> Self {
+        self.index_name = Some(index_name.into());
+        self
+    }
+
+    /// Namespace of the index the documents are read from and written to, unless a call sets
+    /// `VecStoreOptions::name_space`. Defaults to the default namespace, `""`.
+    pub fn namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Environment of a legacy pod-based index, e.g. `us-east1-gcp`, whose host is then
+    /// looked up on that environment's controller instead of the global API.
+    pub fn environment<S: Into<String>>(mut self, environment: S) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Host of the index, as shown in the Pinecone console, e.g.
+    /// `my-index-abc123.svc.aped-4627-b74a.pinecone.io`. Spares looking it up from
+    /// `index_name`. Served over https unless the host has a scheme, e.g. a local emulator
+    /// at `http://localhost:5081`.
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Embeddings provider for the Store. REQUIRED.
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("'embedder' is required")?;
+        let api_key = self.api_key.ok_or("'api_key' is required")?;
+        let client = reqwest::Client::new();
+
+        let host = match self.host {
+            Some(host) => host,
+            None => {
+                let index_name = self
+                    .index_name
+                    .ok_or("Either 'host' or 'index_name' is required")?;
+                lookup_host(&client, &api_key, &index_name, self.environment.as_deref()).await?
+            }
+        };
+        let host = host.trim_end_matches('/');
+        let base_url = if host.contains("://") {
+            host.to_string()
+        } else {
+            format!("https://{}", host)
+        };
+
+        Ok(Store {
+            client,
+            base_url,
+            api_key,
+            namespace: self.namespace,
+            embedder,
+        })
+    }
+}
+
+/// Looks up the host of an index on the control plane: the global API for serverless and
+/// current pod-based indexes, or the controller of `environment` for legacy ones.
+async fn lookup_host(
+    client: &reqwest::Client,
+    api_key: &str,
+    index_name: &str,
+    environment: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let url = match environment {
+        Some(environment) => format!(
+            "https://controller.{}.pinecone.io/databases/{}",
+            environment, index_name
+        ),
+        None => format!("https://api.pinecone.io/indexes/{}", index_name),
+    };
+    let response = client
+        .get(url)
+        .header("Api-Key", api_key)
+        .header("X-Pinecone-API-Version", API_VERSION)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Pinecone request failed with {}: {}", status, body).into());
+    }
+
+    let index: Value = response.json().await?;
+    index["host"]
+        .as_str()
+        .or_else(|| index["status"]["host"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| format!("Pinecone index {:?} has no host yet", index_name).into())
+}
@@ -0,0 +1,5 @@
+mod builder;
+mod pinecone;
+
+pub use builder::*;
+pub use pinecone::*;
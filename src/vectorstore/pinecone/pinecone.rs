@@ -0,0 +1,347 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{Condition, FilterOp, MetadataFilter, VecStoreOptions, VectorStore},
+};
+
+/// Version of the Pinecone REST API the store speaks.
+pub(crate) const API_VERSION: &str = "2024-07";
+/// Metadata field holding the content of a document.
+const TEXT_KEY: &str = "text";
+
+/// A vector store backed by a [Pinecone](https://www.pinecone.io) index, through its REST
+/// API. The content of each document is stored in the `text` metadata field of its vector,
+/// next to the document's metadata.
+pub struct Store {
+    pub(crate) client: reqwest::Client,
+    pub(crate) base_url: String,
+    pub(crate) api_key: String,
+    pub(crate) namespace: String,
+    pub(crate) embedder: Arc<dyn Embedder>,
+}
+
+impl Store {
+    /// Deletes the vectors with the given ids from the store's namespace. Ids that don't
+    /// exist are ignored.
+    pub async fn delete_documents_by_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        self.post(
+            "/vectors/delete",
+            json!({ "ids": ids, "namespace": self.namespace }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn namespace<'a>(&'a self, opt: &'a VecStoreOptions) -> &'a str {
+        opt.name_space.as_deref().unwrap_or(&self.namespace)
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value, Box<dyn Error>> {
+        let response = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .header("Api-Key", &self.api_key)
+            .header("X-Pinecone-API-Version", API_VERSION)
+            .json(&body)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Pinecone request failed with {}: {}", status, body).into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// Translates `VecStoreOptions::filters` to a Pinecone metadata filter, e.g.
+/// `{"year": {"$gte": 2023}, "lang": "rust"}` to
+/// `{"$and": [{"year": {"$gte": 2023}}, {"lang": {"$eq": "rust"}}]}`. `None` for an empty
+/// filter. `$ilike` has no Pinecone equivalent and errors.
+pub(crate) fn pinecone_filter(filters: &Value) -> Result<Option<Value>, Box<dyn Error>> {
+    let filter = MetadataFilter::parse(filters)?;
+    let mut clauses = filter
+        .conditions
+        .iter()
+        .map(pinecone_condition)
+        .collect::<Result<Vec<Value>, _>>()?;
+    Ok(match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(json!({ "$and": clauses })),
+    })
+}
+
+fn pinecone_condition(condition: &Condition) -> Result<Value, Box<dyn Error>> {
+    let (op, operand) = match &condition.op {
+        FilterOp::Eq(value) => ("$eq", value),
+        FilterOp::Ne(value) => ("$ne", value),
+        FilterOp::Gt(value) => ("$gt", value),
+        FilterOp::Gte(value) => ("$gte", value),
+        FilterOp::Lt(value) => ("$lt", value),
+        FilterOp::Lte(value) => ("$lte", value),
+        FilterOp::In(values) => return Ok(json!({ &condition.key: { "$in": values } })),
+        FilterOp::ILike(_) => {
+            return Err(format!(
+                "$ilike on {:?} is not supported by the Pinecone vector store",
+                condition.key
+            )
+            .into())
+        }
+    };
+    if !matches!(
+        operand,
+        Value::String(_) | Value::Number(_) | Value::Bool(_)
+    ) {
+        return Err(format!(
+            "Pinecone can only compare {:?} with a string, number or boolean, got {}",
+            condition.key, operand
+        )
+        .into());
+    }
+    Ok(json!({ &condition.key: { op: operand } }))
+}
+
+/// The metadata of the vector of `doc`: its metadata and its content under `text`. Pinecone
+/// takes strings, numbers, booleans and lists of strings.
+fn pinecone_metadata(doc: &Document) -> Result<Value, Box<dyn Error>> {
+    for (key, value) in &doc.metadata {
+        let supported = match value {
+            Value::String(_) | Value::Number(_) | Value::Bool(_) => true,
+            Value::Array(values) => values.iter().all(Value::is_string),
+            _ => false,
+        };
+        if !supported {
+            return Err(format!(
+                "Pinecone metadata values must be strings, numbers, booleans or lists of \
+                 strings, {:?} is {}",
+                key, value
+            )
+            .into());
+        }
+        if key == TEXT_KEY {
+            return Err(format!(
+                "The {:?} metadata key is reserved for the document content",
+                TEXT_KEY
+            )
+            .into());
+        }
+    }
+    let mut metadata = json!(doc.metadata);
+    metadata[TEXT_KEY] = json!(doc.page_content);
+    Ok(metadata)
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Upserts the documents under new UUIDs.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let metadatas = docs
+            .iter()
+            .map(pinecone_metadata)
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let values = embedder.embed_documents(&texts).await?;
+        if values.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let vectors: Vec<Value> = ids
+            .iter()
+            .zip(values)
+            .zip(metadatas)
+            .map(|((id, values), metadata)| {
+                json!({ "id": id, "values": values, "metadata": metadata })
+            })
+            .collect();
+
+        self.post(
+            "/vectors/upsert",
+            json!({ "vectors": vectors, "namespace": self.namespace(opt) }),
+        )
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Scores are Pinecone's: the similarity for cosine and dotproduct indexes, and the
+    /// squared distance for euclidean ones, which `score_threshold` doesn't suit.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let filter = match &opt.filters {
+            Some(filters) => pinecone_filter(filters)?,
+            None => None,
+        };
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vector = embedder.embed_query(query).await?;
+
+        let mut body = json!({
+            "vector": vector,
+            "topK": limit,
+            "includeMetadata": true,
+            "namespace": self.namespace(opt),
+        });
+        if let Some(filter) = filter {
+            body["filter"] = filter;
+        }
+        let response = self.post("/query", body).await?;
+
+        let matches = response["matches"].as_array().cloned().unwrap_or_default();
+        let mut docs = Vec::with_capacity(matches.len());
+        for item in matches {
+            let mut metadata: HashMap<String, Value> = match &item["metadata"] {
+                Value::Object(map) => map.clone().into_iter().collect(),
+                _ => HashMap::new(),
+            };
+            let page_content = match metadata.remove(TEXT_KEY) {
+                Some(Value::String(text)) => text,
+                _ => String::new(),
+            };
+            docs.push(Document {
+                page_content,
+                metadata,
+                score: item["score"].as_f64().unwrap_or_default(),
+            });
+        }
+
+        if let Some(threshold) = opt.score_threshold {
+            docs.retain(|doc| doc.score >= threshold as f64);
+        }
+        Ok(docs)
+    }
+
+    /// Counts the vectors of the namespace, from the index statistics. Filters are not
+    /// supported, as serverless indexes don't take them there.
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        if opt.filters.is_some() {
+            return Err("Pinecone can't count documents matching filters".into());
+        }
+        let stats = self.post("/describe_index_stats", json!({})).await?;
+        let count = stats["namespaces"][self.namespace(opt)]["vectorCount"]
+            .as_u64()
+            .unwrap_or(0);
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinecone_filter() {
+        assert_eq!(
+            pinecone_filter(&json!({"lang": "rust"})).unwrap(),
+            Some(json!({"lang": {"$eq": "rust"}}))
+        );
+        assert_eq!(
+            pinecone_filter(&json!({"tags": ["a", "b"]})).unwrap(),
+            Some(json!({"tags": {"$in": ["a", "b"]}}))
+        );
+
+        let filter = pinecone_filter(&json!({"year": {"$gte": 2023, "$ne": 2024}}))
+            .unwrap()
+            .unwrap();
+        let clauses = filter["$and"].as_array().unwrap();
+        assert_eq!(clauses.len(), 2);
+        assert!(clauses.contains(&json!({"year": {"$gte": 2023}})));
+        assert!(clauses.contains(&json!({"year": {"$ne": 2024}})));
+
+        assert_eq!(pinecone_filter(&json!({})).unwrap(), None);
+        assert!(pinecone_filter(&json!({"format": {"$ilike": "pdf"}})).is_err());
+        assert!(pinecone_filter(&json!({"author": {"name": "x"}})).is_err());
+    }
+
+    #[test]
+    fn test_pinecone_metadata() {
+        let doc = Document::new("content").with_metadata(HashMap::from([
+            ("year".to_string(), json!(2024)),
+            ("tags".to_string(), json!(["a", "b"])),
+        ]));
+        assert_eq!(
+            pinecone_metadata(&doc).unwrap(),
+            json!({"year": 2024, "tags": ["a", "b"], "text": "content"})
+        );
+
+        let nested = Document::new("content").with_metadata(HashMap::from([(
+            "author".to_string(),
+            json!({"name": "x"}),
+        )]));
+        assert!(pinecone_metadata(&nested).is_err());
+        let reserved = Document::new("content")
+            .with_metadata(HashMap::from([("text".to_string(), json!("x"))]));
+        assert!(pinecone_metadata(&reserved).is_err());
+    }
+
+    /// Runs against Pinecone's local index emulator, so needs Docker.
+    #[tokio::test]
+    #[ignore]
+    async fn test_pinecone_store() {
+        use testcontainers::{
+            core::{IntoContainerPort, WaitFor},
+            runners::AsyncRunner,
+            GenericImage, ImageExt,
+        };
+
+        use crate::vectorstore::{pinecone::StoreBuilder, test_utils::LetterEmbedder};
+
+        let container = GenericImage::new("ghcr.io/pinecone-io/pinecone-index", "latest")
+            .with_exposed_port(5081.tcp())
+            .with_wait_for(WaitFor::seconds(5))
+            .with_env_var("PORT", "5081")
+            .with_env_var("INDEX_TYPE", "serverless")
+            .with_env_var("DIMENSION", "3")
+            .with_env_var("METRIC", "cosine")
+            .start()
+            .await
+            .unwrap();
+        let port = container.get_host_port_ipv4(5081).await.unwrap();
+
+        let store = StoreBuilder::new()
+            .api_key("pclocal")
+            .host(format!("http://localhost:{}", port))
+            .namespace("tests")
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        let opt = VecStoreOptions::default();
+
+        let docs = [("aaa", "x"), ("aab", "y"), ("ccc", "x")].map(|(text, tag)| {
+            Document::new(text).with_metadata(HashMap::from([("tag".to_string(), json!(tag))]))
+        });
+        let ids = store.add_documents(&docs, &opt).await.unwrap();
+
+        let found = store.similarity_search("a", 2, &opt).await.unwrap();
+        assert_eq!(found[0].page_content, "aaa");
+        assert_eq!(found[1].page_content, "aab");
+        assert_eq!(found[0].metadata["tag"], "x");
+
+        let tagged = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        let found = store.similarity_search("c", 10, &tagged).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].page_content, "ccc");
+
+        store.delete_documents_by_ids(&ids[..1]).await.unwrap();
+        let found = store.similarity_search("a", 10, &opt).await.unwrap();
+        assert_eq!(found.len(), 2);
+    }
+}
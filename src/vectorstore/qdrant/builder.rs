@@ -1,10 +1,67 @@
 use crate::embedding::Embedder;
 use crate::vectorstore::qdrant::Store;
-use qdrant_client::qdrant::{CreateCollectionBuilder, Distance, Filter, VectorParamsBuilder};
+use qdrant_client::qdrant::{
+    vectors_config, CreateCollectionBuilder, Distance, Filter, HnswConfigDiff, QuantizationConfig,
+    VectorParamsBuilder,
+};
 use qdrant_client::Qdrant;
 use std::error::Error;
 use std::sync::Arc;
 
+/// Parameters used to create the Qdrant collection when it doesn't exist.
+///
+/// When set on the [`StoreBuilder`], an existing collection is also validated against
+/// this configuration, and the embedder's dimension is checked against `vector_size`.
+#[derive(Clone, Debug)]
+pub struct CollectionConfig {
+    pub(crate) distance: Distance,
+    pub(crate) vector_size: Option<u64>,
+    pub(crate) hnsw_config: Option<HnswConfigDiff>,
+    pub(crate) quantization_config: Option<QuantizationConfig>,
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CollectionConfig {
+    /// Cosine distance, vector size taken from the embedder.
+    pub fn new() -> Self {
+        CollectionConfig {
+            distance: Distance::Cosine,
+            vector_size: None,
+            hnsw_config: None,
+            quantization_config: None,
+        }
+    }
+
+    pub fn distance(mut self, distance: Distance) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// Expected vector size. Must match the embedder's output dimension.
+    pub fn vector_size(mut self, vector_size: u64) -> Self {
+        self.vector_size = Some(vector_size);
+        self
+    }
+
+    pub fn hnsw_config(mut self, hnsw_config: HnswConfigDiff) -> Self {
+        self.hnsw_config = Some(hnsw_config);
+        self
+    }
+
+    pub fn quantization_config<Q: Into<QuantizationConfig>>(
+        mut self,
+        quantization_config: Q,
+    ) -> Self {
+        self.quantization_config = Some(quantization_config.into());
+        self
+    }
+}
+
 pub struct StoreBuilder {
     client: Option<Qdrant>,
     embedder: Option<Arc<dyn Embedder>>,
@@ -13,6 +70,7 @@ pub struct StoreBuilder {
     metadata_field: String,
     recreate_collection: bool,
     search_filter: Option<Filter>,
+    collection_config: Option<CollectionConfig>,
 }
 
 impl Default for StoreBuilder {
@@ -32,6 +90,7 @@ impl StoreBuilder {
             content_field: "page_content".to_string(),
             metadata_field: "metadata".to_string(),
             recreate_collection: false,
+            collection_config: None,
         }
     }
 
@@ -87,6 +146,13 @@ impl StoreBuilder {
         self
     }
 
+    /// Configuration used to create the collection if it doesn't exist, and to
+    /// validate an existing one. See [`CollectionConfig`].
+    pub fn collection_config(mut self, collection_config: CollectionConfig) -> Self {
+        self.collection_config = Some(collection_config);
+        self
+    }
+
     /// Build the Store object.
     pub async fn build(mut self) -> Result<Store, Box<dyn Error>> {
         let client = self.client.take().ok_or("'client' is required")?;
@@ -103,21 +169,51 @@ impl StoreBuilder {
             client.delete_collection(&collection_name).await?;
         }
 
-        // Create the collection if it doesn't exist or recreate_collection flag is set
-        if !collection_exists || self.recreate_collection {
+        let create = !collection_exists || self.recreate_collection;
+        if create || self.collection_config.is_some() {
+            let config = self.collection_config.take().unwrap_or_default();
+
             // Embed some text to get the dimension of the embeddings
             let embeddings = embedder
                 .embed_query("Text to retrieve embeddings dimension")
                 .await?;
             let embeddings_dimension = embeddings.len() as u64;
 
-            client
-                .create_collection(
-                    CreateCollectionBuilder::new(&collection_name).vectors_config(
-                        VectorParamsBuilder::new(embeddings_dimension, Distance::Cosine),
-                    ),
+            if let Some(vector_size) = config.vector_size {
+                if vector_size != embeddings_dimension {
+                    return Err(format!(
+                        "Configured vector size {} doesn't match the embedder dimension {}",
+                        vector_size, embeddings_dimension
+                    )
+                    .into());
+                }
+            }
+
+            if create {
+                let mut vector_params =
+                    VectorParamsBuilder::new(embeddings_dimension, config.distance);
+                if let Some(hnsw_config) = config.hnsw_config {
+                    vector_params = vector_params.hnsw_config(hnsw_config);
+                }
+                if let Some(quantization_config) = config.quantization_config {
+                    vector_params = vector_params.quantization_config(quantization_config);
+                }
+
+                client
+                    .create_collection(
+                        CreateCollectionBuilder::new(&collection_name)
+                            .vectors_config(vector_params),
+                    )
+                    .await?;
+            } else {
+                validate_collection(
+                    &client,
+                    &collection_name,
+                    embeddings_dimension,
+                    config.distance,
                 )
                 .await?;
+            }
         }
 
         Ok(Store {
@@ -130,3 +226,51 @@ impl StoreBuilder {
         })
     }
 }
+
+/// Errors if the existing collection's vector size or distance differs from the expected ones.
+async fn validate_collection(
+    client: &Qdrant,
+    collection_name: &str,
+    vector_size: u64,
+    distance: Distance,
+) -> Result<(), Box<dyn Error>> {
+    let info = client.collection_info(collection_name).await?;
+    let params = info
+        .result
+        .and_then(|info| info.config)
+        .and_then(|config| config.params)
+        .and_then(|params| params.vectors_config)
+        .and_then(|vectors_config| vectors_config.config);
+
+    match params {
+        Some(vectors_config::Config::Params(params)) => {
+            if params.size != vector_size {
+                return Err(format!(
+                    "Collection '{}' has vector size {}, expected {}",
+                    collection_name, params.size, vector_size
+                )
+                .into());
+            }
+            if params.distance != distance as i32 {
+                return Err(format!(
+                    "Collection '{}' uses distance {:?}, expected {:?}",
+                    collection_name,
+                    Distance::try_from(params.distance).unwrap_or(Distance::UnknownDistance),
+                    distance
+                )
+                .into());
+            }
+            Ok(())
+        }
+        Some(vectors_config::Config::ParamsMap(_)) => Err(format!(
+            "Collection '{}' uses named vectors, which are not supported",
+            collection_name
+        )
+        .into()),
+        None => Err(format!(
+            "Could not read the vector configuration of collection '{}'",
+            collection_name
+        )
+        .into()),
+    }
+}
@@ -1,7 +1,11 @@
 use async_trait::async_trait;
 use qdrant_client::client::Payload;
-use qdrant_client::qdrant::{Filter, PointStruct, SearchPointsBuilder, UpsertPointsBuilder};
+use qdrant_client::qdrant::{
+    point_id::PointIdOptions, CountPointsBuilder, Filter, GetPointsBuilder, PointId, PointStruct,
+    SearchPointsBuilder, UpsertPointsBuilder,
+};
 use serde_json::json;
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
 
@@ -14,7 +18,7 @@ pub use qdrant_client::Qdrant as QdrantClient;
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{order_by_ids, VecStoreOptions, VectorStore},
 };
 use uuid::Uuid;
 
@@ -37,7 +41,7 @@ impl VectorStore for Store {
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
 
         let ids = docs.iter().map(|_| Uuid::new_v4().to_string());
         let vectors = embedder.embed_documents(&texts).await?.into_iter();
@@ -51,7 +55,6 @@ impl VectorStore for Store {
         let mut points: Vec<PointStruct> = Vec::with_capacity(docs.len());
 
         for (id, (vector, payload)) in ids.clone().zip(vectors.zip(payloads)) {
-            let vector: Vec<f32> = vector.into_iter().map(|f| f as f32).collect();
             let point = PointStruct::new(id, vector, Payload::try_from(payload).unwrap());
             points.push(point);
         }
@@ -84,12 +87,7 @@ impl VectorStore for Store {
         }
 
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
-        let query_vector: Vec<f32> = embedder
-            .embed_query(query)
-            .await?
-            .into_iter()
-            .map(|f| f as f32)
-            .collect();
+        let query_vector = embedder.embed_query(query).await?;
 
         let mut operation =
             SearchPointsBuilder::new(&self.collection_name, query_vector, limit as u64)
@@ -123,4 +121,55 @@ impl VectorStore for Store {
 
         Ok(documents)
     }
+
+    /// Counts the points matching `search_filter`, exactly.
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        if opt.filters.is_some() {
+            return Err(
+                "'qdrant_client' doesn't support 'serde_json::Value' filters. Use `search_filter` when constructing VectorStore instead"
+                    .into(),
+            );
+        }
+
+        let mut operation = CountPointsBuilder::new(&self.collection_name).exact(true);
+        if let Some(filter) = &self.search_filter {
+            operation = operation.filter(filter.clone());
+        }
+        let response = self.client.count(operation).await?;
+        Ok(response.result.map_or(0, |result| result.count as usize))
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let point_ids: Vec<PointId> = ids.iter().map(|id| PointId::from(id.clone())).collect();
+        let response = self
+            .client
+            .get_points(GetPointsBuilder::new(&self.collection_name, point_ids).with_payload(true))
+            .await?;
+
+        let mut found = HashMap::new();
+        for point in response.result {
+            let id = match point.id.and_then(|id| id.point_id_options) {
+                Some(PointIdOptions::Uuid(uuid)) => uuid,
+                Some(PointIdOptions::Num(num)) => num.to_string(),
+                None => continue,
+            };
+            let page_content = match point.payload.get(&self.content_field) {
+                Some(value) => match value.clone().into_json() {
+                    serde_json::Value::String(s) => s,
+                    other => other.to_string(),
+                },
+                None => String::new(),
+            };
+            let metadata = match point.payload.get(&self.metadata_field) {
+                Some(value) => serde_json::from_value(value.clone().into_json())?,
+                None => HashMap::new(),
+            };
+            found.insert(id, Document::new(page_content).with_metadata(metadata));
+        }
+        order_by_ids(ids, found, opt)
+    }
 }
@@ -0,0 +1,186 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use ::redis::aio::{ConnectionManager, ConnectionManagerConfig};
+
+use super::{
+    MetadataFieldType, Store, VectorAlgorithm, CONTENT_FIELD, METADATA_FIELD, VECTOR_FIELD,
+};
+use crate::{
+    embedding::embedder_trait::Embedder,
+    vectorstore::{probe_vector_dimensions, DistanceMetric},
+};
+
+pub struct StoreBuilder {
+    url: Option<String>,
+    connection: Option<ConnectionManager>,
+    connection_config: ConnectionManagerConfig,
+    index_name: String,
+    vector_algorithm: VectorAlgorithm,
+    ef_construction: usize,
+    m: usize,
+    distance_metric: DistanceMetric,
+    vector_dimensions: i32,
+    metadata_fields: Vec<(String, MetadataFieldType)>,
+    embedder: Option<Arc<dyn Embedder>>,
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            url: None,
+            connection: None,
+            connection_config: ConnectionManagerConfig::new(),
+            index_name: "documents".to_string(),
+            vector_algorithm: VectorAlgorithm::Hnsw,
+            ef_construction: 200,
+            m: 16,
+            distance_metric: DistanceMetric::Cosine,
+            vector_dimensions: 0,
+            metadata_fields: Vec::new(),
+            embedder: None,
+        }
+    }
+
+    /// URL of the Redis server, which must have the RediSearch module, e.g.
+    /// `redis://localhost:6379`. Either this or `connection` is REQUIRED.
+    pub fn url<S: Into<String>>(mut self, url: S) -> Self {
+        self.url = Some(url.into());
+        self.connection = None;
+        self
+    }
+
+    /// Uses an existing connection manager instead of connecting to `url`.
+    pub fn connection(mut self, connection: ConnectionManager) -> Self {
+        self.connection = Some(connection);
+        self.url = None;
+        self
+    }
+
+    /// How long connecting to `url` may take, including reconnections.
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_config = self.connection_config.set_connection_timeout(timeout);
+        self
+    }
+
+    /// How long a command may wait for its response.
+    pub fn response_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_config = self.connection_config.set_response_timeout(timeout);
+        self
+    }
+
+    /// How many times to retry reconnecting after the connection is lost.
+    pub fn number_of_retries(mut self, retries: usize) -> Self {
+        self.connection_config = self.connection_config.set_number_of_retries(retries);
+        self
+    }
+
+    /// Name of the RediSearch index. The documents are stored in hashes whose keys start with
+    /// `<index_name>:`. Defaults to `documents`.
+    pub fn index_name(mut self, index_name: &str) -> Self {
+        self.index_name = index_name.into();
+        self
+    }
+
+    /// Index algorithm of the vector field. Defaults to [`VectorAlgorithm::Hnsw`].
+    pub fn vector_algorithm(mut self, vector_algorithm: VectorAlgorithm) -> Self {
+        self.vector_algorithm = vector_algorithm;
+        self
+    }
+
+    /// `EF_CONSTRUCTION` of an HNSW index. Defaults to 200.
+    pub fn ef_construction(mut self, ef_construction: usize) -> Self {
+        self.ef_construction = ef_construction;
+        self
+    }
+
+    /// `M` of an HNSW index, the maximum number of edges per node. Defaults to 16.
+    pub fn m(mut self, m: usize) -> Self {
+        self.m = m;
+        self
+    }
+
+    /// `DISTANCE_METRIC` of the vector field, which also decides how distances are turned
+    /// into scores. Defaults to [`DistanceMetric::Cosine`].
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Dimension of the vector field. Probed from the embedder when unset.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    /// Indexes the metadata value at `key` as a field of `field_type`, so that
+    /// `VecStoreOptions::filters` can filter on it. Only indexed keys can be filtered on.
+    pub fn metadata_field(mut self, key: &str, field_type: MetadataFieldType) -> Self {
+        self.metadata_fields.push((key.to_string(), field_type));
+        self
+    }
+
+    /// Embeddings provider for the Store. REQUIRED.
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Connects and creates the index if it doesn't exist. An existing index is used as is.
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("'embedder' is required")?;
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        };
+        if !valid_name(&self.index_name) {
+            return Err(format!("Invalid Redis index name {:?}", self.index_name).into());
+        }
+        for (key, _) in &self.metadata_fields {
+            if !valid_name(key)
+                || [CONTENT_FIELD, METADATA_FIELD, VECTOR_FIELD].contains(&key.as_str())
+            {
+                return Err(format!("Invalid metadata field name {:?}", key).into());
+            }
+        }
+
+        let connection = match self.connection {
+            Some(connection) => connection,
+            None => {
+                let url = self.url.ok_or("Either 'url' or 'connection' is required")?;
+                let client = ::redis::Client::open(url)?;
+                ConnectionManager::new_with_config(client, self.connection_config).await?
+            }
+        };
+
+        let store = Store {
+            connection,
+            key_prefix: format!("{}:", self.index_name),
+            index_name: self.index_name,
+            distance_metric: self.distance_metric,
+            metadata_fields: self.metadata_fields,
+            embedder,
+        };
+
+        if !store.index_exists().await? {
+            let dimensions =
+                probe_vector_dimensions(store.embedder.as_ref(), self.vector_dimensions).await?;
+            store
+                .create_index(
+                    self.vector_algorithm,
+                    dimensions,
+                    self.ef_construction,
+                    self.m,
+                )
+                .await?;
+        }
+        Ok(store)
+    }
+}
@@ -0,0 +1,5 @@
+mod builder;
+mod redis;
+
+pub use builder::*;
+pub use redis::*;
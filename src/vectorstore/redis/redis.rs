@@ -0,0 +1,544 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use ::redis::{aio::ConnectionManager, from_redis_value, RedisResult, Value as RedisValue};
+use async_trait::async_trait;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        Condition, DistanceMetric, FilterOp, MetadataFilter, VecStoreOptions, VectorStore,
+    },
+};
+
+/// Hash fields of a document.
+pub(crate) const CONTENT_FIELD: &str = "content";
+pub(crate) const METADATA_FIELD: &str = "metadata";
+pub(crate) const VECTOR_FIELD: &str = "vector";
+/// Alias of the KNN distance in search results.
+const DISTANCE_FIELD: &str = "__distance";
+/// Separator of the values of a TAG field.
+const TAG_SEPARATOR: &str = ",";
+
+/// Index algorithm of the vector field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorAlgorithm {
+    /// Approximate search on a Hierarchical Navigable Small World graph.
+    Hnsw,
+    /// Exact search by brute force, for small datasets.
+    Flat,
+}
+
+/// How an indexed metadata value is indexed, which decides the filters it supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataFieldType {
+    /// Exact, case-insensitive matching of strings (or arrays of strings): `$eq`, `$ne`, `$in`
+    /// and `$ilike`.
+    Tag,
+    /// Numbers: `$eq`, `$ne`, `$in` and the range operators.
+    Numeric,
+}
+
+/// A vector store backed by Redis with the RediSearch module. Documents are stored in hashes
+/// holding their content, their metadata as JSON, their embedding as `FLOAT32` bytes and the
+/// metadata values of the fields declared with `StoreBuilder::metadata_field`.
+pub struct Store {
+    pub(crate) connection: ConnectionManager,
+    pub(crate) index_name: String,
+    pub(crate) key_prefix: String,
+    pub(crate) distance_metric: DistanceMetric,
+    pub(crate) metadata_fields: Vec<(String, MetadataFieldType)>,
+    pub(crate) embedder: Arc<dyn Embedder>,
+}
+
+impl Store {
+    /// Deletes the documents with the given ids. Ids that don't exist are ignored.
+    pub async fn delete_documents_by_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let keys: Vec<String> = ids
+            .iter()
+            .map(|id| format!("{}{}", self.key_prefix, id))
+            .collect();
+        let mut connection = self.connection.clone();
+        let _: () = ::redis::cmd("DEL")
+            .arg(keys)
+            .query_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn index_exists(&self) -> Result<bool, Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let info: RedisResult<RedisValue> = ::redis::cmd("FT.INFO")
+            .arg(&self.index_name)
+            .query_async(&mut connection)
+            .await;
+        match info {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                let message = e.to_string().to_lowercase();
+                if message.contains("unknown index") || message.contains("no such index") {
+                    Ok(false)
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn create_index(
+        &self,
+        algorithm: VectorAlgorithm,
+        dimensions: i32,
+        ef_construction: usize,
+        m: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let metric = match self.distance_metric {
+            DistanceMetric::Cosine => "COSINE",
+            DistanceMetric::L2 => "L2",
+            DistanceMetric::InnerProduct => "IP",
+        };
+
+        let mut cmd = ::redis::cmd("FT.CREATE");
+        cmd.arg(&self.index_name)
+            .arg("ON")
+            .arg("HASH")
+            .arg("PREFIX")
+            .arg(1)
+            .arg(&self.key_prefix)
+            .arg("SCHEMA")
+            .arg(VECTOR_FIELD)
+            .arg("VECTOR");
+        match algorithm {
+            VectorAlgorithm::Hnsw => cmd.arg("HNSW").arg(10),
+            VectorAlgorithm::Flat => cmd.arg("FLAT").arg(6),
+        };
+        cmd.arg("TYPE")
+            .arg("FLOAT32")
+            .arg("DIM")
+            .arg(dimensions)
+            .arg("DISTANCE_METRIC")
+            .arg(metric);
+        if algorithm == VectorAlgorithm::Hnsw {
+            cmd.arg("M")
+                .arg(m)
+                .arg("EF_CONSTRUCTION")
+                .arg(ef_construction);
+        }
+        for (key, field_type) in &self.metadata_fields {
+            match field_type {
+                MetadataFieldType::Tag => {
+                    cmd.arg(key).arg("TAG").arg("SEPARATOR").arg(TAG_SEPARATOR)
+                }
+                MetadataFieldType::Numeric => cmd.arg(key).arg("NUMERIC"),
+            };
+        }
+
+        let mut connection = self.connection.clone();
+        let _: () = cmd.query_async(&mut connection).await?;
+        Ok(())
+    }
+
+    fn score(&self, distance: f64) -> f64 {
+        match self.distance_metric {
+            DistanceMetric::L2 => 1.0 / (1.0 + distance),
+            // COSINE is `1 - cosine similarity` and IP is `1 - inner product`.
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+        }
+    }
+}
+
+/// Translates `VecStoreOptions::filters` to a RediSearch query on the indexed metadata
+/// `fields`, e.g. `{"lang": "rust", "year": {"$gte": 2023}}` to
+/// `@lang:{rust} @year:[2023 +inf]`. Empty for an empty filter. Keys that are not indexed
+/// error.
+pub(crate) fn redis_filter(
+    filters: &Value,
+    fields: &[(String, MetadataFieldType)],
+) -> Result<String, Box<dyn Error>> {
+    let filter = MetadataFilter::parse(filters)?;
+    let clauses = filter
+        .conditions
+        .iter()
+        .map(|condition| {
+            let field_type = fields
+                .iter()
+                .find(|(key, _)| *key == condition.key)
+                .map(|(_, field_type)| *field_type)
+                .ok_or_else(|| {
+                    format!(
+                        "Metadata key {:?} is not indexed; declare it with \
+                         StoreBuilder::metadata_field to filter on it",
+                        condition.key
+                    )
+                })?;
+            match field_type {
+                MetadataFieldType::Tag => tag_clause(condition),
+                MetadataFieldType::Numeric => numeric_clause(condition),
+            }
+        })
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(clauses.join(" "))
+}
+
+fn tag_clause(condition: &Condition) -> Result<String, Box<dyn Error>> {
+    let tags = |values: &[Value]| -> Result<String, Box<dyn Error>> {
+        let tags = values
+            .iter()
+            .map(tag_value)
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(format!("@{}:{{{}}}", condition.key, tags.join(" | ")))
+    };
+    match &condition.op {
+        FilterOp::Eq(value) => tags(std::slice::from_ref(value)),
+        FilterOp::Ne(value) => Ok(format!("-{}", tags(std::slice::from_ref(value))?)),
+        FilterOp::In(values) => tags(values),
+        // TAG fields match case-insensitively.
+        FilterOp::ILike(values) => tags(
+            &values
+                .iter()
+                .map(|value| Value::String(value.clone()))
+                .collect::<Vec<_>>(),
+        ),
+        _ => Err(format!(
+            "Range filters on {:?} need it indexed as a NUMERIC field",
+            condition.key
+        )
+        .into()),
+    }
+}
+
+/// A scalar as a tag, with the characters RediSearch treats as syntax escaped.
+fn tag_value(value: &Value) -> Result<String, Box<dyn Error>> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(_) | Value::Bool(_) => value.to_string(),
+        _ => return Err(format!("Can't match a TAG field with {}", value).into()),
+    };
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if !c.is_alphanumeric() && c != '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Ok(escaped)
+}
+
+fn numeric_clause(condition: &Condition) -> Result<String, Box<dyn Error>> {
+    let number = |value: &Value| match value {
+        Value::Number(n) => Ok(n.to_string()),
+        _ => Err(format!(
+            "{:?} is a NUMERIC field and can't be compared with {}",
+            condition.key, value
+        )),
+    };
+    let key = &condition.key;
+    Ok(match &condition.op {
+        FilterOp::Eq(value) => format!("@{key}:[{n} {n}]", n = number(value)?),
+        FilterOp::Ne(value) => format!("-@{key}:[{n} {n}]", n = number(value)?),
+        FilterOp::Gt(value) => format!("@{key}:[({} +inf]", number(value)?),
+        FilterOp::Gte(value) => format!("@{key}:[{} +inf]", number(value)?),
+        FilterOp::Lt(value) => format!("@{key}:[-inf ({}]", number(value)?),
+        FilterOp::Lte(value) => format!("@{key}:[-inf {}]", number(value)?),
+        FilterOp::In(values) => {
+            let ranges = values
+                .iter()
+                .map(|value| Ok(format!("@{key}:[{n} {n}]", n = number(value)?)))
+                .collect::<Result<Vec<String>, String>>()?;
+            format!("({})", ranges.join(" | "))
+        }
+        FilterOp::ILike(_) => {
+            return Err(format!("$ilike on {:?} needs it indexed as a TAG field", key).into())
+        }
+    })
+}
+
+/// The value of an indexed metadata field in the hash of a document, `None` if the document
+/// doesn't have it.
+fn field_value(
+    key: &str,
+    value: &Value,
+    field_type: MetadataFieldType,
+) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(match (field_type, value) {
+        (_, Value::Null) => None,
+        (MetadataFieldType::Tag, Value::String(s)) => Some(s.clone()),
+        (MetadataFieldType::Tag, Value::Array(values)) => Some(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(TAG_SEPARATOR),
+        ),
+        (MetadataFieldType::Tag, Value::Number(_) | Value::Bool(_)) => Some(value.to_string()),
+        (MetadataFieldType::Numeric, Value::Number(n)) => Some(n.to_string()),
+        _ => {
+            return Err(format!(
+                "Metadata {:?} is indexed as {:?} and can't hold {}",
+                key, field_type, value
+            )
+            .into())
+        }
+    })
+}
+
+fn vector_bytes(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Stores each document in a hash under a new UUID, in one pipeline.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vectors = embedder.embed_documents(&texts).await?;
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let mut pipe = ::redis::pipe();
+        for ((doc, vector), id) in docs.iter().zip(&vectors).zip(&ids) {
+            let mut cmd = ::redis::cmd("HSET");
+            cmd.arg(format!("{}{}", self.key_prefix, id))
+                .arg(CONTENT_FIELD)
+                .arg(&doc.page_content)
+                .arg(METADATA_FIELD)
+                .arg(serde_json::to_string(&doc.metadata)?)
+                .arg(VECTOR_FIELD)
+                .arg(vector_bytes(vector));
+            for (key, field_type) in &self.metadata_fields {
+                if let Some(value) = doc.metadata.get(key) {
+                    if let Some(value) = field_value(key, value, *field_type)? {
+                        cmd.arg(key).arg(value);
+                    }
+                }
+            }
+            pipe.add_command(cmd);
+        }
+
+        let mut connection = self.connection.clone();
+        let _: () = pipe.query_async(&mut connection).await?;
+        Ok(ids)
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let filter = match &opt.filters {
+            Some(filters) => redis_filter(filters, &self.metadata_fields)?,
+            None => String::new(),
+        };
+        let prefilter = if filter.is_empty() {
+            "*".to_string()
+        } else {
+            format!("({})", filter)
+        };
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let mut connection = self.connection.clone();
+        let reply: Vec<RedisValue> = ::redis::cmd("FT.SEARCH")
+            .arg(&self.index_name)
+            .arg(format!(
+                "{}=>[KNN {} @{} $query_vec AS {}]",
+                prefilter, limit, VECTOR_FIELD, DISTANCE_FIELD
+            ))
+            .arg("PARAMS")
+            .arg(2)
+            .arg("query_vec")
+            .arg(vector_bytes(&query_vector))
+            .arg("SORTBY")
+            .arg(DISTANCE_FIELD)
+            .arg("RETURN")
+            .arg(3)
+            .arg(CONTENT_FIELD)
+            .arg(METADATA_FIELD)
+            .arg(DISTANCE_FIELD)
+            .arg("LIMIT")
+            .arg(0)
+            .arg(limit)
+            .arg("DIALECT")
+            .arg(2)
+            .query_async(&mut connection)
+            .await?;
+
+        // The total count, then the key and the fields of each document.
+        let mut docs = Vec::new();
+        for fields in reply.iter().skip(2).step_by(2) {
+            let fields: Vec<String> = from_redis_value(fields)?;
+            let mut fields: HashMap<String, String> = fields
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            let metadata = match fields.get(METADATA_FIELD) {
+                Some(metadata) => serde_json::from_str(metadata)?,
+                None => HashMap::new(),
+            };
+            let distance: f64 = fields
+                .get(DISTANCE_FIELD)
+                .and_then(|distance| distance.parse().ok())
+                .unwrap_or(f64::INFINITY);
+            docs.push(Document {
+                page_content: fields.remove(CONTENT_FIELD).unwrap_or_default(),
+                metadata,
+                score: self.score(distance),
+            });
+        }
+
+        if let Some(threshold) = opt.score_threshold {
+            docs.retain(|doc| doc.score >= threshold as f64);
+        }
+        Ok(docs)
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = match &opt.filters {
+            Some(filters) => redis_filter(filters, &self.metadata_fields)?,
+            None => String::new(),
+        };
+        let query = if filter.is_empty() {
+            "*"
+        } else {
+            filter.as_str()
+        };
+
+        let mut connection = self.connection.clone();
+        let reply: Vec<RedisValue> = ::redis::cmd("FT.SEARCH")
+            .arg(&self.index_name)
+            .arg(query)
+            .arg("LIMIT")
+            .arg(0)
+            .arg(0)
+            .arg("DIALECT")
+            .arg(2)
+            .query_async(&mut connection)
+            .await?;
+        let count = reply
+            .first()
+            .ok_or("Redis returned an empty search reply")?;
+        Ok(from_redis_value(count)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redis_filter() {
+        let fields = vec![
+            ("lang".to_string(), MetadataFieldType::Tag),
+            ("year".to_string(), MetadataFieldType::Numeric),
+        ];
+        let filter = |filters: Value| redis_filter(&filters, &fields);
+
+        assert_eq!(filter(json!({"lang": "rust"})).unwrap(), "@lang:{rust}");
+        assert_eq!(
+            filter(json!({"lang": ["c++", "go"]})).unwrap(),
+            r"@lang:{c\+\+ | go}"
+        );
+        assert_eq!(
+            filter(json!({"lang": {"$ne": "go"}})).unwrap(),
+            "-@lang:{go}"
+        );
+        assert_eq!(
+            filter(json!({"year": {"$gt": 2023}})).unwrap(),
+            "@year:[(2023 +inf]"
+        );
+        assert_eq!(
+            filter(json!({"year": [2023, 2024]})).unwrap(),
+            "(@year:[2023 2023] | @year:[2024 2024])"
+        );
+        assert_eq!(filter(json!({})).unwrap(), "");
+
+        assert!(filter(json!({"author": "x"})).is_err());
+        assert!(filter(json!({"lang": {"$gt": "a"}})).is_err());
+        assert!(filter(json!({"year": "2023"})).is_err());
+    }
+
+    #[test]
+    fn test_field_value() {
+        assert_eq!(
+            field_value("tags", &json!(["a", "b"]), MetadataFieldType::Tag).unwrap(),
+            Some("a,b".to_string())
+        );
+        assert_eq!(
+            field_value("year", &json!(2024), MetadataFieldType::Numeric).unwrap(),
+            Some("2024".to_string())
+        );
+        assert_eq!(
+            field_value("year", &Value::Null, MetadataFieldType::Numeric).unwrap(),
+            None
+        );
+        assert!(field_value("year", &json!("2024"), MetadataFieldType::Numeric).is_err());
+    }
+
+    /// Runs against a Redis Stack container, so needs Docker.
+    #[tokio::test]
+    #[ignore]
+    async fn test_redis_store() {
+        use testcontainers::{
+            core::{IntoContainerPort, WaitFor},
+            runners::AsyncRunner,
+            GenericImage,
+        };
+
+        use crate::vectorstore::{redis::StoreBuilder, test_utils::LetterEmbedder};
+
+        let container = GenericImage::new("redis/redis-stack-server", "7.4.0-v1")
+            .with_exposed_port(6379.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"))
+            .start()
+            .await
+            .unwrap();
+        let port = container.get_host_port_ipv4(6379).await.unwrap();
+
+        let store = StoreBuilder::new()
+            .url(format!("redis://localhost:{}", port))
+            .metadata_field("tag", MetadataFieldType::Tag)
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        let opt = VecStoreOptions::default();
+
+        let docs = [("aaa", "x"), ("aab", "y"), ("ccc", "x")].map(|(text, tag)| {
+            Document::new(text).with_metadata(HashMap::from([("tag".to_string(), json!(tag))]))
+        });
+        let ids = store.add_documents(&docs, &opt).await.unwrap();
+
+        let found = store.similarity_search("a", 2, &opt).await.unwrap();
+        assert_eq!(found[0].page_content, "aaa");
+        assert_eq!(found[1].page_content, "aab");
+        assert_eq!(found[0].metadata["tag"], "x");
+
+        let tagged = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        let found = store.similarity_search("c", 10, &tagged).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].page_content, "ccc");
+        assert_eq!(store.count_documents(&tagged).await.unwrap(), 2);
+
+        store.delete_documents_by_ids(&ids[..1]).await.unwrap();
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 2);
+    }
+}
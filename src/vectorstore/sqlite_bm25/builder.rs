@@ -1,15 +1,31 @@
-use std::{
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
 
 use rusqlite::Result;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use super::{ScoreTransform, Store};
+use crate::vectorstore::{
+    sqlite_utils::{apply_pragmas, open_connection, validate_table_name},
+    IdGenerator, RowIdGenerator,
+};
 
-use super::Store;
+const DEFAULT_OPEN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_TOKENIZER: &str = "unicode61";
+/// The tokenizers built into fts5.
+const FTS5_TOKENIZERS: [&str; 4] = ["unicode61", "ascii", "porter", "trigram"];
 
 pub struct StoreBuilder {
     connection_url: Option<String>,
     table: Option<String>,
+    max_metadata_bytes: Option<usize>,
+    base_filter: Option<Value>,
+    pragmas: Vec<(String, String)>,
+    score_transform: ScoreTransform,
+    open_attempts: u32,
+    open_retry_delay: Duration,
+    id_generator: Arc<dyn IdGenerator>,
+    tokenizer: String,
 }
 
 impl StoreBuilder {
@@ -17,6 +33,14 @@ impl StoreBuilder {
         Self {
             connection_url: None,
             table: None,
+            max_metadata_bytes: None,
+            base_filter: None,
+            pragmas: Vec::new(),
+            score_transform: ScoreTransform::default(),
+            open_attempts: 1,
+            open_retry_delay: DEFAULT_OPEN_RETRY_DELAY,
+            id_generator: Arc::new(RowIdGenerator),
+            tokenizer: DEFAULT_TOKENIZER.to_string(),
         }
     }
 
@@ -30,13 +54,118 @@ impl StoreBuilder {
         self
     }
 
+    /// Rejects documents whose serialized metadata is larger than `max_metadata_bytes`.
+    /// Unlimited by default.
+    pub fn max_metadata_bytes(mut self, max_metadata_bytes: usize) -> Self {
+        self.max_metadata_bytes = Some(max_metadata_bytes);
+        self
+    }
+
+    /// Filter that is always AND-ed with the per-call `VecStoreOptions::filters` on searches,
+    /// e.g. a mandatory tenant filter. Lookups, deletes and upserts by id or metadata also
+    /// only see the documents matching it. Must be a JSON object of metadata key/values.
+    pub fn base_filter(mut self, base_filter: Value) -> Self {
+        self.base_filter = Some(base_filter);
+        self
+    }
+
+    /// How raw bm25 values are mapped to `Document::score`. Defaults to
+    /// [`ScoreTransform::Sigmoid`].
+    pub fn score_transform(mut self, score_transform: ScoreTransform) -> Self {
+        self.score_transform = score_transform;
+        self
+    }
+
+    /// Adds a `PRAGMA key = value` to run on the connection opened by the builder, e.g.
+    /// `.pragma("cache_size", "-20000")`. Keys must be identifiers and values identifiers or
+    /// integers. Pragmas are applied in the order they were added, right after the connection
+    /// is opened.
+    pub fn pragma(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pragmas.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets how the ids returned by `add_documents` are made from the row id of each inserted
+    /// document. Defaults to [`RowIdGenerator`], the row id itself.
+    pub fn id_generator<G: IdGenerator + 'static>(mut self, id_generator: G) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// fts5 tokenizer of the table, e.g. `"porter unicode61"` to match English words by their
+    /// stem, or `"unicode61 remove_diacritics 1"` for multilingual text. Defaults to
+    /// `"unicode61"`. The first word must be a built-in tokenizer (`unicode61`, `ascii`,
+    /// `porter` or `trigram`), followed by identifiers or integers. Only applies when the table
+    /// is created.
+    pub fn tokenizer(mut self, tokenizer: impl Into<String>) -> Self {
+        self.tokenizer = tokenizer.into();
+        self
+    }
+
+    /// Retries opening the database file up to `attempts` times in total when it fails for a
+    /// transient reason, e.g. a network mount that becomes available shortly after startup.
+    /// Waits `delay` before the first retry and doubles it after each. Errors such as the file
+    /// not being a database are not retried. Defaults to a single attempt.
+    pub fn open_retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.open_attempts = attempts.max(1);
+        self.open_retry_delay = delay;
+        self
+    }
+
     pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let base_filter = match &self.base_filter {
+            Some(Value::Object(map)) => map.clone().into_iter().collect(),
+            Some(_) => return Err("Base filter must be a JSON object".into()),
+            None => HashMap::new(),
+        };
+
         let connection_url = self.connection_url.ok_or("Connection URL is required")?;
         let table = self.table.ok_or("Table name is required")?;
+        validate_table_name(&table)?;
+        validate_tokenizer(&self.tokenizer)?;
 
-        let conn = rusqlite::Connection::open(connection_url)?;
+        let conn =
+            open_connection(&connection_url, self.open_attempts, self.open_retry_delay).await?;
+        apply_pragmas(&conn, &self.pragmas)?;
         let pool = Arc::new(Mutex::new(conn));
 
-        Ok(Store { pool, table })
+        Ok(Store {
+            pool,
+            table,
+            max_metadata_bytes: self.max_metadata_bytes,
+            base_filter,
+            score_transform: self.score_transform,
+            id_generator: self.id_generator,
+            tokenizer: self.tokenizer,
+        })
+    }
+
+    /// Builds the store, then drops its tables and recreates them empty. Unlike `build`, which
+    /// keeps existing data, and `delete_all_documents`, which keeps the schema, this gives a
+    /// fresh store, e.g. for test fixtures.
+    pub async fn recreate(self) -> Result<Store, Box<dyn Error>> {
+        let store = self.build().await?;
+        store.drop_tables().await?;
+        store.initialize().await?;
+        Ok(store)
+    }
+}
+
+/// Checks that `tokenizer` can be interpolated into the fts5 `tokenize` option: a built-in
+/// tokenizer followed by arguments that are identifiers or integers.
+fn validate_tokenizer(tokenizer: &str) -> Result<(), Box<dyn Error>> {
+    let mut words = tokenizer.split_whitespace();
+    let valid = words
+        .next()
+        .is_some_and(|name| FTS5_TOKENIZERS.contains(&name))
+        && words.all(|word| word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    if !valid {
+        return Err(format!(
+            "Invalid fts5 tokenizer {:?}: expected one of {} followed by identifiers or integers",
+            tokenizer,
+            FTS5_TOKENIZERS.join(", ")
+        )
+        .into());
     }
+    Ok(())
 }
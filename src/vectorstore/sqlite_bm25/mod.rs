@@ -1,5 +1,9 @@
 mod builder;
+mod score_transform;
 mod sqlite_bm25;
 
 pub use builder::*;
+pub use score_transform::*;
 pub use sqlite_bm25::*;
+
+pub use crate::vectorstore::sqlite_utils::{RowError, SearchResult, SearchStatus, DOCUMENT_ID_KEY};
@@ -0,0 +1,71 @@
+/// How the raw fts5 `bm25()` value is turned into `Document::score`.
+///
+/// fts5 returns *negative* bm25 values, where a more negative value means a better match.
+/// Every transform except `Raw` flips that sign first, so a higher score means more relevant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScoreTransform {
+    /// `1 / (1 + e^-s)` of the negated bm25 value. Monotonic and bounded to `[0.5, 1)`,
+    /// comparable across queries but not calibrated.
+    #[default]
+    Sigmoid,
+    /// `1 - e^-s` of the negated bm25 value. Monotonic and bounded to `[0, 1)`, spreads
+    /// small bm25 values further apart than `Sigmoid`.
+    NegExp,
+    /// Linearly rescales the returned set so the best match scores `1.0` and the worst `0.0`.
+    /// Only meaningful within a single result set; a lone result scores `1.0`.
+    MinMax,
+    /// The unmodified fts5 value. Lower (more negative) means more relevant.
+    Raw,
+}
+
+impl ScoreTransform {
+    /// Transforms the raw bm25 values of one result set, preserving their order.
+    pub fn apply(&self, raw_scores: &[f64]) -> Vec<f64> {
+        match self {
+            ScoreTransform::Sigmoid => raw_scores
+                .iter()
+                .map(|raw| 1.0 / (1.0 + raw.exp()))
+                .collect(),
+            ScoreTransform::NegExp => raw_scores.iter().map(|raw| 1.0 - raw.exp()).collect(),
+            ScoreTransform::MinMax => {
+                let relevance: Vec<f64> = raw_scores.iter().map(|raw| -raw).collect();
+                let min = relevance.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = relevance.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let range = max - min;
+                relevance
+                    .iter()
+                    .map(|s| if range > 0.0 { (s - min) / range } else { 1.0 })
+                    .collect()
+            }
+            ScoreTransform::Raw => raw_scores.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_better_matches_score_higher() {
+        // fts5 bm25: more negative is a better match.
+        let raw = [-3.0, -1.0, -0.5];
+        for transform in [
+            ScoreTransform::Sigmoid,
+            ScoreTransform::NegExp,
+            ScoreTransform::MinMax,
+        ] {
+            let scores = transform.apply(&raw);
+            assert!(
+                scores[0] > scores[1] && scores[1] > scores[2],
+                "{:?} produced {:?}",
+                transform,
+                scores
+            );
+        }
+        assert_eq!(ScoreTransform::MinMax.apply(&raw)[0], 1.0);
+        assert_eq!(ScoreTransform::MinMax.apply(&raw)[2], 0.0);
+        assert_eq!(ScoreTransform::MinMax.apply(&[-2.0]), vec![1.0]);
+        assert_eq!(ScoreTransform::Raw.apply(&raw), raw.to_vec());
+    }
+}
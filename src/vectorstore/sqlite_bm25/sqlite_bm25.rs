@@ -1,20 +1,55 @@
 use async_trait::async_trait;
-use rusqlite::{params, params_from_iter};
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, OptionalExtension};
 use serde_json::{json, Value};
-use std::{
-    collections::HashMap,
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, sync::Arc};
+use tokio::sync::Mutex;
 
+use super::ScoreTransform;
 use crate::{
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        order_by_ids,
+        sqlite_utils::{
+            apply_score_threshold, build_metadata_query, caller_ids, check_metadata_size,
+            collect_rows, documents_by_ids, filters_from_options, SearchResult,
+        },
+        upsert_keys, IdGenerator, VecStoreOptions, VectorStore,
+    },
 };
 
+/// Errors if the row `rowid` exists but doesn't match `base_filter`, since writing under its
+/// rowid would replace a document the store can't see.
+fn check_rowid_in_scope(
+    conn: &rusqlite::Connection,
+    table: &str,
+    rowid: i64,
+    base_filter: &HashMap<String, Value>,
+) -> Result<(), Box<dyn Error>> {
+    let scope = build_metadata_query(base_filter, &HashMap::new(), None, 2)?;
+    let outside: Option<bool> = conn
+        .query_row(
+            &format!(
+                "SELECT ({}) IS NOT 1 FROM {table} WHERE rowid = ?1",
+                scope.sql
+            ),
+            params_from_iter(std::iter::once(SqlValue::from(rowid)).chain(scope.params)),
+            |row| row.get(0),
+        )
+        .optional()?;
+    if outside == Some(true) {
+        return Err(format!("Document {} is outside the store's base filter", rowid).into());
+    }
+    Ok(())
+}
+
 pub struct Store {
     pub pool: Arc<Mutex<rusqlite::Connection>>,
     pub(crate) table: String,
+    pub(crate) max_metadata_bytes: Option<usize>,
+    pub(crate) base_filter: HashMap<String, Value>,
+    pub(crate) score_transform: ScoreTransform,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) tokenizer: String,
 }
 
 impl Store {
@@ -25,14 +60,16 @@ impl Store {
 
     async fn create_table_if_not_exists(&self) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
-        let db = self.pool.lock().unwrap();
+        let tokenizer = &self.tokenizer;
+        let db = self.pool.lock().await;
 
         db.execute(
             &format!(
                 r#"
                 CREATE VIRTUAL TABLE IF NOT EXISTS {table} USING fts5(
                     text,
-                    metadata UNINDEXED
+                    metadata UNINDEXED,
+                    tokenize = '{tokenizer}'
                 );"#
             ),
             [],
@@ -41,15 +78,60 @@ impl Store {
         Ok(())
     }
 
-    fn get_filters(&self, opt: &VecStoreOptions) -> Result<HashMap<String, Value>, Box<dyn Error>> {
-        match &opt.filters {
-            Some(Value::Object(map)) => {
-                let filters = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-                Ok(filters)
-            }
-            None => Ok(HashMap::new()),
-            _ => Err("Invalid filters format".into()),
+    pub(crate) async fn drop_tables(&self) -> Result<(), Box<dyn Error>> {
+        let table = &self.table;
+        let db = self.pool.lock().await;
+        db.execute(&format!("DROP TABLE IF EXISTS {table}"), [])?;
+        Ok(())
+    }
+
+    /// Like `similarity_search`, but returns the rows that failed to decode alongside the
+    /// documents instead of dropping them.
+    pub async fn similarity_search_with_row_errors(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let table = &self.table;
+        let filter = filters_from_options(opt)?;
+        let db = self.pool.lock().await;
+
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, None, 3)?;
+
+        let mut stmt = db.prepare(&format!(
+            r#"
+            SELECT
+                text,
+                metadata,
+                bm25({table}) as score,
+                rowid
+            FROM {table}
+            WHERE {table} MATCH ?1 AND {}
+            ORDER BY score ASC
+            LIMIT ?2
+            "#,
+            metadata_query.sql
+        ))?;
+
+        let params = [
+            SqlValue::from(query.to_string()),
+            SqlValue::from(limit as i64),
+        ];
+        let rows = stmt.query_map(
+            params_from_iter(params.iter().chain(&metadata_query.params)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let mut result = collect_rows(rows);
+
+        let raw_scores: Vec<f64> = result.docs.iter().map(|doc| doc.score).collect();
+        let scores = self.score_transform.apply(&raw_scores);
+        for (doc, score) in result.docs.iter_mut().zip(scores) {
+            doc.score = score;
         }
+        apply_score_threshold(&mut result.docs, opt);
+
+        Ok(result)
     }
 
     pub async fn delete_documents_by_ids(&self, ids: &[i64]) -> Result<(), Box<dyn Error>> {
@@ -62,11 +144,15 @@ impl Store {
             .map(|i| format!("?{}", i))
             .collect::<Vec<_>>()
             .join(",");
+        let scope = build_metadata_query(&self.base_filter, &HashMap::new(), None, ids.len() + 1)?;
 
-        let db = self.pool.lock().unwrap();
+        let db = self.pool.lock().await;
         db.execute(
-            &format!(r#"DELETE FROM {table} WHERE rowid IN ({placeholders})"#),
-            params_from_iter(ids),
+            &format!(
+                r#"DELETE FROM {table} WHERE rowid IN ({placeholders}) AND {}"#,
+                scope.sql
+            ),
+            params_from_iter(ids.iter().map(|id| SqlValue::from(*id)).chain(scope.params)),
         )?;
 
         Ok(())
@@ -77,52 +163,21 @@ impl Store {
         metadata_filters: &HashMap<String, Value>,
     ) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
-        let db = self.pool.lock().unwrap();
-
-        let metadata_query = metadata_filters
-            .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract(metadata, '$.{}') IN ({})",
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-                Value::Number(n) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(" AND ");
+        let db = self.pool.lock().await;
 
-        let where_clause = if metadata_query.is_empty() {
-            "1=1".to_string()
-        } else {
-            metadata_query
-        };
+        let where_clause = build_metadata_query(&self.base_filter, metadata_filters, None, 1)?;
 
-        db.execute(&format!(r#"DELETE FROM {table} WHERE {where_clause}"#), [])?;
+        db.execute(
+            &format!(r#"DELETE FROM {table} WHERE {}"#, where_clause.sql),
+            params_from_iter(&where_clause.params),
+        )?;
 
         Ok(())
     }
 
     pub async fn delete_all_documents(&self) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
-        let db = self.pool.lock().unwrap();
+        let db = self.pool.lock().await;
         db.execute(&format!(r#"DELETE FROM {table}"#), [])?;
         Ok(())
     }
@@ -133,14 +188,41 @@ impl VectorStore for Store {
     async fn add_documents(
         &self,
         docs: &[Document],
-        _opt: &VecStoreOptions,
+        opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        // fts5 tables have no indexed column to hold arbitrary ids, so caller ids are rowids.
+        let rowids = caller_ids(opt, docs.len())?
+            .map(|ids| {
+                ids.iter()
+                    .map(|id| {
+                        id.parse::<i64>().map_err(|_| {
+                            format!("sqlite_bm25 ids must be integers (row ids), got {:?}", id)
+                        })
+                    })
+                    .collect::<Result<Vec<i64>, _>>()
+            })
+            .transpose()?;
+
         let table = &self.table;
-        let mut db = self.pool.lock().unwrap();
+        let mut db = self.pool.lock().await;
         let tx = db.transaction()?;
         let mut ids = Vec::with_capacity(docs.len());
 
-        for doc in docs {
+        for (i, doc) in docs.iter().enumerate() {
+            let metadata = json!(&doc.metadata).to_string();
+            if let Some(rowids) = &rowids {
+                let rowid = rowids[i];
+                check_rowid_in_scope(&tx, table, rowid, &self.base_filter)?;
+                tx.execute(&format!("DELETE FROM {table} WHERE rowid = ?1"), [rowid])?;
+                tx.execute(
+                    &format!("INSERT INTO {table} (rowid, text, metadata) VALUES (?1, ?2, ?3)"),
+                    params![rowid, &doc.page_content, metadata],
+                )?;
+                ids.push(rowid.to_string());
+                continue;
+            }
+
             let id: i64 = tx.query_row(
                 &format!(
                     r#"
@@ -150,11 +232,11 @@ impl VectorStore for Store {
                         (?1, ?2)
                     RETURNING rowid"#
                 ),
-                params![&doc.page_content, json!(&doc.metadata).to_string()],
+                params![&doc.page_content, metadata],
                 |row| row.get(0),
             )?;
 
-            ids.push(id.to_string());
+            ids.push(self.id_generator.generate(doc, id));
         }
 
         tx.commit()?;
@@ -167,79 +249,321 @@ impl VectorStore for Store {
         limit: usize,
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
+        Ok(self
+            .similarity_search_with_row_errors(query, limit, opt)
+            .await?
+            .into_docs())
+    }
+
+    /// The same as `similarity_search`, which is already a bm25 search.
+    async fn keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        self.similarity_search(query, limit, opt).await
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, None, 1)?;
+        let db = self.pool.lock().await;
+        let count: i64 = db.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE {}",
+                self.table, metadata_query.sql
+            ),
+            params_from_iter(&metadata_query.params),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Without `opt.ids`, a document is found by a scan of the unindexed metadata column.
+    async fn upsert_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        let keys = upsert_keys(docs, opt)?;
+        let upsert_key = opt.upsert_key.as_deref().unwrap_or("source").to_string();
+
         let table = &self.table;
-        let filter = self.get_filters(opt)?;
-        let db = self.pool.lock().unwrap();
+        let mut db = self.pool.lock().await;
+        let tx = db.transaction()?;
+
+        for (doc, key) in docs.iter().zip(&keys) {
+            let rowid: Option<i64> = if opt.ids.is_some() {
+                // As in `add_documents`, caller ids are rowids.
+                let rowid = key.parse().map_err(|_| {
+                    format!("sqlite_bm25 ids must be integers (row ids), got {:?}", key)
+                })?;
+                check_rowid_in_scope(&tx, table, rowid, &self.base_filter)?;
+                Some(rowid)
+            } else {
+                let filter =
+                    HashMap::from([(upsert_key.clone(), doc.metadata[&upsert_key].clone())]);
+                let condition = build_metadata_query(&self.base_filter, &filter, None, 1)?;
+                tx.query_row(
+                    &format!("SELECT rowid FROM {table} WHERE {} LIMIT 1", condition.sql),
+                    params_from_iter(&condition.params),
+                    |row| row.get(0),
+                )
+                .optional()?
+            };
+            tx.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {table} (rowid, text, metadata) VALUES (?1, ?2, ?3)"
+                ),
+                params![rowid, &doc.page_content, json!(&doc.metadata).to_string()],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(keys)
+    }
+
+    async fn get_documents_by_ids(
+        &self,
+        ids: &[String],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let found = {
+            let db = self.pool.lock().await;
+            documents_by_ids(&db, &self.table, ids, &self.base_filter)?
+        };
+        order_by_ids(ids, found, opt)
+    }
+}
 
-        let mut metadata_query = filter
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectorstore::{
+        sqlite_bm25::{StoreBuilder, DOCUMENT_ID_KEY},
+        test_utils::{assert_get_documents_by_ids_applies_base_filter, tenant_doc},
+    };
+
+    #[tokio::test]
+    async fn test_count_documents() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .table("docs")
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+
+        let docs: Vec<Document> = ["en", "en", "fr"]
             .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract(metadata, '$.{}') IN ({})",
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-                Value::Number(n) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
+            .enumerate()
+            .map(|(i, lang)| {
+                Document::new(format!("document {}", i))
+                    .with_metadata(HashMap::from([("lang".to_string(), json!(lang))]))
             })
-            .collect::<Vec<String>>()
-            .join(" AND ");
+            .collect();
+        store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let count = |filters: Option<Value>| {
+            let opt = match filters {
+                Some(filters) => VecStoreOptions::new().with_filters(filters),
+                None => VecStoreOptions::new(),
+            };
+            let store = &store;
+            async move { store.count_documents(&opt).await.unwrap() }
+        };
+        assert_eq!(count(None).await, 3);
+        assert_eq!(count(Some(json!({"lang": "en"}))).await, 2);
+        assert_eq!(count(Some(json!({"lang": {"$ne": "en"}}))).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_score_threshold() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .table("docs")
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+            .add_documents(
+                &[Document::new("rust is fast"), Document::new("rust is safe")],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let search = |score_threshold: f32| {
+            let opt = VecStoreOptions::new().with_score_threshold(score_threshold);
+            let store = &store;
+            async move { store.similarity_search("rust", 10, &opt).await.unwrap() }
+        };
+        assert_eq!(search(0.0).await.len(), 2);
+        // Sigmoid scores stay below 1.0.
+        assert!(search(1.0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_results_carry_row_ids() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .table("docs")
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+            .add_documents(
+                &[Document::new("rust is fast"), Document::new("go is fast")],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let docs = store
+            .similarity_search("rust", 10, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        let id = docs[0].metadata[DOCUMENT_ID_KEY].as_i64().unwrap();
+        assert_eq!(id, 1);
 
-        if metadata_query.is_empty() {
-            metadata_query = "1=1".to_string();
+        store.delete_documents_by_ids(&[id]).await.unwrap();
+        let docs = store
+            .similarity_search("fast", 10, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "go is fast");
+
+        let store: &dyn VectorStore = &store;
+        let docs = store
+            .keyword_search("go", 10, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_porter_tokenizer_matches_stems() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .table("docs")
+            .tokenizer("porter unicode61")
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+            .add_documents(
+                &[Document::new("she was running late")],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let docs = store
+            .similarity_search("run", 10, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+
+        for tokenizer in [
+            "",
+            "icu",
+            "porter'; DROP TABLE docs; --",
+            "unicode61 tokenchars '-'",
+        ] {
+            let built = StoreBuilder::new()
+                .connection_url(":memory:")
+                .table("docs")
+                .tokenizer(tokenizer)
+                .build()
+                .await;
+            assert!(built.is_err(), "{:?}", tokenizer);
         }
+    }
 
-        let mut stmt = db.prepare(&format!(
-            r#"
-            SELECT
-                text,
-                metadata,
-                bm25({table}) as score
-            FROM {table}
-            WHERE {table} MATCH ?1 AND {metadata_query}
-            ORDER BY score DESC
-            LIMIT ?2
-            "#
-        ))?;
+    /// A store scoped to tenant `a` holding a document of tenant `b` (rowid 1) and one of
+    /// tenant `a` (rowid 2), with their ids.
+    async fn tenant_store() -> (Store, Vec<String>) {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .table("docs")
+            .base_filter(json!({"tenant": "a"}))
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        let docs = [tenant_doc("theirs", "b"), tenant_doc("ours", "a")];
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        (store, ids)
+    }
+
+    /// Every stored text, regardless of the base filter.
+    async fn stored_texts(store: &Store) -> Vec<String> {
+        let conn = store.pool.lock().await;
+        let mut stmt = conn
+            .prepare(&format!("SELECT text FROM {} ORDER BY rowid", store.table))
+            .unwrap();
+        let texts = stmt.query_map([], |row| row.get(0)).unwrap();
+        texts.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_documents_by_ids_applies_base_filter() {
+        let (store, ids) = tenant_store().await;
+        assert_get_documents_by_ids_applies_base_filter(&store, &ids).await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_ids_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        store.delete_documents_by_ids(&[1, 2]).await.unwrap();
+        assert_eq!(stored_texts(&store).await, vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_metadata_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        let filter = HashMap::from([("source".to_string(), json!("doc.md"))]);
+        store.delete_documents_by_metadata(&filter).await.unwrap();
+        assert_eq!(stored_texts(&store).await, vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_writes_under_other_tenant_rowid_fail() {
+        let (store, _) = tenant_store().await;
+        let opt = VecStoreOptions::default().with_ids(vec!["1".to_string()]);
+        let doc = tenant_doc("ours too", "a");
+
+        assert!(store.add_documents(&[doc.clone()], &opt).await.is_err());
+        assert!(store.upsert_documents(&[doc], &opt).await.is_err());
+        assert_eq!(stored_texts(&store).await, vec!["theirs", "ours"]);
+    }
+
+    #[tokio::test]
+    async fn test_upsert_keeps_other_tenant_row() {
+        let (store, _) = tenant_store().await;
 
-        let docs = stmt
-            .query_map(params![query, limit as i64], |row| {
-                let page_content: String = row.get(0)?;
-                let metadata_json: String = row.get(1)?;
-                let raw_score: f64 = row.get(2)?;
-
-                // 将 BM25 分数转换为 0-1 范围
-                // BM25 分数通常是正数，越大表示越相关
-                // 使用 sigmoid 函数进行归一化: 1 / (1 + e^(-score))
-                let score = 1.0 / (1.0 + (-raw_score).exp());
-
-                let metadata: HashMap<String, Value> =
-                    serde_json::from_str(&metadata_json).unwrap();
-
-                Ok(Document {
-                    page_content,
-                    metadata,
-                    score,
-                })
-            })?
-            .collect::<Result<Vec<Document>, rusqlite::Error>>()?;
-
-        Ok(docs)
+        store
+            .upsert_documents(
+                &[tenant_doc("ours, updated", "a")],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored_texts(&store).await, vec!["theirs", "ours, updated"]);
     }
 }
@@ -1,21 +1,54 @@
-use std::{
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, num::NonZeroUsize, sync::Arc, time::Duration};
+
+use lru::LruCache;
 
-use rusqlite::{ffi::sqlite3_auto_extension, Connection, Result};
-use sqlite_vec::sqlite3_vec_init;
+use rusqlite::Result;
+use serde_json::Value;
+use tokio::sync::Mutex;
 
 use super::Store;
-use crate::embedding::embedder_trait::Embedder;
+use crate::{
+    embedding::embedder_trait::Embedder,
+    language_models::llm::LLM,
+    vectorstore::{
+        probe_vector_dimensions,
+        sqlite_utils::{
+            apply_pragmas, check_sqlite_vec, open_connection, register_sqlite_vec,
+            validate_table_name, Metric,
+        },
+        IdGenerator, RowIdGenerator,
+    },
+};
+
+const DEFAULT_BATCH_SIZE: i32 = 100;
+const DEFAULT_FILTER_OVERFETCH: usize = 4;
+const DEFAULT_OPEN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_RRF_K: f64 = 60.0;
+const DEFAULT_KEYWORD_CACHE_SIZE: usize = 256;
 
 pub struct StoreBuilder {
     pool: Option<Arc<Mutex<rusqlite::Connection>>>,
     connection_url: Option<String>,
     table: String,
     vector_dimensions: i32,
+    probe_dimensions: bool,
     batch_size: i32,
     embedder: Option<Arc<dyn Embedder>>,
+    llm: Option<Box<dyn LLM>>,
+    keyword_cache: bool,
+    keyword_cache_size: usize,
+    max_metadata_bytes: Option<usize>,
+    base_filter: Option<Value>,
+    pragmas: Vec<(String, String)>,
+    filter_overfetch: usize,
+    open_attempts: u32,
+    open_retry_delay: Duration,
+    id_generator: Arc<dyn IdGenerator>,
+    doc_id_column: bool,
+    keyword_fallback: bool,
+    metric: Metric,
+    rrf_k: f64,
+    hybrid_weights: [f64; 2],
 }
 
 impl StoreBuilder {
@@ -25,8 +58,24 @@ impl StoreBuilder {
             connection_url: None,
             table: "documents".to_string(),
             vector_dimensions: 0,
-            batch_size: 2048,
+            probe_dimensions: false,
+            batch_size: DEFAULT_BATCH_SIZE,
             embedder: None,
+            llm: None,
+            keyword_cache: true,
+            keyword_cache_size: DEFAULT_KEYWORD_CACHE_SIZE,
+            max_metadata_bytes: None,
+            base_filter: None,
+            pragmas: Vec::new(),
+            filter_overfetch: DEFAULT_FILTER_OVERFETCH,
+            open_attempts: 1,
+            open_retry_delay: DEFAULT_OPEN_RETRY_DELAY,
+            id_generator: Arc::new(RowIdGenerator),
+            doc_id_column: false,
+            keyword_fallback: false,
+            metric: Metric::default(),
+            rrf_k: DEFAULT_RRF_K,
+            hybrid_weights: [1.0, 1.0],
         }
     }
 
@@ -47,6 +96,7 @@ impl StoreBuilder {
         self
     }
 
+    /// Number of texts sent per `embed_documents` call by `add_documents`. Defaults to 100.
     pub fn batch_size(mut self, batch_size: i32) -> Self {
         self.batch_size = batch_size;
         self
@@ -62,10 +112,162 @@ impl StoreBuilder {
         self
     }
 
-    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+    /// LLM used by hybrid search to turn the query into keywords for the bm25 side, which
+    /// otherwise matches the raw query text. Optional; vector search never uses it.
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    /// Caches the keywords the LLM extracts for each hybrid search query, so a repeated query
+    /// doesn't call the LLM again. On by default.
+    pub fn keyword_cache(mut self, keyword_cache: bool) -> Self {
+        self.keyword_cache = keyword_cache;
+        self
+    }
+
+    /// Number of queries whose keywords are cached, least recently used first out. Defaults
+    /// to 256; 0 disables the cache.
+    pub fn keyword_cache_size(mut self, keyword_cache_size: usize) -> Self {
+        self.keyword_cache_size = keyword_cache_size;
+        self
+    }
+
+    /// Rejects documents whose serialized metadata is larger than `max_metadata_bytes`.
+    /// Unlimited by default.
+    pub fn max_metadata_bytes(mut self, max_metadata_bytes: usize) -> Self {
+        self.max_metadata_bytes = Some(max_metadata_bytes);
+        self
+    }
+
+    /// Filter that is always AND-ed with the per-call `VecStoreOptions::filters` on searches,
+    /// e.g. a mandatory tenant filter. Lookups, deletes and upserts by id or metadata also
+    /// only see the documents matching it. Must be a JSON object of metadata key/values.
+    pub fn base_filter(mut self, base_filter: Value) -> Self {
+        self.base_filter = Some(base_filter);
+        self
+    }
+
+    /// Adds a `PRAGMA key = value` to run on the connection opened by the builder, e.g.
+    /// `.pragma("cache_size", "-20000")`. Keys must be identifiers and values identifiers or
+    /// integers. Pragmas are applied in the order they were added, right after the connection
+    /// is opened (and so after the sqlite-vec extension is registered). They are not applied
+    /// to a connection passed in through `pool`.
+    pub fn pragma(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pragmas.push((key.into(), value.into()));
+        self
+    }
+
+    /// When a search has metadata filters, `limit * filter_overfetch` nearest neighbours are
+    /// fetched before filtering, so that filtered results still fill `limit`. Defaults to 4.
+    pub fn filter_overfetch(mut self, filter_overfetch: usize) -> Self {
+        self.filter_overfetch = filter_overfetch.max(1);
+        self
+    }
+
+    /// When a vector or hybrid search finds nothing (for vector search: nothing scoring at
+    /// least `VecStoreOptions::score_threshold`), returns the keyword search results instead,
+    /// with `via_keyword_fallback: true` in their metadata. The keyword search uses the
+    /// keywords from the `llm`, if set, and the same score threshold. Helps with queries the
+    /// embedder handles poorly. Off by default.
+    pub fn keyword_fallback(mut self, keyword_fallback: bool) -> Self {
+        self.keyword_fallback = keyword_fallback;
+        self
+    }
+
+    /// Constant `k` of the reciprocal rank fusion of hybrid search, where a document ranked `r`
+    /// (from 0) by a signal contributes `1 / (k + r + 1)` to its score. Lower values favor the
+    /// top-ranked documents of each signal, higher values smooth the ranks out. Defaults to 60;
+    /// `build` errors if it is negative or not finite.
+    pub fn rrf_k(mut self, k: f64) -> Self {
+        self.rrf_k = k;
+        self
+    }
+
+    /// Weights of the vector and bm25 signals in the hybrid score: a document ranked `r` by a
+    /// signal contributes `weight / (k + r + 1)`. Raise `bm25_weight` to favor keyword matches
+    /// over semantic ones. Both default to 1.0; `build` errors if either is negative or not
+    /// finite.
+    pub fn hybrid_weights(mut self, vec_weight: f64, bm25_weight: f64) -> Self {
+        self.hybrid_weights = [vec_weight, bm25_weight];
+        self
+    }
+
+    /// Sets how the ids returned by `add_documents` are made from the row id of each inserted
+    /// document. Defaults to [`RowIdGenerator`], the row id itself.
+    pub fn id_generator<G: IdGenerator + 'static>(mut self, id_generator: G) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Also stores the generated ids in an indexed `doc_id` column, added to existing tables on
+    /// `initialize`. Off by default.
+    pub fn doc_id_column(mut self, doc_id_column: bool) -> Self {
+        self.doc_id_column = doc_id_column;
+        self
+    }
+
+    /// Distance metric of the vec0 index, which also decides how distances are turned into
+    /// scores. Defaults to [`Metric::L2`]. The metric is fixed when the table is created, and
+    /// `initialize` errors if an existing table uses another one.
+    pub fn distance_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Retries opening the database file up to `attempts` times in total when it fails for a
+    /// transient reason, e.g. a network mount that becomes available shortly after startup.
+    /// Waits `delay` before the first retry and doubles it after each. Errors such as the file
+    /// not being a database are not retried. Defaults to a single attempt.
+    pub fn open_retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.open_attempts = attempts.max(1);
+        self.open_retry_delay = delay;
+        self
+    }
+
+    /// Embeds a sentinel text once during `build` to learn the embedder's output dimension.
+    /// Sets `vector_dimensions` when unset, otherwise checks that it matches. Off by default
+    /// since it calls the embedder.
+    pub fn probe_dimensions(mut self, probe_dimensions: bool) -> Self {
+        self.probe_dimensions = probe_dimensions;
+        self
+    }
+
+    pub async fn build(mut self) -> Result<Store, Box<dyn Error>> {
+        validate_table_name(&self.table)?;
         if self.embedder.is_none() {
             return Err("Embedder is required".into());
         }
+        if !self.rrf_k.is_finite() || self.rrf_k < 0.0 {
+            return Err(
+                format!("rrf_k must be finite and non-negative, got {}", self.rrf_k).into(),
+            );
+        }
+        if let Some(weight) = self
+            .hybrid_weights
+            .iter()
+            .find(|weight| !weight.is_finite() || **weight < 0.0)
+        {
+            return Err(format!(
+                "Hybrid weights must be finite and non-negative, got {}",
+                weight
+            )
+            .into());
+        }
+        if self.probe_dimensions {
+            self.vector_dimensions =
+                probe_vector_dimensions(self.embedder.as_deref().unwrap(), self.vector_dimensions)
+                    .await?;
+        }
+
+        let base_filter = match &self.base_filter {
+            Some(Value::Object(map)) => map.clone().into_iter().collect(),
+            Some(_) => return Err("Base filter must be a JSON object".into()),
+            None => HashMap::new(),
+        };
+        let keyword_cache = NonZeroUsize::new(self.keyword_cache_size)
+            .filter(|_| self.keyword_cache)
+            .map(|size| Mutex::new(LruCache::new(size)));
 
         Ok(Store {
             pool: self.get_pool().await?,
@@ -73,25 +275,47 @@ impl StoreBuilder {
             vector_dimensions: self.vector_dimensions,
             batch_size: self.batch_size,
             embedder: self.embedder.unwrap(),
+            llm: self.llm,
+            keyword_cache,
+            max_metadata_bytes: self.max_metadata_bytes,
+            base_filter,
+            filter_overfetch: self.filter_overfetch,
+            id_generator: self.id_generator,
+            doc_id_column: self.doc_id_column,
+            keyword_fallback: self.keyword_fallback,
+            metric: self.metric,
+            rrf_k: self.rrf_k,
+            hybrid_weights: self.hybrid_weights,
         })
     }
 
+    /// Builds the store, then drops its tables and recreates them empty. Unlike `build`, which
+    /// keeps existing data, and `delete_all_documents`, which keeps the schema, this gives a
+    /// fresh store, e.g. for test fixtures.
+    pub async fn recreate(self) -> Result<Store, Box<dyn Error>> {
+        let store = self.build().await?;
+        store.drop_tables().await?;
+        store.initialize().await?;
+        Ok(store)
+    }
+
     async fn get_pool(&self) -> Result<Arc<Mutex<rusqlite::Connection>>, Box<dyn Error>> {
         if let Some(pool) = &self.pool {
+            check_sqlite_vec(&pool.lock().await)?;
             return Ok(pool.clone());
         }
 
-        unsafe {
-            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
-        }
+        register_sqlite_vec();
 
         let connection_url = self
             .connection_url
             .as_ref()
             .ok_or_else(|| "Connection URL or DB is required")?;
 
-        let pool: rusqlite::Connection = Connection::open(connection_url)
-            .map_err(|e| format!("Failed to open SQLite connection: {}", e))?;
+        let pool =
+            open_connection(connection_url, self.open_attempts, self.open_retry_delay).await?;
+        apply_pragmas(&pool, &self.pragmas)?;
+        check_sqlite_vec(&pool)?;
 
         let pool = Arc::new(Mutex::new(pool));
 
@@ -3,3 +3,7 @@ mod sqlite_hybrid;
 
 pub use builder::*;
 pub use sqlite_hybrid::*;
+
+pub use crate::vectorstore::sqlite_utils::{
+    Metric, RowError, SearchResult, SearchStatus, DOCUMENT_ID_KEY,
+};
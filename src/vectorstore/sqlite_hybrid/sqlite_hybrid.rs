@@ -1,24 +1,126 @@
-use std::{
-    collections::HashMap,
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use lru::LruCache;
 
 use crate::{
     embedding::embedder_trait::Embedder,
+    language_models::llm::LLM,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        order_by_ids,
+        sqlite_utils::{
+            apply_score_threshold, build_metadata_query, caller_ids, check_metadata_size,
+            collect_rows, content_key, cosine_similarity, create_vec_tables, documents_by_ids,
+            duplicate_mask, ensure_doc_id_column, filters_from_options, insert_documents,
+            nearest_documents, read_embedding, rows_by_doc_id, search_within_ids,
+            verify_embedding_dimensions, write_upserts, Metric, SearchResult, VecTables,
+            VEC0_MAX_K,
+        },
+        upsert_keys, IdGenerator, VecStoreOptions, VectorStore,
+    },
 };
 use async_trait::async_trait;
-use rusqlite::params;
+use rusqlite::{params_from_iter, types::Value as SqlValue};
 use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+/// Default number of candidates each signal fetches, as a multiple of the final `limit`.
+const DEFAULT_CANDIDATE_MULTIPLIER: usize = 4;
+
+fn candidates(requested: Option<usize>, limit: usize) -> usize {
+    requested
+        .unwrap_or(limit * DEFAULT_CANDIDATE_MULTIPLIER)
+        .max(limit)
+}
+
+/// Merges the vector and keyword rankings with weighted reciprocal rank fusion: a document
+/// ranked `r` (from 0) by a signal contributes `weights[signal] / (rrf_k + r + 1)` to its
+/// score. Returns the documents best first. With `rank_debug`, each signal's rank and score
+/// are added to the metadata.
+fn reciprocal_rank_fusion(
+    rankings: [Vec<Document>; 2],
+    rrf_k: f64,
+    weights: [f64; 2],
+    rank_debug: bool,
+) -> Vec<Document> {
+    let mut fused: Vec<Document> = Vec::new();
+    // Per fused document, its (rank, score) in the vector and keyword lists.
+    let mut signals: Vec<[Option<(usize, f64)>; 2]> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (signal, docs) in rankings.into_iter().enumerate() {
+        for (rank, doc) in docs.into_iter().enumerate() {
+            let score = weights[signal] / (rrf_k + rank as f64 + 1.0);
+            let signal_score = doc.score;
+            let key = content_key(&doc);
+            let i = match positions.get(&key) {
+                Some(&i) => {
+                    fused[i].score += score;
+                    i
+                }
+                None => {
+                    positions.insert(key, fused.len());
+                    signals.push([None, None]);
+                    fused.push(doc.with_score(score));
+                    fused.len() - 1
+                }
+            };
+            signals[i][signal] = Some((rank + 1, signal_score));
+        }
+    }
+
+    if rank_debug {
+        for (doc, signals) in fused.iter_mut().zip(&signals) {
+            for ((rank_key, score_key), signal) in
+                [("vec_rank", "vec_score"), ("bm25_rank", "bm25_score")]
+                    .into_iter()
+                    .zip(signals)
+            {
+                let (rank, score) = match signal {
+                    Some((rank, score)) => (json!(rank), json!(score)),
+                    None => (Value::Null, Value::Null),
+                };
+                doc.metadata.insert(rank_key.to_string(), rank);
+                doc.metadata.insert(score_key.to_string(), score);
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused
+}
+
+const KEYWORDS_PROMPT: &str = "Extract the most important search keywords from the \
+following question. Answer with the keywords only, separated by commas.\n\nQuestion: ";
+
+/// Which index [`Store::search`] queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Nearest neighbours of the query embedding, as `similarity_search`.
+    Vector,
+    /// bm25 full-text match, as `keyword_search`.
+    Keyword,
+    /// Both, merged with reciprocal rank fusion.
+    #[default]
+    Hybrid,
+}
 
 pub struct Store {
     pub(crate) pool: Arc<Mutex<rusqlite::Connection>>,
     pub(crate) table: String,
     pub(crate) vector_dimensions: i32,
     pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) llm: Option<Box<dyn LLM>>,
+    pub(crate) keyword_cache: Option<Mutex<LruCache<String, String>>>,
     pub(crate) batch_size: i32,
+    pub(crate) max_metadata_bytes: Option<usize>,
+    pub(crate) base_filter: HashMap<String, Value>,
+    pub(crate) filter_overfetch: usize,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) doc_id_column: bool,
+    pub(crate) keyword_fallback: bool,
+    pub(crate) metric: Metric,
+    pub(crate) rrf_k: f64,
+    pub(crate) hybrid_weights: [f64; 2],
 }
 
 impl Store {
@@ -27,53 +129,25 @@ impl Store {
         Ok(())
     }
 
+    /// The tables of this store, for the write and search helpers shared with `sqlite_vec`.
+    fn tables(&self) -> VecTables {
+        VecTables {
+            table: self.table.clone(),
+            keyword_index: true,
+            base_filter: self.base_filter.clone(),
+            id_generator: self.id_generator.clone(),
+            doc_id_column: self.doc_id_column,
+        }
+    }
+
     async fn create_table_if_not_exists(&self) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
-        let db = &self.pool.lock().unwrap();
-
-        db.execute(
-            &format!(
-                r#"
-                CREATE TABLE IF NOT EXISTS {table}
-                (
-                  rowid INTEGER PRIMARY KEY AUTOINCREMENT,
-                  text TEXT,
-                  metadata BLOB,
-                  text_embedding BLOB
-                )
-                ;
-                "#
-            ),
-            (),
-        )?;
+        let db = &self.pool.lock().await;
 
-        let dimensions = self.vector_dimensions;
-
-        db.execute(
-            &format!(
-                r#"
-                CREATE VIRTUAL TABLE IF NOT EXISTS vec_{table} USING vec0(
-                text_embedding float[{dimensions}],
-                );
-                "#
-            ),
-            (),
-        )?;
-
-        db.execute(
-            &format!(
-                r#"
-                CREATE TRIGGER IF NOT EXISTS embed_text_{table}
-                AFTER INSERT ON {table}
-                BEGIN
-                    INSERT INTO vec_{table}(rowid, text_embedding)
-                    VALUES (new.rowid, new.text_embedding)
-                    ;
-                END;
-                "#
-            ),
-            (),
-        )?;
+        create_vec_tables(db, table, self.vector_dimensions, self.metric)?;
+        if self.doc_id_column {
+            ensure_doc_id_column(db, table)?;
+        }
 
         db.execute(
             &format!(
@@ -132,15 +206,14 @@ impl Store {
         Ok(())
     }
 
-    fn get_filters(&self, opt: &VecStoreOptions) -> Result<HashMap<String, Value>, Box<dyn Error>> {
-        match &opt.filters {
-            Some(Value::Object(map)) => {
-                let filters = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-                Ok(filters)
-            }
-            None => Ok(HashMap::new()),
-            _ => Err("Invalid filters format".into()),
-        }
+    /// Drops the store's tables; their triggers go with them.
+    pub(crate) async fn drop_tables(&self) -> Result<(), Box<dyn Error>> {
+        let table = &self.table;
+        let db = self.pool.lock().await;
+        db.execute_batch(&format!(
+            "DROP TABLE IF EXISTS {table}; DROP TABLE IF EXISTS vec_{table}; DROP TABLE IF EXISTS bm25_{table};"
+        ))?;
+        Ok(())
     }
 
     pub async fn delete_documents_by_metadata(
@@ -152,54 +225,178 @@ impl Store {
         }
 
         let table = &self.table;
-        let mut db = self.pool.lock().unwrap();
+        let mut db = self.pool.lock().await;
         let tx = db.transaction()?;
 
         // Build metadata filter conditions
-        let metadata_conditions = metadata_filters
-            .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract(metadata, '$.{}') IN ({})",
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-                Value::Number(n) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(" AND ");
+        let metadata_conditions =
+            build_metadata_query(&self.base_filter, metadata_filters, None, 1)?;
 
         // Delete from main table
         tx.execute(
             &format!(
                 r#"DELETE FROM {table}
                 WHERE {}"#,
-                metadata_conditions
+                metadata_conditions.sql
             ),
-            (),
+            params_from_iter(&metadata_conditions.params),
         )?;
 
         tx.commit()?;
         Ok(())
     }
 
+    /// Cosine similarity between the stored embeddings of documents `id_a` and `id_b`,
+    /// without re-embedding. Errors if either id does not exist.
+    pub async fn similarity_between(&self, id_a: i64, id_b: i64) -> Result<f64, Box<dyn Error>> {
+        let db = self.pool.lock().await;
+        let a = read_embedding(&db, &self.table, id_a, &self.base_filter)?;
+        let b = read_embedding(&db, &self.table, id_b, &self.base_filter)?;
+        cosine_similarity(&a, &b)
+    }
+
+    /// Health check that decodes the stored embeddings of up to `sample_size` random documents
+    /// and errors, listing their rowids, if any doesn't have `vector_dimensions` elements,
+    /// e.g. after documents were added with another embedding model. Unlike
+    /// `StoreBuilder::probe_dimensions`, this inspects the stored data rather than the embedder.
+    pub async fn verify_dimensions(&self, sample_size: usize) -> Result<(), Box<dyn Error>> {
+        let db = self.pool.lock().await;
+        verify_embedding_dimensions(&db, &self.table, self.vector_dimensions, sample_size)
+    }
+
+    /// Like `similarity_search`, but returns the rows that failed to decode alongside the
+    /// documents instead of dropping them.
+    pub async fn similarity_search_with_row_errors(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let mut result = self.vector_ranking(query, limit, opt).await?;
+        result.docs.truncate(limit);
+        Ok(result)
+    }
+
+    /// All `vec_candidates` nearest documents for a search of `limit` results, best first and
+    /// not cut to `limit`.
+    async fn vector_ranking(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        self.metric.check_options(opt)?;
+        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
+        let filter = filters_from_options(opt)?;
+
+        // vec0 applies the metadata filter after picking the k nearest, so fetch more
+        // neighbours when filtering to still fill `limit`.
+        let vec_candidates = if filter.is_empty() && self.base_filter.is_empty() {
+            candidates(opt.vec_candidates, limit)
+        } else {
+            opt.vec_candidates
+                .unwrap_or(limit * DEFAULT_CANDIDATE_MULTIPLIER.max(self.filter_overfetch))
+                .max(limit)
+        }
+        .min(VEC0_MAX_K);
+        let db = self.pool.lock().await;
+        nearest_documents(
+            &db,
+            &self.tables(),
+            query_vector_json,
+            vec_candidates,
+            &filter,
+            self.metric,
+        )
+    }
+
+    /// Like `similarity_search`, but only ranks the documents whose rowid is in `ids`, e.g.
+    /// candidates narrowed down beforehand by business logic. Filters in `opt` and the base
+    /// filter still apply.
+    pub async fn similarity_search_within_ids(
+        &self,
+        query: &str,
+        ids: &[i64],
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        self.metric.check_options(opt)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, Some("e"), 3)?;
+        let db = self.pool.lock().await;
+        let mut docs = search_within_ids(
+            &db,
+            &self.table,
+            &query_vector_json,
+            ids,
+            limit,
+            &metadata_query,
+            self.metric,
+        )?
+        .into_docs();
+        apply_score_threshold(&mut docs, opt);
+        Ok(docs)
+    }
+
+    /// Like `add_documents`, but also returns the embedding of each document, e.g. to store
+    /// it elsewhere without embedding the documents again. With `reject_duplicates`, skipped
+    /// documents come with the embedding already stored for them.
+    pub async fn add_documents_returning_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        let caller_ids = caller_ids(opt, docs.len())?;
+
+        let skip = if opt.reject_duplicates {
+            let db = self.pool.lock().await;
+            duplicate_mask(&db, &self.table, docs, &self.base_filter)?
+        } else {
+            vec![false; docs.len()]
+        };
+        let texts: Vec<String> = docs
+            .iter()
+            .zip(&skip)
+            .filter(|(_, skip)| !**skip)
+            .map(|(d, _)| opt.embedding_text(d))
+            .collect();
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+
+        let batch_size = self.batch_size as usize;
+        let mut batches = texts.chunks(batch_size);
+
+        let mut vectors = Vec::with_capacity(docs.len());
+
+        while let Some(batch) = batches.next() {
+            let vector = embedder.embed_documents(batch).await?;
+            vectors.extend(vector);
+        }
+
+        if vectors.len() != texts.len() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Number of vectors and documents do not match",
+            )));
+        }
+
+        let mut db = self.pool.lock().await;
+        insert_documents(
+            &mut db,
+            &self.tables(),
+            docs,
+            skip,
+            vectors,
+            caller_ids,
+            opt.reject_duplicates,
+        )
+    }
+
     pub async fn delete_documents_by_ids(&self, ids: &[i64]) -> Result<(), Box<dyn Error>> {
         if ids.is_empty() {
             return Ok(());
@@ -207,18 +404,21 @@ impl Store {
 
         let table = &self.table;
         let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let scope = build_metadata_query(&self.base_filter, &HashMap::new(), None, ids.len() + 1)?;
 
-        let mut db = self.pool.lock().unwrap();
+        let mut db = self.pool.lock().await;
         let tx = db.transaction()?;
 
         let query = format!(
             r#"
             DELETE FROM {table}
-            WHERE rowid IN ({placeholders})
-            "#
+            WHERE rowid IN ({placeholders}) AND {}
+            "#,
+            scope.sql
         );
 
-        tx.execute(&query, rusqlite::params_from_iter(ids))?;
+        let params = ids.iter().map(|id| SqlValue::from(*id)).chain(scope.params);
+        tx.execute(&query, rusqlite::params_from_iter(params))?;
         tx.commit()?;
 
         Ok(())
@@ -227,7 +427,7 @@ impl Store {
     pub async fn delete_all_documents(&self) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
 
-        let mut db = self.pool.lock().unwrap();
+        let mut db = self.pool.lock().await;
         let tx = db.transaction()?;
 
         tx.execute(
@@ -244,237 +444,600 @@ impl Store {
         Ok(())
     }
 
+    /// Searches the vector index, the keyword index, or both depending on `mode`.
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        mode: SearchMode,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        match mode {
+            SearchMode::Vector => self.similarity_search(query, limit, opt).await,
+            SearchMode::Keyword => self.keyword_search(query, limit, opt).await,
+            SearchMode::Hybrid => self.hybrid_search(query, limit, opt).await,
+        }
+    }
+
+    /// Runs both searches for `vec_candidates`/`keyword_candidates` results each, merges them
+    /// with reciprocal rank fusion and keeps the best `limit`. `Document::score` is the fused
+    /// score, which `opt.score_threshold` applies to. With `opt.rank_debug`, each signal's rank
+    /// and score are added to the metadata.
+    async fn hybrid_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let vector_docs = self.vector_ranking(query, limit, opt).await?.into_docs();
+        let keyword_query = self.keyword_query(query).await?;
+        let keyword_docs = self
+            .keyword_ranking(&keyword_query, limit, opt)
+            .await?
+            .into_docs();
+
+        let mut fused = reciprocal_rank_fusion(
+            [vector_docs, keyword_docs],
+            self.rrf_k,
+            self.hybrid_weights,
+            opt.rank_debug,
+        );
+        apply_score_threshold(&mut fused, opt);
+        fused.truncate(limit);
+        self.apply_keyword_fallback(fused, query, limit, opt).await
+    }
+
+    /// The full-text query for the keyword side of hybrid search: with an LLM configured, the
+    /// keywords it extracts from `query`, OR-ed; otherwise `query` itself. Extracted keywords
+    /// are cached by query unless `StoreBuilder::keyword_cache` is off.
+    async fn keyword_query(&self, query: &str) -> Result<String, Box<dyn Error>> {
+        let Some(llm) = &self.llm else {
+            return Ok(query.to_string());
+        };
+        if let Some(cache) = &self.keyword_cache {
+            if let Some(cached) = cache.lock().await.get(query) {
+                return Ok(cached.clone());
+            }
+        }
+        let answer = llm.invoke(&format!("{}{}", KEYWORDS_PROMPT, query)).await?;
+        let keywords: Vec<String> = answer
+            .split([',', '\n'])
+            .map(|keyword| keyword.trim().trim_matches('"').trim())
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| format!("\"{}\"", keyword.replace('"', "\"\"")))
+            .collect();
+        let keyword_query = if keywords.is_empty() {
+            query.to_string()
+        } else {
+            keywords.join(" OR ")
+        };
+        if let Some(cache) = &self.keyword_cache {
+            cache
+                .lock()
+                .await
+                .put(query.to_string(), keyword_query.clone());
+        }
+        Ok(keyword_query)
+    }
+
+    /// With `StoreBuilder::keyword_fallback` enabled, replaces `docs` by the keyword search
+    /// results for the `keyword_query` of `query` when `docs` is empty, flagging each with
+    /// `via_keyword_fallback`. `opt.score_threshold` applies to the keyword scores.
+    async fn apply_keyword_fallback(
+        &self,
+        docs: Vec<Document>,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if !self.keyword_fallback || !docs.is_empty() {
+            return Ok(docs);
+        }
+        let keyword_query = self.keyword_query(query).await?;
+        let mut docs = self
+            .keyword_search_with_row_errors(&keyword_query, limit, opt)
+            .await?
+            .into_docs();
+        apply_score_threshold(&mut docs, opt);
+        for doc in docs.iter_mut() {
+            doc.metadata
+                .insert("via_keyword_fallback".to_string(), Value::Bool(true));
+        }
+        Ok(docs)
+    }
+
+    /// bm25 full-text search. Scores are the sigmoid of the negated bm25 value, and documents
+    /// scoring below `opt.score_threshold` are dropped.
     pub async fn keyword_search(
         &self,
         query: &str,
         limit: usize,
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut docs = self
+            .keyword_search_with_row_errors(query, limit, opt)
+            .await?
+            .into_docs();
+        apply_score_threshold(&mut docs, opt);
+        Ok(docs)
+    }
+
+    /// Like `keyword_search`, but returns the rows that failed to decode alongside the
+    /// documents instead of dropping them.
+    pub async fn keyword_search_with_row_errors(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let mut result = self.keyword_ranking(query, limit, opt).await?;
+        result.docs.truncate(limit);
+        Ok(result)
+    }
+
+    /// All `keyword_candidates` best keyword matches for a search of `limit` results, best
+    /// first and not cut to `limit`.
+    async fn keyword_ranking(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
         let table = format!("bm25_{}", self.table);
-        let filter = self.get_filters(opt)?;
-        let db = self.pool.lock().unwrap();
+        let filter = filters_from_options(opt)?;
+        let db = self.pool.lock().await;
 
-        let metadata_query = self.build_metadata_query(&filter, None);
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, None, 3)?;
 
         let mut stmt = db.prepare(&format!(
             r#"
             SELECT
                 text,
                 metadata,
-                bm25({table}) as score
+                bm25({table}) as score,
+                rowid
             FROM {table}
-            WHERE {table} MATCH ?1 AND {metadata_query}
-            ORDER BY score DESC
+            WHERE {table} MATCH ?1 AND {}
+            ORDER BY score ASC
             LIMIT ?2
-            "#
+            "#,
+            metadata_query.sql
         ))?;
 
-        let docs = stmt
-            .query_map(params![query, limit as i64], |row| {
-                let page_content: String = row.get(0)?;
-                let metadata_json: String = row.get(1)?;
-                let raw_score: f64 = row.get(2)?;
+        let keyword_candidates = candidates(opt.keyword_candidates, limit);
+        let params = [
+            SqlValue::from(query.to_string()),
+            SqlValue::from(keyword_candidates as i64),
+        ];
+        let rows = stmt.query_map(
+            params_from_iter(params.iter().chain(&metadata_query.params)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let mut result = collect_rows(rows);
+        for doc in result.docs.iter_mut() {
+            // 将 BM25 分数转换为 0-1 范围
+            // fts5 的 BM25 分数是负数，越小表示越相关
+            // 使用 sigmoid 函数进行归一化: 1 / (1 + e^(score))
+            doc.score = 1.0 / (1.0 + doc.score.exp());
+        }
 
-                // 将 BM25 分数转换为 0-1 范围
-                // BM25 分数通常���正数，越大表示越相关
-                // 使用 sigmoid 函数进行归一化: 1 / (1 + e^(-score))
-                let score = 1.0 / (1.0 + (-raw_score).exp());
+        Ok(result)
+    }
+}
 
-                let metadata: HashMap<String, Value> =
-                    serde_json::from_str(&metadata_json).unwrap();
+#[async_trait]
+impl VectorStore for Store {
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self
+            .add_documents_returning_embeddings(docs, opt)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
 
-                Ok(Document {
-                    page_content,
-                    metadata,
-                    score,
-                })
-            })?
-            .collect::<Result<Vec<Document>, rusqlite::Error>>()?;
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut docs = self
+            .similarity_search_with_row_errors(query, limit, opt)
+            .await?
+            .into_docs();
+        apply_score_threshold(&mut docs, opt);
+        self.apply_keyword_fallback(docs, query, limit, opt).await
+    }
 
-        Ok(docs)
+    async fn keyword_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        Store::keyword_search(self, query, limit, opt).await
     }
 
-    fn build_metadata_query(
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, None, 1)?;
+        let db = self.pool.lock().await;
+        let count: i64 = db.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE {}",
+                self.table, metadata_query.sql
+            ),
+            params_from_iter(&metadata_query.params),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    async fn upsert_documents(
         &self,
-        filter: &HashMap<String, Value>,
-        table_prefix: Option<&str>,
-    ) -> String {
-        let prefix = table_prefix.unwrap_or("");
-        let metadata_path = if prefix.is_empty() {
-            "metadata".to_string()
-        } else {
-            format!("{}.metadata", prefix)
-        };
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        let keys = upsert_keys(docs, opt)?;
+        let table = &self.table;
 
-        let query = filter
+        let existing = {
+            let db = self.pool.lock().await;
+            ensure_doc_id_column(&db, table)?;
+            rows_by_doc_id(&db, table, &keys, &self.base_filter)?
+        };
+        // The embedding of an unchanged text is kept, unless a template may embed metadata.
+        let reembed: Vec<bool> = docs
             .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract({}, '$.{}') IN ({})",
-                        metadata_path,
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!(
-                        "json_extract({}, '$.{}') = {}",
-                        metadata_path, k, json_value
-                    )
-                }
-                Value::Number(n) => {
-                    format!("json_extract({}, '$.{}') = {}", metadata_path, k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract({}, '$.{}') = {}", metadata_path, k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!(
-                        "json_extract({}, '$.{}') = {}",
-                        metadata_path, k, json_value
-                    )
-                }
+            .zip(&existing)
+            .map(|(doc, row)| match row {
+                Some((_, text)) => *text != doc.page_content || opt.embedding_template.is_some(),
+                None => true,
             })
-            .collect::<Vec<String>>()
-            .join(" AND ");
-
-        if query.is_empty() {
-            "1=1".to_string()
-        } else {
-            query
+            .collect();
+        let texts: Vec<String> = docs
+            .iter()
+            .zip(&reembed)
+            .filter(|(_, reembed)| **reembed)
+            .map(|(d, _)| opt.embedding_text(d))
+            .collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size as usize) {
+            vectors.extend(embedder.embed_documents(batch).await?);
+        }
+        if vectors.len() != texts.len() {
+            return Err("Number of vectors and documents do not match".into());
         }
+
+        let mut db = self.pool.lock().await;
+        write_upserts(
+            &mut db,
+            &self.tables(),
+            docs,
+            keys,
+            &existing,
+            &reembed,
+            vectors,
+        )
     }
-}
 
-#[async_trait]
-impl VectorStore for Store {
-    async fn add_documents(
+    async fn get_documents_by_ids(
         &self,
-        docs: &[Document],
+        ids: &[String],
         opt: &VecStoreOptions,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let found = {
+            let db = self.pool.lock().await;
+            documents_by_ids(&db, &self.table, ids, &self.base_filter)?
+        };
+        order_by_ids(ids, found, opt)
+    }
+}
 
-        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
 
-        let batch_size = self.batch_size as usize;
-        let mut batches = texts.chunks(batch_size);
+    use futures::Stream;
 
-        let mut vectors = Vec::with_capacity(docs.len());
+    use super::*;
+    use crate::{
+        language_models::{GenerateResult, LLMError},
+        schemas::{Message, StreamData},
+        vectorstore::{
+            sqlite_hybrid::StoreBuilder,
+            test_utils::{
+                assert_get_documents_by_ids_applies_base_filter,
+                assert_skipped_duplicate_returns_stored_id, assert_upsert_replaces_raced_row,
+                store_raced_row, tenant_doc, LetterEmbedder, RacingEmbedder,
+            },
+        },
+    };
 
-        while let Some(batch) = batches.next() {
-            let vector = embedder.embed_documents(batch).await?;
-            vectors.extend(vector);
-        }
+    async fn store_with(texts: &[&str]) -> Store {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        let docs: Vec<Document> = texts.iter().map(|text| Document::new(*text)).collect();
+        store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        store
+    }
 
-        if vectors.len() != docs.len() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Number of vectors and documents do not match",
-            )));
+    #[tokio::test]
+    async fn test_upsert_replaces_row_stored_while_embedding() {
+        let store = store_with(&[]).await;
+        let pool = store.pool.clone();
+        let embedder = RacingEmbedder(move || store_raced_row(&pool.try_lock().unwrap()));
+        assert_upsert_replaces_raced_row(&store, embedder).await;
+    }
+
+    #[tokio::test]
+    async fn test_skipped_duplicate_returns_stored_id() {
+        assert_skipped_duplicate_returns_stored_id(&store_with(&[]).await).await;
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_with_limit_above_vec0_max_k() {
+        let store = store_with(&["aaa", "aab", "ccc"]).await;
+
+        // limit * 4 candidates is more than vec0 takes as `k`.
+        let found = store
+            .search("aab", 2000, SearchMode::Hybrid, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].page_content, "aab");
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_fuses_candidates_below_limit() {
+        // "a" is the nearest vector, "apple apple ccc" the best keyword match, and
+        // "apple b" second in both, so it only wins if fusion sees more than the top result.
+        let store = store_with(&["a", "apple b", "aab", "aac", "apple apple ccc"]).await;
+
+        let found = store
+            .search("apple", 1, SearchMode::Hybrid, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].page_content, "apple b");
+    }
+
+    #[derive(Clone)]
+    struct CountingLLM {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LLM for CountingLLM {
+        async fn generate(&self, _messages: &[Message]) -> Result<GenerateResult, LLMError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(GenerateResult {
+                generation: "aab".to_string(),
+                ..Default::default()
+            })
         }
 
-        let table = &self.table;
+        async fn stream(
+            &self,
+            _messages: &[Message],
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>
+        {
+            Ok(Box::pin(futures::stream::empty()))
+        }
+    }
 
-        let mut db = self.pool.lock().unwrap();
-        let tx = db.transaction()?;
+    #[tokio::test]
+    async fn test_keyword_cache_calls_llm_once_per_query() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .llm(CountingLLM {
+                calls: calls.clone(),
+            })
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
 
-        let mut ids = Vec::with_capacity(docs.len());
-
-        for (doc, vector) in docs.iter().zip(vectors.iter()) {
-            let text_embedding = json!(&vector).to_string();
-
-            let id: i64 = tx
-                .query_row(
-                    &format!(
-                        r#"
-                    INSERT INTO {table}
-                        (text, metadata, text_embedding)
-                    VALUES
-                        (?, ?, ?)
-                    RETURNING rowid"#
-                    ),
-                    params![
-                        &doc.page_content,
-                        &json!(doc.metadata).to_string(),
-                        &text_embedding
-                    ],
-                    |row| row.get::<_, i64>(0),
-                )?
-                .try_into()
+        for _ in 0..2 {
+            store
+                .search("aab", 2, SearchMode::Hybrid, &VecStoreOptions::default())
+                .await
                 .unwrap();
-
-            ids.push(id.to_string());
         }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
 
-        tx.commit()?;
+    #[tokio::test]
+    async fn test_keyword_fallback_applies_score_threshold() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .keyword_fallback(true)
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+            .add_documents(
+                &[Document::new("banana split")],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
 
-        Ok(ids)
+        // Neither the vector score nor the sigmoid of the bm25 score reaches 0.9.
+        let opt = VecStoreOptions::default().with_score_threshold(0.9);
+        let found = store.similarity_search("split", 5, &opt).await.unwrap();
+        assert!(found.is_empty());
     }
 
-    async fn similarity_search(
-        &self,
-        query: &str,
-        limit: usize,
-        opt: &VecStoreOptions,
-    ) -> Result<Vec<Document>, Box<dyn Error>> {
-        let table = &self.table;
-        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
-        let db = self.pool.lock().unwrap();
+    #[tokio::test]
+    async fn test_keyword_fallback_searches_llm_keywords() {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .llm(CountingLLM {
+                calls: Arc::new(AtomicUsize::new(0)),
+            })
+            .keyword_fallback(true)
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+            .add_documents(&[Document::new("aab")], &VecStoreOptions::default())
+            .await
+            .unwrap();
 
-        let filter = self.get_filters(opt)?;
-        let metadata_query = self.build_metadata_query(&filter, Some("e"));
+        // "zzz" is too far from "aab" for the threshold and doesn't match it as a keyword,
+        // but the LLM turns it into "aab".
+        let opt = VecStoreOptions::default().with_score_threshold(0.4);
+        let found = store.similarity_search("zzz", 5, &opt).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata["via_keyword_fallback"], json!(true));
+    }
 
-        let mut stmt = db.prepare(&format!(
-            r#"SELECT
-                e.text,
-                e.metadata,
-                v.distance
-            FROM {table} e
-            INNER JOIN vec_{table} v on v.rowid = e.rowid
-            WHERE v.text_embedding match ?1 AND k = ?2 AND {metadata_query}
-            ORDER BY distance
-            LIMIT ?3"#
-        ))?;
+    #[tokio::test]
+    async fn test_build_rejects_invalid_fusion_parameters() {
+        let build = |rrf_k: f64, weights: [f64; 2]| {
+            StoreBuilder::new()
+                .connection_url(":memory:")
+                .vector_dimensions(3)
+                .embedder(LetterEmbedder)
+                .rrf_k(rrf_k)
+                .hybrid_weights(weights[0], weights[1])
+                .build()
+        };
+        assert!(build(f64::NAN, [1.0, 1.0]).await.is_err());
+        assert!(build(-1.0, [1.0, 1.0]).await.is_err());
+        assert!(build(60.0, [1.0, f64::NAN]).await.is_err());
+        assert!(build(60.0, [f64::INFINITY, 1.0]).await.is_err());
+        assert!(build(60.0, [-0.5, 1.0]).await.is_err());
+        assert!(build(0.0, [0.0, 1.0]).await.is_ok());
+    }
 
-        let doubled_limit = limit * 2;
-        let docs = stmt
-            .query_map(
-                params![query_vector_json, limit as i32, doubled_limit as i32],
-                |row| {
-                    let page_content: String = row.get(0)?;
-                    let metadata_json: String = row.get(1)?;
-                    let distance: f64 = row.get(2)?;
-                    let score = 1.0 / (1.0 + distance);
-                    let metadata: HashMap<String, Value> =
-                        serde_json::from_str(&metadata_json).unwrap();
-
-                    Ok(Document {
-                        page_content,
-                        metadata,
-                        score,
-                    })
-                },
-            )?
-            .collect::<Result<Vec<Document>, rusqlite::Error>>()?;
-
-        let mut seen = std::collections::HashSet::new();
-        let mut unique_docs: Vec<Document> = docs
-            .into_iter()
-            .filter(|doc| {
-                let key = format!("{}{}", doc.page_content, json!(doc.metadata));
-                seen.insert(key)
-            })
-            .collect();
+    #[test]
+    fn test_rrf_k_favors_top_keyword_match() {
+        let ranking = |names: &[&str]| -> Vec<Document> {
+            names.iter().map(|name| Document::new(*name)).collect()
+        };
+        let vector = ranking(&["v0", "v1", "v2", "a", "v4", "v5", "v6", "v7", "v8", "c"]);
+        let keyword = ranking(&["c", "k1", "k2", "a"]);
 
-        unique_docs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        unique_docs.truncate(limit);
+        let top = |rrf_k: f64, weights: [f64; 2]| {
+            reciprocal_rank_fusion([vector.clone(), keyword.clone()], rrf_k, weights, false)[0]
+                .page_content
+                .clone()
+        };
+        // With the default constant, ranking well in both lists wins; with 1, the perfect
+        // keyword match does.
+        assert_eq!(top(60.0, [1.0, 1.0]), "a");
+        assert_eq!(top(1.0, [1.0, 1.0]), "c");
+        // So does it when keyword matches weigh more.
+        assert_eq!(top(60.0, [1.0, 2.0]), "c");
+    }
+
+    /// A store scoped to tenant `a` holding one document of tenant `a` and one of tenant `b`,
+    /// with their ids.
+    async fn tenant_store() -> (Store, Vec<String>) {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .base_filter(json!({"tenant": "a"}))
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        let docs = [tenant_doc("ours", "a"), tenant_doc("theirs", "b")];
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        (store, ids)
+    }
+
+    /// Every stored text, regardless of the base filter.
+    async fn stored_texts(store: &Store) -> Vec<String> {
+        let conn = store.pool.lock().await;
+        let mut stmt = conn
+            .prepare(&format!("SELECT text FROM {} ORDER BY rowid", store.table))
+            .unwrap();
+        let texts = stmt.query_map([], |row| row.get(0)).unwrap();
+        texts.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_documents_by_ids_applies_base_filter() {
+        let (store, ids) = tenant_store().await;
+        assert_get_documents_by_ids_applies_base_filter(&store, &ids).await;
+    }
 
-        Ok(unique_docs)
+    #[tokio::test]
+    async fn test_delete_documents_by_ids_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        store.delete_documents_by_ids(&[1, 2]).await.unwrap();
+        assert_eq!(stored_texts(&store).await, vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_metadata_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        let filter = HashMap::from([("source".to_string(), json!("doc.md"))]);
+        store.delete_documents_by_metadata(&filter).await.unwrap();
+        assert_eq!(stored_texts(&store).await, vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_similarity_between_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        assert!(store.similarity_between(1, 1).await.is_ok());
+        assert!(store.similarity_between(1, 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_keeps_other_tenant_row() {
+        let (store, _) = tenant_store().await;
+        let opt = VecStoreOptions::default().with_ids(vec!["k".to_string()]);
+
+        store
+            .upsert_documents(&[tenant_doc("their k", "b")], &opt)
+            .await
+            .unwrap();
+        store
+            .upsert_documents(&[tenant_doc("our k", "a")], &opt)
+            .await
+            .unwrap();
+        assert_eq!(
+            stored_texts(&store).await,
+            vec!["ours", "theirs", "their k", "our k"]
+        );
     }
 }
@@ -0,0 +1,1629 @@
+//! Helpers shared by the SQLite-backed stores (`sqlite_vec`, `sqlite_bm25`, `sqlite_hybrid`).
+
+use std::{collections::HashMap, error::Error, time::Duration};
+
+use rusqlite::{types::Value as SqlValue, Connection};
+use serde_json::{json, Value};
+
+use crate::{
+    schemas::Document,
+    vectorstore::{Condition, FilterOp, MetadataFilter, VecStoreOptions},
+};
+
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+use std::sync::Arc;
+
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+use crate::vectorstore::IdGenerator;
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+use rusqlite::{ffi::sqlite3_auto_extension, types::ValueRef};
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+use sqlite_vec::sqlite3_vec_init;
+
+/// Metadata key under which search results carry the rowid of their document, e.g. to pass it
+/// to `delete_documents_by_ids`. Set on the returned documents only; a stored value under this
+/// key is overwritten.
+pub const DOCUMENT_ID_KEY: &str = "_id";
+
+/// The largest `k` a vec0 KNN query accepts.
+pub(crate) const VEC0_MAX_K: usize = 4096;
+
+/// A result row that could not be turned into a `Document`.
+#[derive(Debug)]
+pub struct RowError {
+    /// Position of the row in the result set.
+    pub index: usize,
+    pub error: Box<dyn Error + Send + Sync>,
+}
+
+/// The documents of a search together with the rows that failed to decode, so one bad row
+/// doesn't fail the whole search.
+#[derive(Debug, Default)]
+pub struct SearchResult {
+    pub docs: Vec<Document>,
+    pub row_errors: Vec<RowError>,
+}
+
+/// Whether a search matched anything and read every matching row, to tell a legitimately
+/// empty result from one that lost rows to decoding errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStatus {
+    /// The query ran and matched no rows.
+    NoMatches,
+    /// Every matching row was returned.
+    Complete,
+    /// This many matching rows could not be decoded and were skipped; the remaining
+    /// documents, possibly none, were returned.
+    RowsSkipped(usize),
+}
+
+impl SearchResult {
+    pub fn status(&self) -> SearchStatus {
+        match (self.docs.is_empty(), self.row_errors.len()) {
+            (true, 0) => SearchStatus::NoMatches,
+            (false, 0) => SearchStatus::Complete,
+            (_, skipped) => SearchStatus::RowsSkipped(skipped),
+        }
+    }
+
+    /// Returns the documents, or an error if any row was skipped, for callers that treat
+    /// skipped rows as a failure rather than a warning.
+    pub fn into_complete_docs(self) -> Result<Vec<Document>, Box<dyn Error>> {
+        if let Some(first) = self.row_errors.first() {
+            return Err(format!(
+                "{} result rows could not be decoded; first at row {}: {}",
+                self.row_errors.len(),
+                first.index,
+                first.error
+            )
+            .into());
+        }
+        Ok(self.docs)
+    }
+
+    /// Returns the documents, logging a warning for each row error.
+    pub fn into_docs(self) -> Vec<Document> {
+        for row_error in &self.row_errors {
+            log::warn!(
+                "Skipping result row {}: {}",
+                row_error.index,
+                row_error.error
+            );
+        }
+        self.docs
+    }
+}
+
+/// Registers the sqlite-vec extension for every connection opened afterwards.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn register_sqlite_vec() {
+    unsafe {
+        sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
+    }
+}
+
+/// Errors with an actionable message unless the sqlite-vec extension is loaded on `conn`,
+/// rather than letting `CREATE VIRTUAL TABLE ... USING vec0` fail later with "no such module".
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn check_sqlite_vec(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    conn.query_row("SELECT vec_version()", [], |row| row.get::<_, String>(0))
+        .map_err(|e| {
+            format!(
+                "sqlite-vec extension not loaded ({}); ensure it's linked or installed, and \
+                 that connections passed to the builder were opened after it was registered",
+                e
+            )
+        })?;
+    Ok(())
+}
+
+/// Distance metric of a `vec0` index, set when its table is created.
+///
+/// vec0 has no inner-product metric; for normalized embeddings, `Cosine` ranks documents the
+/// same way a dot product would.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// Euclidean distance, scored `1 / (1 + distance)`. The vec0 default.
+    #[default]
+    L2,
+    /// Cosine distance (`1 - cosine similarity`), scored as the cosine similarity clamped to
+    /// 0..1.
+    Cosine,
+    /// Manhattan distance, scored `1 / (1 + distance)`.
+    L1,
+}
+
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+impl Metric {
+    /// The value of the vec0 `distance_metric` option.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::L2 => "l2",
+            Metric::Cosine => "cosine",
+            Metric::L1 => "l1",
+        }
+    }
+
+    fn from_vec0(name: &str) -> Option<Self> {
+        match name {
+            "l2" => Some(Metric::L2),
+            "cosine" => Some(Metric::Cosine),
+            "l1" => Some(Metric::L1),
+            _ => None,
+        }
+    }
+
+    /// The SQL function computing this distance outside of a vec0 KNN query.
+    pub(crate) fn distance_function(&self) -> &'static str {
+        match self {
+            Metric::L2 => "vec_distance_l2",
+            Metric::Cosine => "vec_distance_cosine",
+            Metric::L1 => "vec_distance_l1",
+        }
+    }
+
+    /// Errors unless `opt.distance_metric` is unset or this metric, since a vec0 index can only
+    /// be searched with the metric it was created with.
+    pub(crate) fn check_options(&self, opt: &VecStoreOptions) -> Result<(), Box<dyn Error>> {
+        use crate::vectorstore::DistanceMetric;
+
+        let requested = match opt.distance_metric {
+            None => return Ok(()),
+            Some(DistanceMetric::L2) => Metric::L2,
+            Some(DistanceMetric::Cosine) => Metric::Cosine,
+            Some(DistanceMetric::InnerProduct) => {
+                return Err("sqlite-vec has no inner product distance; for normalized \
+                            embeddings, use the cosine metric instead"
+                    .into())
+            }
+        };
+        if requested != *self {
+            return Err(format!(
+                "The table uses the {} distance metric, but the search asked for {}; set \
+                 StoreBuilder::distance_metric when creating the table instead",
+                self.as_str(),
+                requested.as_str()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Turns a distance into a score in 0..1, higher meaning more similar.
+    pub fn score(&self, distance: f64) -> f64 {
+        match self {
+            Metric::L2 | Metric::L1 => 1.0 / (1.0 + distance),
+            Metric::Cosine => (1.0 - distance).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Creates the documents table, its `vec0` index `vec_{table}` and the trigger that indexes
+/// inserted rows, unless they exist. Errors if `vec_{table}` exists with another metric.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn create_vec_tables(
+    conn: &Connection,
+    table: &str,
+    dimensions: i32,
+    metric: Metric,
+) -> Result<(), Box<dyn Error>> {
+    check_sqlite_vec(conn)?;
+    let metric_name = metric.as_str();
+    conn.execute_batch(&format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS {table}
+        (
+          rowid INTEGER PRIMARY KEY AUTOINCREMENT,
+          text TEXT,
+          metadata BLOB,
+          text_embedding BLOB
+        );
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS vec_{table} USING vec0(
+          text_embedding float[{dimensions}] distance_metric={metric_name}
+        );
+
+        CREATE TRIGGER IF NOT EXISTS embed_text_{table}
+        AFTER INSERT ON {table}
+        BEGIN
+            INSERT INTO vec_{table}(rowid, text_embedding)
+            VALUES (new.rowid, new.text_embedding);
+        END;
+        "#
+    ))?;
+
+    let existing = vec_table_metric(conn, table)?;
+    if existing != Some(metric) {
+        return Err(format!(
+            "vec_{} was created with distance metric {}, but the store is configured for {}",
+            table,
+            existing.map_or("unknown", |m| m.as_str()),
+            metric_name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Reads the distance metric from the DDL of `vec_{table}`. `None` if the table does not exist
+/// or its metric is not one of [`Metric`]; tables created without the option use L2.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn vec_table_metric(
+    conn: &Connection,
+    table: &str,
+) -> Result<Option<Metric>, Box<dyn Error>> {
+    use rusqlite::OptionalExtension;
+
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = ?1",
+            [format!("vec_{table}")],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(sql) = sql else {
+        return Ok(None);
+    };
+    let sql: String = sql
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    Ok(match sql.split_once("distance_metric=") {
+        Some((_, rest)) => {
+            let name: String = rest.chars().take_while(|c| c.is_alphanumeric()).collect();
+            Metric::from_vec0(&name)
+        }
+        None => Some(Metric::L2),
+    })
+}
+
+/// Ranks the documents with rowid in `ids` by their `metric` distance to `query_vector_json`,
+/// scoring them like the vec0 searches. Rather than a vec0 KNN query, which applies
+/// other constraints only after picking its `k` nearest and so could miss every candidate,
+/// this computes the distance of each candidate directly. `metadata_query` must number its
+/// placeholders from `?3`.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn search_within_ids(
+    conn: &Connection,
+    table: &str,
+    query_vector_json: &str,
+    ids: &[i64],
+    limit: usize,
+    metadata_query: &FilterSql,
+    metric: Metric,
+) -> Result<SearchResult, Box<dyn Error>> {
+    if ids.is_empty() {
+        return Ok(SearchResult::default());
+    }
+    let ids = ids
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT
+            e.text,
+            e.metadata,
+            {}(v.text_embedding, ?1) AS distance,
+            e.rowid
+        FROM {table} e
+        INNER JOIN vec_{table} v on v.rowid = e.rowid
+        WHERE e.rowid IN ({ids}) AND {}
+        ORDER BY distance
+        LIMIT ?2"#,
+        metric.distance_function(),
+        metadata_query.sql
+    ))?;
+    let params = [
+        SqlValue::from(query_vector_json.to_string()),
+        SqlValue::from(limit as i64),
+    ];
+    let rows = stmt.query_map(
+        rusqlite::params_from_iter(params.iter().chain(&metadata_query.params)),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    let mut result = collect_rows(rows);
+    for doc in result.docs.iter_mut() {
+        doc.score = metric.score(doc.score);
+    }
+    Ok(result)
+}
+
+/// For `VecStoreOptions::reject_duplicates`: whether each of `docs` repeats the exact
+/// `page_content` of a stored document matching `base_filter` or of an earlier document in
+/// `docs`. Creates the index on the content column that the lookups use, if it does not exist
+/// yet.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn duplicate_mask(
+    conn: &Connection,
+    table: &str,
+    docs: &[Document],
+    base_filter: &HashMap<String, Value>,
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    conn.execute(
+        &format!("CREATE INDEX IF NOT EXISTS {table}_text ON {table} (text)"),
+        [],
+    )?;
+    let mut seen = std::collections::HashSet::new();
+    docs.iter()
+        .map(|doc| {
+            let repeated = !seen.insert(doc.page_content.as_str());
+            let existing = existing_content_id(conn, table, &doc.page_content, base_filter)?;
+            Ok(repeated || existing.is_some())
+        })
+        .collect()
+}
+
+/// The rowid of a stored document matching `base_filter` whose content is exactly `content`,
+/// with its `doc_id` if the table has that column and the row has one.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn existing_content_id(
+    conn: &Connection,
+    table: &str,
+    content: &str,
+    base_filter: &HashMap<String, Value>,
+) -> Result<Option<(i64, Option<String>)>, Box<dyn Error>> {
+    use rusqlite::OptionalExtension;
+
+    let doc_id = if has_doc_id_column(conn, table)? {
+        "doc_id"
+    } else {
+        "NULL"
+    };
+    let scope = build_metadata_query(base_filter, &HashMap::new(), None, 2)?;
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT rowid, {doc_id} FROM {table} WHERE text = ?1 AND {} LIMIT 1",
+                scope.sql
+            ),
+            rusqlite::params_from_iter(
+                std::iter::once(SqlValue::from(content.to_string())).chain(scope.params),
+            ),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?)
+}
+
+/// Whether `table` has the `doc_id` column added by [`ensure_doc_id_column`].
+pub(crate) fn has_doc_id_column(conn: &Connection, table: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(conn.query_row(
+        &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{table}') WHERE name = 'doc_id'"),
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// The stored documents among `ids` that match `base_filter`, keyed by id, for
+/// `get_documents_by_ids`. Ids are matched against the `doc_id` column when the table has one,
+/// and as row ids for the rows without a `doc_id`.
+pub(crate) fn documents_by_ids(
+    conn: &Connection,
+    table: &str,
+    ids: &[String],
+    base_filter: &HashMap<String, Value>,
+) -> Result<HashMap<String, Document>, Box<dyn Error>> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rowids: Vec<SqlValue> = ids
+        .iter()
+        .filter_map(|id| id.parse::<i64>().ok())
+        .map(SqlValue::Integer)
+        .collect();
+    let placeholders = |first: usize, count: usize| {
+        (first..first + count)
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let (sql, params) = if has_doc_id_column(conn, table)? {
+        let scope = build_metadata_query(
+            base_filter,
+            &HashMap::new(),
+            None,
+            ids.len() + rowids.len() + 1,
+        )?;
+        let sql = format!(
+            "SELECT COALESCE(doc_id, CAST(rowid AS TEXT)), text, metadata FROM {table}
+            WHERE (doc_id IN ({}) OR (doc_id IS NULL AND rowid IN ({}))) AND {}",
+            placeholders(1, ids.len()),
+            placeholders(ids.len() + 1, rowids.len()),
+            scope.sql
+        );
+        let params: Vec<SqlValue> = ids
+            .iter()
+            .map(|id| SqlValue::Text(id.clone()))
+            .chain(rowids)
+            .chain(scope.params)
+            .collect();
+        (sql, params)
+    } else {
+        let scope = build_metadata_query(base_filter, &HashMap::new(), None, rowids.len() + 1)?;
+        let sql = format!(
+            "SELECT CAST(rowid AS TEXT), text, metadata FROM {table}
+            WHERE rowid IN ({}) AND {}",
+            placeholders(1, rowids.len()),
+            scope.sql
+        );
+        (sql, rowids.into_iter().chain(scope.params).collect())
+    };
+
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+    let mut docs = HashMap::new();
+    for row in rows {
+        let (id, page_content, metadata_json) = row?;
+        let metadata: HashMap<String, Value> = serde_json::from_str(&metadata_json)?;
+        docs.insert(id, Document::new(page_content).with_metadata(metadata));
+    }
+    Ok(docs)
+}
+
+/// For `upsert_documents`: the rowid and text of the row matching `base_filter` stored under
+/// each of `doc_ids`, if any. The table must have the `doc_id` column.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn rows_by_doc_id(
+    conn: &Connection,
+    table: &str,
+    doc_ids: &[String],
+    base_filter: &HashMap<String, Value>,
+) -> Result<Vec<Option<(i64, String)>>, Box<dyn Error>> {
+    use rusqlite::OptionalExtension;
+
+    let scope = build_metadata_query(base_filter, &HashMap::new(), None, 2)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT rowid, text FROM {table} WHERE doc_id = ?1 AND {} LIMIT 1",
+        scope.sql
+    ))?;
+    doc_ids
+        .iter()
+        .map(|doc_id| {
+            let params = std::iter::once(SqlValue::from(doc_id.clone())).chain(&scope.params);
+            Ok(stmt
+                .query_row(rusqlite::params_from_iter(params), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()?)
+        })
+        .collect()
+}
+
+/// Writes `doc` under `doc_id` with `INSERT OR REPLACE`, keeping the rowid of the row it
+/// replaces. `REPLACE` doesn't fire the delete triggers, so the `vec0` entry of a replaced
+/// row is deleted here before the insert trigger adds the new one.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn replace_row(
+    conn: &Connection,
+    table: &str,
+    rowid: Option<i64>,
+    doc: &Document,
+    embedding: &[f32],
+    doc_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(rowid) = rowid {
+        conn.execute(
+            &format!("DELETE FROM vec_{table} WHERE rowid = ?1"),
+            [rowid],
+        )?;
+    }
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {table} (rowid, text, metadata, text_embedding, doc_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)"
+        ),
+        rusqlite::params![
+            rowid,
+            &doc.page_content,
+            json!(&doc.metadata).to_string(),
+            encode_embedding(embedding),
+            doc_id
+        ],
+    )?;
+    Ok(())
+}
+
+/// Adds the indexed `doc_id` column, which holds the ids produced by the store's
+/// `IdGenerator`, to `table` if it does not have it yet.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn ensure_doc_id_column(conn: &Connection, table: &str) -> Result<(), Box<dyn Error>> {
+    if !has_doc_id_column(conn, table)? {
+        conn.execute(&format!("ALTER TABLE {table} ADD COLUMN doc_id TEXT"), [])?;
+    }
+    conn.execute(
+        &format!("CREATE INDEX IF NOT EXISTS {table}_doc_id ON {table} (doc_id)"),
+        [],
+    )?;
+    Ok(())
+}
+
+/// Deletes the rows matching `base_filter` stored under `doc_id`, with their `vec0` entries,
+/// so that a document can be added again under the same id.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn delete_by_doc_id(
+    conn: &Connection,
+    table: &str,
+    doc_id: &str,
+    base_filter: &HashMap<String, Value>,
+) -> Result<(), Box<dyn Error>> {
+    let scope = build_metadata_query(base_filter, &HashMap::new(), None, 2)?;
+    let params: Vec<SqlValue> = std::iter::once(SqlValue::from(doc_id.to_string()))
+        .chain(scope.params)
+        .collect();
+    conn.execute(
+        &format!(
+            "DELETE FROM vec_{table}
+            WHERE rowid IN (SELECT rowid FROM {table} WHERE doc_id = ?1 AND {})",
+            scope.sql
+        ),
+        rusqlite::params_from_iter(&params),
+    )?;
+    conn.execute(
+        &format!("DELETE FROM {table} WHERE doc_id = ?1 AND {}", scope.sql),
+        rusqlite::params_from_iter(&params),
+    )?;
+    Ok(())
+}
+
+/// The tables a store built on sqlite-vec writes to, and how the rows in them are scoped and
+/// identified. The shared search and write helpers below take one of these, so each store
+/// only supplies its own.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+#[derive(Clone)]
+pub(crate) struct VecTables {
+    /// The documents table, whose embeddings are indexed in `vec_{table}`.
+    pub(crate) table: String,
+    /// Whether the documents are also indexed in the FTS5 table `bm25_{table}`.
+    pub(crate) keyword_index: bool,
+    pub(crate) base_filter: HashMap<String, Value>,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) doc_id_column: bool,
+}
+
+/// The `k` nearest documents matching `filter` and the base filter, scored with `metric`, best
+/// first and with one result per [`content_key`].
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn nearest_documents(
+    conn: &Connection,
+    tables: &VecTables,
+    query_vector_json: String,
+    k: usize,
+    filter: &HashMap<String, Value>,
+    metric: Metric,
+) -> Result<SearchResult, Box<dyn Error>> {
+    let table = &tables.table;
+    let metadata_query = build_metadata_query(&tables.base_filter, filter, Some("e"), 4)?;
+    log::debug!(
+        "Executing query with metadata filter: {}",
+        metadata_query.sql
+    );
+
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT
+            e.text,
+            e.metadata,
+            v.distance,
+            e.rowid
+        FROM {table} e
+        INNER JOIN vec_{table} v on v.rowid = e.rowid
+        WHERE v.text_embedding match ?1 AND k = ?2 AND {}
+        ORDER BY distance
+        LIMIT ?3"#,
+        metadata_query.sql
+    ))?;
+    let params = [
+        SqlValue::from(query_vector_json),
+        SqlValue::from(k as i64),
+        SqlValue::from(k as i64),
+    ];
+    let rows = stmt.query_map(
+        rusqlite::params_from_iter(params.iter().chain(&metadata_query.params)),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    let SearchResult { docs, row_errors } = collect_rows(rows);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut docs: Vec<Document> = docs
+        .into_iter()
+        .map(|doc| {
+            let distance = doc.score;
+            doc.with_score(metric.score(distance))
+        })
+        .filter(|doc| seen.insert(content_key(doc)))
+        .collect();
+    docs.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Ok(SearchResult { docs, row_errors })
+}
+
+/// Stores the documents of `add_documents_returning_embeddings` in one transaction, each with
+/// the next of `vectors` unless it is flagged in `skip`, and under its id in `caller_ids` if
+/// given. With `reject_duplicates`, a document already stored gets the id and embedding of
+/// the stored one instead. Returns the id and embedding of each document.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn insert_documents(
+    conn: &mut Connection,
+    tables: &VecTables,
+    docs: &[Document],
+    skip: Vec<bool>,
+    vectors: Vec<Vec<f32>>,
+    caller_ids: Option<&[String]>,
+    reject_duplicates: bool,
+) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+    let VecTables {
+        table,
+        base_filter,
+        id_generator,
+        ..
+    } = tables;
+    let tx = conn.transaction()?;
+    let mut results = Vec::with_capacity(docs.len());
+
+    if caller_ids.is_some() {
+        ensure_doc_id_column(&tx, table)?;
+    }
+
+    let mut vectors = vectors.into_iter();
+    for (i, (doc, skip)) in docs.iter().zip(skip).enumerate() {
+        let vector = if skip { None } else { vectors.next() };
+        if reject_duplicates {
+            if let Some((id, doc_id)) =
+                existing_content_id(&tx, table, &doc.page_content, base_filter)?
+            {
+                let embedding = read_embedding(&tx, table, id, base_filter)?;
+                let doc_id = doc_id.unwrap_or_else(|| id_generator.generate(doc, id));
+                results.push((doc_id, embedding));
+                continue;
+            }
+        }
+        let vector = vector.ok_or("Duplicate document was deleted while adding documents")?;
+        if let Some(ids) = caller_ids {
+            delete_by_doc_id(&tx, table, &ids[i], base_filter)?;
+        }
+        let id: i64 = tx.query_row(
+            &format!(
+                r#"
+                INSERT INTO {table}
+                    (text, metadata, text_embedding)
+                VALUES
+                    (?1, ?2, ?3)
+                RETURNING rowid"#
+            ),
+            rusqlite::params![
+                &doc.page_content,
+                &json!(&doc.metadata).to_string(),
+                &encode_embedding(&vector)
+            ],
+            |row| row.get(0),
+        )?;
+
+        let doc_id = match caller_ids {
+            Some(ids) => ids[i].clone(),
+            None => id_generator.generate(doc, id),
+        };
+        if tables.doc_id_column || caller_ids.is_some() {
+            tx.execute(
+                &format!("UPDATE {table} SET doc_id = ?1 WHERE rowid = ?2"),
+                rusqlite::params![&doc_id, id],
+            )?;
+        }
+        results.push((doc_id, vector));
+    }
+
+    tx.commit()?;
+    Ok(results)
+}
+
+/// Stores the documents of `upsert_documents` under `keys` in one write transaction. A
+/// document not flagged in `reembed` keeps its stored embedding and only has its metadata
+/// updated; the others are written with the next of `vectors`. `existing` holds the rows
+/// [`rows_by_doc_id`] found before embedding. Returns `keys`.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn write_upserts(
+    conn: &mut Connection,
+    tables: &VecTables,
+    docs: &[Document],
+    keys: Vec<String>,
+    existing: &[Option<(i64, String)>],
+    reembed: &[bool],
+    vectors: Vec<Vec<f32>>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let table = &tables.table;
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    // Another writer may have stored these keys while the texts were being embedded, so the
+    // rows are looked up again under the write lock.
+    let current = rows_by_doc_id(&tx, table, &keys, &tables.base_filter)?;
+    let mut vectors = vectors.into_iter();
+    for (i, doc) in docs.iter().enumerate() {
+        let rowid = current[i].as_ref().map(|(rowid, _)| *rowid);
+        if !reembed[i] {
+            if current[i] != existing[i] {
+                return Err(format!("Document {} changed while it was upserted", keys[i]).into());
+            }
+            let metadata = json!(&doc.metadata).to_string();
+            tx.execute(
+                &format!("UPDATE {table} SET metadata = ?1 WHERE rowid = ?2"),
+                rusqlite::params![&metadata, rowid],
+            )?;
+            if tables.keyword_index {
+                tx.execute(
+                    &format!("UPDATE bm25_{table} SET metadata = ?1 WHERE rowid = ?2"),
+                    rusqlite::params![&metadata, rowid],
+                )?;
+            }
+            continue;
+        }
+        // Like the `vec0` entry, the FTS5 entry of a replaced row isn't deleted by a trigger.
+        if let (Some(rowid), true) = (rowid, tables.keyword_index) {
+            tx.execute(
+                &format!("DELETE FROM bm25_{table} WHERE rowid = ?1"),
+                [rowid],
+            )?;
+        }
+        let vector = vectors
+            .next()
+            .ok_or("Missing embedding for upserted document")?;
+        replace_row(&tx, table, rowid, doc, &vector, &keys[i])?;
+    }
+
+    tx.commit()?;
+    Ok(keys)
+}
+
+/// The caller-supplied ids of `opt`, checked to have one id per document.
+pub(crate) fn caller_ids(
+    opt: &VecStoreOptions,
+    num_docs: usize,
+) -> Result<Option<&[String]>, Box<dyn Error>> {
+    let Some(ids) = &opt.ids else {
+        return Ok(None);
+    };
+    if ids.len() != num_docs {
+        return Err(format!("Got {} ids for {} documents", ids.len(), num_docs).into());
+    }
+    if opt.reject_duplicates {
+        return Err("ids and reject_duplicates can't be used together".into());
+    }
+    Ok(Some(ids))
+}
+
+/// The per-call filters of `opt`, which must be a JSON object if set.
+pub(crate) fn filters_from_options(
+    opt: &VecStoreOptions,
+) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+    match &opt.filters {
+        Some(Value::Object(map)) => Ok(map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+        None => Ok(HashMap::new()),
+        _ => Err("Invalid filters format".into()),
+    }
+}
+
+/// Rejects documents whose serialized metadata is larger than `max_bytes`, if set.
+pub(crate) fn check_metadata_size(
+    docs: &[Document],
+    max_bytes: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(max_bytes) = max_bytes {
+        for (i, doc) in docs.iter().enumerate() {
+            let size = json!(&doc.metadata).to_string().len();
+            if size > max_bytes {
+                return Err(format!(
+                    "Metadata of document {} is {} bytes, exceeding the limit of {} bytes",
+                    i, size, max_bytes
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The search condition for `filter`, AND-ed with the store's `base_filter` so callers can
+/// narrow the results but never widen them. `table_prefix` qualifies the metadata column and
+/// `first_param` is the number of the first placeholder, see [`metadata_filter_sql`].
+pub(crate) fn build_metadata_query(
+    base_filter: &HashMap<String, Value>,
+    filter: &HashMap<String, Value>,
+    table_prefix: Option<&str>,
+    first_param: usize,
+) -> Result<FilterSql, Box<dyn Error>> {
+    let metadata_path = match table_prefix {
+        Some(prefix) if !prefix.is_empty() => format!("{}.metadata", prefix),
+        _ => "metadata".to_string(),
+    };
+    metadata_filter_sql(
+        &metadata_path,
+        base_filter.iter().chain(filter.iter()),
+        first_param,
+    )
+}
+
+/// Errors unless `table` matches `^[A-Za-z_][A-Za-z0-9_]*$`, since table names are
+/// interpolated into the DDL and queries. Every SQLite `StoreBuilder::build` checks this.
+pub(crate) fn validate_table_name(table: &str) -> Result<(), Box<dyn Error>> {
+    let mut chars = table.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("Invalid table name: {:?}", table).into());
+    }
+    Ok(())
+}
+
+/// An SQL condition together with the values bound to its placeholders.
+#[derive(Debug, Clone)]
+pub(crate) struct FilterSql {
+    pub(crate) sql: String,
+    pub(crate) params: Vec<SqlValue>,
+}
+
+/// Compiles metadata filters into an SQL condition on the JSON column `metadata_path`.
+///
+/// Filter values are never interpolated into the SQL: they are bound to numbered
+/// placeholders `?first_param`, `?first_param + 1`, ..., so that they follow the query's own
+/// parameters. Bind [`FilterSql::params`] after those.
+///
+/// The entries are parsed into a [`MetadataFilter`], whose conditions are AND-ed, so unknown
+/// operators are an error. `$ne` uses `IS NOT`, so it also matches documents without the key.
+/// `$ilike` compares as `LOWER(json_extract(...)) = LOWER(?)`. Because that wraps the column
+/// in `LOWER`, an expression index on `json_extract(metadata, '$.key')` can't serve it; index
+/// `LOWER(json_extract(metadata, '$.key'))` instead for keys filtered this way.
+///
+/// A key containing dots addresses nested metadata: `source.type` matches
+/// `{"source": {"type": ...}}`. See [`json_path`].
+///
+/// The condition is `1=1` when there are no filters.
+pub(crate) fn metadata_filter_sql<'a, I>(
+    metadata_path: &str,
+    filters: I,
+    first_param: usize,
+) -> Result<FilterSql, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (&'a String, &'a Value)>,
+{
+    let filter = MetadataFilter::from_entries(filters)?;
+
+    let mut params = Vec::new();
+    let mut placeholder = |value: SqlValue| {
+        params.push(value);
+        format!("?{}", first_param + params.len() - 1)
+    };
+
+    let mut conditions = Vec::new();
+    for Condition { key, op } in &filter.conditions {
+        let column = format!("json_extract({}, '{}')", metadata_path, json_path(key)?);
+        let condition = match op {
+            FilterOp::Eq(value) => format!("{} = {}", column, placeholder(json_to_sql(value))),
+            FilterOp::Ne(value) => {
+                format!("{} IS NOT {}", column, placeholder(json_to_sql(value)))
+            }
+            FilterOp::Gt(value) => format!("{} > {}", column, placeholder(json_to_sql(value))),
+            FilterOp::Gte(value) => format!("{} >= {}", column, placeholder(json_to_sql(value))),
+            FilterOp::Lt(value) => format!("{} < {}", column, placeholder(json_to_sql(value))),
+            FilterOp::Lte(value) => format!("{} <= {}", column, placeholder(json_to_sql(value))),
+            FilterOp::In(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|val| placeholder(json_to_sql(val)))
+                    .collect();
+                format!("{} IN ({})", column, values.join(","))
+            }
+            FilterOp::ILike(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|val| format!("LOWER({})", placeholder(SqlValue::Text(val.clone()))))
+                    .collect();
+                format!("LOWER({}) IN ({})", column, values.join(","))
+            }
+        };
+        conditions.push(condition);
+    }
+
+    let sql = if conditions.is_empty() {
+        "1=1".to_string()
+    } else {
+        conditions.join(" AND ")
+    };
+    Ok(FilterSql { sql, params })
+}
+
+/// The JSON path of a filter key: `source.type` becomes `$.source.type`. The path is part of
+/// the SQL text, since binding it would keep expression indexes from being used, so each
+/// dot-separated segment must match `[A-Za-z0-9_]+`.
+fn json_path(key: &str) -> Result<String, Box<dyn Error>> {
+    let valid = key.split('.').all(|segment| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    if !valid {
+        return Err(format!("Invalid metadata filter key: {:?}", key).into());
+    }
+    Ok(format!("$.{}", key))
+}
+
+/// The SQL value `json_extract` returns for a JSON scalar, so that the two compare equal.
+/// Objects and arrays compare as their JSON text.
+fn json_to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// Decodes `(text, metadata, score, rowid)` rows, keeping the raw score and collecting the rows
+/// that fail to read or whose metadata isn't valid JSON. The rowid goes into the metadata under
+/// [`DOCUMENT_ID_KEY`].
+pub(crate) fn collect_rows<I>(rows: I) -> SearchResult
+where
+    I: Iterator<Item = rusqlite::Result<(String, String, f64, i64)>>,
+{
+    let mut result = SearchResult::default();
+    for (index, row) in rows.enumerate() {
+        let decoded = row
+            .map_err(|e| -> Box<dyn Error + Send + Sync> { Box::new(e) })
+            .and_then(|(page_content, metadata_json, score, rowid)| {
+                let mut metadata: HashMap<String, Value> = serde_json::from_str(&metadata_json)?;
+                metadata.insert(DOCUMENT_ID_KEY.to_string(), json!(rowid));
+                Ok(Document {
+                    page_content,
+                    metadata,
+                    score,
+                })
+            });
+        match decoded {
+            Ok(doc) => result.docs.push(doc),
+            Err(error) => result.row_errors.push(RowError { index, error }),
+        }
+    }
+    result
+}
+
+/// Drops the documents scoring below `opt.score_threshold`, if set. Scores must already be
+/// normalized, so that the threshold means the same across stores.
+pub(crate) fn apply_score_threshold(docs: &mut Vec<Document>, opt: &VecStoreOptions) {
+    if let Some(score_threshold) = opt.score_threshold {
+        docs.retain(|doc| doc.score >= score_threshold as f64);
+    }
+}
+
+/// Identifies a search result by its content and metadata, ignoring [`DOCUMENT_ID_KEY`], to
+/// merge results that repeat the same document.
+pub(crate) fn content_key(doc: &Document) -> String {
+    let metadata: HashMap<&String, &Value> = doc
+        .metadata
+        .iter()
+        .filter(|(key, _)| *key != DOCUMENT_ID_KEY)
+        .collect();
+    format!("{}{}", doc.page_content, json!(metadata))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Checks that a pragma key is an identifier and its value an identifier or integer, so
+/// they can be safely interpolated into a `PRAGMA` statement.
+pub(crate) fn validate_pragmas(pragmas: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    for (key, value) in pragmas {
+        if !is_identifier(key) {
+            return Err(format!("Invalid pragma name: {:?}", key).into());
+        }
+        if !is_identifier(value) && !is_integer(value) {
+            return Err(format!("Invalid value for pragma {}: {:?}", key, value).into());
+        }
+    }
+    Ok(())
+}
+
+/// Applies the pragmas in order. Some pragmas (e.g. `journal_mode`) return a row, which is
+/// read and discarded.
+pub(crate) fn apply_pragmas(
+    conn: &Connection,
+    pragmas: &[(String, String)],
+) -> Result<(), Box<dyn Error>> {
+    validate_pragmas(pragmas)?;
+    Ok(execute_pragmas(conn, pragmas)?)
+}
+
+/// Runs already validated `pragmas`, e.g. from a pool's connection initializer, which must
+/// return a `rusqlite::Error`.
+pub(crate) fn execute_pragmas(
+    conn: &Connection,
+    pragmas: &[(String, String)],
+) -> rusqlite::Result<()> {
+    for (key, value) in pragmas {
+        let mut stmt = conn.prepare(&format!("PRAGMA {} = {}", key, value))?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
+    }
+    Ok(())
+}
+
+/// Whether opening or first reading the database failed for a reason that may go away, e.g. a
+/// network mount that is not available yet. Corrupt or non-database files are not transient.
+fn is_transient_open_error(error: &rusqlite::Error) -> bool {
+    use rusqlite::ErrorCode;
+
+    matches!(
+        error.sqlite_error_code(),
+        Some(
+            ErrorCode::CannotOpen
+                | ErrorCode::SystemIoFailure
+                | ErrorCode::DatabaseBusy
+                | ErrorCode::DatabaseLocked
+        )
+    )
+}
+
+/// Opens the database at `path` and reads its schema, so that an unreadable file fails here
+/// rather than on first use. Transient failures are retried up to `attempts` times in total,
+/// waiting `delay` before the first retry and doubling it after each.
+pub(crate) async fn open_connection(
+    path: &str,
+    attempts: u32,
+    mut delay: Duration,
+) -> Result<Connection, Box<dyn Error>> {
+    let open = || -> rusqlite::Result<Connection> {
+        let conn = Connection::open(path)?;
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        Ok(conn)
+    };
+
+    let mut attempt = 1;
+    loop {
+        match open() {
+            Ok(conn) => return Ok(conn),
+            Err(e) if attempt < attempts && is_transient_open_error(&e) => {
+                log::warn!(
+                    "Failed to open SQLite database {} (attempt {}/{}), retrying in {:?}: {}",
+                    path,
+                    attempt,
+                    attempts,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(format!("Failed to open SQLite connection: {}", e).into()),
+        }
+    }
+}
+
+/// Checks that the embeddings of up to `sample_size` randomly chosen rows of `table` have
+/// `dimensions` elements, or, when `dimensions` is 0, all the same number. Errors listing the
+/// rowids of the mismatched or undecodable embeddings.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn verify_embedding_dimensions(
+    conn: &Connection,
+    table: &str,
+    dimensions: i32,
+    sample_size: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT rowid, text_embedding FROM {table} ORDER BY random() LIMIT ?1"
+    ))?;
+    let mut rows = stmt.query([sample_size as i64])?;
+
+    let mut expected = (dimensions > 0).then_some(dimensions as usize);
+    let mut problems = Vec::new();
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get(0)?;
+        match decode_embedding(row.get_ref(1)?) {
+            Ok(embedding) => match expected {
+                Some(expected) if embedding.len() != expected => problems.push(format!(
+                    "rowid {} has {} dimensions",
+                    rowid,
+                    embedding.len()
+                )),
+                Some(_) => {}
+                None => expected = Some(embedding.len()),
+            },
+            Err(e) => problems.push(format!("rowid {} can't be decoded: {}", rowid, e)),
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Expected {} dimensions in {}: {}",
+        expected.unwrap_or_default(),
+        table,
+        problems.join(", ")
+    )
+    .into())
+}
+
+/// Encodes an embedding as the little-endian `float32` blob `vec0` stores natively.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+/// Decodes a stored `text_embedding`, written either as a little-endian `float32` blob (as the
+/// stores insert it) or as JSON text (as rows written by older versions hold it).
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn decode_embedding(value: ValueRef<'_>) -> Result<Vec<f32>, Box<dyn Error>> {
+    match value {
+        ValueRef::Text(text) => Ok(serde_json::from_slice(text)?),
+        ValueRef::Blob(blob) if blob.len() % 4 == 0 => Ok(blob
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()),
+        _ => Err("Stored embedding is neither JSON text nor a float32 blob".into()),
+    }
+}
+
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> Result<f64, Box<dyn Error>> {
+    if a.len() != b.len() {
+        return Err(format!(
+            "Embedding dimensions do not match: {} vs {}",
+            a.len(),
+            b.len()
+        )
+        .into());
+    }
+    let dot: f64 = a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum();
+    let norm_a = a.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(dot / (norm_a * norm_b))
+}
+
+/// Reads the stored embedding of the document `id` from `table`. Errors if it doesn't exist
+/// or doesn't match `base_filter`.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn read_embedding(
+    conn: &Connection,
+    table: &str,
+    id: i64,
+    base_filter: &HashMap<String, Value>,
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    let scope = build_metadata_query(base_filter, &HashMap::new(), None, 2)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT text_embedding FROM {table} WHERE rowid = ?1 AND {}",
+        scope.sql
+    ))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(
+        std::iter::once(SqlValue::from(id)).chain(scope.params),
+    ))?;
+    match rows.next()? {
+        Some(row) => decode_embedding(row.get_ref(0)?),
+        None => Err(format!("Document {} not found", id).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_pragmas() {
+        let ok = |k: &str, v: &str| validate_pragmas(&[(k.into(), v.into())]).is_ok();
+        assert!(ok("cache_size", "-20000"));
+        assert!(ok("synchronous", "NORMAL"));
+        assert!(ok("mmap_size", "268435456"));
+        assert!(!ok("cache_size; DROP TABLE documents", "1"));
+        assert!(!ok("synchronous", "OFF; DROP TABLE documents"));
+        assert!(!ok("temp_store", "'memory'"));
+        assert!(!ok("", "1"));
+    }
+
+    #[tokio::test]
+    async fn test_open_connection_does_not_retry_non_database() {
+        let path =
+            std::env::temp_dir().join(format!("langchain_not_a_db_{}.sqlite", std::process::id()));
+        std::fs::write(&path, b"this is not an SQLite database, just some text").unwrap();
+
+        let start = std::time::Instant::now();
+        let result = open_connection(path.to_str().unwrap(), 5, Duration::from_secs(1)).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_validate_table_name() {
+        assert!(validate_table_name("documents").is_ok());
+        assert!(validate_table_name("_docs_2").is_ok());
+        assert!(validate_table_name("").is_err());
+        assert!(validate_table_name("2docs").is_err());
+        assert!(validate_table_name("docs; DROP TABLE x").is_err());
+        assert!(validate_table_name("evil; DROP TABLE documents; --").is_err());
+        assert!(validate_table_name("docs--").is_err());
+        assert!(validate_table_name("docs\"").is_err());
+        assert!(validate_table_name("main.docs").is_err());
+        assert!(validate_table_name("docs\0").is_err());
+        assert!(validate_table_name("dócs").is_err());
+        assert!(validate_table_name(" docs").is_err());
+    }
+
+    #[test]
+    fn test_check_metadata_size() {
+        // The metadata of the second document serializes to `{"k":"v"}`, 9 bytes.
+        let docs = [
+            Document::new("a"),
+            Document::new("b").with_metadata(HashMap::from([("k".to_string(), json!("v"))])),
+        ];
+        assert!(check_metadata_size(&docs, None).is_ok());
+        assert!(check_metadata_size(&docs, Some(9)).is_ok());
+
+        let err = check_metadata_size(&docs, Some(8)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Metadata of document 1 is 9 bytes, exceeding the limit of 8 bytes"
+        );
+    }
+
+    #[test]
+    fn test_collect_rows_keeps_good_rows() {
+        let rows = vec![
+            Ok(("a".to_string(), r#"{"page":1}"#.to_string(), 0.5, 1)),
+            Ok(("b".to_string(), "not json".to_string(), 0.4, 2)),
+            Err(rusqlite::Error::InvalidQuery),
+            Ok(("c".to_string(), "{}".to_string(), 0.3, 4)),
+        ];
+
+        let result = collect_rows(rows.into_iter());
+
+        let contents: Vec<&str> = result
+            .docs
+            .iter()
+            .map(|d| d.page_content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["a", "c"]);
+        assert_eq!(result.docs[1].metadata[DOCUMENT_ID_KEY], json!(4));
+        let failed: Vec<usize> = result.row_errors.iter().map(|e| e.index).collect();
+        assert_eq!(failed, vec![1, 2]);
+        assert_eq!(result.status(), SearchStatus::RowsSkipped(2));
+        assert!(result.into_complete_docs().is_err());
+
+        let empty = collect_rows(std::iter::empty());
+        assert_eq!(empty.status(), SearchStatus::NoMatches);
+        assert!(empty.into_complete_docs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ilike_filter_matches_mixed_case() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE docs (metadata TEXT)", [])
+            .unwrap();
+        for format in ["PDF", "pdf", "Pdf", "docx", "O'Reilly"] {
+            conn.execute(
+                "INSERT INTO docs (metadata) VALUES (?1)",
+                [json!({ "format": format }).to_string()],
+            )
+            .unwrap();
+        }
+        let count = |filter: Value| -> i64 {
+            let filters: HashMap<String, Value> = serde_json::from_value(filter).unwrap();
+            let condition = metadata_filter_sql("metadata", &filters, 1).unwrap();
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM docs WHERE {}", condition.sql),
+                rusqlite::params_from_iter(&condition.params),
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(count(json!({ "format": "pdf" })), 1);
+        assert_eq!(count(json!({ "format": { "$ilike": "pdf" } })), 3);
+        assert_eq!(count(json!({ "format": { "$ilike": "PDF" } })), 3);
+        assert_eq!(count(json!({ "format": { "$ilike": ["pdf", "DOCX"] } })), 4);
+        assert_eq!(count(json!({ "format": { "$ilike": "o'reilly" } })), 1);
+        assert_eq!(count(json!({ "format": "x' OR 1=1 --" })), 0);
+    }
+
+    #[test]
+    fn test_dotted_keys_filter_nested_metadata() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE docs (metadata TEXT)", [])
+            .unwrap();
+        for metadata in [
+            json!({ "source": { "type": "pdf", "lang": "en" } }),
+            json!({ "source": { "type": "pdf", "lang": "fr" } }),
+            json!({ "source": { "type": "html", "lang": "en" } }),
+            json!({ "source.type": "pdf", "flags": { "is_draft": true } }),
+        ] {
+            conn.execute(
+                "INSERT INTO docs (metadata) VALUES (?1)",
+                [metadata.to_string()],
+            )
+            .unwrap();
+        }
+        let count = |filter: Value| -> i64 {
+            let filters: HashMap<String, Value> = serde_json::from_value(filter).unwrap();
+            let condition = metadata_filter_sql("metadata", &filters, 1).unwrap();
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM docs WHERE {}", condition.sql),
+                rusqlite::params_from_iter(&condition.params),
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(count(json!({ "source.type": "pdf" })), 2);
+        assert_eq!(
+            count(json!({ "source.type": "pdf", "source.lang": "en" })),
+            1
+        );
+        assert_eq!(count(json!({ "source.lang": ["en", "fr"] })), 3);
+        assert_eq!(count(json!({ "source.type": { "$ilike": "HTML" } })), 1);
+        assert_eq!(count(json!({ "flags.is_draft": true })), 1);
+
+        for key in ["it's", "a b", "source.", "a'); DROP TABLE docs;--"] {
+            let filters = HashMap::from([(key.to_string(), json!("x"))]);
+            assert!(metadata_filter_sql("metadata", &filters, 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_documents_by_ids() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE docs (text TEXT, metadata TEXT)", [])
+            .unwrap();
+        for text in ["a", "b", "c"] {
+            conn.execute(
+                "INSERT INTO docs (text, metadata) VALUES (?1, '{}')",
+                [text],
+            )
+            .unwrap();
+        }
+        let ids = ["3", "1", "9", "x"].map(String::from);
+        let docs = documents_by_ids(&conn, "docs", &ids, &HashMap::new()).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs["3"].page_content, "c");
+        assert_eq!(docs["1"].page_content, "a");
+
+        conn.execute("ALTER TABLE docs ADD COLUMN doc_id TEXT", [])
+            .unwrap();
+        conn.execute("UPDATE docs SET doc_id = 'doc-b' WHERE rowid = 2", [])
+            .unwrap();
+        let ids = ["doc-b", "2", "3"].map(String::from);
+        let docs = documents_by_ids(&conn, "docs", &ids, &HashMap::new()).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs["doc-b"].page_content, "b");
+        assert_eq!(docs["3"].page_content, "c");
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE docs (metadata TEXT)", [])
+            .unwrap();
+        for metadata in [
+            json!({ "year": 2021, "lang": "rust" }),
+            json!({ "year": 2023, "lang": "go" }),
+            json!({ "year": 2024, "lang": "rust" }),
+            json!({ "year": 2025.5 }),
+        ] {
+            conn.execute(
+                "INSERT INTO docs (metadata) VALUES (?1)",
+                [metadata.to_string()],
+            )
+            .unwrap();
+        }
+        let count = |filter: Value| -> i64 {
+            let filters: HashMap<String, Value> = serde_json::from_value(filter).unwrap();
+            let condition = metadata_filter_sql("metadata", &filters, 1).unwrap();
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM docs WHERE {}", condition.sql),
+                rusqlite::params_from_iter(&condition.params),
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(count(json!({ "year": { "$gte": 2023 } })), 3);
+        assert_eq!(count(json!({ "year": { "$gt": 2023, "$lt": 2025 } })), 1);
+        assert_eq!(count(json!({ "year": { "$lte": 2023 } })), 2);
+        assert_eq!(count(json!({ "lang": { "$ne": "go" } })), 3);
+        assert_eq!(
+            count(json!({ "year": { "$gte": 2023 }, "lang": { "$ne": "go" } })),
+            2
+        );
+        assert_eq!(count(json!({ "lang": { "$in": ["go", "rust"] } })), 3);
+        assert_eq!(count(json!({ "lang": { "$eq": "go" } })), 1);
+
+        let filters = HashMap::from([("year".to_string(), json!({ "$gte": 2023, "$foo": 1 }))]);
+        assert!(metadata_filter_sql("metadata", &filters, 1).is_err());
+    }
+
+    #[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+    #[test]
+    fn test_search_within_ids_ranks_only_candidates() {
+        register_sqlite_vec();
+        let conn = Connection::open_in_memory().unwrap();
+        create_vec_tables(&conn, "docs", 2, Metric::L2).unwrap();
+        for (text, embedding) in [("a", [1.0, 0.0]), ("b", [0.9, 0.1]), ("c", [0.0, 1.0])] {
+            conn.execute(
+                "INSERT INTO docs (text, metadata, text_embedding) VALUES (?1, '{}', ?2)",
+                rusqlite::params![text, encode_embedding(&embedding)],
+            )
+            .unwrap();
+        }
+
+        let no_filter =
+            build_metadata_query(&HashMap::new(), &HashMap::new(), Some("e"), 3).unwrap();
+        let result = search_within_ids(
+            &conn,
+            "docs",
+            "[1.0, 0.0]",
+            &[2, 3],
+            10,
+            &no_filter,
+            Metric::L2,
+        )
+        .unwrap();
+
+        let contents: Vec<&str> = result
+            .docs
+            .iter()
+            .map(|d| d.page_content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["b", "c"]);
+        assert!(result.docs[0].score > result.docs[1].score);
+    }
+
+    #[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+    #[test]
+    fn test_replace_row_keeps_rowid() {
+        register_sqlite_vec();
+        let conn = Connection::open_in_memory().unwrap();
+        create_vec_tables(&conn, "docs", 2, Metric::L2).unwrap();
+        ensure_doc_id_column(&conn, "docs").unwrap();
+        let keys = vec!["a.md".to_string(), "b.md".to_string()];
+
+        replace_row(
+            &conn,
+            "docs",
+            None,
+            &Document::new("old"),
+            &[1.0, 0.0],
+            &keys[0],
+        )
+        .unwrap();
+        let rows = rows_by_doc_id(&conn, "docs", &keys, &HashMap::new()).unwrap();
+        assert_eq!(rows, vec![Some((1, "old".to_string())), None]);
+
+        replace_row(
+            &conn,
+            "docs",
+            Some(1),
+            &Document::new("new"),
+            &[0.0, 1.0],
+            &keys[0],
+        )
+        .unwrap();
+        let rows = rows_by_doc_id(&conn, "docs", &keys, &HashMap::new()).unwrap();
+        assert_eq!(rows, vec![Some((1, "new".to_string())), None]);
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM vec_docs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            read_embedding(&conn, "docs", 1, &HashMap::new()).unwrap(),
+            vec![0.0, 1.0]
+        );
+    }
+
+    #[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+    #[test]
+    fn test_verify_embedding_dimensions_reports_rowids() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE docs (text_embedding BLOB)", [])
+            .unwrap();
+        let insert = |embedding: &[f32]| {
+            conn.execute(
+                "INSERT INTO docs (text_embedding) VALUES (?1)",
+                [encode_embedding(embedding)],
+            )
+            .unwrap();
+        };
+        insert(&[1.0, 0.0]);
+        insert(&[0.0, 1.0]);
+        assert!(verify_embedding_dimensions(&conn, "docs", 2, 10).is_ok());
+        assert!(verify_embedding_dimensions(&conn, "docs", 0, 10).is_ok());
+
+        insert(&[1.0, 0.0, 0.0]);
+        let error = verify_embedding_dimensions(&conn, "docs", 2, 10)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("rowid 3 has 3 dimensions"), "{}", error);
+        assert!(verify_embedding_dimensions(&conn, "docs", 0, 10).is_err());
+    }
+
+    #[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+    #[test]
+    fn test_decode_embedding() {
+        let blob = encode_embedding(&[1.0, -0.5]);
+        assert_eq!(
+            decode_embedding(ValueRef::Blob(&blob)).unwrap(),
+            vec![1.0, -0.5]
+        );
+        assert_eq!(
+            decode_embedding(ValueRef::Text(b"[1.0,-0.5]")).unwrap(),
+            vec![1.0, -0.5]
+        );
+        assert!(decode_embedding(ValueRef::Integer(1)).is_err());
+
+        let sim = cosine_similarity(&[1.0, 0.0], &[1.0, 1.0]).unwrap();
+        assert!((sim - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+        assert!(cosine_similarity(&[1.0], &[1.0, 0.0]).is_err());
+    }
+
+    #[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+    #[test]
+    fn test_vec_table_metric_is_validated() {
+        use crate::vectorstore::DistanceMetric;
+
+        register_sqlite_vec();
+        let conn = Connection::open_in_memory().unwrap();
+        create_vec_tables(&conn, "docs", 2, Metric::Cosine).unwrap();
+        assert_eq!(
+            vec_table_metric(&conn, "docs").unwrap(),
+            Some(Metric::Cosine)
+        );
+        create_vec_tables(&conn, "docs", 2, Metric::Cosine).unwrap();
+        assert!(create_vec_tables(&conn, "docs", 2, Metric::L2).is_err());
+
+        // Tables created before the metric was configurable use vec0's default.
+        conn.execute_batch("CREATE VIRTUAL TABLE vec_old USING vec0(text_embedding float[2]);")
+            .unwrap();
+        assert_eq!(vec_table_metric(&conn, "old").unwrap(), Some(Metric::L2));
+        assert_eq!(vec_table_metric(&conn, "missing").unwrap(), None);
+
+        assert_eq!(Metric::Cosine.score(0.0), 1.0);
+        assert_eq!(Metric::Cosine.score(1.5), 0.0);
+        assert_eq!(Metric::L2.score(1.0), 0.5);
+
+        let opt = |metric| VecStoreOptions::new().with_distance_metric(metric);
+        assert!(Metric::Cosine
+            .check_options(&VecStoreOptions::new())
+            .is_ok());
+        assert!(Metric::Cosine
+            .check_options(&opt(DistanceMetric::Cosine))
+            .is_ok());
+        assert!(Metric::L2
+            .check_options(&opt(DistanceMetric::Cosine))
+            .is_err());
+        assert!(Metric::L2
+            .check_options(&opt(DistanceMetric::InnerProduct))
+            .is_err());
+    }
+}
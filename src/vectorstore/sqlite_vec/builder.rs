@@ -1,21 +1,44 @@
-use std::{
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
+
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Result;
+use serde_json::Value;
 
-use rusqlite::{ffi::sqlite3_auto_extension, Connection, Result};
-use sqlite_vec::sqlite3_vec_init;
+use super::{SqlitePool, Store};
+use crate::{
+    embedding::embedder_trait::Embedder,
+    vectorstore::{
+        probe_vector_dimensions,
+        sqlite_utils::{
+            check_sqlite_vec, execute_pragmas, open_connection, register_sqlite_vec,
+            validate_pragmas, validate_table_name, Metric,
+        },
+        IdGenerator, RowIdGenerator,
+    },
+};
 
-use super::Store;
-use crate::embedding::embedder_trait::Embedder;
+const DEFAULT_FILTER_OVERFETCH: usize = 4;
+const DEFAULT_OPEN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
 
 pub struct StoreBuilder {
-    pool: Option<Arc<Mutex<rusqlite::Connection>>>,
+    pool: Option<SqlitePool>,
     connection_url: Option<String>,
+    max_connections: u32,
     table: String,
     vector_dimensions: i32,
+    probe_dimensions: bool,
     batch_size: i32,
     embedder: Option<Arc<dyn Embedder>>,
+    max_metadata_bytes: Option<usize>,
+    base_filter: Option<Value>,
+    pragmas: Vec<(String, String)>,
+    filter_overfetch: usize,
+    open_attempts: u32,
+    open_retry_delay: Duration,
+    id_generator: Arc<dyn IdGenerator>,
+    doc_id_column: bool,
+    metric: Metric,
 }
 
 impl StoreBuilder {
@@ -23,14 +46,27 @@ impl StoreBuilder {
         StoreBuilder {
             pool: None,
             connection_url: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
             table: "documents".to_string(),
             vector_dimensions: 0,
+            probe_dimensions: false,
             batch_size: 2048,
             embedder: None,
+            max_metadata_bytes: None,
+            base_filter: None,
+            pragmas: Vec::new(),
+            filter_overfetch: DEFAULT_FILTER_OVERFETCH,
+            open_attempts: 1,
+            open_retry_delay: DEFAULT_OPEN_RETRY_DELAY,
+            id_generator: Arc::new(RowIdGenerator),
+            doc_id_column: false,
+            metric: Metric::default(),
         }
     }
 
-    pub fn pool(mut self, pool: Arc<Mutex<rusqlite::Connection>>) -> Self {
+    /// Uses an existing connection pool, whose connections must have the sqlite-vec
+    /// extension loaded.
+    pub fn pool(mut self, pool: SqlitePool) -> Self {
         self.pool = Some(pool);
         self.connection_url = None;
         self
@@ -42,6 +78,14 @@ impl StoreBuilder {
         self
     }
 
+    /// Maximum number of connections the pool opened by the builder keeps, so that concurrent
+    /// searches don't wait for each other. Defaults to 10. An in-memory database (`:memory:`)
+    /// always uses a single connection, since each connection would get its own database.
+    pub fn max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
     pub fn table(mut self, table: &str) -> Self {
         self.table = table.into();
         self
@@ -62,10 +106,94 @@ impl StoreBuilder {
         self
     }
 
-    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+    /// Rejects documents whose serialized metadata is larger than `max_metadata_bytes`.
+    /// Unlimited by default.
+    pub fn max_metadata_bytes(mut self, max_metadata_bytes: usize) -> Self {
+        self.max_metadata_bytes = Some(max_metadata_bytes);
+        self
+    }
+
+    /// Filter that is always AND-ed with the per-call `VecStoreOptions::filters` on searches,
+    /// e.g. a mandatory tenant filter. Lookups, deletes and upserts by id or metadata also
+    /// only see the documents matching it. Must be a JSON object of metadata key/values.
+    pub fn base_filter(mut self, base_filter: Value) -> Self {
+        self.base_filter = Some(base_filter);
+        self
+    }
+
+    /// Adds a `PRAGMA key = value` to run on the connection opened by the builder, e.g.
+    /// `.pragma("cache_size", "-20000")`. Keys must be identifiers and values identifiers or
+    /// integers. Pragmas are applied in the order they were added, right after the connection
+    /// is opened (and so after the sqlite-vec extension is registered). They are not applied
+    /// to a connection passed in through `pool`.
+    pub fn pragma(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.pragmas.push((key.into(), value.into()));
+        self
+    }
+
+    /// When a search has metadata filters, `limit * filter_overfetch` nearest neighbours are
+    /// fetched before filtering, so that filtered results still fill `limit`. Defaults to 4.
+    pub fn filter_overfetch(mut self, filter_overfetch: usize) -> Self {
+        self.filter_overfetch = filter_overfetch.max(1);
+        self
+    }
+
+    /// Sets how the ids returned by `add_documents` are made from the row id of each inserted
+    /// document. Defaults to [`RowIdGenerator`], the row id itself.
+    pub fn id_generator<G: IdGenerator + 'static>(mut self, id_generator: G) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Also stores the generated ids in an indexed `doc_id` column, added to existing tables on
+    /// `initialize`. Off by default.
+    pub fn doc_id_column(mut self, doc_id_column: bool) -> Self {
+        self.doc_id_column = doc_id_column;
+        self
+    }
+
+    /// Distance metric of the vec0 index, which also decides how distances are turned into
+    /// scores. Defaults to [`Metric::L2`]. The metric is fixed when the table is created, and
+    /// `initialize` errors if an existing table uses another one.
+    pub fn distance_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Retries opening the database file up to `attempts` times in total when it fails for a
+    /// transient reason, e.g. a network mount that becomes available shortly after startup.
+    /// Waits `delay` before the first retry and doubles it after each. Errors such as the file
+    /// not being a database are not retried. Defaults to a single attempt.
+    pub fn open_retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.open_attempts = attempts.max(1);
+        self.open_retry_delay = delay;
+        self
+    }
+
+    /// Embeds a sentinel text once during `build` to learn the embedder's output dimension.
+    /// Sets `vector_dimensions` when unset, otherwise checks that it matches. Off by default
+    /// since it calls the embedder.
+    pub fn probe_dimensions(mut self, probe_dimensions: bool) -> Self {
+        self.probe_dimensions = probe_dimensions;
+        self
+    }
+
+    pub async fn build(mut self) -> Result<Store, Box<dyn Error>> {
+        validate_table_name(&self.table)?;
         if self.embedder.is_none() {
             return Err("Embedder is required".into());
         }
+        if self.probe_dimensions {
+            self.vector_dimensions =
+                probe_vector_dimensions(self.embedder.as_deref().unwrap(), self.vector_dimensions)
+                    .await?;
+        }
+
+        let base_filter = match &self.base_filter {
+            Some(Value::Object(map)) => map.clone().into_iter().collect(),
+            Some(_) => return Err("Base filter must be a JSON object".into()),
+            None => HashMap::new(),
+        };
 
         Ok(Store {
             pool: self.get_pool().await?,
@@ -73,27 +201,57 @@ impl StoreBuilder {
             vector_dimensions: self.vector_dimensions,
             embedder: self.embedder.unwrap(),
             batch_size: self.batch_size,
+            max_metadata_bytes: self.max_metadata_bytes,
+            base_filter,
+            filter_overfetch: self.filter_overfetch,
+            id_generator: self.id_generator,
+            doc_id_column: self.doc_id_column,
+            metric: self.metric,
         })
     }
 
-    async fn get_pool(&self) -> Result<Arc<Mutex<rusqlite::Connection>>, Box<dyn Error>> {
+    /// Builds the store, then drops its tables and recreates them empty. Unlike `build`, which
+    /// keeps existing data, and `delete_all_documents`, which keeps the schema, this gives a
+    /// fresh store, e.g. for test fixtures.
+    pub async fn recreate(self) -> Result<Store, Box<dyn Error>> {
+        let store = self.build().await?;
+        store.drop_tables().await?;
+        store.initialize().await?;
+        Ok(store)
+    }
+
+    async fn get_pool(&self) -> Result<SqlitePool, Box<dyn Error>> {
         if let Some(pool) = &self.pool {
+            check_sqlite_vec(&pool.get()?)?;
             return Ok(pool.clone());
         }
 
-        unsafe {
-            sqlite3_auto_extension(Some(std::mem::transmute(sqlite3_vec_init as *const ())));
-        }
+        register_sqlite_vec();
 
         let connection_url = self
             .connection_url
             .as_ref()
             .ok_or_else(|| "Connection URL or DB is required")?;
+        validate_pragmas(&self.pragmas)?;
 
-        let pool: rusqlite::Connection = Connection::open(connection_url)
-            .map_err(|e| format!("Failed to open SQLite connection: {}", e))?;
+        let pragmas = self.pragmas.clone();
+        let init = move |conn: &mut rusqlite::Connection| execute_pragmas(conn, &pragmas);
 
-        let pool = Arc::new(Mutex::new(pool));
+        let pool = if connection_url == ":memory:" {
+            // The database lives as long as its only connection, so never recycle it.
+            r2d2::Pool::builder()
+                .max_size(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .build(SqliteConnectionManager::memory().with_init(init))?
+        } else {
+            // Wait for the database to be openable before the pool opens its connections.
+            open_connection(connection_url, self.open_attempts, self.open_retry_delay).await?;
+            r2d2::Pool::builder()
+                .max_size(self.max_connections)
+                .build(SqliteConnectionManager::file(connection_url).with_init(init))?
+        };
+        check_sqlite_vec(&pool.get()?)?;
 
         Ok(pool)
     }
@@ -3,3 +3,7 @@ mod sqlite_vec;
 
 pub use builder::*;
 pub use sqlite_vec::*;
+
+pub use crate::vectorstore::sqlite_utils::{
+    Metric, RowError, SearchResult, SearchStatus, DOCUMENT_ID_KEY,
+};
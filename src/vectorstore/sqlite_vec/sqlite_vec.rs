@@ -1,25 +1,46 @@
-use std::{
-    collections::HashMap,
-    error::Error,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, error::Error, sync::Arc};
 
+use async_stream::stream;
 use async_trait::async_trait;
-use rusqlite::{params, params_from_iter};
+use rusqlite::{params_from_iter, types::Value as SqlValue};
 use serde_json::{json, Value};
 
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        maximal_marginal_relevance, order_by_ids,
+        sqlite_utils::{
+            apply_score_threshold, build_metadata_query, caller_ids, check_metadata_size,
+            cosine_similarity, create_vec_tables, decode_embedding, documents_by_ids,
+            duplicate_mask, ensure_doc_id_column, filters_from_options, insert_documents,
+            nearest_documents, read_embedding, rows_by_doc_id, search_within_ids,
+            verify_embedding_dimensions, write_upserts, FilterSql, Metric, SearchResult, VecTables,
+            DOCUMENT_ID_KEY, VEC0_MAX_K,
+        },
+        upsert_keys, DocumentStream, IdGenerator, VecStoreOptions, VectorStore,
+    },
 };
 
+/// Number of nearest neighbours fetched by the first page of a threshold stream; each
+/// following page doubles it, up to [`VEC0_MAX_K`].
+const THRESHOLD_STREAM_PAGE_SIZE: usize = 32;
+
+/// Pool of connections to the store's database.
+pub type SqlitePool = r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>;
+
 pub struct Store {
-    pub pool: Arc<Mutex<rusqlite::Connection>>,
+    pub pool: SqlitePool,
     pub(crate) table: String,
     pub(crate) vector_dimensions: i32,
     pub(crate) embedder: Arc<dyn Embedder>,
     pub(crate) batch_size: i32,
+    pub(crate) max_metadata_bytes: Option<usize>,
+    pub(crate) base_filter: HashMap<String, Value>,
+    pub(crate) filter_overfetch: usize,
+    pub(crate) id_generator: Arc<dyn IdGenerator>,
+    pub(crate) doc_id_column: bool,
+    pub(crate) metric: Metric,
 }
 
 impl Store {
@@ -28,61 +49,332 @@ impl Store {
         Ok(())
     }
 
+    /// Runs `f` with a pooled connection on tokio's blocking thread pool, since waiting for a
+    /// connection and running the queries would otherwise block an async worker thread.
+    async fn with_connection<T, F>(&self, f: F) -> Result<T, Box<dyn Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut rusqlite::Connection) -> Result<T, Box<dyn Error>> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut db = pool.get().map_err(|e| e.to_string())?;
+            f(&mut db).map_err(|e| e.to_string())
+        })
+        .await?;
+        Ok(result?)
+    }
+
+    /// The tables of this store, for the write and search helpers shared with `sqlite_hybrid`.
+    fn tables(&self) -> VecTables {
+        VecTables {
+            table: self.table.clone(),
+            keyword_index: false,
+            base_filter: self.base_filter.clone(),
+            id_generator: self.id_generator.clone(),
+            doc_id_column: self.doc_id_column,
+        }
+    }
+
     async fn create_table_if_not_exists(&self) -> Result<(), Box<dyn Error>> {
-        let table = &self.table;
-        let db = &self.pool.lock().unwrap();
-
-        db.execute(
-            &format!(
-                r#"
-                CREATE TABLE IF NOT EXISTS {table}
-                (
-                  rowid INTEGER PRIMARY KEY AUTOINCREMENT,
-                  text TEXT,
-                  metadata BLOB,
-                  text_embedding BLOB
-                )
-                ;"#
-            ),
-            (),
-        )?;
-
-        let dimensions = self.vector_dimensions;
-        db.execute(
-            &format!(
-                r#"
-                CREATE VIRTUAL TABLE IF NOT EXISTS vec_{table} USING vec0(
-                  text_embedding float[{dimensions}]
-                );"#
-            ),
-            (),
-        )?;
-
-        db.execute(
-            &format!(
-                r#"
-                CREATE TRIGGER IF NOT EXISTS embed_text_{table}
-                AFTER INSERT ON {table}
-                BEGIN
-                    INSERT INTO vec_{table}(rowid, text_embedding)
-                    VALUES (new.rowid, new.text_embedding);
-                END;"#
-            ),
-            (),
-        )?;
+        let table = self.table.clone();
+        let (vector_dimensions, metric) = (self.vector_dimensions, self.metric);
+        let doc_id_column = self.doc_id_column;
+        self.with_connection(move |db| {
+            create_vec_tables(db, &table, vector_dimensions, metric)?;
+            if doc_id_column {
+                ensure_doc_id_column(db, &table)?;
+            }
+            Ok(())
+        })
+        .await
+    }
 
-        Ok(())
+    /// Drops the store's tables; their triggers go with them.
+    pub(crate) async fn drop_tables(&self) -> Result<(), Box<dyn Error>> {
+        let table = self.table.clone();
+        self.with_connection(move |db| {
+            db.execute_batch(&format!(
+                "DROP TABLE IF EXISTS {table}; DROP TABLE IF EXISTS vec_{table};"
+            ))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns the filtered documents among the `k` nearest neighbours, closest first.
+    fn fetch_nearest(
+        pool: &SqlitePool,
+        table: &str,
+        metadata_query: &FilterSql,
+        query_vector_json: &str,
+        k: usize,
+        metric: Metric,
+    ) -> Result<Vec<Document>, Box<dyn Error + Send + Sync>> {
+        let db = pool.get()?;
+        let mut stmt = db.prepare(&format!(
+            r#"SELECT
+                e.text,
+                e.metadata,
+                v.distance,
+                e.rowid
+            FROM {table} e
+            INNER JOIN vec_{table} v on v.rowid = e.rowid
+            WHERE v.text_embedding match ?1 AND k = ?2 AND {}
+            ORDER BY distance"#,
+            metadata_query.sql
+        ))?;
+
+        let params = [
+            SqlValue::from(query_vector_json.to_string()),
+            SqlValue::from(k.min(VEC0_MAX_K) as i64),
+        ];
+        let rows = stmt
+            .query_map(
+                params_from_iter(params.iter().chain(&metadata_query.params)),
+                |row| {
+                    let page_content: String = row.get(0)?;
+                    let metadata_json: String = row.get(1)?;
+                    let distance: f64 = row.get(2)?;
+                    let rowid: i64 = row.get(3)?;
+                    Ok((page_content, metadata_json, distance, rowid))
+                },
+            )?
+            .collect::<Result<Vec<(String, String, f64, i64)>, rusqlite::Error>>()?;
+
+        rows.into_iter()
+            .map(|(page_content, metadata_json, distance, rowid)| {
+                let mut metadata: HashMap<String, Value> = serde_json::from_str(&metadata_json)?;
+                metadata.insert(DOCUMENT_ID_KEY.to_string(), json!(rowid));
+                Ok(Document {
+                    page_content,
+                    metadata,
+                    score: metric.score(distance),
+                })
+            })
+            .collect()
+    }
+
+    /// Cosine similarity between the stored embeddings of documents `id_a` and `id_b`,
+    /// without re-embedding. Errors if either id does not exist.
+    pub async fn similarity_between(&self, id_a: i64, id_b: i64) -> Result<f64, Box<dyn Error>> {
+        let (table, base_filter) = (self.table.clone(), self.base_filter.clone());
+        self.with_connection(move |db| {
+            let a = read_embedding(db, &table, id_a, &base_filter)?;
+            let b = read_embedding(db, &table, id_b, &base_filter)?;
+            cosine_similarity(&a, &b)
+        })
+        .await
+    }
+
+    /// Health check that decodes the stored embeddings of up to `sample_size` random documents
+    /// and errors, listing their rowids, if any doesn't have `vector_dimensions` elements,
+    /// e.g. after documents were added with another embedding model. Unlike
+    /// `StoreBuilder::probe_dimensions`, this inspects the stored data rather than the embedder.
+    pub async fn verify_dimensions(&self, sample_size: usize) -> Result<(), Box<dyn Error>> {
+        let (table, vector_dimensions) = (self.table.clone(), self.vector_dimensions);
+        self.with_connection(move |db| {
+            verify_embedding_dimensions(db, &table, vector_dimensions, sample_size)
+        })
+        .await
     }
 
-    fn get_filters(&self, opt: &VecStoreOptions) -> Result<HashMap<String, Value>, Box<dyn Error>> {
-        match &opt.filters {
-            Some(Value::Object(map)) => {
-                let filters = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-                Ok(filters)
+    /// Like `similarity_search`, but returns the rows that failed to decode alongside the
+    /// documents instead of dropping them.
+    pub async fn similarity_search_with_row_errors(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        self.metric.check_options(opt)?;
+        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
+
+        let filter = filters_from_options(opt)?;
+        // vec0 applies the metadata filter after picking the k nearest, so fetch more
+        // neighbours when filtering to still fill `limit`.
+        let k = if filter.is_empty() && self.base_filter.is_empty() {
+            limit
+        } else {
+            limit * self.filter_overfetch
+        }
+        .min(VEC0_MAX_K);
+        let (tables, metric) = (self.tables(), self.metric);
+        let mut result = self
+            .with_connection(move |db| {
+                nearest_documents(db, &tables, query_vector_json, k, &filter, metric)
+            })
+            .await?;
+        apply_score_threshold(&mut result.docs, opt);
+        result.docs.truncate(limit);
+        Ok(result)
+    }
+
+    /// Like `similarity_search`, but returns the stored embedding of each document with it,
+    /// e.g. to re-rank the results without embedding them again.
+    pub async fn similarity_search_with_embeddings(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(Document, Vec<f32>)>, Box<dyn Error>> {
+        let query_vector = self.embedder.embed_query(query).await?;
+        self.nearest_with_embeddings(&query_vector, limit, opt)
+            .await
+    }
+
+    async fn nearest_with_embeddings(
+        &self,
+        query_vector: &[f32],
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(Document, Vec<f32>)>, Box<dyn Error>> {
+        self.metric.check_options(opt)?;
+        let (table, metric) = (self.table.clone(), self.metric);
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, Some("e"), 4)?;
+        let k = if filter.is_empty() && self.base_filter.is_empty() {
+            limit
+        } else {
+            limit * self.filter_overfetch
+        }
+        .min(VEC0_MAX_K);
+        let query_vector_json = json!(query_vector).to_string();
+
+        self.with_connection(move |db| {
+            let mut stmt = db.prepare(&format!(
+                r#"SELECT
+                    e.text,
+                    e.metadata,
+                    v.distance,
+                    v.text_embedding,
+                    e.rowid
+                FROM {table} e
+                INNER JOIN vec_{table} v on v.rowid = e.rowid
+                WHERE v.text_embedding match ?1 AND k = ?2 AND {}
+                ORDER BY distance
+                LIMIT ?3"#,
+                metadata_query.sql
+            ))?;
+            let params = [
+                SqlValue::from(query_vector_json),
+                SqlValue::from(k as i64),
+                SqlValue::from(limit as i64),
+            ];
+            let mut rows = stmt.query(params_from_iter(
+                params.iter().chain(&metadata_query.params),
+            ))?;
+
+            let mut results = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut metadata: HashMap<String, Value> =
+                    serde_json::from_str(&row.get::<_, String>(1)?)?;
+                metadata.insert(DOCUMENT_ID_KEY.to_string(), json!(row.get::<_, i64>(4)?));
+                let distance: f64 = row.get(2)?;
+                let doc = Document {
+                    page_content: row.get(0)?,
+                    metadata,
+                    score: metric.score(distance),
+                };
+                results.push((doc, decode_embedding(row.get_ref(3)?)?));
             }
-            None => Ok(HashMap::new()),
-            _ => Err("Invalid filters format".into()),
+            Ok(results)
+        })
+        .await
+    }
+
+    /// Like `similarity_search`, but only ranks the documents whose rowid is in `ids`, e.g.
+    /// candidates narrowed down beforehand by business logic. Filters in `opt` and the base
+    /// filter still apply.
+    pub async fn similarity_search_within_ids(
+        &self,
+        query: &str,
+        ids: &[i64],
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        self.metric.check_options(opt)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, Some("e"), 3)?;
+        let (table, metric, ids) = (self.table.clone(), self.metric, ids.to_vec());
+        let mut docs = self
+            .with_connection(move |db| {
+                search_within_ids(
+                    db,
+                    &table,
+                    &query_vector_json,
+                    &ids,
+                    limit,
+                    &metadata_query,
+                    metric,
+                )
+            })
+            .await?
+            .into_docs();
+        apply_score_threshold(&mut docs, opt);
+        Ok(docs)
+    }
+
+    /// Like `add_documents`, but also returns the embedding of each document, e.g. to store
+    /// it elsewhere without embedding the documents again. With `reject_duplicates`, skipped
+    /// documents come with the embedding already stored for them.
+    pub async fn add_documents_returning_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        let caller_ids = caller_ids(opt, docs.len())?.map(<[String]>::to_vec);
+        let docs: Arc<[Document]> = docs.into();
+
+        let skip = if opt.reject_duplicates {
+            let (table, base_filter, docs) =
+                (self.table.clone(), self.base_filter.clone(), docs.clone());
+            self.with_connection(move |db| duplicate_mask(db, &table, &docs, &base_filter))
+                .await?
+        } else {
+            vec![false; docs.len()]
+        };
+        let texts: Vec<String> = docs
+            .iter()
+            .zip(&skip)
+            .filter(|(_, skip)| !**skip)
+            .map(|(d, _)| opt.embedding_text(d))
+            .collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let batch_size = self.batch_size as usize;
+        let mut batches = texts.chunks(batch_size);
+        let mut vectors = Vec::with_capacity(docs.len());
+        while let Some(batch) = batches.next() {
+            let vector = embedder.embed_documents(batch).await?;
+            vectors.extend(vector);
         }
+
+        if vectors.len() != texts.len() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Number of vectors and documents do not match",
+            )));
+        }
+
+        let tables = self.tables();
+        let reject_duplicates = opt.reject_duplicates;
+        self.with_connection(move |db| {
+            insert_documents(
+                db,
+                &tables,
+                &docs,
+                skip,
+                vectors,
+                caller_ids.as_deref(),
+                reject_duplicates,
+            )
+        })
+        .await
     }
 
     pub async fn delete_documents_by_ids(&self, ids: &[i64]) -> Result<(), Box<dyn Error>> {
@@ -95,18 +387,28 @@ impl Store {
             .map(|i| format!("?{}", i))
             .collect::<Vec<_>>()
             .join(",");
-        let mut db = self.pool.lock().unwrap();
-        let tx = db.transaction()?;
-
-        let main_sql = format!(r#"DELETE FROM {table} WHERE rowid IN ({placeholders})"#);
-        tx.execute(&main_sql, params_from_iter(ids))?;
-
+        let scope = build_metadata_query(&self.base_filter, &HashMap::new(), None, ids.len() + 1)?;
+        let main_sql = format!(
+            r#"DELETE FROM {table} WHERE rowid IN ({placeholders}) AND {}"#,
+            scope.sql
+        );
+        // Only the vectors of the rows deleted above, not of rows outside the base filter.
         let vec_table = format!("vec_{}", table);
-        let vec_sql = format!(r#"DELETE FROM {vec_table} WHERE rowid IN ({placeholders})"#);
-        tx.execute(&vec_sql, params_from_iter(ids))?;
-
-        tx.commit()?;
-        Ok(())
+        let vec_sql = format!(
+            r#"DELETE FROM {vec_table}
+            WHERE rowid IN ({placeholders}) AND rowid NOT IN (SELECT rowid FROM {table})"#
+        );
+        let ids = ids.to_vec();
+
+        self.with_connection(move |db| {
+            let tx = db.transaction()?;
+            let params = ids.iter().map(|id| SqlValue::from(*id)).chain(scope.params);
+            tx.execute(&main_sql, params_from_iter(params))?;
+            tx.execute(&vec_sql, params_from_iter(&ids))?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
     }
 
     pub async fn delete_documents_by_metadata(
@@ -118,47 +420,17 @@ impl Store {
         }
 
         let table = &self.table;
-        let mut db = self.pool.lock().unwrap();
-        let tx = db.transaction()?;
 
         // 构建 metadata 过滤条件
-        let metadata_conditions = metadata_filters
-            .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract(metadata, '$.{}') IN ({})",
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-                Value::Number(n) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract(metadata, '$.{}') = {}", k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!("json_extract(metadata, '$.{}') = {}", k, json_value)
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(" AND ");
+        let metadata_conditions =
+            build_metadata_query(&self.base_filter, metadata_filters, None, 1)?;
 
         // 删除主表中符合条件的记录
         let main_sql = format!(
             r#"DELETE FROM {table}
             WHERE {}"#,
-            metadata_conditions
+            metadata_conditions.sql
         );
-        tx.execute(&main_sql, ())?;
 
         // 同步删除向量表中的相关记录
         let vec_table = format!("vec_{}", table);
@@ -166,27 +438,31 @@ impl Store {
             r#"DELETE FROM {vec_table}
             WHERE rowid NOT IN (SELECT rowid FROM {table})"#
         );
-        tx.execute(&vec_sql, ())?;
 
-        tx.commit()?;
-        Ok(())
+        self.with_connection(move |db| {
+            let tx = db.transaction()?;
+            tx.execute(&main_sql, params_from_iter(&metadata_conditions.params))?;
+            tx.execute(&vec_sql, ())?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
     }
 
     pub async fn delete_all_documents(&self) -> Result<(), Box<dyn Error>> {
-        if !self.table.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Err("Invalid table name".into());
-        }
-
-        let mut db = self.pool.lock().unwrap();
-        let tx = db.transaction()?;
+        let table = self.table.clone();
+        self.with_connection(move |db| {
+            let tx = db.transaction()?;
 
-        tx.execute(&format!("DELETE FROM {}", self.table), ())?;
+            tx.execute(&format!("DELETE FROM {}", table), ())?;
 
-        let vec_table = format!("vec_{}", self.table);
-        tx.execute(&format!("DELETE FROM {}", vec_table), ())?;
+            let vec_table = format!("vec_{}", table);
+            tx.execute(&format!("DELETE FROM {}", vec_table), ())?;
 
-        tx.commit()?;
-        Ok(())
+            tx.commit()?;
+            Ok(())
+        })
+        .await
     }
 }
 
@@ -197,146 +473,365 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
-        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
-        let batch_size = self.batch_size as usize;
-        let mut batches = texts.chunks(batch_size);
-        let mut vectors = Vec::with_capacity(docs.len());
-        while let Some(batch) = batches.next() {
-            let vector = embedder.embed_documents(batch).await?;
-            vectors.extend(vector);
-        }
+        Ok(self
+            .add_documents_returning_embeddings(docs, opt)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
 
-        if vectors.len() != docs.len() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Number of vectors and documents do not match",
-            )));
-        }
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        Ok(self
+            .similarity_search_with_row_errors(query, limit, opt)
+            .await?
+            .into_docs())
+    }
 
-        let table = &self.table;
-        let mut db = self.pool.lock().unwrap();
-        let tx = db.transaction()?;
-        let mut ids = Vec::with_capacity(docs.len());
+    /// Uses the stored embeddings of the candidates, so unlike the default it needs no
+    /// `VecStoreOptions::embedder`.
+    async fn mmr_search(
+        &self,
+        query: &str,
+        k: usize,
+        fetch_k: usize,
+        lambda: f64,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let query_vector = self.embedder.embed_query(query).await?;
+        let candidates = self
+            .nearest_with_embeddings(&query_vector, fetch_k.max(k), opt)
+            .await?;
+        let embeddings: Vec<Vec<f32>> = candidates.iter().map(|(_, e)| e.clone()).collect();
+
+        let selected = maximal_marginal_relevance(&query_vector, &embeddings, k, lambda);
+        Ok(selected
+            .into_iter()
+            .map(|i| candidates[i].0.clone())
+            .collect())
+    }
+
+    async fn similarity_search_threshold_stream(
+        &self,
+        query: &str,
+        opt: &VecStoreOptions,
+    ) -> Result<DocumentStream, Box<dyn Error>> {
+        let score_threshold =
+            opt.score_threshold
+                .ok_or("score_threshold is required for a threshold stream")? as f64;
+        self.metric.check_options(opt)?;
+
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, Some("e"), 3)?;
+        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
 
-        for (doc, vector) in docs.iter().zip(vectors.iter()) {
-            let text_embedding = json!(vector).to_string();
-            let id: i64 = tx.query_row(
+        let table = self.table.clone();
+        let pool = self.pool.clone();
+        let metric = self.metric;
+        let total: usize = {
+            let table = table.clone();
+            self.with_connection(move |db| {
+                let count = db.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get::<_, i64>(0)
+                })?;
+                Ok(count as usize)
+            })
+            .await?
+        };
+
+        // vec0 only answers "k nearest" queries, so fetch growing prefixes of the ranking
+        // and only emit the documents past the ones already sent. The stream ends after the
+        // `VEC0_MAX_K` nearest documents, the most vec0 returns. Each page is fetched on the
+        // blocking thread pool, like `with_connection` does.
+        let stream = stream! {
+            let mut emitted = 0;
+            let mut k = THRESHOLD_STREAM_PAGE_SIZE;
+            loop {
+                let fetch = {
+                    let (pool, table) = (pool.clone(), table.clone());
+                    let (metadata_query, query_vector_json) =
+                        (metadata_query.clone(), query_vector_json.clone());
+                    tokio::task::spawn_blocking(move || {
+                        Self::fetch_nearest(
+                            &pool,
+                            &table,
+                            &metadata_query,
+                            &query_vector_json,
+                            k,
+                            metric,
+                        )
+                    })
+                };
+                let docs = match fetch.await {
+                    Ok(Ok(docs)) => docs,
+                    Ok(Err(e)) => {
+                        yield Err(e);
+                        return;
+                    }
+                    Err(e) => {
+                        yield Err(e.into());
+                        return;
+                    }
+                };
+                for doc in docs.into_iter().skip(emitted) {
+                    if doc.score < score_threshold {
+                        return;
+                    }
+                    emitted += 1;
+                    yield Ok(doc);
+                }
+                if k >= total || k >= VEC0_MAX_K {
+                    break;
+                }
+                k = (k * 2).min(VEC0_MAX_K);
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let filter = filters_from_options(opt)?;
+        let metadata_query = build_metadata_query(&self.base_filter, &filter, None, 1)?;
+        let table = self.table.clone();
+        self.with_connection(move |db| {
+            let count: i64 = db.query_row(
                 &format!(
-                    r#"
-                    INSERT INTO {table}
-                        (text, metadata, text_embedding)
-                    VALUES
-                        (?1, ?2, ?3)
-                    RETURNING rowid"#
+                    "SELECT COUNT(*) FROM {} WHERE {}",
+                    table, metadata_query.sql
                 ),
-                params![
-                    &doc.page_content,
-                    &json!(&doc.metadata).to_string(),
-                    &text_embedding
-                ],
+                params_from_iter(&metadata_query.params),
                 |row| row.get(0),
             )?;
+            Ok(count as usize)
+        })
+        .await
+    }
 
-            ids.push(id.to_string());
+    async fn upsert_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        check_metadata_size(docs, self.max_metadata_bytes)?;
+        let keys = upsert_keys(docs, opt)?;
+
+        let existing = {
+            let (table, base_filter) = (self.table.clone(), self.base_filter.clone());
+            let keys = keys.clone();
+            self.with_connection(move |db| {
+                ensure_doc_id_column(db, &table)?;
+                rows_by_doc_id(db, &table, &keys, &base_filter)
+            })
+            .await?
+        };
+        // The embedding of an unchanged text is kept, unless a template may embed metadata.
+        let reembed: Vec<bool> = docs
+            .iter()
+            .zip(&existing)
+            .map(|(doc, row)| match row {
+                Some((_, text)) => *text != doc.page_content || opt.embedding_template.is_some(),
+                None => true,
+            })
+            .collect();
+        let texts: Vec<String> = docs
+            .iter()
+            .zip(&reembed)
+            .filter(|(_, reembed)| **reembed)
+            .map(|(d, _)| opt.embedding_text(d))
+            .collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size as usize) {
+            vectors.extend(embedder.embed_documents(batch).await?);
+        }
+        if vectors.len() != texts.len() {
+            return Err("Number of vectors and documents do not match".into());
         }
 
-        tx.commit()?;
-        Ok(ids)
+        let (tables, docs) = (self.tables(), docs.to_vec());
+        self.with_connection(move |db| {
+            write_upserts(db, &tables, &docs, keys, &existing, &reembed, vectors)
+        })
+        .await
     }
 
-    async fn similarity_search(
+    async fn get_documents_by_ids(
         &self,
-        query: &str,
-        limit: usize,
+        ids: &[String],
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
-        let table = &self.table;
-        let query_vector_json = json!(self.embedder.embed_query(query).await?).to_string();
-        let db = self.pool.lock().unwrap();
+        let (table, base_filter) = (self.table.clone(), self.base_filter.clone());
+        let found = {
+            let ids = ids.to_vec();
+            self.with_connection(move |db| documents_by_ids(db, &table, &ids, &base_filter))
+                .await?
+        };
+        order_by_ids(ids, found, opt)
+    }
+}
 
-        let filter = self.get_filters(opt)?;
-        let mut metadata_query = filter
-            .iter()
-            .map(|(k, v)| match v {
-                Value::Array(arr) => {
-                    let values: Vec<String> =
-                        arr.iter().map(|val| json!(val).to_string()).collect();
-                    format!(
-                        "json_extract(e.metadata, '$.{}') IN ({})",
-                        k,
-                        values.join(",")
-                    )
-                }
-                Value::String(s) => {
-                    let json_value = json!(s).to_string();
-                    format!("json_extract(e.metadata, '$.{}') = {}", k, json_value)
-                }
-                Value::Number(n) => {
-                    format!("json_extract(e.metadata, '$.{}') = {}", k, n)
-                }
-                Value::Bool(b) => {
-                    format!("json_extract(e.metadata, '$.{}') = {}", k, b)
-                }
-                _ => {
-                    let json_value = json!(v).to_string();
-                    format!("json_extract(e.metadata, '$.{}') = {}", k, json_value)
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(" AND ");
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::vectorstore::{
+        sqlite_vec::StoreBuilder,
+        test_utils::{
+            assert_get_documents_by_ids_applies_base_filter,
+            assert_skipped_duplicate_returns_stored_id, assert_upsert_replaces_raced_row,
+            store_raced_row, tenant_doc, LetterEmbedder, RacingEmbedder,
+        },
+    };
+
+    async fn store_with(texts: &[&str]) -> Store {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        let docs: Vec<Document> = texts.iter().map(|text| Document::new(*text)).collect();
+        store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        store
+    }
 
-        if metadata_query.is_empty() {
-            metadata_query = "1 = 1".to_string();
-        }
+    #[tokio::test]
+    async fn test_threshold_stream_stops_at_vec0_max_k() {
+        let texts = vec!["ab"; VEC0_MAX_K + 10];
+        let store = store_with(&texts).await;
+
+        let opt = VecStoreOptions::default().with_score_threshold(0.0);
+        let docs: Vec<_> = store
+            .similarity_search_threshold_stream("ab", &opt)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(docs.len(), VEC0_MAX_K);
+        assert!(docs.iter().all(|doc| doc.is_ok()));
+    }
 
-        println!("Executing query with metadata filter: {}", metadata_query);
+    #[tokio::test]
+    async fn test_upsert_replaces_row_stored_while_embedding() {
+        let store = store_with(&[]).await;
+        let pool = store.pool.clone();
+        let embedder = RacingEmbedder(move || store_raced_row(&pool.get().unwrap()));
+        assert_upsert_replaces_raced_row(&store, embedder).await;
+    }
 
-        let mut stmt = db.prepare(&format!(
-            r#"SELECT
-                e.text,
-                e.metadata,
-                v.distance
-            FROM {table} e
-            INNER JOIN vec_{table} v on v.rowid = e.rowid
-            WHERE v.text_embedding match ?1 AND k = ?2 AND {metadata_query}
-            ORDER BY distance
-            LIMIT ?3"#
-        ))?;
+    #[tokio::test]
+    async fn test_skipped_duplicate_returns_stored_id() {
+        assert_skipped_duplicate_returns_stored_id(&store_with(&[]).await).await;
+    }
 
-        let doubled_limit = limit * 2;
-        let docs = stmt
-            .query_map(
-                params![query_vector_json, limit as i32, doubled_limit as i32],
-                |row| {
-                    let page_content: String = row.get(0)?;
-                    let metadata_json: String = row.get(1)?;
-                    let distance: f64 = row.get(2)?;
-                    let score = 1.0 / (1.0 + distance);
-                    let metadata: HashMap<String, Value> =
-                        serde_json::from_str(&metadata_json).unwrap();
-
-                    Ok(Document {
-                        page_content,
-                        metadata,
-                        score,
-                    })
-                },
-            )?
-            .collect::<Result<Vec<Document>, rusqlite::Error>>()?;
+    #[tokio::test]
+    async fn test_similarity_search_with_limit_above_vec0_max_k() {
+        let store = store_with(&["aaa", "aab", "ccc"]).await;
+
+        let found = store
+            .similarity_search("aab", 5000, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].page_content, "aab");
+
+        // With a filter, limit * filter_overfetch neighbours are fetched.
+        let filtered = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        assert!(store
+            .similarity_search("aab", 2000, &filtered)
+            .await
+            .is_ok());
+    }
 
-        let mut seen = std::collections::HashSet::new();
-        let mut unique_docs: Vec<Document> = docs
-            .into_iter()
-            .filter(|doc| {
-                let key = format!("{}{}", doc.page_content, json!(doc.metadata));
-                seen.insert(key)
-            })
-            .collect();
+    /// A store scoped to tenant `a` holding one document of tenant `a` and one of tenant `b`,
+    /// with their ids.
+    async fn tenant_store() -> (Store, Vec<String>) {
+        let store = StoreBuilder::new()
+            .connection_url(":memory:")
+            .vector_dimensions(3)
+            .embedder(LetterEmbedder)
+            .base_filter(json!({"tenant": "a"}))
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        let docs = [tenant_doc("ours", "a"), tenant_doc("theirs", "b")];
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        (store, ids)
+    }
 
-        unique_docs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        unique_docs.truncate(limit);
+    /// Every stored text, regardless of the base filter.
+    fn stored_texts(store: &Store) -> Vec<String> {
+        let conn = store.pool.get().unwrap();
+        let mut stmt = conn
+            .prepare(&format!("SELECT text FROM {} ORDER BY rowid", store.table))
+            .unwrap();
+        let texts = stmt.query_map([], |row| row.get(0)).unwrap();
+        texts.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_documents_by_ids_applies_base_filter() {
+        let (store, ids) = tenant_store().await;
+        assert_get_documents_by_ids_applies_base_filter(&store, &ids).await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_ids_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        store.delete_documents_by_ids(&[1, 2]).await.unwrap();
+        assert_eq!(stored_texts(&store), vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_documents_by_metadata_applies_base_filter() {
+        let (store, _) = tenant_store().await;
 
-        Ok(unique_docs)
+        let filter = HashMap::from([("source".to_string(), json!("doc.md"))]);
+        store.delete_documents_by_metadata(&filter).await.unwrap();
+        assert_eq!(stored_texts(&store), vec!["theirs"]);
+    }
+
+    #[tokio::test]
+    async fn test_similarity_between_applies_base_filter() {
+        let (store, _) = tenant_store().await;
+
+        assert!(store.similarity_between(1, 1).await.is_ok());
+        assert!(store.similarity_between(1, 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_keeps_other_tenant_row() {
+        let (store, _) = tenant_store().await;
+        let opt = VecStoreOptions::default().with_ids(vec!["k".to_string()]);
+
+        store
+            .upsert_documents(&[tenant_doc("their k", "b")], &opt)
+            .await
+            .unwrap();
+        store
+            .upsert_documents(&[tenant_doc("our k", "a")], &opt)
+            .await
+            .unwrap();
+        assert_eq!(
+            stored_texts(&store),
+            vec!["ours", "theirs", "their k", "our k"]
+        );
     }
 }
@@ -2,7 +2,7 @@ use std::{error::Error, sync::Arc};
 
 use surrealdb::{Connection, Surreal};
 
-use crate::embedding::embedder_trait::Embedder;
+use crate::{embedding::embedder_trait::Embedder, vectorstore::probe_vector_dimensions};
 
 use super::Store;
 
@@ -12,6 +12,7 @@ pub struct StoreBuilder<C: Connection> {
     collection_table_name: Option<String>,
     collection_metadata_key_name: Option<String>,
     vector_dimensions: i32,
+    probe_dimensions: bool,
     embedder: Option<Arc<dyn Embedder>>,
     schemafull: bool,
 }
@@ -29,6 +30,7 @@ impl<C: Connection> StoreBuilder<C> {
             collection_table_name: Some("document".to_string()),
             collection_metadata_key_name: Some("collection".to_string()),
             vector_dimensions: 0,
+            probe_dimensions: false,
             embedder: None,
             schemafull: true,
         }
@@ -42,6 +44,7 @@ impl<C: Connection> StoreBuilder<C> {
             collection_table_name: None,
             collection_metadata_key_name: None,
             vector_dimensions: 0,
+            probe_dimensions: false,
             embedder: None,
             schemafull: false,
         }
@@ -107,10 +110,23 @@ impl<C: Connection> StoreBuilder<C> {
     }
 
     // Finalize the builder and construct the Store object
-    pub async fn build(self) -> Result<Store<C>, Box<dyn Error>> {
+    /// Embeds a sentinel text once during `build` to learn the embedder's output dimension.
+    /// Sets `vector_dimensions` when unset, otherwise checks that it matches. Off by default
+    /// since it calls the embedder.
+    pub fn probe_dimensions(mut self, probe_dimensions: bool) -> Self {
+        self.probe_dimensions = probe_dimensions;
+        self
+    }
+
+    pub async fn build(mut self) -> Result<Store<C>, Box<dyn Error>> {
         if self.embedder.is_none() {
             return Err("Embedder is required".into());
         }
+        if self.probe_dimensions {
+            self.vector_dimensions =
+                probe_vector_dimensions(self.embedder.as_deref().unwrap(), self.vector_dimensions)
+                    .await?;
+        }
 
         if self.db.is_none() {
             return Err("Db is required".into());
@@ -96,7 +96,7 @@ impl<C: Connection> VectorStore for Store<C> {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
 
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
 
@@ -0,0 +1,111 @@
+use std::{error::Error, path::PathBuf, sync::atomic::AtomicU64};
+
+use ::tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    query::AllQuery,
+    schema::{JsonObjectOptions, Schema, TextFieldIndexing, FAST, INDEXED, STORED, TEXT},
+    Index, Order, ReloadPolicy,
+};
+use tokio::sync::Mutex;
+
+use super::Store;
+
+const DEFAULT_WRITER_HEAP_SIZE: usize = 50_000_000;
+
+pub struct StoreBuilder {
+    index_path: Option<PathBuf>,
+    text_field: String,
+    metadata_field: String,
+    writer_heap_size: usize,
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            index_path: None,
+            text_field: "text".to_string(),
+            metadata_field: "metadata".to_string(),
+            writer_heap_size: DEFAULT_WRITER_HEAP_SIZE,
+        }
+    }
+
+    /// Directory holding the index. It is created if missing, and an existing index in it
+    /// is reopened. Without a path the index lives in memory.
+    pub fn index_path<P: Into<PathBuf>>(mut self, index_path: P) -> Self {
+        self.index_path = Some(index_path.into());
+        self
+    }
+
+    /// Name of the full-text field holding `page_content`. Defaults to `text`.
+    pub fn text_field<S: Into<String>>(mut self, text_field: S) -> Self {
+        self.text_field = text_field.into();
+        self
+    }
+
+    /// Name of the JSON field holding the metadata. Defaults to `metadata`. Metadata values
+    /// are indexed untokenized, so filters match whole values.
+    pub fn metadata_field<S: Into<String>>(mut self, metadata_field: S) -> Self {
+        self.metadata_field = metadata_field.into();
+        self
+    }
+
+    /// Memory budget of the index writer, in bytes. Defaults to 50MB.
+    pub fn writer_heap_size(mut self, writer_heap_size: usize) -> Self {
+        self.writer_heap_size = writer_heap_size;
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_u64_field("id", INDEXED | STORED | FAST);
+        let text_field = schema_builder.add_text_field(&self.text_field, TEXT | STORED);
+        let metadata_options = JsonObjectOptions::default()
+            .set_stored()
+            .set_indexing_options(TextFieldIndexing::default().set_tokenizer("raw"));
+        let metadata_field = schema_builder.add_json_field(&self.metadata_field, metadata_options);
+        let schema = schema_builder.build();
+
+        let index = match &self.index_path {
+            Some(path) => {
+                std::fs::create_dir_all(path)?;
+                Index::open_or_create(MmapDirectory::open(path)?, schema.clone())?
+            }
+            None => Index::create_in_ram(schema.clone()),
+        };
+
+        let writer = index.writer(self.writer_heap_size)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        // Continue numbering after the highest id already in the index.
+        let last_id = reader
+            .searcher()
+            .search(
+                &AllQuery,
+                &TopDocs::with_limit(1).order_by_fast_field::<u64>("id", Order::Desc),
+            )?
+            .first()
+            .map(|(id, _)| *id)
+            .unwrap_or(0);
+
+        Ok(Store {
+            index,
+            schema,
+            writer: Mutex::new(writer),
+            reader,
+            id_field,
+            text_field,
+            metadata_field,
+            next_id: AtomicU64::new(last_id + 1),
+        })
+    }
+}
@@ -0,0 +1,5 @@
+mod builder;
+mod tantivy;
+
+pub use self::tantivy::*;
+pub use builder::*;
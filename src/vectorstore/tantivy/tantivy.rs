@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ::tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, Occur, Query, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption, Schema},
+    Index, IndexReader, IndexWriter, TantivyDocument, Term,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{
+    schemas::Document,
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+
+/// Keyword store backed by an embedded Tantivy index, an alternative to `sqlite_bm25` that
+/// needs no C extension.
+///
+/// `similarity_search` parses the query with Tantivy's query parser over the text field
+/// (so `+must -not "a phrase" title:rust` style queries work) and ranks results by BM25.
+/// Scores are raw BM25 values, higher meaning more relevant.
+pub struct Store {
+    pub(crate) index: Index,
+    pub(crate) schema: Schema,
+    pub(crate) writer: Mutex<IndexWriter>,
+    pub(crate) reader: IndexReader,
+    pub(crate) id_field: Field,
+    pub(crate) text_field: Field,
+    pub(crate) metadata_field: Field,
+    pub(crate) next_id: AtomicU64,
+}
+
+impl Store {
+    fn get_filters(&self, opt: &VecStoreOptions) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+        match &opt.filters {
+            Some(Value::Object(map)) => {
+                let filters = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                Ok(filters)
+            }
+            None => Ok(HashMap::new()),
+            _ => Err("Invalid filters format".into()),
+        }
+    }
+
+    /// Builds one clause per filter key; array values match any of their elements. Values
+    /// are matched as exact terms on the key's JSON path, so they need no escaping.
+    fn build_filter_queries(
+        &self,
+        filter: &HashMap<String, Value>,
+    ) -> Result<Vec<(Occur, Box<dyn Query>)>, Box<dyn Error>> {
+        filter
+            .iter()
+            .map(|(key, value)| {
+                let values: Vec<&Value> = match value {
+                    Value::Array(values) => values.iter().collect(),
+                    value => vec![value],
+                };
+                let clauses = values
+                    .iter()
+                    .map(|value| {
+                        let term = self.metadata_term(key, value)?;
+                        let query: Box<dyn Query> =
+                            Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                        Ok((Occur::Should, query))
+                    })
+                    .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+                let query: Box<dyn Query> = Box::new(BooleanQuery::new(clauses));
+                Ok((Occur::Must, query))
+            })
+            .collect()
+    }
+
+    /// The term of `value` at `key` in the metadata field, typed as Tantivy indexes JSON
+    /// values: integers as i64 when they fit, then u64, other numbers as f64.
+    fn metadata_term(&self, key: &str, value: &Value) -> Result<Term, Box<dyn Error>> {
+        let mut term = Term::from_field_json_path(self.metadata_field, key, false);
+        match value {
+            Value::String(value) => term.append_type_and_str(value),
+            Value::Bool(value) => term.append_type_and_fast_value(*value),
+            Value::Number(number) => {
+                if let Some(value) = number.as_i64() {
+                    term.append_type_and_fast_value(value)
+                } else if let Some(value) = number.as_u64() {
+                    term.append_type_and_fast_value(value)
+                } else {
+                    term.append_type_and_fast_value(number.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            _ => return Err(format!("Unsupported filter value for {}: {}", key, value).into()),
+        }
+        Ok(term)
+    }
+
+    pub async fn delete_documents_by_ids(&self, ids: &[u64]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut writer = self.writer.lock().await;
+        for id in ids {
+            writer.delete_term(Term::from_field_u64(self.id_field, *id));
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_documents(&self) -> Result<(), Box<dyn Error>> {
+        let mut writer = self.writer.lock().await;
+        writer.delete_all_documents()?;
+        writer.commit()?;
+        self.reader.reload()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        _opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let text_name = self.schema.get_field_name(self.text_field);
+        let metadata_name = self.schema.get_field_name(self.metadata_field);
+
+        let mut writer = self.writer.lock().await;
+        let mut ids = Vec::with_capacity(docs.len());
+        for doc in docs {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let json_doc = json!({
+                "id": id,
+                text_name: doc.page_content,
+                metadata_name: doc.metadata,
+            });
+            writer.add_document(TantivyDocument::parse_json(
+                &self.schema,
+                &json_doc.to_string(),
+            )?)?;
+            ids.push(id.to_string());
+        }
+        writer.commit()?;
+        self.reader.reload()?;
+
+        Ok(ids)
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let parser = QueryParser::for_index(&self.index, vec![self.text_field]);
+        let text_query = parser.parse_query(query)?;
+
+        let filter = self.get_filters(opt)?;
+        let query: Box<dyn Query> = if filter.is_empty() {
+            text_query
+        } else {
+            let mut clauses = self.build_filter_queries(&filter)?;
+            clauses.push((Occur::Must, text_query));
+            Box::new(BooleanQuery::new(clauses))
+        };
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
+
+        let text_name = self.schema.get_field_name(self.text_field);
+        let metadata_name = self.schema.get_field_name(self.metadata_field);
+        let mut docs = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let score = score as f64;
+            if opt
+                .score_threshold
+                .is_some_and(|threshold| score < threshold as f64)
+            {
+                continue;
+            }
+
+            let stored: TantivyDocument = searcher.doc(address)?;
+            // Stored fields serialize as arrays of values.
+            let stored: Value = serde_json::from_str(&stored.to_json(&self.schema))?;
+            let page_content = stored[text_name][0].as_str().unwrap_or("").to_string();
+            let metadata = match &stored[metadata_name][0] {
+                Value::Object(map) => map.clone().into_iter().collect(),
+                _ => HashMap::new(),
+            };
+
+            docs.push(Document {
+                page_content,
+                metadata,
+                score,
+            });
+        }
+
+        Ok(docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectorstore::tantivy::StoreBuilder;
+
+    fn doc(text: &str, metadata: Value) -> Document {
+        let Value::Object(map) = metadata else {
+            panic!("metadata must be an object");
+        };
+        Document::new(text).with_metadata(map.into_iter().collect())
+    }
+
+    fn texts(docs: &[Document]) -> Vec<&str> {
+        docs.iter().map(|doc| doc.page_content.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_add_search_and_delete() {
+        let store = StoreBuilder::new().build().await.unwrap();
+        let opt = VecStoreOptions::default();
+        let ids = store
+            .add_documents(
+                &[
+                    doc("rust ownership", json!({})),
+                    doc("python typing", json!({})),
+                    doc("rust lifetimes", json!({})),
+                ],
+                &opt,
+            )
+            .await
+            .unwrap();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+
+        let found = store.similarity_search("rust", 10, &opt).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|doc| doc.page_content.starts_with("rust")));
+
+        store.delete_documents_by_ids(&[1]).await.unwrap();
+        let found = store.similarity_search("rust", 10, &opt).await.unwrap();
+        assert_eq!(texts(&found), vec!["rust lifetimes"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_with_filters() {
+        let store = StoreBuilder::new().build().await.unwrap();
+        store
+            .add_documents(
+                &[
+                    doc(
+                        "rust guide",
+                        json!({"lang": "en-US", "year": 2021, "draft": false}),
+                    ),
+                    doc(
+                        "rust guide",
+                        json!({"lang": "fr: FR", "year": 2023, "draft": false}),
+                    ),
+                    doc(
+                        "rust guide",
+                        json!({"lang": "de", "year": 2023, "draft": true}),
+                    ),
+                ],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let search = |filters: Value| {
+            let store = &store;
+            async move {
+                let opt = VecStoreOptions::default().with_filters(filters);
+                let found = store.similarity_search("rust", 10, &opt).await.unwrap();
+                let mut langs: Vec<String> = found
+                    .iter()
+                    .map(|doc| doc.metadata["lang"].as_str().unwrap().to_string())
+                    .collect();
+                langs.sort();
+                langs
+            }
+        };
+        // Values with query syntax characters match as they are.
+        assert_eq!(search(json!({"lang": "fr: FR"})).await, vec!["fr: FR"]);
+        assert_eq!(search(json!({"year": 2023})).await, vec!["de", "fr: FR"]);
+        assert_eq!(
+            search(json!({"year": 2023, "draft": false})).await,
+            vec!["fr: FR"]
+        );
+        assert_eq!(
+            search(json!({"lang": ["en-US", "de"]})).await,
+            vec!["de", "en-US"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ids_continue_after_reopening() {
+        let path = std::env::temp_dir().join("langchain_rust_tantivy_store_test");
+        let _ = std::fs::remove_dir_all(&path);
+        let opt = VecStoreOptions::default();
+
+        {
+            let store = StoreBuilder::new().index_path(&path).build().await.unwrap();
+            let docs = [doc("first", json!({})), doc("second", json!({}))];
+            store.add_documents(&docs, &opt).await.unwrap();
+        }
+        let store = StoreBuilder::new().index_path(&path).build().await.unwrap();
+        let ids = store
+            .add_documents(&[doc("third", json!({}))], &opt)
+            .await
+            .unwrap();
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(ids, vec!["3"]);
+    }
+}
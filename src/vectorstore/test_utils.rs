@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+
+use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+#[cfg(any(
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid"
+))]
+use crate::{
+    schemas::Document,
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+
+/// Embeds a text as its counts of the letters `a`, `b` and `c`, plus 0.1 so that no vector
+/// is all zeros.
+pub(crate) struct LetterEmbedder;
+
+#[async_trait]
+impl Embedder for LetterEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for document in documents {
+            embeddings.push(self.embed_query(document).await?);
+        }
+        Ok(embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        Ok(['a', 'b', 'c']
+            .iter()
+            .map(|letter| text.chars().filter(|c| c == letter).count() as f32 + 0.1)
+            .collect())
+    }
+}
+
+/// Embeds like [`LetterEmbedder`], but first runs its closure, e.g. to write what a
+/// concurrent writer would while the documents are being embedded.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) struct RacingEmbedder<F>(pub(crate) F);
+
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+#[async_trait]
+impl<F: Fn() + Send + Sync> Embedder for RacingEmbedder<F> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f32>>, EmbedderError> {
+        (self.0)();
+        LetterEmbedder.embed_documents(documents).await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbedderError> {
+        LetterEmbedder.embed_query(text).await
+    }
+}
+
+/// Stores a document under the key `k` in the `documents` table, as a concurrent upsert of
+/// the same key would.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) fn store_raced_row(conn: &rusqlite::Connection) {
+    let raced = Document::new("raced");
+    super::sqlite_utils::replace_row(conn, "documents", None, &raced, &[1.0, 1.0, 1.0], "k")
+        .unwrap();
+}
+
+/// Upserts a document under the key `k` with `embedder`, which stores another one under `k`
+/// while embedding, and checks that the upsert replaced it.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) async fn assert_upsert_replaces_raced_row<E: Embedder + 'static>(
+    store: &dyn VectorStore,
+    embedder: E,
+) {
+    let opt = VecStoreOptions::default()
+        .with_ids(vec!["k".to_string()])
+        .with_embedder(embedder);
+    store
+        .upsert_documents(&[Document::new("abc")], &opt)
+        .await
+        .unwrap();
+
+    let opt = VecStoreOptions::default();
+    assert_eq!(store.count_documents(&opt).await.unwrap(), 1);
+    let docs = store
+        .get_documents_by_ids(&["k".to_string()], &opt)
+        .await
+        .unwrap();
+    assert_eq!(docs[0].page_content, "abc");
+}
+
+/// Checks that a document skipped by `reject_duplicates` gets the id of the stored one.
+#[cfg(any(feature = "sqlite-vec", feature = "sqlite-hybrid"))]
+pub(crate) async fn assert_skipped_duplicate_returns_stored_id(store: &dyn VectorStore) {
+    store
+        .add_documents(
+            &[Document::new("abc")],
+            &VecStoreOptions::default().with_ids(vec!["doc-1".to_string()]),
+        )
+        .await
+        .unwrap();
+
+    let ids = store
+        .add_documents(
+            &[Document::new("abc"), Document::new("cab")],
+            &VecStoreOptions::default().with_reject_duplicates(true),
+        )
+        .await
+        .unwrap();
+    assert_eq!(ids[0], "doc-1");
+    assert_ne!(ids[1], "doc-1");
+}
+
+/// A document of `tenant`, for stores scoped by a `{"tenant": ...}` base filter. All of them
+/// share the `source` metadata.
+#[cfg(any(
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid"
+))]
+pub(crate) fn tenant_doc(text: &str, tenant: &str) -> Document {
+    Document::new(text).with_metadata(std::collections::HashMap::from([
+        ("tenant".to_string(), serde_json::json!(tenant)),
+        ("source".to_string(), serde_json::json!("doc.md")),
+    ]))
+}
+
+/// Checks that `store`, scoped to the tenant of the document "ours", only returns that one
+/// of the documents stored under `ids`.
+#[cfg(any(
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid"
+))]
+pub(crate) async fn assert_get_documents_by_ids_applies_base_filter(
+    store: &dyn VectorStore,
+    ids: &[String],
+) {
+    let docs = store
+        .get_documents_by_ids(ids, &VecStoreOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].page_content, "ours");
+}
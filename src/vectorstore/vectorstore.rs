@@ -1,10 +1,15 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error, pin::Pin};
 
 use async_trait::async_trait;
+use futures::Stream;
+use serde_json::Value;
 
 use crate::schemas::{self, Document};
 
-use super::VecStoreOptions;
+use super::{maximal_marginal_relevance, VecStoreOptions};
+
+pub type DocumentStream =
+    Pin<Box<dyn Stream<Item = Result<Document, Box<dyn Error + Send + Sync>>> + Send>>;
 
 // VectorStore is the trait for saving and querying documents in the
 // form of vector embeddings.
@@ -16,13 +21,244 @@ pub trait VectorStore: Send + Sync {
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>>;
 
+    /// Adds plain texts, with optional per-text metadata, by wrapping them in `Document`s.
+    /// `metadatas`, when given, must have one entry per text.
+    async fn add_texts(
+        &self,
+        texts: &[String],
+        metadatas: Option<&[HashMap<String, Value>]>,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        if let Some(metadatas) = metadatas {
+            if metadatas.len() != texts.len() {
+                return Err(format!(
+                    "Got {} metadatas for {} texts",
+                    metadatas.len(),
+                    texts.len()
+                )
+                .into());
+            }
+        }
+
+        let docs: Vec<Document> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let doc = Document::new(text.clone());
+                match metadatas {
+                    Some(metadatas) => doc.with_metadata(metadatas[i].clone()),
+                    None => doc,
+                }
+            })
+            .collect();
+
+        self.add_documents(&docs, opt).await
+    }
+
     async fn similarity_search(
         &self,
         query: &str,
         limit: usize,
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>>;
+
+    /// `similarity_search` with `opt.default_limit` as the limit. Errors if it is unset.
+    async fn search(
+        &self,
+        query: &str,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let limit = opt
+            .default_limit
+            .ok_or("VecStoreOptions::default_limit must be set to search without a limit")?;
+        self.similarity_search(query, limit, opt).await
+    }
+
+    /// Full-text (e.g. bm25) search that ignores embeddings, so that code written against
+    /// `dyn VectorStore` can switch between dense, sparse and hybrid retrieval.
+    /// Stores that don't implement it return an error.
+    async fn keyword_search(
+        &self,
+        _query: &str,
+        _limit: usize,
+        _opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        Err("keyword_search is not supported by this vector store".into())
+    }
+
+    /// Streams documents in descending score order, stopping at the first document scoring
+    /// below `opt.score_threshold` instead of fetching a fixed `limit`.
+    /// Stores that don't implement it return an error.
+    async fn similarity_search_threshold_stream(
+        &self,
+        _query: &str,
+        _opt: &VecStoreOptions,
+    ) -> Result<DocumentStream, Box<dyn Error>> {
+        Err("similarity_search_threshold_stream is not supported by this vector store".into())
+    }
+
+    /// Fetches the `fetch_k` most similar documents, then picks `k` of them by maximal
+    /// marginal relevance (see [`maximal_marginal_relevance`]), trading similarity to the query
+    /// for diversity to avoid near-duplicate results. `lambda` is in `[0, 1]`: 1.0 orders by
+    /// similarity and 0.0 maximizes diversity.
+    ///
+    /// This default embeds the candidates with `opt.embedder`, which must be set; stores that
+    /// keep the embeddings use the stored ones instead.
+    async fn mmr_search(
+        &self,
+        query: &str,
+        k: usize,
+        fetch_k: usize,
+        lambda: f64,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let embedder = opt
+            .embedder
+            .clone()
+            .ok_or("mmr_search needs VecStoreOptions::embedder to embed the candidates")?;
+        let candidates = self.similarity_search(query, fetch_k.max(k), opt).await?;
+        if candidates.is_empty() {
+            return Ok(candidates);
+        }
+        let query_embedding = embedder.embed_query(query).await?;
+        let texts: Vec<String> = candidates.iter().map(|d| d.page_content.clone()).collect();
+        let embeddings = embedder.embed_documents(&texts).await?;
+
+        let selected = maximal_marginal_relevance(&query_embedding, &embeddings, k, lambda);
+        Ok(selected
+            .into_iter()
+            .map(|i| candidates[i].clone())
+            .collect())
+    }
+
+    /// Number of stored documents matching `opt.filters`, e.g. for monitoring or pagination.
+    /// Stores that don't implement it return an error.
+    async fn count_documents(&self, _opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        Err("count_documents is not supported by this vector store".into())
+    }
+
+    /// Inserts the documents that are not stored yet and replaces the ones that are, so that a
+    /// long-lived index can follow changes to its sources. A document is identified by its
+    /// entry in `opt.ids` if given, and otherwise by its metadata value at `opt.upsert_key`
+    /// (`"source"` by default), which it must have. Documents whose text is unchanged only
+    /// have their metadata updated, without being embedded again.
+    ///
+    /// Returns the key of each document. Stores that don't implement it return an error.
+    async fn upsert_documents(
+        &self,
+        _docs: &[Document],
+        _opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        Err("upsert_documents is not supported by this vector store".into())
+    }
+
+    /// The documents stored under `ids`, as returned by `add_documents`, in the order of
+    /// `ids`. Ids that are not stored are omitted, or an error with
+    /// `opt.error_on_missing_ids`. Stores that don't implement it return an error.
+    async fn get_documents_by_ids(
+        &self,
+        _ids: &[String],
+        _opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        Err("get_documents_by_ids is not supported by this vector store".into())
+    }
 }
+
+/// Embeds a short sentinel text to learn the embedder's output dimension. A non-zero
+/// `configured` dimension must match it; the probed dimension is returned.
+#[cfg(any(
+    feature = "postgres",
+    feature = "sqlite-vec",
+    feature = "sqlite-hybrid",
+    feature = "surrealdb",
+    feature = "redis-vector",
+    feature = "mongodb"
+))]
+pub(crate) async fn probe_vector_dimensions(
+    embedder: &dyn crate::embedding::embedder_trait::Embedder,
+    configured: i32,
+) -> Result<i32, Box<dyn Error>> {
+    let dimensions = embedder
+        .embed_query("Text to retrieve embeddings dimension")
+        .await
+        .map_err(|e| format!("Failed to probe the embedder dimension: {}", e))?
+        .len() as i32;
+
+    if configured > 0 && configured != dimensions {
+        return Err(format!(
+            "Configured vector_dimensions {} doesn't match the embedder dimension {}",
+            configured, dimensions
+        )
+        .into());
+    }
+    Ok(dimensions)
+}
+
+/// The key identifying each of `docs` for `upsert_documents`: its entry in `opt.ids`, or
+/// else its metadata value at `opt.upsert_key`. Errors on missing and repeated keys.
+#[cfg(any(
+    feature = "postgres",
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid"
+))]
+pub(crate) fn upsert_keys(
+    docs: &[Document],
+    opt: &VecStoreOptions,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let keys = match &opt.ids {
+        Some(ids) if ids.len() != docs.len() => {
+            return Err(format!("Got {} ids for {} documents", ids.len(), docs.len()).into());
+        }
+        Some(ids) => ids.clone(),
+        None => {
+            let key = opt.upsert_key.as_deref().unwrap_or("source");
+            docs.iter()
+                .enumerate()
+                .map(|(i, doc)| match doc.metadata.get(key) {
+                    Some(Value::String(s)) => Ok(s.clone()),
+                    Some(value) if !value.is_null() => Ok(value.to_string()),
+                    _ => Err(format!(
+                        "Document {} has no {:?} metadata to upsert by",
+                        i, key
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    let mut seen = std::collections::HashSet::new();
+    if let Some(key) = keys.iter().find(|key| !seen.insert(*key)) {
+        return Err(format!("Upsert key {:?} is repeated in the same call", key).into());
+    }
+    Ok(keys)
+}
+
+/// Orders the documents `found` by id as `ids`, for `get_documents_by_ids`. Missing ids are
+/// skipped, or reported as an error with `opt.error_on_missing_ids`.
+#[cfg(any(
+    feature = "postgres",
+    feature = "sqlite-vec",
+    feature = "sqlite-bm25",
+    feature = "sqlite-hybrid",
+    feature = "qdrant",
+    feature = "opensearch",
+    feature = "in-memory",
+    test
+))]
+pub(crate) fn order_by_ids(
+    ids: &[String],
+    found: HashMap<String, Document>,
+    opt: &VecStoreOptions,
+) -> Result<Vec<Document>, Box<dyn Error>> {
+    if opt.error_on_missing_ids {
+        let missing: Vec<&String> = ids.iter().filter(|id| !found.contains_key(*id)).collect();
+        if !missing.is_empty() {
+            return Err(format!("Documents not found for ids {:?}", missing).into());
+        }
+    }
+    Ok(ids.iter().filter_map(|id| found.get(id).cloned()).collect())
+}
+
 impl<VS> From<VS> for Box<dyn VectorStore>
 where
     VS: 'static + VectorStore,
@@ -0,0 +1,101 @@
+use std::{error::Error, sync::Arc};
+
+use tokio::sync::OnceCell;
+
+use super::{ConsistencyLevel, Store};
+use crate::embedding::embedder_trait::Embedder;
+
+pub struct StoreBuilder {
+    scheme: String,
+    host: String,
+    api_key: Option<String>,
+    class_name: Option<String>,
+    consistency_level: Option<ConsistencyLevel>,
+    embedder: Option<Arc<dyn Embedder>>,
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            scheme: "http".to_string(),
+            host: "localhost:8080".to_string(),
+            api_key: None,
+            class_name: None,
+            consistency_level: None,
+            embedder: None,
+        }
+    }
+
+    /// Defaults to `http`. Weaviate Cloud clusters are served over `https`.
+    pub fn scheme<S: Into<String>>(mut self, scheme: S) -> Self {
+        self.scheme = scheme.into();
+        self
+    }
+
+    /// Host of the Weaviate instance, with the port if it isn't the scheme's default, e.g.
+    /// `my-cluster.weaviate.network`. Defaults to `localhost:8080`.
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// API key sent as `Authorization: Bearer <key>`, as Weaviate Cloud and self-hosted
+    /// instances with API key authentication expect.
+    pub fn api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Name of the Weaviate class holding the documents. REQUIRED. Must start with an
+    /// uppercase letter. The class is created on the first `add_documents` if it doesn't
+    /// exist.
+    pub fn class_name(mut self, class_name: &str) -> Self {
+        self.class_name = Some(class_name.to_string());
+        self
+    }
+
+    /// Consistency level of writes and searches on a replicated class. The server's default
+    /// (`QUORUM`) when unset.
+    pub fn consistency_level(mut self, consistency_level: ConsistencyLevel) -> Self {
+        self.consistency_level = Some(consistency_level);
+        self
+    }
+
+    /// Embeddings provider for the Store. REQUIRED.
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("'embedder' is required")?;
+        let class_name = self.class_name.ok_or("'class_name' is required")?;
+        let mut chars = class_name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_uppercase())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!(
+                "Invalid Weaviate class name {:?}: it must start with an uppercase letter and \
+                 contain only letters, digits and underscores",
+                class_name
+            )
+            .into());
+        }
+
+        Ok(Store {
+            client: reqwest::Client::new(),
+            base_url: format!("{}://{}", self.scheme, self.host.trim_end_matches('/')),
+            api_key: self.api_key,
+            class_name,
+            class_ready: OnceCell::new(),
+            consistency_level: self.consistency_level,
+            embedder,
+        })
+    }
+}
@@ -0,0 +1,571 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Map, Value};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{Condition, FilterOp, MetadataFilter, VecStoreOptions, VectorStore},
+};
+
+/// Property holding the content of a document.
+const CONTENT_PROPERTY: &str = "page_content";
+/// Property holding the whole metadata of a document as JSON, from which it is read back.
+const METADATA_PROPERTY: &str = "metadata_json";
+
+/// How many replicas of a replicated class must acknowledge a write or answer a read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistencyLevel {
+    One,
+    Quorum,
+    All,
+}
+
+impl ConsistencyLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsistencyLevel::One => "ONE",
+            ConsistencyLevel::Quorum => "QUORUM",
+            ConsistencyLevel::All => "ALL",
+        }
+    }
+}
+
+/// A vector store backed by a class of a [Weaviate](https://weaviate.io) instance, self-hosted
+/// or on Weaviate Cloud.
+///
+/// Documents are stored with the embeddings of the store's embedder, as the class is created
+/// without a vectorizer. Besides the content and the JSON of the whole metadata, every
+/// metadata entry whose key is a valid property name and whose value is a scalar or an array
+/// is stored as a property of its own, which `VecStoreOptions::filters` can filter on.
+pub struct Store {
+    pub(crate) client: reqwest::Client,
+    pub(crate) base_url: String,
+    pub(crate) api_key: Option<String>,
+    pub(crate) class_name: String,
+    /// Set once the class is known to exist.
+    pub(crate) class_ready: OnceCell<()>,
+    pub(crate) consistency_level: Option<ConsistencyLevel>,
+    pub(crate) embedder: Arc<dyn Embedder>,
+}
+
+impl Store {
+    /// Deletes the documents with the given ids. Ids that don't exist are ignored.
+    pub async fn delete_documents_by_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        for id in ids {
+            let response = self
+                .request(
+                    Method::DELETE,
+                    &format!("/v1/objects/{}/{}", self.class_name, id),
+                    None,
+                )
+                .send()
+                .await?;
+            let status = response.status();
+            if !status.is_success() && status != StatusCode::NOT_FOUND {
+                let body = response.text().await.unwrap_or_default();
+                return Err(format!("Weaviate request failed with {}: {}", status, body).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the class exists, creating it first when `create` is set.
+    async fn ensure_class(&self, create: bool) -> Result<bool, Box<dyn Error>> {
+        if self.class_ready.get().is_some() {
+            return Ok(true);
+        }
+
+        let response = self
+            .request(
+                Method::GET,
+                &format!("/v1/schema/{}", self.class_name),
+                None,
+            )
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND if !create => return Ok(false),
+            StatusCode::NOT_FOUND => {
+                let schema = json!({
+                    "class": self.class_name,
+                    "vectorizer": "none",
+                    "vectorIndexConfig": { "distance": "cosine" },
+                    "properties": [
+                        { "name": CONTENT_PROPERTY, "dataType": ["text"] },
+                        {
+                            "name": METADATA_PROPERTY,
+                            "dataType": ["text"],
+                            "indexFilterable": false,
+                            "indexSearchable": false,
+                        },
+                    ],
+                });
+                let response = self
+                    .request(Method::POST, "/v1/schema", Some(schema))
+                    .send()
+                    .await?;
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                // Another writer may have created it in the meantime.
+                if !status.is_success() && !body.contains("already exists") {
+                    return Err(format!("Weaviate request failed with {}: {}", status, body).into());
+                }
+            }
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                return Err(format!("Weaviate request failed with {}: {}", status, body).into());
+            }
+        }
+
+        let _ = self.class_ready.set(());
+        Ok(true)
+    }
+
+    fn request(&self, method: Method, path: &str, body: Option<Value>) -> reqwest::RequestBuilder {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        if let Some(consistency_level) = self.consistency_level {
+            request = request.query(&[("consistency_level", consistency_level.as_str())]);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        request
+    }
+
+    /// Runs a GraphQL query and returns its `data`, or an error with the GraphQL errors.
+    async fn graphql(&self, query: String) -> Result<Value, Box<dyn Error>> {
+        let response = self
+            .request(Method::POST, "/v1/graphql", Some(json!({ "query": query })))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Weaviate request failed with {}: {}", status, body).into());
+        }
+        let mut body: Value = response.json().await?;
+        if let Some(errors) = body.get("errors").filter(|errors| !errors.is_null()) {
+            return Err(format!("Weaviate query failed: {}", errors).into());
+        }
+        Ok(body["data"].take())
+    }
+
+    /// GraphQL arguments shared by the search and count queries.
+    fn query_arguments(&self, opt: &VecStoreOptions) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut arguments = Vec::new();
+        if let Some(filters) = &opt.filters {
+            if let Some(where_filter) = weaviate_where(filters)? {
+                arguments.push(format!("where: {}", graphql_input(&where_filter)));
+            }
+        }
+        Ok(arguments)
+    }
+}
+
+/// Translates `VecStoreOptions::filters` to a Weaviate `where` filter, e.g.
+/// `{"lang": "rust"}` to `{"operator": "Equal", "path": ["lang"], "valueText": "rust"}`.
+/// Several conditions are combined with `And`. `None` for an empty filter. `$ilike` has no
+/// Weaviate equivalent and errors.
+pub(crate) fn weaviate_where(filters: &Value) -> Result<Option<Value>, Box<dyn Error>> {
+    let filter = MetadataFilter::parse(filters)?;
+    let mut operands = filter
+        .conditions
+        .iter()
+        .map(weaviate_condition)
+        .collect::<Result<Vec<Value>, _>>()?;
+    Ok(match operands.len() {
+        0 => None,
+        1 => operands.pop(),
+        _ => Some(json!({ "operator": "And", "operands": operands })),
+    })
+}
+
+fn weaviate_condition(condition: &Condition) -> Result<Value, Box<dyn Error>> {
+    let (operator, value) = match &condition.op {
+        FilterOp::Eq(value) => ("Equal", value.clone()),
+        FilterOp::Ne(value) => ("NotEqual", value.clone()),
+        FilterOp::Gt(value) => ("GreaterThan", value.clone()),
+        FilterOp::Gte(value) => ("GreaterThanEqual", value.clone()),
+        FilterOp::Lt(value) => ("LessThan", value.clone()),
+        FilterOp::Lte(value) => ("LessThanEqual", value.clone()),
+        FilterOp::In(values) => ("ContainsAny", Value::Array(values.clone())),
+        FilterOp::ILike(_) => {
+            return Err(format!(
+                "$ilike on {:?} is not supported by the Weaviate vector store",
+                condition.key
+            )
+            .into())
+        }
+    };
+
+    let value_type = match &value {
+        Value::Array(values) => {
+            let mut types = values.iter().map(scalar_type);
+            let first = types.next().flatten();
+            let value_type = types.try_fold(first, |acc, value_type| match (acc, value_type) {
+                (Some(a), Some(b)) if a == b => Some(Some(a)),
+                _ => None,
+            });
+            value_type
+                .flatten()
+                .map(|value_type| format!("{}Array", value_type))
+        }
+        value if operator != "ContainsAny" => scalar_type(value).map(str::to_string),
+        _ => None,
+    };
+    let value_type = value_type.ok_or_else(|| {
+        format!(
+            "Weaviate can only compare {:?} with a string, number or boolean, or an array of \
+             one type of them, got {}",
+            condition.key, value
+        )
+    })?;
+
+    let mut filter = Map::new();
+    filter.insert("operator".to_string(), json!(operator));
+    filter.insert("path".to_string(), json!([condition.key]));
+    filter.insert(format!("value{}", value_type), value);
+    Ok(Value::Object(filter))
+}
+
+/// The suffix of the `value*` field of a filter on `value`. Integers are compared as numbers,
+/// the type Weaviate's auto-schema gives to JSON numbers.
+fn scalar_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::String(_) => Some("Text"),
+        Value::Bool(_) => Some("Boolean"),
+        Value::Number(_) => Some("Number"),
+        _ => None,
+    }
+}
+
+/// Renders a JSON value as a GraphQL input value: object keys are unquoted, and so are the
+/// values of `operator` keys, which are GraphQL enums.
+pub(crate) fn graphql_input(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let fields: Vec<String> = map
+                .iter()
+                .map(|(key, value)| match (key.as_str(), value) {
+                    ("operator", Value::String(operator)) => format!("{}: {}", key, operator),
+                    _ => format!("{}: {}", key, graphql_input(value)),
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(graphql_input).collect();
+            format!("[{}]", values.join(", "))
+        }
+        // JSON strings, numbers and booleans are valid GraphQL literals.
+        value => value.to_string(),
+    }
+}
+
+/// The properties of the object of `doc`: its content, the JSON of its metadata, and the
+/// metadata entries that can be filtered on.
+fn weaviate_properties(doc: &Document) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let mut properties = Map::new();
+    for (key, value) in &doc.metadata {
+        let mut chars = key.chars();
+        let valid_name = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let filterable = match value {
+            Value::Array(values) => values.iter().all(|value| scalar_type(value).is_some()),
+            value => scalar_type(value).is_some(),
+        };
+        if valid_name && filterable && key != CONTENT_PROPERTY && key != METADATA_PROPERTY {
+            properties.insert(key.clone(), value.clone());
+        }
+    }
+    properties.insert(CONTENT_PROPERTY.to_string(), json!(doc.page_content));
+    properties.insert(
+        METADATA_PROPERTY.to_string(),
+        json!(serde_json::to_string(&doc.metadata)?),
+    );
+    Ok(properties)
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Adds the documents under new UUIDs with the batch endpoint, creating the class if it
+    /// doesn't exist.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| opt.embedding_text(d)).collect();
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let vectors = embedder.embed_documents(&texts).await?;
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let mut objects = Vec::with_capacity(docs.len());
+        for ((doc, vector), id) in docs.iter().zip(vectors).zip(&ids) {
+            objects.push(json!({
+                "class": self.class_name,
+                "id": id,
+                "properties": weaviate_properties(doc)?,
+                "vector": vector,
+            }));
+        }
+
+        self.ensure_class(true).await?;
+        let response = self
+            .request(
+                Method::POST,
+                "/v1/batch/objects",
+                Some(json!({ "objects": objects })),
+            )
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Weaviate request failed with {}: {}", status, body).into());
+        }
+
+        // The batch succeeds as a whole even when some objects fail.
+        let results: Vec<Value> = response.json().await?;
+        for result in &results {
+            if let Some(errors) = result["result"]["errors"]["error"].as_array() {
+                if !errors.is_empty() {
+                    return Err(format!(
+                        "Weaviate failed to add object {}: {}",
+                        result["id"],
+                        Value::Array(errors.clone())
+                    )
+                    .into());
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Weaviate doesn't support namespaces".into());
+        }
+        let mut arguments = self.query_arguments(opt)?;
+        if !self.ensure_class(false).await? {
+            return Ok(Vec::new());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        arguments.push(format!("nearVector: {{vector: {}}}", json!(query_vector)));
+        arguments.push(format!("limit: {}", limit));
+        if let Some(consistency_level) = self.consistency_level {
+            arguments.push(format!("consistencyLevel: {}", consistency_level.as_str()));
+        }
+
+        let query = format!(
+            "{{ Get {{ {}({}) {{ {} {} _additional {{ id distance }} }} }} }}",
+            self.class_name,
+            arguments.join(", "),
+            CONTENT_PROPERTY,
+            METADATA_PROPERTY,
+        );
+        let mut data = self.graphql(query).await?;
+        let objects = match data["Get"][&self.class_name].take() {
+            Value::Array(objects) => objects,
+            _ => Vec::new(),
+        };
+
+        let mut docs = Vec::with_capacity(objects.len());
+        for object in objects {
+            let page_content = object[CONTENT_PROPERTY]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let metadata: HashMap<String, Value> = match object[METADATA_PROPERTY].as_str() {
+                Some(metadata) => serde_json::from_str(metadata)?,
+                None => HashMap::new(),
+            };
+            let distance = object["_additional"]["distance"]
+                .as_f64()
+                .unwrap_or(f64::INFINITY);
+            docs.push(Document {
+                page_content,
+                metadata,
+                // The class is created with cosine distance.
+                score: 1.0 - distance,
+            });
+        }
+
+        if let Some(threshold) = opt.score_threshold {
+            docs.retain(|doc| doc.score >= threshold as f64);
+        }
+        Ok(docs)
+    }
+
+    async fn count_documents(&self, opt: &VecStoreOptions) -> Result<usize, Box<dyn Error>> {
+        let arguments = self.query_arguments(opt)?;
+        if !self.ensure_class(false).await? {
+            return Ok(0);
+        }
+
+        let arguments = if arguments.is_empty() {
+            String::new()
+        } else {
+            format!("({})", arguments.join(", "))
+        };
+        let query = format!(
+            "{{ Aggregate {{ {}{} {{ meta {{ count }} }} }} }}",
+            self.class_name, arguments
+        );
+        let data = self.graphql(query).await?;
+        let count = data["Aggregate"][&self.class_name][0]["meta"]["count"]
+            .as_u64()
+            .ok_or("Weaviate returned an invalid count")?;
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weaviate_where() {
+        let filter = weaviate_where(&json!({"lang": "rust"})).unwrap().unwrap();
+        assert_eq!(
+            filter,
+            json!({"operator": "Equal", "path": ["lang"], "valueText": "rust"})
+        );
+        assert_eq!(
+            graphql_input(&filter),
+            r#"{operator: Equal, path: ["lang"], valueText: "rust"}"#
+        );
+
+        let filter = weaviate_where(&json!({"year": {"$gte": 2023, "$lt": 2024.5}}))
+            .unwrap()
+            .unwrap();
+        assert_eq!(filter["operator"], "And");
+        let operands = filter["operands"].as_array().unwrap();
+        assert!(operands.contains(
+            &json!({"operator": "GreaterThanEqual", "path": ["year"], "valueNumber": 2023})
+        ));
+        assert!(operands
+            .contains(&json!({"operator": "LessThan", "path": ["year"], "valueNumber": 2024.5})));
+
+        assert_eq!(
+            weaviate_where(&json!({"tags": ["a", "b"]})).unwrap(),
+            Some(
+                json!({"operator": "ContainsAny", "path": ["tags"], "valueTextArray": ["a", "b"]})
+            )
+        );
+        assert_eq!(
+            weaviate_where(&json!({"n": [1, 2.5]})).unwrap().unwrap()["valueNumberArray"],
+            json!([1, 2.5])
+        );
+
+        assert_eq!(weaviate_where(&json!({})).unwrap(), None);
+        assert!(weaviate_where(&json!({"tags": ["a", 1]})).is_err());
+        assert!(weaviate_where(&json!({"format": {"$ilike": "pdf"}})).is_err());
+        assert!(weaviate_where(&json!({"author": {"name": "x"}})).is_err());
+    }
+
+    #[test]
+    fn test_weaviate_properties() {
+        let doc = Document::new("text").with_metadata(HashMap::from([
+            ("year".to_string(), json!(2024)),
+            ("tags".to_string(), json!(["a", "b"])),
+            ("author".to_string(), json!({"name": "x"})),
+            ("source-url".to_string(), json!("https://example.com")),
+        ]));
+        let properties = weaviate_properties(&doc).unwrap();
+
+        assert_eq!(properties["page_content"], "text");
+        assert_eq!(properties["year"], 2024);
+        assert_eq!(properties["tags"], json!(["a", "b"]));
+        assert!(!properties.contains_key("author"));
+        assert!(!properties.contains_key("source-url"));
+        let metadata: HashMap<String, Value> =
+            serde_json::from_str(properties["metadata_json"].as_str().unwrap()).unwrap();
+        assert_eq!(metadata, doc.metadata);
+    }
+
+    /// Runs against a Weaviate container, so needs Docker.
+    #[tokio::test]
+    #[ignore]
+    async fn test_weaviate_store() {
+        use testcontainers::{
+            core::{IntoContainerPort, WaitFor},
+            runners::AsyncRunner,
+            GenericImage, ImageExt,
+        };
+
+        use crate::vectorstore::{test_utils::LetterEmbedder, weaviate::StoreBuilder};
+
+        let container = GenericImage::new("semitechnologies/weaviate", "1.25.4")
+            .with_exposed_port(8080.tcp())
+            .with_wait_for(WaitFor::message_on_stderr("Serving weaviate"))
+            .with_env_var("AUTHENTICATION_ANONYMOUS_ACCESS_ENABLED", "true")
+            .with_env_var("DEFAULT_VECTORIZER_MODULE", "none")
+            .with_env_var("PERSISTENCE_DATA_PATH", "/var/lib/weaviate")
+            .start()
+            .await
+            .unwrap();
+        let port = container.get_host_port_ipv4(8080).await.unwrap();
+
+        let store = StoreBuilder::new()
+            .host(format!("localhost:{}", port))
+            .class_name("LangchainRust")
+            .consistency_level(ConsistencyLevel::One)
+            .embedder(LetterEmbedder)
+            .build()
+            .await
+            .unwrap();
+        let opt = VecStoreOptions::default();
+
+        // The class doesn't exist until the first documents are added.
+        assert!(store
+            .similarity_search("a", 2, &opt)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 0);
+
+        let docs = [("aaa", "x"), ("aab", "y"), ("ccc", "x")].map(|(text, tag)| {
+            Document::new(text).with_metadata(HashMap::from([("tag".to_string(), json!(tag))]))
+        });
+        let ids = store.add_documents(&docs, &opt).await.unwrap();
+
+        let found = store.similarity_search("a", 2, &opt).await.unwrap();
+        assert_eq!(found[0].page_content, "aaa");
+        assert_eq!(found[1].page_content, "aab");
+        assert_eq!(found[0].metadata["tag"], "x");
+
+        let tagged = VecStoreOptions::new().with_filters(json!({"tag": "x"}));
+        let found = store.similarity_search("c", 10, &tagged).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].page_content, "ccc");
+        assert_eq!(store.count_documents(&tagged).await.unwrap(), 2);
+
+        store.delete_documents_by_ids(&ids[..1]).await.unwrap();
+        assert_eq!(store.count_documents(&opt).await.unwrap(), 2);
+    }
+}